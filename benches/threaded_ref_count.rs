@@ -0,0 +1,67 @@
+//! Throughput of cloning and dropping `ThreadedCc`s from multiple threads.
+//!
+//! Each thread clones and drops its own object. The objects are allocated
+//! next to each other, so their reference counts might share cache lines.
+//! Compare with the `padded_ref_count` feature:
+//!
+//! ```plain
+//! cargo bench --bench threaded_ref_count
+//! cargo bench --bench threaded_ref_count --features padded_ref_count
+//! ```
+
+use gcmodule::{ThreadedCc, ThreadedObjectSpace};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 2_000_000;
+
+fn bench<T: Send + Sync + 'static>(name: &str, objects: Vec<ThreadedCc<T>>) {
+    let barrier = Arc::new(Barrier::new(objects.len()));
+    let threads: Vec<_> = objects
+        .into_iter()
+        .map(|object| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                let start = Instant::now();
+                for _ in 0..ITERATIONS {
+                    drop(object.clone());
+                }
+                start.elapsed()
+            })
+        })
+        .collect();
+    let count = threads.len();
+    let elapsed: Duration = threads
+        .into_iter()
+        .map(|t| t.join().unwrap())
+        .max()
+        .unwrap();
+    println!(
+        "{:>10} {:>2} threads: {:>6.2} ns/clone+drop",
+        name,
+        count,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+fn main() {
+    let space = ThreadedObjectSpace::default();
+    let max_threads = thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .min(8);
+    let mut thread_counts = vec![1, 2, max_threads];
+    thread_counts.sort_unstable();
+    thread_counts.dedup();
+    for threads in thread_counts {
+        // Untracked objects have no GcHeader. They are the most compact.
+        let untracked = (0..threads).map(|i| space.create(i)).collect();
+        bench("untracked", untracked);
+
+        let tracked = (0..threads)
+            .map(|_| space.create(Box::new(0) as Box<dyn gcmodule::Trace + Send + Sync>))
+            .collect();
+        bench("tracked", tracked);
+    }
+}
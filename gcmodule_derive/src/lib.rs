@@ -17,60 +17,477 @@
 //!
 //! struct MyType;
 //! ```
+//!
+//! # Container attributes
+//!
+//! - `#[trace(skip)]`: Do not trace anything. The type is not tracked.
+//! - `#[trace(children_iter)]`: Also generate an inherent method
+//!   `fn cc_children(&self) -> impl Iterator<Item = &dyn Trace>` that
+//!   iterates over the traced fields whose types are tracked. The items
+//!   are fields, not the objects referred by them. For enums, only fields
+//!   of the current variant are included.
+//!
+//! - `#[trace(finalize)]`: Make `Trace::finalize` call
+//!   `gcmodule::gc_compat::Finalize::finalize`. Requires the `gc_compat`
+//!   feature.
+//! - `#[trace(with(path))]`: Trace by calling
+//!   `path(&self, tracer: &mut gcmodule::Tracer)`. The type is tracked.
+//!   Fields are not traced. This is required for unions, since the
+//!   derive cannot know which field is active.
+//!
+//! ```
+//! use gcmodule::{Cc, Trace, Tracer};
+//!
+//! #[derive(Trace)]
+//! #[trace(with(trace_value))]
+//! union Value {
+//!     int: i64,
+//!     list: std::mem::ManuallyDrop<Cc<Vec<Box<dyn Trace>>>>,
+//! }
+//!
+//! fn trace_value(value: &Value, tracer: &mut Tracer) {
+//!     // Suppose odd numbers are tagged integers.
+//!     if unsafe { value.int } & 1 == 0 {
+//!         unsafe { value.list.trace(tracer) };
+//!     }
+//! }
+//! ```
+//!
+//! - `#[trace(name = "...")]`: Use the name for `Trace::type_name`, shown
+//!   in debug logs and heap reports, instead of the full type path from
+//!   `std::any::type_name`.
+//!
+//! ```
+//! use gcmodule::{Cc, Trace};
+//!
+//! #[derive(Trace)]
+//! #[trace(name = "Node")]
+//! struct Node<T> {
+//!     next: Option<Cc<Node<T>>>,
+//!     value: T,
+//! }
+//!
+//! let node = Node { next: None, value: 1u8 };
+//! assert_eq!(node.type_name(), "Node");
+//! ```
+//!
+//! - `#[trace(bound = "T: Trace")]`: Use the given where predicates,
+//!   instead of inferred bounds. See [Bounds](#bounds).
+//! - `#[trace(acyclic_if = "expr")]`: The type is not tracked if the
+//!   `bool` expression is true. Otherwise, tracking is decided by fields.
+//!   This is useful for generic types whose cyclicity depends on a type
+//!   parameter that is not a field.
+//!
+//! ```
+//! use gcmodule::Trace;
+//! use std::marker::PhantomData;
+//!
+//! trait Policy: 'static {
+//!     const SHARED: bool;
+//! }
+//! struct Local;
+//! impl Policy for Local {
+//!     const SHARED: bool = false;
+//! }
+//!
+//! #[derive(Trace)]
+//! #[trace(acyclic_if = "!P::SHARED")]
+//! struct Handle<P: Policy> {
+//!     // Only points to other objects with shared policies.
+//!     value: Option<Box<dyn Trace>>,
+//!     _policy: PhantomData<P>,
+//! }
+//!
+//! assert!(!Handle::<Local>::is_type_tracked());
+//! ```
+//!
+//! - `#[trace(ignore_params(T, ...))]`: The type parameters do not affect
+//!   tracing. Fields using them are not traced, and they only get the
+//!   `T: 'static` bound. This is useful for marker type parameters used in
+//!   types other than `PhantomData`, which is already ignored.
+//!
+//! ```
+//! use gcmodule::Trace;
+//!
+//! // Implements `Trace` only if `T` does.
+//! #[derive(Trace)]
+//! struct Typed<T>(std::marker::PhantomData<T>, Option<Box<T>>);
+//!
+//! #[derive(Trace)]
+//! #[trace(ignore_params(T))]
+//! struct Key<T> {
+//!     id: u64,
+//!     kind: Typed<T>,
+//! }
+//!
+//! struct NotTrace;
+//! assert!(!Key::<NotTrace>::is_type_tracked());
+//! ```
+//!
+//! # Field attributes
+//!
+//! - `#[trace(skip)]`: Do not trace the field. References, like `&'a T`,
+//!   are not traced either, since they do not own the values.
+//! - `#[unsafe_ignore_trace]`: Same as `#[trace(skip)]`, for compatibility
+//!   with the `gc` crate. Requires the `gc_compat` feature.
+//!
+//! # Variant attributes
+//!
+//! - `#[trace(skip)]`: Do not trace fields of the variant.
+//! - `#[trace(with(path))]`: Trace the variant by calling
+//!   `path(&self, tracer: &mut gcmodule::Tracer)`. The type is tracked.
+//!
+//! # Enums
+//!
+//! The type is tracked if any traced field in any variant is tracked.
+//! Unit variants, and variants without traced fields, are not matched by
+//! `trace`. Explicit discriminants are allowed.
+//!
+//! ```
+//! use gcmodule::{Trace, Tracer};
+//!
+//! #[derive(Trace)]
+//! #[repr(u8)]
+//! enum Value {
+//!     Nil = 0,
+//!     Int(i64) = 1,
+//!     List(Vec<Box<dyn Trace>>) = 2,
+//!     #[trace(with(trace_opaque))]
+//!     Opaque(usize) = 3,
+//! }
+//!
+//! fn trace_opaque(value: &Value, tracer: &mut Tracer) {
+//!     // Look up the object by id, and trace it.
+//!     let _ = (value, tracer);
+//! }
+//!
+//! assert!(Value::is_type_tracked());
+//! ```
+//!
+//! # Recursive types
+//!
+//! A type with fields referring to itself, like `next: RawCc<Node<O>, O>`
+//! in `struct Node<O>`, is always tracked, since it can form cycles.
+//!
+//! # Lifetimes
+//!
+//! Since `Trace` requires `'static`, for a type with lifetime parameters,
+//! like `S<'a>`, `Trace` is implemented for `S<'static>`.
+//!
+//! ```
+//! use gcmodule::{Cc, Trace};
+//!
+//! #[derive(Trace)]
+//! struct Config<'a> {
+//!     name: &'a str,
+//!     value: Cc<u32>,
+//! }
+//!
+//! let config = Cc::new(Config { name: "x", value: Cc::new(1) });
+//! assert!(!Config::is_type_tracked());
+//! ```
+//!
+//! # Bounds
+//!
+//! Type parameters without trait bounds written by the user get a
+//! `T: Trace` bound if they are used by traced fields. Uses in skipped fields and
+//! `PhantomData<T>` do not count. Other type parameters, including those
+//! with bounds like `O: AbstractObjectSpace`, get a `T: 'static` bound.
+//!
+//! ```
+//! use gcmodule::Trace;
+//! use std::marker::PhantomData;
+//!
+//! #[derive(Trace)]
+//! struct Id<K, V> {
+//!     value: V, // V: Trace
+//!     _kind: PhantomData<K>,
+//! }
+//!
+//! struct NotTrace;
+//! assert!(!Id::<NotTrace, u32>::is_type_tracked());
+//! ```
+//!
+//! # Edge names
+//!
+//! References found in a field are labeled with the field name, like `b`
+//! or `0`, via `Tracer::trace_named`. The names show up in
+//! `gcmodule::analysis` reports.
+//!
+//! # DeepClone
+//!
+//! `derive(DeepClone)` implements `gcmodule::DeepClone` using the same
+//! attributes. Traced fields are cloned by `DeepClone`. Other fields,
+//! including fields with `#[trace(skip)]`, are cloned by `Clone`. Type
+//! parameters used by traced fields get a `T: DeepClone + Trace` bound,
+//! and those used by other fields get a `T: Clone` bound. Types with
+//! `#[trace(skip)]` are cloned by `Clone`. `#[trace(with(...))]` is not
+//! supported.
+//!
+//! ```
+//! use gcmodule::{Cc, DeepClone, Trace};
+//!
+//! #[derive(DeepClone, Trace)]
+//! enum List<T> {
+//!     Nil,
+//!     Cons(T, Cc<List<T>>),
+//! }
+//!
+//! let tail = Cc::new(List::Nil);
+//! let list = Cc::new(List::Cons(1, tail.clone()));
+//! let copy = gcmodule::deep_clone(&list);
+//! match &*copy {
+//!     List::Cons(1, next) => assert!(!Cc::ptr_eq(next, &tail)),
+//!     _ => unreachable!(),
+//! }
+//! ```
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
+use proc_macro2::Span;
+use proc_macro2::TokenTree;
 use quote::quote;
 use quote::ToTokens;
+use syn::spanned::Spanned;
 use syn::Data;
 
-#[proc_macro_derive(Trace, attributes(trace))]
+#[cfg_attr(
+    not(feature = "gc_compat"),
+    proc_macro_derive(Trace, attributes(trace))
+)]
+#[cfg_attr(
+    feature = "gc_compat",
+    proc_macro_derive(Trace, attributes(trace, unsafe_ignore_trace))
+)]
 pub fn gcmodule_trace_derive(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+    match derive_trace(input) {
+        Ok(generated) => generated.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Implement `gcmodule::gc_compat::Finalize` with an empty `finalize`.
+#[cfg(feature = "gc_compat")]
+#[proc_macro_derive(Finalize)]
+pub fn gcmodule_finalize_derive(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as syn::DeriveInput);
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
-    let ident = input.ident;
+    let ident = &input.ident;
+    let generated = quote! {
+        const _: () = {
+            extern crate gcmodule as _gcmodule;
+            impl #impl_generics _gcmodule::gc_compat::Finalize for #ident #ty_generics #where_clause {}
+        };
+    };
+    generated.into()
+}
+
+/// Implement `gcmodule::DeepClone`. See [DeepClone](index.html#deepclone).
+#[proc_macro_derive(DeepClone, attributes(trace))]
+pub fn gcmodule_deep_clone_derive(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+    match derive_deep_clone(input) {
+        Ok(generated) => generated.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn derive_trace(input: syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let (input, ty_generics) = static_lifetimes(input)?;
+    let ident = &input.ident;
+    let container = ContainerAttrs::parse(&input.attrs)?;
+    for ident in container.ignore_params.iter() {
+        if !input.generics.type_params().any(|p| &p.ident == ident) {
+            return Err(syn::Error::new(ident.span(), "not a type parameter"));
+        }
+    }
+    let generics = trace_generics(&input, &container)?;
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
     let mut trace_fn_body = Vec::new();
     let mut is_type_tracked_fn_body = Vec::new();
-    if !input.attrs.into_iter().any(is_skipped) {
-        match input.data {
+    let mut is_recursive = false;
+    let mut has_self_ref_fn_body = Vec::new();
+    let mut init_self_ref_fn_body = Vec::new();
+    // Patterns, and traced fields of types that might be tracked.
+    let mut children = Vec::new();
+    if let (false, Some(with)) = (container.skip, &container.with) {
+        trace_fn_body.push(quote! {
+            #with(self, tracer);
+        });
+        // Fields are unknown to the derive.
+        is_recursive = true;
+    } else if !container.skip {
+        let mut types = Vec::new();
+        match &input.data {
             Data::Struct(data) => {
-                for (i, field) in data.fields.into_iter().enumerate() {
-                    if field.attrs.into_iter().any(is_skipped) {
+                let mut fields = Vec::new();
+                for (member, ty) in traced_fields(&data.fields, &container.ignore_params)? {
+                    let name = quote! { stringify!(#member) };
+                    trace_fn_body.push(trace_field(ident, &name, &quote! { &self.#member }));
+                    fields.push(child(ty, &quote! { &self.#member }));
+                    init_self_ref_fn_body.push(quote! {
+                        _gcmodule::Trace::__gcmodule_init_self_ref(&self.#member, weak);
+                    });
+                    types.push(ty);
+                }
+                children.push((None, fields));
+            }
+            Data::Enum(data) => {
+                let mut trace_arms = Vec::new();
+                let mut init_self_ref_arms = Vec::new();
+                for variant in data.variants.iter() {
+                    let attrs = VariantAttrs::parse(&variant.attrs)?;
+                    let variant_ident = &variant.ident;
+                    if let Some(with) = &attrs.with {
+                        trace_arms.push(quote! {
+                            Self::#variant_ident { .. } => #with(self, tracer),
+                        });
+                        // Fields are unknown to the derive.
+                        is_recursive = true;
                         continue;
                     }
-                    let trace_field = match field.ident {
-                        Some(i) => quote! {
-                            if gcmodule::DEBUG_ENABLED {
-                                eprintln!("[gc] Trace({}): visit .{}", stringify!(#ident), stringify!(#i));
-                            }
-                            self.#i.trace(tracer);
-                        },
-                        None => {
-                            let i = syn::Index::from(i);
-                            quote! {
-                                if gcmodule::DEBUG_ENABLED {
-                                    eprintln!("[gc] Trace({}): visit .{}", stringify!(#ident), stringify!(#i));
-                                }
-                                self.#i.trace(tracer);
-                            }
-                        }
+                    let fields = if attrs.skip {
+                        Vec::new()
+                    } else {
+                        traced_fields(&variant.fields, &container.ignore_params)?
                     };
-                    trace_fn_body.push(trace_field);
-                    let ty = field.ty;
-                    is_type_tracked_fn_body.push(quote! {
-                        if <#ty as _gcmodule::Trace>::is_type_tracked() {
-                            return true;
+                    if fields.is_empty() {
+                        // Unit variants, and variants without traced fields,
+                        // are not matched.
+                        continue;
+                    }
+                    let members: Vec<_> = fields.iter().map(|(member, _)| member).collect();
+                    let bindings: Vec<_> = (0..fields.len())
+                        .map(|i| quote::format_ident!("__gcmodule_field_{}", i))
+                        .collect();
+                    // `{ 0: x }` also matches tuple variants.
+                    let pattern = quote! {
+                        Self::#variant_ident { #( #members: #bindings, )* .. }
+                    };
+                    let traces = members.iter().zip(&bindings).map(|(member, binding)| {
+                        let name = quote! {
+                            concat!(stringify!(#variant_ident), ".", stringify!(#member))
+                        };
+                        trace_field(ident, &name, &quote! { #binding })
+                    });
+                    trace_arms.push(quote! {
+                        #pattern => { #( #traces )* }
+                    });
+                    init_self_ref_arms.push(quote! {
+                        #pattern => {
+                            #( _gcmodule::Trace::__gcmodule_init_self_ref(#bindings, weak); )*
                         }
                     });
+                    let variant_children = fields
+                        .iter()
+                        .zip(&bindings)
+                        .map(|((_, ty), binding)| child(ty, &quote! { #binding }))
+                        .collect();
+                    children.push((Some(pattern), variant_children));
+                    types.extend(fields.iter().map(|(_, ty)| *ty));
                 }
+                trace_fn_body.push(match_self(trace_arms));
+                init_self_ref_fn_body.push(match_self(init_self_ref_arms));
             }
-            Data::Enum(_) | Data::Union(_) => {
+            Data::Union(_) => {
                 trace_fn_body.push(quote! {
-                    compile_error!("enum or union are not supported");
+                    compile_error!("union is not supported without #[trace(with(...))]");
                 });
             }
         };
+        for ty in types {
+            if mentions_ident(ty.to_token_stream(), ident) {
+                // Recursive types, like `Node` with a `RawCc<Node, O>`
+                // field, can form cycles. Calling `is_type_tracked`
+                // would recurse forever.
+                is_recursive = true;
+            }
+            is_type_tracked_fn_body.push(quote! {
+                if <#ty as _gcmodule::Trace>::is_type_tracked() {
+                    return true;
+                }
+            });
+            has_self_ref_fn_body.push(quote! {
+                if <#ty as _gcmodule::Trace>::__gcmodule_has_self_ref() {
+                    return true;
+                }
+            });
+        }
     }
+    let children_iter = if container.children_iter {
+        // Arrays of the same length for all variants. Padded with `None`.
+        let len = children
+            .iter()
+            .map(|(_, fields)| fields.len())
+            .max()
+            .unwrap_or(0);
+        let mut arrays = children.iter().map(|(_, fields)| {
+            let padding = (fields.len()..len).map(|_| quote! { ::core::option::Option::None });
+            quote! { [ #( #fields, )* #( #padding, )* ] }
+        });
+        let len = proc_macro2::Literal::usize_unsuffixed(len);
+        let array = match &input.data {
+            Data::Enum(_) => {
+                let patterns = children.iter().map(|(pattern, _)| pattern);
+                quote! {
+                    match self {
+                        #( #patterns => #arrays, )*
+                        #[allow(unreachable_patterns)]
+                        _ => [::core::option::Option::None; #len],
+                    }
+                }
+            }
+            _ => arrays.next().unwrap_or_else(|| quote! { [] }),
+        };
+        quote! {
+            impl #impl_generics #ident #ty_generics #where_clause {
+                /// Iterate over fields visited by `Trace::trace` whose types
+                /// might be tracked.
+                #[allow(dead_code)]
+                pub fn cc_children(&self) -> impl Iterator<Item = &dyn _gcmodule::Trace> + '_ {
+                    let children: [::core::option::Option<&dyn _gcmodule::Trace>; #len] = #array;
+                    ::core::iter::IntoIterator::into_iter(children).flatten()
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let type_name_fn = match &container.name {
+        Some(name) => quote! {
+            fn type_name(&self) -> &'static str {
+                #name
+            }
+        },
+        None => quote! {},
+    };
+    let finalize_fn = if container.finalize {
+        quote! {
+            fn finalize(&self) {
+                _gcmodule::gc_compat::Finalize::finalize(self)
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let is_type_tracked_fn_body = if is_recursive {
+        quote! { true }
+    } else {
+        quote! {
+            #( #is_type_tracked_fn_body )*
+            false
+        }
+    };
+    let is_type_tracked_fn_body = match &container.acyclic_if {
+        Some(expr) => quote! {
+            if #expr {
+                return false;
+            }
+            #is_type_tracked_fn_body
+        },
+        None => is_type_tracked_fn_body,
+    };
     let generated = quote! {
         const _: () = {
             extern crate gcmodule as _gcmodule;
@@ -79,23 +496,597 @@ pub fn gcmodule_trace_derive(input: TokenStream) -> TokenStream {
                     #( #trace_fn_body )*
                 }
                 fn is_type_tracked() -> bool {
-                    #( #is_type_tracked_fn_body )*
+                    #is_type_tracked_fn_body
+                }
+                fn __gcmodule_has_self_ref() -> bool {
+                    #( #has_self_ref_fn_body )*
                     false
                 }
+                fn __gcmodule_init_self_ref(&self, weak: &dyn ::core::any::Any) {
+                    #( #init_self_ref_fn_body )*
+                    let _ = weak;
+                }
+                #finalize_fn
+                #type_name_fn
             }
+            #children_iter
         };
     };
-    generated.into()
+    Ok(generated)
+}
+
+fn derive_deep_clone(input: syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let container = ContainerAttrs::parse(&input.attrs)?;
+    if container.with.is_some() {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "#[trace(with(...))] is not supported by derive(DeepClone)",
+        ));
+    }
+    let mut generics = input.generics.clone();
+    let mut deep_cloned = proc_macro2::TokenStream::new();
+    let mut cloned = proc_macro2::TokenStream::new();
+    let body = if container.skip {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(syn::parse_quote! { Self: ::core::clone::Clone });
+        quote! { ::core::clone::Clone::clone(self) }
+    } else {
+        let mut arms = Vec::new();
+        let mut add_arm =
+            |path: proc_macro2::TokenStream, fields: &syn::Fields, skip: bool| -> syn::Result<()> {
+                let traced: Vec<syn::Member> = if skip {
+                    Vec::new()
+                } else {
+                    traced_fields(fields, &container.ignore_params)?
+                        .into_iter()
+                        .map(|(member, _)| member)
+                        .collect()
+                };
+                let mut members = Vec::new();
+                let mut bindings = Vec::new();
+                let mut values = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    let member = match &field.ident {
+                        Some(ident) => syn::Member::Named(ident.clone()),
+                        None => syn::Member::Unnamed(syn::Index::from(i)),
+                    };
+                    let binding = quote::format_ident!("__gcmodule_field_{}", i);
+                    if traced.contains(&member) {
+                        deep_cloned.extend(field.ty.to_token_stream());
+                        values.push(quote! {
+                            _gcmodule::DeepClone::deep_clone(#binding, cloner)
+                        });
+                    } else {
+                        cloned.extend(field.ty.to_token_stream());
+                        values.push(quote! { ::core::clone::Clone::clone(#binding) });
+                    }
+                    members.push(member);
+                    bindings.push(binding);
+                }
+                // `{ 0: x }` also works for tuple and unit structs and variants.
+                arms.push(quote! {
+                    #path { #( #members: #bindings, )* } => #path { #( #members: #values, )* },
+                });
+                Ok(())
+            };
+        match &input.data {
+            Data::Struct(data) => add_arm(quote! { Self }, &data.fields, false)?,
+            Data::Enum(data) => {
+                for variant in data.variants.iter() {
+                    let attrs = VariantAttrs::parse(&variant.attrs)?;
+                    if attrs.with.is_some() {
+                        return Err(syn::Error::new(
+                            variant.span(),
+                            "#[trace(with(...))] is not supported by derive(DeepClone)",
+                        ));
+                    }
+                    let variant_ident = &variant.ident;
+                    add_arm(quote! { Self::#variant_ident }, &variant.fields, attrs.skip)?;
+                }
+            }
+            Data::Union(_) => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "union is not supported by derive(DeepClone)",
+                ));
+            }
+        }
+        if arms.is_empty() {
+            quote! { match *self {} }
+        } else {
+            quote! {
+                match self {
+                    #( #arms )*
+                }
+            }
+        }
+    };
+    let deep_cloned = used_idents(deep_cloned);
+    let cloned = used_idents(cloned);
+    let predicates: Vec<syn::WherePredicate> = input
+        .generics
+        .type_params()
+        .flat_map(|p| {
+            let ident = &p.ident;
+            let mut predicates = Vec::new();
+            if deep_cloned.contains(ident) {
+                predicates.push(syn::parse_quote! {
+                    #ident: _gcmodule::DeepClone + _gcmodule::Trace
+                });
+            }
+            if cloned.contains(ident) {
+                predicates.push(syn::parse_quote! { #ident: ::core::clone::Clone });
+            }
+            predicates
+        })
+        .collect();
+    generics.make_where_clause().predicates.extend(predicates);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let generated = quote! {
+        const _: () = {
+            extern crate gcmodule as _gcmodule;
+            impl #impl_generics _gcmodule::DeepClone for #ident #ty_generics #where_clause {
+                fn deep_clone(&self, cloner: &mut _gcmodule::DeepCloner) -> Self {
+                    let _ = &cloner;
+                    #body
+                }
+            }
+        };
+    };
+    Ok(generated)
+}
+
+/// Attributes on the type.
+#[derive(Default)]
+struct ContainerAttrs {
+    skip: bool,
+    children_iter: bool,
+    finalize: bool,
+    with: Option<syn::Path>,
+    bound: Option<Vec<syn::WherePredicate>>,
+    acyclic_if: Option<syn::Expr>,
+    ignore_params: Vec<syn::Ident>,
+    name: Option<syn::LitStr>,
+}
+
+impl ContainerAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for meta in trace_metas(attrs)? {
+            match meta_name(&meta).as_deref() {
+                Some("skip") => result.skip = true,
+                Some("children_iter") => result.children_iter = true,
+                #[cfg(feature = "gc_compat")]
+                Some("finalize") => result.finalize = true,
+                Some("with") => result.with = Some(with_path(&meta)?),
+                Some("bound") => result.bound = Some(bound_predicates(&meta)?),
+                Some("acyclic_if") => result.acyclic_if = Some(acyclic_if_expr(&meta)?),
+                Some("ignore_params") => result.ignore_params.extend(param_list(&meta)?),
+                Some("name") => result.name = Some(name_str(&meta)?),
+                _ => return Err(unknown_attr(&meta)),
+            }
+        }
+        if result.skip && result.with.is_some() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "#[trace(skip)] and #[trace(with(...))] are exclusive",
+            ));
+        }
+        Ok(result)
+    }
+}
+
+/// Attributes on a field.
+#[derive(Default)]
+struct FieldAttrs {
+    skip: bool,
+}
+
+impl FieldAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for meta in trace_metas(attrs)? {
+            match meta_name(&meta).as_deref() {
+                Some("skip") => result.skip = true,
+                _ => return Err(unknown_attr(&meta)),
+            }
+        }
+        #[cfg(feature = "gc_compat")]
+        if attrs.iter().any(|a| a.path.is_ident("unsafe_ignore_trace")) {
+            result.skip = true;
+        }
+        Ok(result)
+    }
+}
+
+/// Attributes on an enum variant.
+#[derive(Default)]
+struct VariantAttrs {
+    skip: bool,
+    with: Option<syn::Path>,
+}
+
+impl VariantAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for meta in trace_metas(attrs)? {
+            match meta_name(&meta).as_deref() {
+                Some("skip") => result.skip = true,
+                Some("with") => result.with = Some(with_path(&meta)?),
+                _ => return Err(unknown_attr(&meta)),
+            }
+        }
+        if result.skip && result.with.is_some() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "#[trace(skip)] and #[trace(with(...))] are exclusive",
+            ));
+        }
+        Ok(result)
+    }
+}
+
+/// Members and types of traced fields. Fields using type parameters in
+/// `ignored` are not traced.
+fn traced_fields<'a>(
+    fields: &'a syn::Fields,
+    ignored: &[syn::Ident],
+) -> syn::Result<Vec<(syn::Member, &'a syn::Type)>> {
+    let mut result = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if !is_traced(field)?
+            || used_idents(field.ty.to_token_stream())
+                .iter()
+                .any(|ident| ignored.contains(ident))
+        {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(syn::Index::from(i)),
+        };
+        result.push((member, &field.ty));
+    }
+    Ok(result)
+}
+
+/// Trace a field. `name` is the edge name. `value` is a reference to the
+/// field.
+fn trace_field(
+    ident: &syn::Ident,
+    name: &proc_macro2::TokenStream,
+    value: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        _gcmodule::__debug_visit(stringify!(#ident), #name);
+        tracer.trace_named(#name, #value);
+    }
+}
+
+/// Match `self` with `arms`. Other variants do nothing.
+/// Expression of a traced field for `cc_children`. `None` if the type of the
+/// field is not tracked.
+fn child(ty: &syn::Type, value: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote! {
+        if <#ty as _gcmodule::Trace>::is_type_tracked() {
+            ::core::option::Option::Some(#value as &dyn _gcmodule::Trace)
+        } else {
+            ::core::option::Option::None
+        }
+    }
+}
+
+fn match_self(arms: Vec<proc_macro2::TokenStream>) -> proc_macro2::TokenStream {
+    if arms.is_empty() {
+        return quote! {};
+    }
+    quote! {
+        match self {
+            #( #arms )*
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+    }
+}
+
+/// Whether `field` is traced. Fields with `#[trace(skip)]` and references
+/// are not traced. References do not own the values they point to.
+fn is_traced(field: &syn::Field) -> syn::Result<bool> {
+    let mut ty = &field.ty;
+    while let syn::Type::Group(syn::TypeGroup { elem, .. })
+    | syn::Type::Paren(syn::TypeParen { elem, .. }) = ty
+    {
+        ty = elem;
+    }
+    let is_reference = matches!(ty, syn::Type::Reference(_));
+    Ok(!FieldAttrs::parse(&field.attrs)?.skip && !is_reference)
+}
+
+/// Replace lifetime parameters with `'static`, since `Trace` requires
+/// `'static`. Return the new input, and type arguments of the type, like
+/// `<'static, T>`.
+fn static_lifetimes(
+    mut input: syn::DeriveInput,
+) -> syn::Result<(syn::DeriveInput, proc_macro2::TokenStream)> {
+    let lifetimes: Vec<syn::Ident> = input
+        .generics
+        .lifetimes()
+        .map(|l| l.lifetime.ident.clone())
+        .collect();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let ty_generics = replace_lifetimes(ty_generics.to_token_stream(), &lifetimes);
+    if lifetimes.is_empty() {
+        return Ok((input, ty_generics));
+    }
+    input.generics.params = input
+        .generics
+        .params
+        .into_iter()
+        .filter(|p| !matches!(p, syn::GenericParam::Lifetime(_)))
+        .collect();
+    let input = syn::parse2(replace_lifetimes(input.to_token_stream(), &lifetimes))?;
+    Ok((input, ty_generics))
 }
 
-fn is_skipped(attr: syn::Attribute) -> bool {
-    // check if `#[trace(skip)]` exists.
-    if attr.path.to_token_stream().to_string() == "trace" {
-        for token in attr.tokens {
-            if token.to_string() == "(skip)" {
-                return true;
+/// Replace lifetimes named `names` in `tokens` with `'static`.
+fn replace_lifetimes(
+    tokens: proc_macro2::TokenStream,
+    names: &[syn::Ident],
+) -> proc_macro2::TokenStream {
+    let mut result = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(p) if p.as_char() == '\'' => {
+                result.push(TokenTree::Punct(p));
+                if let Some(TokenTree::Ident(i)) = tokens.peek() {
+                    if names.contains(i) {
+                        let span = i.span();
+                        tokens.next();
+                        result.push(TokenTree::Ident(syn::Ident::new("static", span)));
+                    }
+                }
             }
+            TokenTree::Group(g) => {
+                let stream = replace_lifetimes(g.stream(), names);
+                let mut group = proc_macro2::Group::new(g.delimiter(), stream);
+                group.set_span(g.span());
+                result.push(TokenTree::Group(group));
+            }
+            token => result.push(token),
         }
     }
-    false
+    result.into_iter().collect()
+}
+
+/// Generics of the `Trace` impl.
+///
+/// With `#[trace(bound = "...")]`, add the given predicates. Otherwise,
+/// add `T: Trace` for type parameters without user-written bounds that are
+/// used by traced fields, outside `PhantomData`, and `T: 'static` for other
+/// type parameters.
+fn trace_generics(
+    input: &syn::DeriveInput,
+    container: &ContainerAttrs,
+) -> syn::Result<syn::Generics> {
+    let mut generics = input.generics.clone();
+    let predicates = match &container.bound {
+        Some(bound) => bound.clone(),
+        None => {
+            // Fields are not traced with `skip` or `with`.
+            let mut traced = proc_macro2::TokenStream::new();
+            let mut fields = Vec::new();
+            match (container.skip, &container.with, &input.data) {
+                (false, None, Data::Struct(data)) => fields.push(&data.fields),
+                (false, None, Data::Enum(data)) => {
+                    for variant in data.variants.iter() {
+                        let attrs = VariantAttrs::parse(&variant.attrs)?;
+                        if !attrs.skip && attrs.with.is_none() {
+                            fields.push(&variant.fields);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            for fields in fields {
+                for (_, ty) in traced_fields(fields, &container.ignore_params)? {
+                    traced.extend(ty.to_token_stream());
+                }
+            }
+            let used = used_idents(traced);
+            input
+                .generics
+                .type_params()
+                .map(|p| {
+                    let ident = &p.ident;
+                    if !p.bounds.iter().any(is_trait_bound)
+                        && !has_where_bound(&input.generics, ident)
+                        && used.contains(ident)
+                    {
+                        syn::parse_quote! { #ident: _gcmodule::Trace }
+                    } else {
+                        syn::parse_quote! { #ident: 'static }
+                    }
+                })
+                .collect()
+        }
+    };
+    generics.make_where_clause().predicates.extend(predicates);
+    Ok(generics)
+}
+
+/// Whether the where clause has trait bounds on the type parameter `ident`.
+fn has_where_bound(generics: &syn::Generics, ident: &syn::Ident) -> bool {
+    generics.where_clause.as_ref().is_some_and(|w| {
+        w.predicates.iter().any(|p| match p {
+            syn::WherePredicate::Type(t) => {
+                mentions_ident(t.bounded_ty.to_token_stream(), ident)
+                    && t.bounds.iter().any(is_trait_bound)
+            }
+            _ => false,
+        })
+    })
+}
+
+fn is_trait_bound(bound: &syn::TypeParamBound) -> bool {
+    matches!(bound, syn::TypeParamBound::Trait(_))
+}
+
+/// Identifiers in `tokens`, skipping `PhantomData<...>`.
+fn used_idents(tokens: proc_macro2::TokenStream) -> Vec<syn::Ident> {
+    let mut result = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(i) if i == "PhantomData" => {
+                // Skip `<...>` or `::<...>`. The `>` of `->` in
+                // `fn() -> T` does not close the bracket.
+                let mut depth = 0;
+                let mut after_minus = false;
+                while let Some(token) = tokens.peek() {
+                    let c = match token {
+                        TokenTree::Punct(p) => Some(p.as_char()),
+                        _ => None,
+                    };
+                    match c {
+                        Some('<') => depth += 1,
+                        Some('>') if depth > 0 && !after_minus => depth -= 1,
+                        Some(':') if depth == 0 => {}
+                        _ if depth == 0 => break,
+                        _ => {}
+                    }
+                    after_minus = c == Some('-');
+                    tokens.next();
+                    if depth == 0 && c == Some('>') {
+                        break;
+                    }
+                }
+            }
+            TokenTree::Ident(i) => result.push(i),
+            TokenTree::Group(g) => result.extend(used_idents(g.stream())),
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Extract predicates from `bound = "T: Trace"`.
+fn bound_predicates(meta: &syn::NestedMeta) -> syn::Result<Vec<syn::WherePredicate>> {
+    if let syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+        lit: syn::Lit::Str(s),
+        ..
+    })) = meta
+    {
+        let parser =
+            syn::punctuated::Punctuated::<syn::WherePredicate, syn::Token![,]>::parse_terminated;
+        return Ok(s.parse_with(parser)?.into_iter().collect());
+    }
+    Err(syn::Error::new(
+        meta.span(),
+        "expected #[trace(bound = \"...\")]",
+    ))
+}
+
+/// Extract the expression from `acyclic_if = "..."`.
+fn acyclic_if_expr(meta: &syn::NestedMeta) -> syn::Result<syn::Expr> {
+    if let syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+        lit: syn::Lit::Str(s),
+        ..
+    })) = meta
+    {
+        return s.parse();
+    }
+    Err(syn::Error::new(
+        meta.span(),
+        "expected #[trace(acyclic_if = \"...\")]",
+    ))
+}
+
+/// Extract the string from `name = "..."`.
+fn name_str(meta: &syn::NestedMeta) -> syn::Result<syn::LitStr> {
+    if let syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+        lit: syn::Lit::Str(s),
+        ..
+    })) = meta
+    {
+        return Ok(s.clone());
+    }
+    Err(syn::Error::new(
+        meta.span(),
+        "expected #[trace(name = \"...\")]",
+    ))
+}
+
+/// Extract `T`, `U` from `ignore_params(T, U)`.
+fn param_list(meta: &syn::NestedMeta) -> syn::Result<Vec<syn::Ident>> {
+    if let syn::NestedMeta::Meta(syn::Meta::List(list)) = meta {
+        let idents: Option<Vec<syn::Ident>> = list
+            .nested
+            .iter()
+            .map(|nested| match nested {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => path.get_ident().cloned(),
+                _ => None,
+            })
+            .collect();
+        if let Some(idents) = idents {
+            return Ok(idents);
+        }
+    }
+    Err(syn::Error::new(
+        meta.span(),
+        "expected #[trace(ignore_params(T, ...))]",
+    ))
+}
+
+/// Whether `tokens` refer to `ident`, or `Self`.
+fn mentions_ident(tokens: proc_macro2::TokenStream, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(i) => &i == ident || i == "Self",
+        TokenTree::Group(g) => mentions_ident(g.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Extract `a`, `b = "c"` from `#[trace(a, b = "c")]`.
+fn trace_metas(attrs: &[syn::Attribute]) -> syn::Result<Vec<syn::NestedMeta>> {
+    let mut result = Vec::new();
+    for attr in attrs {
+        if !attr.path.is_ident("trace") {
+            continue;
+        }
+        match attr.parse_meta()? {
+            syn::Meta::List(list) => result.extend(list.nested),
+            meta => return Err(syn::Error::new(meta.span(), "expected #[trace(...)]")),
+        }
+    }
+    Ok(result)
+}
+
+fn meta_name(meta: &syn::NestedMeta) -> Option<String> {
+    match meta {
+        syn::NestedMeta::Meta(meta) => meta.path().get_ident().map(|i| i.to_string()),
+        syn::NestedMeta::Lit(_) => None,
+    }
+}
+
+/// Extract `path` from `with(path)`.
+fn with_path(meta: &syn::NestedMeta) -> syn::Result<syn::Path> {
+    if let syn::NestedMeta::Meta(syn::Meta::List(list)) = meta {
+        if let Some(syn::NestedMeta::Meta(syn::Meta::Path(path))) = list.nested.first() {
+            if list.nested.len() == 1 {
+                return Ok(path.clone());
+            }
+        }
+    }
+    Err(syn::Error::new(
+        meta.span(),
+        "expected #[trace(with(path))]",
+    ))
+}
+
+fn unknown_attr(meta: &syn::NestedMeta) -> syn::Error {
+    syn::Error::new(meta.span(), "unknown trace attribute")
 }
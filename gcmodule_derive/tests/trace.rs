@@ -9,7 +9,7 @@ fn test_named_struct() {
     struct S0 {
         a: u8,
         b: String,
-        c: &'static str,
+        _c: &'static str,
     }
     assert!(!S0::is_type_tracked());
 
@@ -21,6 +21,37 @@ fn test_named_struct() {
     assert!(S1::is_type_tracked());
 }
 
+#[test]
+fn test_std_types() {
+    use std::borrow::Cow;
+    use std::collections::{BTreeSet, BinaryHeap, HashSet};
+    use std::marker::PhantomData;
+    use std::num::NonZeroUsize;
+    use std::ops::Range;
+    use std::time::{Duration, Instant};
+
+    #[derive(DeriveTrace)]
+    struct S0 {
+        a: HashSet<u8>,
+        b: BTreeSet<u8>,
+        c: BinaryHeap<u8>,
+        d: Box<[u8]>,
+        e: [u8; 4],
+        f: Cow<'static, str>,
+        g: PhantomData<dyn Trace>,
+        h: Range<usize>,
+        i: (Duration, Instant),
+        j: NonZeroUsize,
+    }
+    assert!(!S0::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    struct S1 {
+        a: [Box<dyn Trace>; 2],
+    }
+    assert!(S1::is_type_tracked());
+}
+
 #[test]
 fn test_type_parameters() {
     #[derive(DeriveTrace)]
@@ -100,3 +131,526 @@ fn test_real_cycles() {
     }
     assert_eq!(gcmodule::collect_thread_cycles(), 3);
 }
+
+#[test]
+fn test_children_iter() {
+    #[derive(DeriveTrace)]
+    #[trace(children_iter)]
+    struct S0 {
+        a: Cc<u8>,
+        #[trace(skip)]
+        _b: Cc<u8>,
+        c: Box<dyn Trace>,
+    }
+    let s = S0 {
+        a: Cc::new(1),
+        _b: Cc::new(2),
+        c: Box::new(3u8),
+    };
+    // `Cc<u8>` is not tracked.
+    assert_eq!(s.cc_children().count(), 1);
+
+    #[derive(DeriveTrace)]
+    #[trace(children_iter)]
+    struct S1;
+    assert_eq!(S1.cc_children().count(), 0);
+
+    #[derive(DeriveTrace)]
+    #[trace(children_iter)]
+    enum E {
+        A(Box<dyn Trace>, u8, Box<dyn Trace>),
+        B { b: Box<dyn Trace> },
+        C,
+    }
+    assert_eq!(
+        E::A(Box::new(1u8), 2, Box::new(3u8)).cc_children().count(),
+        2
+    );
+    assert_eq!(E::B { b: Box::new(1u8) }.cc_children().count(), 1);
+    assert_eq!(E::C.cc_children().count(), 0);
+}
+
+#[test]
+fn test_self_ref() {
+    use gcmodule::{SelfRef, ThreadedObjectSpace};
+
+    #[derive(DeriveTrace)]
+    struct Inner {
+        outer: SelfRef<Outer>,
+        other: SelfRef<u8>,
+    }
+
+    #[derive(DeriveTrace)]
+    struct Outer {
+        this: SelfRef<Outer>,
+        inner: Inner,
+        children: RefCell<Vec<Box<dyn Trace>>>,
+    }
+
+    let new_outer = || Outer {
+        this: SelfRef::new(),
+        inner: Inner {
+            outer: SelfRef::new(),
+            other: SelfRef::new(),
+        },
+        children: Default::default(),
+    };
+    let a = Cc::new(new_outer());
+    assert!(Cc::ptr_eq(&a, &a.this.get().unwrap()));
+    assert!(Cc::ptr_eq(&a, &a.inner.outer.get().unwrap()));
+    assert!(a.inner.other.weak().is_none());
+    assert_eq!(a.strong_count(), 1);
+
+    // Strong references created via SelfRef can form cycles.
+    a.children
+        .borrow_mut()
+        .push(Box::new(a.this.get().unwrap()));
+    drop(a);
+    assert_eq!(gcmodule::collect_thread_cycles(), 1);
+
+    let b: Cc<Outer> = Cc::new_cyclic(|_| new_outer());
+    assert!(Cc::ptr_eq(&b, &b.this.get().unwrap()));
+
+    #[derive(DeriveTrace)]
+    struct Threaded {
+        this: SelfRef<Threaded, ThreadedObjectSpace>,
+    }
+    let space = ThreadedObjectSpace::default();
+    let c = space.create(Threaded {
+        this: SelfRef::new(),
+    });
+    let this = c.borrow().this.get().unwrap();
+    assert!(gcmodule::ThreadedCc::ptr_eq(&c, &this));
+}
+
+#[test]
+fn test_generic_over_space() {
+    use gcmodule::{AbstractObjectSpace, ObjectSpace, RawCc, ThreadedObjectSpace};
+    use std::sync::Mutex;
+
+    // Written once for both `Cc` and `ThreadedCc`.
+    #[derive(DeriveTrace)]
+    struct Node<O: AbstractObjectSpace> {
+        next: Mutex<Option<RawCc<Node<O>, O>>>,
+        value: RawCc<u32, O>,
+    }
+    assert!(Node::<ObjectSpace>::is_type_tracked());
+    assert!(Node::<ThreadedObjectSpace>::is_type_tracked());
+
+    let space = ObjectSpace::default();
+    let a = space.create(Node {
+        next: Mutex::new(None),
+        value: space.create(1),
+    });
+    *a.next.lock().unwrap() = Some(a.clone());
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+
+    let space = ThreadedObjectSpace::default();
+    let a = space.create(Node {
+        next: Mutex::new(None),
+        value: space.create(1),
+    });
+    *a.borrow().next.lock().unwrap() = Some(a.clone());
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[test]
+fn test_edge_names() {
+    use gcmodule::analysis::find_cycle_through;
+
+    #[derive(DeriveTrace)]
+    struct Node {
+        id: u32,
+        next: RefCell<Option<Cc<Node>>>,
+    }
+
+    #[derive(DeriveTrace)]
+    struct Tuple(u32, RefCell<Option<Cc<Tuple>>>);
+
+    let node = Cc::new(Node {
+        id: 1,
+        next: RefCell::new(None),
+    });
+    *node.next.borrow_mut() = Some(node.clone());
+    let cycle = find_cycle_through(&node).unwrap();
+    assert_eq!(cycle[0].name, Some("next"));
+    assert_eq!(node.id, 1);
+    node.next.borrow_mut().take();
+
+    let tuple = Cc::new(Tuple(2, RefCell::new(None)));
+    *tuple.1.borrow_mut() = Some(tuple.clone());
+    let cycle = find_cycle_through(&tuple).unwrap();
+    assert_eq!(cycle[0].name, Some("1"));
+    assert_eq!(tuple.0, 2);
+    tuple.1.borrow_mut().take();
+}
+
+#[test]
+fn test_union_with() {
+    use gcmodule::Tracer;
+    use std::mem::ManuallyDrop;
+
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+
+    #[derive(DeriveTrace)]
+    #[trace(with(trace_value))]
+    union Value {
+        int: usize,
+        list: ManuallyDrop<List>,
+    }
+
+    // `Value` is a list if `is_list` is set.
+    struct Tagged {
+        is_list: bool,
+        value: Value,
+    }
+    impl Trace for Tagged {
+        fn trace(&self, tracer: &mut Tracer) {
+            if self.is_list {
+                self.value.trace(tracer);
+            }
+        }
+    }
+    impl Drop for Tagged {
+        fn drop(&mut self) {
+            if self.is_list {
+                unsafe { ManuallyDrop::drop(&mut self.value.list) };
+            }
+        }
+    }
+
+    fn trace_value(value: &Value, tracer: &mut Tracer) {
+        unsafe { value.list.trace(tracer) };
+    }
+
+    assert!(Value::is_type_tracked());
+    let list: List = Default::default();
+    let tagged = Cc::new(Tagged {
+        is_list: true,
+        value: Value {
+            list: ManuallyDrop::new(list.clone()),
+        },
+    });
+    list.borrow_mut().push(Box::new(tagged));
+    let _int = Cc::new(Tagged {
+        is_list: false,
+        value: Value { int: 1 },
+    });
+    drop(list);
+    assert_eq!(gcmodule::collect_thread_cycles(), 2);
+}
+
+#[test]
+fn test_inferred_bounds() {
+    use std::marker::PhantomData;
+
+    struct NotTrace;
+
+    #[derive(DeriveTrace)]
+    struct S<T, U, M> {
+        a: Option<T>,
+        #[trace(skip)]
+        _b: U,
+        _c: PhantomData<fn() -> M>,
+    }
+    assert!(!S::<u32, NotTrace, NotTrace>::is_type_tracked());
+    assert!(S::<Box<dyn Trace>, NotTrace, NotTrace>::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    #[trace(skip)]
+    struct K<T>(T);
+    assert!(!K::<NotTrace>::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    #[trace(bound = "Box<T>: Trace")]
+    struct B<T> {
+        a: Box<T>,
+    }
+    assert!(!B::<u32>::is_type_tracked());
+
+    let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    let s = Cc::new(S {
+        a: Some(a.clone()),
+        _b: NotTrace,
+        _c: PhantomData::<fn() -> NotTrace>,
+    });
+    a.borrow_mut().push(Box::new(s));
+    drop(a);
+    assert_eq!(gcmodule::collect_thread_cycles(), 2);
+}
+
+#[test]
+fn test_lifetimes() {
+    struct NotTrace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+
+    #[derive(DeriveTrace)]
+    struct S<'a, 'b: 'a, T>
+    where
+        T: 'a,
+    {
+        name: &'a str,
+        _other: &'b NotTrace,
+        value: Option<T>,
+        list: Vec<(&'a u8, List)>,
+    }
+
+    static NOT_TRACE: NotTrace = NotTrace;
+    assert!(S::<'static, 'static, u32>::is_type_tracked());
+    let a: List = Default::default();
+    let s = Cc::new(S {
+        name: "s",
+        _other: &NOT_TRACE,
+        value: Some(a.clone()),
+        list: Vec::new(),
+    });
+    assert_eq!(s.name, "s");
+    a.borrow_mut().push(Box::new(s));
+    drop(a);
+    assert_eq!(gcmodule::collect_thread_cycles(), 2);
+}
+
+#[test]
+fn test_acyclic_if() {
+    #[derive(DeriveTrace)]
+    #[trace(acyclic_if = "!T::is_type_tracked()")]
+    struct S<T: Trace> {
+        a: Option<Box<dyn Trace>>,
+        b: Option<T>,
+    }
+    assert!(!S::<u32>::is_type_tracked());
+    assert!(S::<Box<dyn Trace>>::is_type_tracked());
+
+    // Fields still decide if the expression is false.
+    #[derive(DeriveTrace)]
+    #[trace(acyclic_if = "false")]
+    struct U(u32);
+    assert!(!U::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    #[trace(acyclic_if = "false")]
+    struct V(Option<Box<dyn Trace>>);
+    assert!(V::is_type_tracked());
+}
+
+#[test]
+fn test_enum() {
+    use gcmodule::analysis::find_cycle_through;
+    use gcmodule::Tracer;
+
+    #[derive(DeriveTrace)]
+    #[repr(u8)]
+    enum E0 {
+        _A = 1,
+        _B(u32) = 3,
+        _C { _x: String } = 5,
+    }
+    assert!(!E0::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    enum E1<T> {
+        _A,
+        _B(Option<T>),
+        _C { _x: u8, _y: Vec<T> },
+    }
+    assert!(!E1::<u32>::is_type_tracked());
+    assert!(E1::<Box<dyn Trace>>::is_type_tracked());
+
+    #[derive(DeriveTrace)]
+    enum E2 {
+        _A(u8),
+        #[trace(skip)]
+        _B(Box<dyn Trace>),
+    }
+    assert!(!E2::is_type_tracked());
+
+    // Cycles through tuple and struct variants.
+    #[derive(DeriveTrace)]
+    enum Node {
+        Leaf,
+        Tuple(u8, RefCell<Option<Cc<Node>>>),
+        Named { next: RefCell<Option<Cc<Node>>> },
+    }
+    let leaf = Cc::new(Node::Leaf);
+    let a = Cc::new(Node::Tuple(1, RefCell::new(Some(leaf))));
+    let b = Cc::new(Node::Named {
+        next: RefCell::new(Some(a.clone())),
+    });
+    if let Node::Tuple(_, next) = &*a {
+        *next.borrow_mut() = Some(b.clone());
+    }
+    let cycle = find_cycle_through(&a).unwrap();
+    let names: Vec<_> = cycle.iter().map(|edge| edge.name).collect();
+    assert_eq!(names, [Some("Tuple.1"), Some("Named.next")]);
+    drop((a, b));
+    assert_eq!(gcmodule::collect_thread_cycles(), 2);
+
+    // `with` on a variant.
+    #[derive(DeriveTrace)]
+    enum E3 {
+        _A,
+        #[trace(with(trace_b))]
+        B(Rc<RefCell<Option<Cc<E3>>>>),
+    }
+    fn trace_b(value: &E3, tracer: &mut Tracer) {
+        if let E3::B(next) = value {
+            next.borrow().trace(tracer);
+        }
+    }
+    assert!(E3::is_type_tracked());
+    let next = Rc::new(RefCell::new(None));
+    let b = Cc::new(E3::B(next.clone()));
+    *next.borrow_mut() = Some(b.clone());
+    drop((b, next));
+    assert_eq!(gcmodule::collect_thread_cycles(), 1);
+}
+
+#[test]
+fn test_ignore_params() {
+    use gcmodule::Tracer;
+
+    // A marker that is `Trace` only if `T` is.
+    struct Tag<T>(std::marker::PhantomData<T>);
+    impl<T: Trace> Trace for Tag<T> {
+        fn trace(&self, _tracer: &mut Tracer) {
+            unreachable!("ignored fields are not traced");
+        }
+        fn is_type_tracked() -> bool {
+            true
+        }
+    }
+
+    #[derive(DeriveTrace)]
+    #[trace(ignore_params(K))]
+    struct S<K, V> {
+        _tag: Tag<K>,
+        value: V,
+    }
+
+    #[derive(DeriveTrace)]
+    #[trace(ignore_params(K))]
+    enum E<K> {
+        _A(Tag<K>),
+        B(Cc<Box<dyn Trace>>),
+    }
+
+    struct NotTrace;
+    assert!(!S::<NotTrace, u8>::is_type_tracked());
+    assert!(S::<NotTrace, Box<dyn Trace>>::is_type_tracked());
+    assert!(E::<NotTrace>::is_type_tracked());
+
+    let s: Cc<S<NotTrace, u8>> = Cc::new(S {
+        _tag: Tag(Default::default()),
+        value: 1,
+    });
+    assert_eq!(s.value, 1);
+    let value: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+    let _e = Cc::new(E::<NotTrace>::B(value));
+    assert_eq!(gcmodule::collect_thread_cycles(), 0);
+}
+
+#[test]
+fn test_deep_clone() {
+    use gcmodule::DeepClone;
+    use gcmodule_derive::DeepClone as DeriveDeepClone;
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct NotTrace(u8);
+
+    #[derive(DeriveDeepClone, DeriveTrace, Default)]
+    struct Node {
+        value: u32,
+        children: Vec<Cc<RefCell<Node>>>,
+    }
+
+    #[derive(DeriveDeepClone, DeriveTrace)]
+    struct Pair<T>(T, Cc<T>, #[trace(skip)] NotTrace);
+
+    #[derive(DeriveDeepClone, DeriveTrace)]
+    struct Unit;
+
+    #[derive(DeriveDeepClone, DeriveTrace)]
+    enum E {
+        A,
+        B(Cc<u8>),
+        C {
+            x: Cc<u8>,
+            #[trace(skip)]
+            y: Rc<u8>,
+        },
+    }
+
+    let leaf = Cc::new(RefCell::new(Node::default()));
+    let root = Cc::new(RefCell::new(Node {
+        value: 1,
+        children: vec![leaf.clone(), leaf.clone()],
+    }));
+    leaf.borrow_mut().children.push(root.clone());
+    let copy = gcmodule::deep_clone(&root);
+    {
+        let copy = copy.borrow();
+        assert_eq!(copy.value, 1);
+        assert!(!Cc::ptr_eq(&copy.children[0], &leaf));
+        assert!(Cc::ptr_eq(&copy.children[0], &copy.children[1]));
+    }
+    let copied_root = copy.borrow().children[0].borrow().children[0].clone();
+    assert!(Cc::ptr_eq(&copied_root, &copy));
+    drop((leaf, root, copy, copied_root));
+    assert_eq!(gcmodule::collect_thread_cycles(), 4);
+
+    let pair = Pair(Cc::new(1u8), Cc::new(Cc::new(2u8)), NotTrace(3));
+    let copy = gcmodule::deep_clone(&pair);
+    assert!(!Cc::ptr_eq(&pair.0, &copy.0));
+    assert!(!Cc::ptr_eq(&pair.1, &copy.1));
+    assert_eq!((*copy.0, **copy.1), (1, 2));
+    assert_eq!(copy.2, NotTrace(3));
+
+    let Unit = Unit.deep_clone(&mut Default::default());
+
+    let x = Cc::new(1);
+    let y = Rc::new(2);
+    let values = vec![
+        E::A,
+        E::B(x.clone()),
+        E::C {
+            x: x.clone(),
+            y: y.clone(),
+        },
+    ];
+    let copy = gcmodule::deep_clone(&values);
+    match &copy[..] {
+        [E::A, E::B(b), E::C { x: c, y: d }] => {
+            assert!(Cc::ptr_eq(b, c));
+            assert!(!Cc::ptr_eq(b, &x));
+            assert!(Rc::ptr_eq(d, &y));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_type_name() {
+    #[derive(DeriveTrace)]
+    #[trace(name = "Named")]
+    struct Named(RefCell<Vec<Box<dyn Trace>>>);
+
+    #[derive(DeriveTrace)]
+    struct Unnamed(RefCell<Vec<Box<dyn Trace>>>);
+
+    let space = gcmodule::ObjectSpace::default();
+    let a = space.create(Named(Default::default()));
+    let b = space.create(Unnamed(Default::default()));
+    let c: Cc<dyn Trace> = space.create(Named(Default::default())).into_dyn();
+    assert_eq!((*a).type_name(), "Named");
+    assert_eq!(
+        b.0.type_name(),
+        std::any::type_name::<RefCell<Vec<Box<dyn Trace>>>>()
+    );
+
+    let counts = space.count_tracked_by_type();
+    assert_eq!(counts["Named"], 2);
+    assert_eq!(counts[std::any::type_name::<Unnamed>()], 1);
+    drop((a, b, c));
+}
@@ -0,0 +1,137 @@
+//! Callbacks on creation and drop of objects, for external registries.
+//!
+//! With the `alloc_hook` feature disabled, the wrappers are no-ops.
+
+use alloc::string::String;
+
+#[cfg(feature = "alloc_hook")]
+pub use imp::{clear_alloc_hook, set_alloc_hook, AllocEvent, AllocKind};
+
+/// Report a new object. `info` returns the type name, debug name and id.
+#[inline]
+pub(crate) fn on_new(info: impl FnOnce() -> (&'static str, String, usize)) {
+    #[cfg(feature = "alloc_hook")]
+    imp::call(imp::AllocKind::New, info);
+    #[cfg(not(feature = "alloc_hook"))]
+    let _ = info;
+}
+
+/// Report a dropped `T`. `info` returns the type name, debug name and id.
+#[inline]
+pub(crate) fn on_drop(info: impl FnOnce() -> (&'static str, String, usize)) {
+    #[cfg(feature = "alloc_hook")]
+    imp::call(imp::AllocKind::Drop, info);
+    #[cfg(not(feature = "alloc_hook"))]
+    let _ = info;
+}
+
+#[cfg(feature = "alloc_hook")]
+mod imp {
+    use std::cell::Cell;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering::{Acquire, Release};
+    use std::sync::Arc;
+    use std::sync::RwLock;
+
+    /// What happened to an object reported by
+    /// [`AllocEvent`](struct.AllocEvent.html).
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum AllocKind {
+        /// A [`Cc<T>`](type.Cc.html) or
+        /// [`ThreadedCc<T>`](type.ThreadedCc.html) was created.
+        New,
+
+        /// The `T` was dropped, either because the last strong reference
+        /// was dropped, or by the collector. The memory might still be
+        /// kept by weak references.
+        Drop,
+    }
+
+    /// An event passed to the hook set by
+    /// [`set_alloc_hook`](fn.set_alloc_hook.html).
+    #[derive(Clone, Copy, Debug)]
+    pub struct AllocEvent<'a> {
+        /// What happened.
+        pub kind: AllocKind,
+
+        /// Name of `T`.
+        pub type_name: &'static str,
+
+        /// Name used by the debug log, including the address.
+        pub name: &'a str,
+
+        /// Same as [`RawCc::id`](struct.RawCc.html#method.id). Ids can be
+        /// reused after an object is released.
+        pub id: usize,
+    }
+
+    type Hook = Arc<dyn Fn(&AllocEvent) + Send + Sync>;
+
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+    static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+    thread_local!(static IN_HOOK: Cell<bool> = const { Cell::new(false) });
+
+    /// Call `hook` when an object is created, or its value is dropped, in
+    /// any thread. Replace the hook set previously.
+    ///
+    /// This allows tools to maintain a registry of live objects, ex. to
+    /// correlate them with application-level object tables, or find leaks
+    /// and double drops. Objects created or dropped by `hook` itself are
+    /// not reported. `hook` might be called during collections, and should
+    /// not access other `Cc<T>`s.
+    ///
+    /// Requires the `alloc_hook` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{AllocKind, Cc};
+    /// use std::sync::atomic::{AtomicIsize, Ordering};
+    ///
+    /// static LIVE: AtomicIsize = AtomicIsize::new(0);
+    /// gcmodule::set_alloc_hook(|event| {
+    ///     let delta = if event.kind == AllocKind::New { 1 } else { -1 };
+    ///     LIVE.fetch_add(delta, Ordering::SeqCst);
+    /// });
+    /// let a = Cc::new(1);
+    /// assert_eq!(LIVE.load(Ordering::SeqCst), 1);
+    /// drop(a);
+    /// assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+    /// gcmodule::clear_alloc_hook();
+    /// ```
+    pub fn set_alloc_hook(hook: impl Fn(&AllocEvent) + Send + Sync + 'static) {
+        *HOOK.write().unwrap() = Some(Arc::new(hook));
+        INSTALLED.store(true, Release);
+    }
+
+    /// Remove the hook set by [`set_alloc_hook`](fn.set_alloc_hook.html).
+    pub fn clear_alloc_hook() {
+        INSTALLED.store(false, Release);
+        *HOOK.write().unwrap() = None;
+    }
+
+    pub(crate) fn call(kind: AllocKind, info: impl FnOnce() -> (&'static str, String, usize)) {
+        if !INSTALLED.load(Acquire) || IN_HOOK.with(|h| h.replace(true)) {
+            return;
+        }
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                IN_HOOK.with(|h| h.set(false));
+            }
+        }
+        let _reset = Reset;
+        // Clone the hook so it can call `set_alloc_hook`.
+        let hook = HOOK.read().unwrap().clone();
+        if let Some(hook) = hook {
+            let (type_name, name, id) = info();
+            hook(&AllocEvent {
+                kind,
+                type_name,
+                name: &name,
+                id,
+            });
+        }
+    }
+}
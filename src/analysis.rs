@@ -0,0 +1,470 @@
+//! Tools to understand why objects are kept alive.
+
+use crate::cc::CcDyn;
+use crate::collect::visit_list;
+use crate::collect::GcHeader;
+use crate::collect::Linked;
+use crate::Cc;
+use crate::ObjectSpace;
+use crate::Visitor;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io;
+
+/// An edge from one tracked object to another. Returned by
+/// [`find_cycle_through`](fn.find_cycle_through.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdgeInfo {
+    /// Type name of the object the edge starts from.
+    pub from_type: &'static str,
+
+    /// Type name of the object the edge points to.
+    pub to_type: &'static str,
+
+    /// Position of the edge among edges visited by `Trace::trace` of the
+    /// source object, starting from 0.
+    ///
+    /// `Tracer` does not report field names. With `derive(Trace)`, the
+    /// index follows the field order, skipping untracked fields.
+    pub index: usize,
+
+    /// Name of the edge, usually a field name, passed to
+    /// [`Tracer::trace_named`](../trait.Visitor.html#method.trace_named).
+    /// `derive(Trace)` names edges after fields.
+    pub name: Option<&'static str>,
+}
+
+/// Find a shortest cycle containing `cc`.
+///
+/// Return edges of the cycle, starting from `cc` and ending at `cc`, or
+/// `None` if `cc` is not part of a cycle. Only edges reported by
+/// `Trace::trace` are considered. Objects whose `Trace` implementation
+/// skips edges (ex. a borrowed `RefCell`) might hide cycles.
+///
+/// This walks objects reachable from `cc`. The time complexity is
+/// `O(reachable objects + edges)`.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::analysis::find_cycle_through;
+/// use gcmodule::{Cc, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let a: List = Default::default();
+/// let b: List = Default::default();
+/// a.borrow_mut().push(Box::new(b.clone()));
+/// assert!(find_cycle_through(&a).is_none());
+///
+/// b.borrow_mut().push(Box::new(a.clone()));
+/// let cycle = find_cycle_through(&a).unwrap();
+/// assert_eq!(cycle.len(), 2);
+/// # a.borrow_mut().clear();
+/// ```
+pub fn find_cycle_through<T: ?Sized>(cc: &Cc<T>) -> Option<Vec<EdgeInfo>> {
+    let mut start: *const () = std::ptr::null();
+    cc.trace(&mut |header| start = header);
+    if start.is_null() {
+        // Untracked objects cannot form cycles.
+        return None;
+    }
+
+    // Object to (parent object, edge index) on a shortest path from start.
+    let mut parents: HashMap<*const (), (*const (), usize)> = HashMap::new();
+    let mut queue: VecDeque<*const ()> = VecDeque::new();
+    queue.push_back(start);
+    while let Some(current) = queue.pop_front() {
+        let mut closing_index = None;
+        let mut index = 0;
+        value_of(current).gc_traverse(&mut |next| {
+            if next == start {
+                closing_index.get_or_insert(index);
+            } else if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert((current, index));
+                queue.push_back(next);
+            }
+            index += 1;
+        });
+        if let Some(index) = closing_index {
+            let mut edges = vec![edge(current, start, index)];
+            let mut to = current;
+            while to != start {
+                let (from, index) = parents[&to];
+                edges.push(edge(from, to, index));
+                to = from;
+            }
+            edges.reverse();
+            return Some(edges);
+        }
+    }
+    None
+}
+
+/// Count tracked objects reachable from `from`, including `from` itself.
+///
+/// Only edges reported by `Trace::trace` are followed. Untracked objects,
+/// like `Cc<u32>`, are not counted. This is useful in tests to check that
+/// a subgraph got disconnected.
+///
+/// The time complexity is `O(reachable objects + edges)`.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let a: List = Default::default();
+/// let b: List = Default::default();
+/// a.borrow_mut().push(Box::new(b.clone()));
+/// b.borrow_mut().push(Box::new(a.clone()));
+/// b.borrow_mut().push(Box::new(Cc::new(1)));
+/// assert_eq!(gcmodule::reachable_count(&a), 2);
+/// # b.borrow_mut().clear();
+/// ```
+pub fn reachable_count<T: ?Sized>(from: &Cc<T>) -> usize {
+    let mut count = 0;
+    walk(from, |_| {
+        count += 1;
+        true
+    });
+    count
+}
+
+/// Whether `to` is reachable from `from` via edges reported by
+/// `Trace::trace`. An object is reachable from itself.
+///
+/// Untracked objects, like `Cc<u32>`, are not reported by `Trace::trace`.
+/// They are only reachable from themselves.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let a: List = Default::default();
+/// let b: List = Default::default();
+/// a.borrow_mut().push(Box::new(b.clone()));
+/// assert!(gcmodule::is_reachable(&a, &b));
+/// assert!(!gcmodule::is_reachable(&b, &a));
+///
+/// a.borrow_mut().clear();
+/// assert!(!gcmodule::is_reachable(&a, &b));
+/// ```
+pub fn is_reachable<T: ?Sized, U: ?Sized>(from: &Cc<T>, to: &Cc<U>) -> bool {
+    if from.id() == to.id() {
+        return true;
+    }
+    let mut target: *const () = std::ptr::null();
+    to.trace(&mut |header| target = header);
+    if target.is_null() {
+        return false;
+    }
+    let mut found = false;
+    walk(from, |header| {
+        found = header == target;
+        !found
+    });
+    found
+}
+
+/// Visit tracked objects reachable from `cc`, in depth-first order, until
+/// `f` returns `false`.
+fn walk<T: ?Sized>(cc: &Cc<T>, mut f: impl FnMut(*const ()) -> bool) {
+    let mut start: *const () = std::ptr::null();
+    cc.trace(&mut |header| start = header);
+    if start.is_null() {
+        return;
+    }
+    let mut visited: HashSet<*const ()> = HashSet::new();
+    let mut stack = vec![start];
+    visited.insert(start);
+    while let Some(current) = stack.pop() {
+        if !f(current) {
+            return;
+        }
+        value_of(current).gc_traverse(&mut |next| {
+            if visited.insert(next) {
+                stack.push(next);
+            }
+        });
+    }
+}
+
+fn value_of<'a>(header: *const ()) -> &'a dyn CcDyn {
+    // safety: Tracers receive pointers to `GcHeader`s of live objects. The
+    // objects are kept alive by `cc` during the walk.
+    let header: &GcHeader = unsafe { &*(header as *const GcHeader) };
+    header.value()
+}
+
+fn edge(from: *const (), to: *const (), index: usize) -> EdgeInfo {
+    let from = value_of(from);
+    EdgeInfo {
+        from_type: from.gc_type_name(),
+        to_type: value_of(to).gc_type_name(),
+        index,
+        name: edges_of(from)[index].1,
+    }
+}
+
+/// Edges visited by `Trace::trace`, with names.
+#[derive(Default)]
+struct Edges(Vec<(*const (), Option<&'static str>)>);
+
+impl Visitor for Edges {
+    fn visit(&mut self, header: *const ()) {
+        self.0.push((header, None));
+    }
+
+    fn visit_named(&mut self, name: &'static str, header: *const ()) {
+        self.0.push((header, Some(name)));
+    }
+}
+
+fn edges_of(value: &dyn CcDyn) -> Vec<(*const (), Option<&'static str>)> {
+    let mut edges = Edges::default();
+    value.gc_traverse(&mut edges);
+    edges.0
+}
+
+/// Tracked objects and edges between them. Returned by
+/// [`snapshot`](fn.snapshot.html).
+///
+/// The tables can be exported as CSV for offline analysis via
+/// [`write_csv`](struct.HeapSnapshot.html#method.write_csv).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    /// Tracked objects.
+    pub objects: Vec<ObjectRow>,
+
+    /// Edges between tracked objects.
+    pub edges: Vec<EdgeRow>,
+}
+
+/// A tracked object in a [`HeapSnapshot`](struct.HeapSnapshot.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectRow {
+    /// Identity of the object.
+    ///
+    /// With the `seqid` feature, this is the
+    /// [sequence id](../type.Cc.html#method.sequence_id) of the object,
+    /// which is stable across snapshots. Otherwise, it is the position of
+    /// the object in this snapshot.
+    pub id: u64,
+
+    /// Type name of the object.
+    pub type_name: &'static str,
+
+    /// Size of the allocation, including the `GcHeader`.
+    pub size: usize,
+
+    /// Strong reference count. 0 means the value was dropped, and the
+    /// object is kept alive by weak references.
+    pub ref_count: usize,
+}
+
+/// An edge in a [`HeapSnapshot`](struct.HeapSnapshot.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EdgeRow {
+    /// `id` of the source object.
+    pub from: u64,
+
+    /// `id` of the target object.
+    pub to: u64,
+
+    /// Position of the edge among edges of the source object. See
+    /// [`EdgeInfo::index`](struct.EdgeInfo.html#structfield.index).
+    pub index: usize,
+
+    /// Name of the edge. See
+    /// [`EdgeInfo::name`](struct.EdgeInfo.html#structfield.name).
+    pub name: Option<&'static str>,
+}
+
+/// Take a snapshot of objects tracked by `space`.
+///
+/// Edges to objects in other spaces are not included. This walks all
+/// tracked objects and edges.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::analysis::snapshot;
+/// use gcmodule::{ObjectSpace, Trace};
+/// use std::cell::RefCell;
+///
+/// let space = ObjectSpace::default();
+/// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+/// a.borrow_mut().push(Box::new(a.clone()));
+///
+/// let snapshot = snapshot(&space);
+/// let mut objects = Vec::new();
+/// let mut edges = Vec::new();
+/// snapshot.write_csv(&mut objects, &mut edges).unwrap();
+/// let id = snapshot.objects[0].id;
+/// let expected = format!("from,to,index,name\n{},{},0,\n", id, id);
+/// assert_eq!(String::from_utf8(edges).unwrap(), expected);
+/// # a.borrow_mut().clear();
+/// ```
+pub fn snapshot(space: &ObjectSpace) -> HeapSnapshot {
+    let list: &GcHeader = &space.list.borrow();
+    let mut result = HeapSnapshot::default();
+    let mut ids: HashMap<*const (), u64> = HashMap::new();
+    visit_list(list, |header| {
+        let value = header.value();
+        #[cfg(feature = "seqid")]
+        let id = header.seq;
+        #[cfg(not(feature = "seqid"))]
+        let id = result.objects.len() as u64;
+        ids.insert(header as *const GcHeader as *const (), id);
+        result.objects.push(ObjectRow {
+            id,
+            type_name: value.gc_type_name(),
+            size: value.gc_alloc_size(),
+            ref_count: value.gc_ref_count(),
+        });
+    });
+    visit_list(list, |header| {
+        let value = header.value();
+        let id = ids[&(header as *const GcHeader as *const ())];
+        // Values of objects with ref count 0 were dropped.
+        if value.gc_ref_count() > 0 {
+            for (index, (to, name)) in edges_of(value).into_iter().enumerate() {
+                if let Some(&to) = ids.get(&to) {
+                    result.edges.push(EdgeRow {
+                        from: id,
+                        to,
+                        index,
+                        name,
+                    });
+                }
+            }
+        }
+    });
+    result
+}
+
+impl HeapSnapshot {
+    /// Write the objects table (`id,type_name,size,ref_count`) to `objects`,
+    /// and the edges table (`from,to,index,name`) to `edges`, in CSV format.
+    /// Edges without names have empty names.
+    pub fn write_csv(
+        &self,
+        mut objects: impl io::Write,
+        mut edges: impl io::Write,
+    ) -> io::Result<()> {
+        writeln!(objects, "id,type_name,size,ref_count")?;
+        for row in &self.objects {
+            writeln!(
+                objects,
+                "{},{},{},{}",
+                row.id,
+                csv_quote(row.type_name),
+                row.size,
+                row.ref_count
+            )?;
+        }
+        writeln!(edges, "from,to,index,name")?;
+        for row in &self.edges {
+            let name = row.name.map(csv_quote).unwrap_or_default();
+            writeln!(edges, "{},{},{},{}", row.from, row.to, row.index, name)?;
+        }
+        Ok(())
+    }
+
+    /// Write the graph in GraphViz DOT format. Nodes are labeled by type
+    /// names. Edges are labeled by
+    /// [`EdgeRow::name`](struct.EdgeRow.html#structfield.name), or
+    /// [`EdgeRow::index`](struct.EdgeRow.html#structfield.index) if unnamed.
+    pub fn write_dot(&self, mut out: impl io::Write) -> io::Result<()> {
+        writeln!(out, "digraph {{")?;
+        for row in &self.objects {
+            writeln!(out, "  {} [label={}];", row.id, quote(row.type_name))?;
+        }
+        for row in &self.edges {
+            let label = match row.name {
+                Some(name) => quote(name),
+                None => quote(&row.index.to_string()),
+            };
+            writeln!(out, "  {} -> {} [label={}];", row.from, row.to, label)?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Write the snapshot in JSON format, as an object with `objects` and
+    /// `edges` arrays. Fields are named after
+    /// [`ObjectRow`](struct.ObjectRow.html) and
+    /// [`EdgeRow`](struct.EdgeRow.html).
+    pub fn write_json(&self, mut out: impl io::Write) -> io::Result<()> {
+        write!(out, "{{\"objects\":[")?;
+        for (i, row) in self.objects.iter().enumerate() {
+            write!(
+                out,
+                "{}{{\"id\":{},\"type_name\":{},\"size\":{},\"ref_count\":{}}}",
+                if i == 0 { "" } else { "," },
+                row.id,
+                quote(row.type_name),
+                row.size,
+                row.ref_count
+            )?;
+        }
+        write!(out, "],\"edges\":[")?;
+        for (i, row) in self.edges.iter().enumerate() {
+            write!(
+                out,
+                "{}{{\"from\":{},\"to\":{},\"index\":{},\"name\":{}}}",
+                if i == 0 { "" } else { "," },
+                row.from,
+                row.to,
+                row.index,
+                row.name.map(quote).unwrap_or_else(|| "null".to_string())
+            )?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+/// Render the reference graph of objects tracked by `space` in GraphViz
+/// DOT format. See [`HeapSnapshot::write_dot`](struct.HeapSnapshot.html#method.write_dot).
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::analysis::dump_graph;
+/// use gcmodule::{ObjectSpace, Trace};
+/// use std::cell::RefCell;
+///
+/// let space = ObjectSpace::default();
+/// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+/// a.borrow_mut().push(Box::new(a.clone()));
+///
+/// let dot = dump_graph(&space);
+/// assert!(dot.starts_with("digraph {"));
+/// assert_eq!(dot.matches(" -> ").count(), 1);
+/// # a.borrow_mut().clear();
+/// ```
+pub fn dump_graph(space: &ObjectSpace) -> String {
+    let mut out = Vec::new();
+    snapshot(space)
+        .write_dot(&mut out)
+        .expect("writing to Vec does not fail");
+    String::from_utf8(out).expect("DOT output is UTF-8")
+}
+
+/// Quote a string in DOT or JSON. Type names do not contain control
+/// characters, so only `"` and `\` need escaping.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quote a CSV field. Type names can contain `,`.
+fn csv_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
@@ -0,0 +1,150 @@
+//! Bump allocation for tracked objects. See `ObjectSpace::with_arena`.
+
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::mem;
+use std::ptr::NonNull;
+
+/// Size and alignment of a chunk. Objects find their chunks by rounding
+/// down their addresses.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Objects larger than this use the global allocator.
+const MAX_OBJECT_SIZE: usize = CHUNK_SIZE / 8;
+
+/// Metadata at the start of a chunk.
+struct ChunkHeader {
+    /// Number of objects allocated in the chunk and not released.
+    live: Cell<usize>,
+}
+
+/// Allocates objects by bumping an offset in the current chunk. A chunk is
+/// freed as a whole when all objects in it are released. Objects are not
+/// moved, so memory in a chunk is not reused until the chunk is empty.
+pub(crate) struct Arena {
+    /// Chunk used by new allocations. Null if there is none.
+    current: Cell<*mut ChunkHeader>,
+
+    /// Offset of the next allocation in `current`.
+    offset: Cell<usize>,
+}
+
+impl Arena {
+    pub(crate) fn new() -> Self {
+        Self {
+            current: Cell::new(std::ptr::null_mut()),
+            offset: Cell::new(0),
+        }
+    }
+
+    /// Allocate memory. Return `None` if `layout` does not fit in a chunk.
+    fn try_alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() > MAX_OBJECT_SIZE || layout.align() > MAX_OBJECT_SIZE {
+            return None;
+        }
+        let mut chunk = self.current.get();
+        let mut start = align_up(self.offset.get(), layout.align());
+        if chunk.is_null() || start + layout.size() > CHUNK_SIZE {
+            self.retire_current();
+            chunk = new_chunk();
+            self.current.set(chunk);
+            start = align_up(mem::size_of::<ChunkHeader>(), layout.align());
+        }
+        self.offset.set(start + layout.size());
+        // safety: `chunk` is valid. `start + size` is within the chunk.
+        unsafe {
+            let live = &(*chunk).live;
+            live.set(live.get() + 1);
+            Some(NonNull::new_unchecked((chunk as *mut u8).add(start)))
+        }
+    }
+
+    /// Release memory allocated by `try_alloc`. Return `false` if `layout`
+    /// does not fit in a chunk, in which case `ptr` was not allocated by
+    /// `try_alloc`.
+    ///
+    /// # Safety
+    ///
+    /// If `layout` fits in a chunk, `ptr` must be allocated by `try_alloc`
+    /// of this arena with the same `layout`.
+    unsafe fn try_dealloc(&self, ptr: *mut u8, layout: Layout) -> bool {
+        if layout.size() > MAX_OBJECT_SIZE || layout.align() > MAX_OBJECT_SIZE {
+            return false;
+        }
+        let chunk = ptr.wrapping_sub(ptr as usize % CHUNK_SIZE) as *mut ChunkHeader;
+        let live = &(*chunk).live;
+        debug_assert!(live.get() > 0);
+        live.set(live.get() - 1);
+        if live.get() == 0 {
+            if chunk == self.current.get() {
+                // Reuse the chunk from its start.
+                self.offset.set(mem::size_of::<ChunkHeader>());
+            } else {
+                free_chunk(chunk);
+            }
+        }
+        true
+    }
+
+    /// Stop allocating from the current chunk. Free it if it is empty.
+    /// Otherwise, it is freed by `dealloc` of its last object.
+    fn retire_current(&self) {
+        let chunk = self.current.replace(std::ptr::null_mut());
+        // safety: `chunk` is valid if not null.
+        if !chunk.is_null() && unsafe { (*chunk).live.get() } == 0 {
+            free_chunk(chunk);
+        }
+    }
+}
+
+// Objects that do not fit in a chunk use the global allocator.
+unsafe impl GlobalAlloc for Arena {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.try_alloc(layout) {
+            Some(ptr) => ptr.as_ptr(),
+            None => alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !self.try_dealloc(ptr, layout) {
+            dealloc(ptr, layout);
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // Objects keep the arena alive. There are no live objects here.
+        self.retire_current();
+    }
+}
+
+fn chunk_layout() -> Layout {
+    // CHUNK_SIZE is a power of 2.
+    Layout::from_size_align(CHUNK_SIZE, CHUNK_SIZE).unwrap()
+}
+
+fn new_chunk() -> *mut ChunkHeader {
+    let layout = chunk_layout();
+    // safety: The layout has a non-zero size.
+    let chunk = unsafe { alloc(layout) } as *mut ChunkHeader;
+    if chunk.is_null() {
+        handle_alloc_error(layout);
+    }
+    // safety: `chunk` is valid and aligned.
+    unsafe { chunk.write(ChunkHeader { live: Cell::new(0) }) };
+    chunk
+}
+
+fn free_chunk(chunk: *mut ChunkHeader) {
+    // safety: `chunk` was allocated by `new_chunk`. `ChunkHeader` does not
+    // need drop.
+    unsafe { dealloc(chunk as *mut u8, chunk_layout()) };
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) & !(align - 1)
+}
@@ -1,17 +1,34 @@
+use crate::alloc_hook;
+#[cfg(feature = "std")]
 use crate::collect;
 use crate::collect::AbstractObjectSpace;
 use crate::collect::ObjectSpace;
 use crate::debug;
 use crate::ref_count::RefCount;
+#[cfg(feature = "std")]
+use crate::ref_count::SingleThreadRefCount;
 use crate::trace::Trace;
 use crate::trace::Tracer;
+use alloc::alloc::{alloc, handle_alloc_error};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::alloc::Layout;
+use std::any::TypeId;
 use std::cell::UnsafeCell;
+#[cfg(feature = "std")]
+use std::convert::Infallible;
 use std::mem;
 use std::mem::ManuallyDrop;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::panic::UnwindSafe;
+#[cfg(feature = "std")]
+use std::pin::Pin;
 use std::ptr::NonNull;
+#[cfg(feature = "versioning")]
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
 
 // Types not tracked by the cycle collector:
 //
@@ -39,9 +56,13 @@ use std::ptr::NonNull;
 pub(crate) struct RawCcBox<T: ?Sized, O: AbstractObjectSpace> {
     pub(crate) ref_count: O::RefCount,
 
-    #[cfg(test)]
+    #[cfg(all(test, feature = "std"))]
     pub(crate) name: String,
 
+    /// Mutation version. See `RawCc::touch`.
+    #[cfg(feature = "versioning")]
+    version: AtomicU64,
+
     value: UnsafeCell<ManuallyDrop<T>>,
 }
 
@@ -77,8 +98,14 @@ pub type Cc<T> = RawCc<T, ObjectSpace>;
 pub type Weak<T> = RawWeak<T, ObjectSpace>;
 
 /// Low-level type for [`Cc<T>`](type.Cc.html).
+///
+/// It is a non-null pointer. `Option<RawCc<T, O>>` is guaranteed to have
+/// the same size as `RawCc<T, O>`.
 pub struct RawCc<T: ?Sized, O: AbstractObjectSpace>(NonNull<RawCcBox<T, O>>);
 
+const _: () = assert!(mem::size_of::<Option<Cc<()>>>() == mem::size_of::<Cc<()>>());
+const _: () = assert!(mem::size_of::<Option<Cc<dyn Trace>>>() == mem::size_of::<Cc<dyn Trace>>());
+
 /// Low-level type for [`Weak<T>`](type.Weak.html).
 pub struct RawWeak<T: ?Sized, O: AbstractObjectSpace>(NonNull<RawCcBox<T, O>>);
 
@@ -98,6 +125,10 @@ pub trait CcDyn {
     /// Returns the reference count for cycle detection.
     fn gc_ref_count(&self) -> usize;
 
+    /// Whether `T` has been dropped. The allocation is kept alive by
+    /// references.
+    fn gc_is_dropped(&self) -> bool;
+
     /// Visit referents for cycle detection.
     fn gc_traverse(&self, tracer: &mut Tracer);
 
@@ -107,6 +138,35 @@ pub trait CcDyn {
     /// - Operate on the object.
     fn gc_clone(&self) -> Box<dyn GcClone>;
 
+    /// Increase the reference count. Like `gc_clone` but does not allocate.
+    fn gc_inc_ref(&self);
+
+    /// Decrease the reference count increased by `gc_inc_ref`. This might
+    /// release the object.
+    fn gc_dec_ref(&self);
+
+    /// Increase the weak reference count.
+    fn gc_inc_weak(&self);
+
+    /// Decrease the weak reference count increased by `gc_inc_weak`. This
+    /// might release the object.
+    fn gc_dec_weak(&self);
+
+    /// Force drop the value T.
+    fn gc_drop_t(&self);
+
+    /// Call `Trace::finalize` if T is not dropped.
+    fn gc_finalize(&self);
+
+    /// Name of the type `T`.
+    fn gc_type_name(&self) -> &'static str;
+
+    /// `TypeId` of the type `T`.
+    fn gc_type_id(&self) -> TypeId;
+
+    /// Size of the allocation, including the `GcHeader`.
+    fn gc_alloc_size(&self) -> usize;
+
     #[cfg(feature = "debug")]
     /// Name used in collect.rs.
     fn gc_debug_name(&self) -> String {
@@ -121,8 +181,14 @@ pub trait GcClone {
     /// Force drop the value T.
     fn gc_drop_t(&self);
 
+    /// Call `Trace::finalize` if T is not dropped.
+    fn gc_finalize(&self);
+
     /// Returns the reference count. This is useful for verification.
     fn gc_ref_count(&self) -> usize;
+
+    /// Name of the type `T`.
+    fn gc_type_name(&self) -> &'static str;
 }
 
 /// A dummy implementation without drop side-effects.
@@ -141,18 +207,113 @@ impl CcDyn for CcDummy {
     fn gc_ref_count(&self) -> usize {
         1
     }
+    fn gc_is_dropped(&self) -> bool {
+        false
+    }
     fn gc_traverse(&self, _tracer: &mut Tracer) {}
     fn gc_clone(&self) -> Box<dyn GcClone> {
         panic!("bug: CcDummy::gc_clone should never be called");
     }
+    fn gc_inc_ref(&self) {
+        panic!("bug: CcDummy::gc_inc_ref should never be called");
+    }
+    fn gc_dec_ref(&self) {
+        panic!("bug: CcDummy::gc_dec_ref should never be called");
+    }
+    fn gc_inc_weak(&self) {
+        panic!("bug: CcDummy::gc_inc_weak should never be called");
+    }
+    fn gc_dec_weak(&self) {
+        panic!("bug: CcDummy::gc_dec_weak should never be called");
+    }
+    fn gc_drop_t(&self) {}
+    fn gc_finalize(&self) {}
+    fn gc_type_name(&self) -> &'static str {
+        "CcDummy"
+    }
+    fn gc_type_id(&self) -> TypeId {
+        TypeId::of::<Self>()
+    }
+    fn gc_alloc_size(&self) -> usize {
+        0
+    }
 }
 
+#[cfg(feature = "std")]
 impl<T: Trace> Cc<T> {
     /// Constructs a new [`Cc<T>`](type.Cc.html) in a thread-local storage.
     ///
     /// To collect cycles, use [`collect_thread_cycles`](fn.collect_thread_cycles.html).
+    /// Within [`ObjectSpace::enter`](struct.ObjectSpace.html#method.enter),
+    /// the entered space is used instead.
     pub fn new(value: T) -> Cc<T> {
-        collect::THREAD_OBJECT_SPACE.with(|space| Self::new_in_space(value, space))
+        collect::with_current_space(|space| Self::new_in_space(value, space))
+    }
+
+    /// Constructs a new [`Cc<T>`](type.Cc.html) in a thread-local storage,
+    /// while giving `data_fn` a [`Weak<T>`](type.Weak.html) to the
+    /// allocation. This is similar to `Rc::new_cyclic`.
+    ///
+    /// The [`Weak<T>`](type.Weak.html) can be stored in the value, ex. as a
+    /// parent pointer. Calling [`upgrade`](struct.RawWeak.html#method.upgrade)
+    /// on it returns `None` until `new_cyclic` returns.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{Cc, Trace, Weak};
+    ///
+    /// #[derive(Trace)]
+    /// struct Node {
+    ///     #[trace(skip)]
+    ///     this: Weak<Node>,
+    /// }
+    ///
+    /// let node = Cc::new_cyclic(|weak| {
+    ///     assert!(weak.upgrade().is_none());
+    ///     Node { this: weak.clone() }
+    /// });
+    /// assert!(Cc::ptr_eq(&node, &node.this.upgrade().unwrap()));
+    /// ```
+    pub fn new_cyclic(data_fn: impl FnOnce(&Weak<T>) -> T) -> Cc<T> {
+        collect::with_current_space(|space| Self::new_cyclic_in_space(data_fn, space, || ()))
+    }
+
+    /// Constructs a new `Pin<Cc<T>>` in a thread-local storage. If `T` does
+    /// not implement `Unpin`, then the value will be pinned in memory and
+    /// unable to be moved. This is similar to `Rc::pin`.
+    ///
+    /// The value is never moved by the collector. Unreachable values are
+    /// dropped in place before their memory is released, so the `Drop`
+    /// guarantee of pinning holds.
+    ///
+    /// Like `Rc`, [`Cc<T>`](type.Cc.html) only gives shared access to the
+    /// value, and `Pin::as_ref` projects `Pin<Cc<T>>` to `Pin<&T>`. Interior
+    /// mutability types like `RefCell<F>` are not structurally pinned. To
+    /// poll a future stored in the graph, store `Pin<Box<F>>` in the cell
+    /// instead. `Pin<Box<T>>` and `Pin<Cc<T>>` implement
+    /// [`Trace`](trait.Trace.html) like `Box<T>` and `Cc<T>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{Cc, Trace};
+    /// use std::cell::RefCell;
+    /// use std::pin::Pin;
+    ///
+    /// #[derive(Trace)]
+    /// struct Task {
+    ///     children: RefCell<Vec<Pin<Cc<Task>>>>,
+    /// }
+    ///
+    /// let task = Cc::pin(Task { children: Default::default() });
+    /// task.children.borrow_mut().push(task.clone());
+    /// drop(task);
+    /// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+    /// ```
+    pub fn pin(value: T) -> Pin<Cc<T>> {
+        // safety: The value is not moved until dropped. See above.
+        unsafe { Pin::new_unchecked(Self::new(value)) }
     }
 }
 
@@ -163,27 +324,21 @@ impl<T: Trace, O: AbstractObjectSpace> RawCc<T, O> {
     /// To collect cycles, call `ObjectSpace::collect_cycles()`.
     pub(crate) fn new_in_space(value: T, space: &O) -> Self {
         let is_tracked = T::is_type_tracked();
-        let cc_box = RawCcBox {
-            ref_count: space.new_ref_count(is_tracked),
-            value: UnsafeCell::new(ManuallyDrop::new(value)),
-            #[cfg(test)]
-            name: debug::NEXT_DEBUG_NAME.with(|n| n.get().to_string()),
-        };
+        let cc_box = RawCcBox::new(value, space.new_ref_count(is_tracked));
         let ccbox_ptr: *mut RawCcBox<T, O> = if is_tracked {
             // Create a GcHeader before the CcBox. This is similar to cpython.
             let header = space.empty_header();
-            let cc_box_with_header = RawCcBoxWithGcHeader { header, cc_box };
-            let mut boxed = Box::new(cc_box_with_header);
-            // Fix-up fields in GcHeader. This is done after the creation of the
-            // Box so the memory addresses are stable.
+            let ptr = alloc_tracked(space, RawCcBoxWithGcHeader { header, cc_box });
+            // safety: ptr is valid.
+            let boxed = unsafe { &mut *ptr };
+            // Fix-up fields in GcHeader. This is done after the allocation
+            // so the memory addresses are stable.
             space.insert(&mut boxed.header, &boxed.cc_box);
             debug_assert_eq!(
                 mem::size_of::<O::Header>() + mem::size_of::<RawCcBox<T, O>>(),
                 mem::size_of::<RawCcBoxWithGcHeader<T, O>>()
             );
-            let ptr: *mut RawCcBox<T, O> = &mut boxed.cc_box;
-            Box::leak(boxed);
-            ptr
+            &mut boxed.cc_box
         } else {
             Box::into_raw(Box::new(cc_box))
         };
@@ -195,6 +350,94 @@ impl<T: Trace, O: AbstractObjectSpace> RawCc<T, O> {
         } else {
             debug::log(|| (result.debug_name(), "new (CcBox)"));
         }
+        alloc_hook::on_new(|| result.inner().alloc_info());
+        debug_assert_eq!(result.ref_count(), 1);
+        result.init_self_ref(None);
+        result
+    }
+
+    /// Initialize `SelfRef` fields in the value, if any.
+    fn init_self_ref(&self, weak: Option<&RawWeak<T, O>>) {
+        if T::__gcmodule_has_self_ref() {
+            match weak {
+                Some(weak) => self.inner().deref().__gcmodule_init_self_ref(weak),
+                None => self
+                    .inner()
+                    .deref()
+                    .__gcmodule_init_self_ref(&self.downgrade()),
+            }
+        }
+    }
+
+    /// Constructs a new `RawCc<T>` in the given space, while giving
+    /// `data_fn` a weak reference to it. `lock_list` is called to protect
+    /// `space.insert`.
+    pub(crate) fn new_cyclic_in_space<L>(
+        data_fn: impl FnOnce(&RawWeak<T, O>) -> T,
+        space: &O,
+        lock_list: impl Fn() -> L,
+    ) -> Self {
+        let is_tracked = T::is_type_tracked();
+        // Ref count 0 with the dropped flag, so `upgrade` returns `None`,
+        // and `drop_t` skips the uninitialized value. The weak count is
+        // owned by `weak` below.
+        let ref_count = space.new_ref_count(is_tracked);
+        ref_count.set_dropped();
+        ref_count.dec_ref();
+        ref_count.inc_weak();
+        let cc_box = RawCcBox::<mem::MaybeUninit<T>, O> {
+            ref_count,
+            value: UnsafeCell::new(ManuallyDrop::new(mem::MaybeUninit::uninit())),
+            #[cfg(all(test, feature = "std"))]
+            name: debug::NEXT_DEBUG_NAME.with(|n| n.get().to_string()),
+            #[cfg(feature = "versioning")]
+            version: AtomicU64::new(0),
+        };
+        // `MaybeUninit<T>` has the same layout as `T`. The GcHeader is not
+        // linked until the value is initialized, so the collector does not
+        // see the uninitialized value.
+        let (header_ptr, ccbox_ptr): (*mut O::Header, *mut RawCcBox<T, O>) = if is_tracked {
+            let header = space.empty_header();
+            let ptr = alloc_tracked(space, RawCcBoxWithGcHeader { header, cc_box })
+                as *mut RawCcBoxWithGcHeader<T, O>;
+            // safety: ptr is valid.
+            unsafe { (&mut (*ptr).header as *mut _, &mut (*ptr).cc_box as *mut _) }
+        } else {
+            let ptr = Box::into_raw(Box::new(cc_box)) as *mut RawCcBox<T, O>;
+            (std::ptr::null_mut(), ptr)
+        };
+        // safety: ccbox_ptr cannot be null from the above code.
+        let weak = RawWeak(unsafe { NonNull::new_unchecked(ccbox_ptr) });
+        let link = || {
+            if is_tracked {
+                let _locked = lock_list();
+                // safety: header_ptr and ccbox_ptr are valid. The header
+                // is not linked yet.
+                unsafe { space.insert(&mut *header_ptr, &*ccbox_ptr) };
+            }
+        };
+
+        // If `data_fn` panics, it might have cloned `weak`. Link the object
+        // so it is released like a dropped object kept by weak references,
+        // when `weak` is dropped after the guard.
+        let guard = LinkOnPanic(&link);
+        let value = data_fn(&weak);
+        mem::forget(guard);
+
+        // safety: ccbox_ptr is valid. The value is not initialized yet.
+        let inner = unsafe { &*ccbox_ptr };
+        unsafe { std::ptr::write(inner.value.get() as *mut T, value) };
+        // Increase the ref count before clearing the dropped flag. Otherwise
+        // a weak reference upgraded and dropped by another thread would
+        // drop the value.
+        inner.inc_ref();
+        inner.ref_count.clear_dropped();
+        link();
+        let result = RawCc(weak.0);
+        result.init_self_ref(Some(&weak));
+        drop(weak);
+        debug::log(|| (result.debug_name(), "new-cyclic"));
+        alloc_hook::on_new(|| result.inner().alloc_info());
         debug_assert_eq!(result.ref_count(), 1);
         result
     }
@@ -223,21 +466,412 @@ impl<T: Trace, O: AbstractObjectSpace> RawCc<T, O> {
     }
 }
 
+impl<T: ?Sized, O: AbstractObjectSpace> RawCc<T, O> {
+    /// Return `coerce`. Used by [`cc_coerce!`](macro.cc_coerce.html) to
+    /// infer the closure type outside `unsafe`.
+    #[doc(hidden)]
+    pub fn __coerce_fn<U: ?Sized, F: FnOnce(*const T) -> *const U>(&self, coerce: F) -> F {
+        coerce
+    }
+
+    /// Convert to `RawCc<U>` by applying `coerce` to the value pointer.
+    /// Used by [`cc_coerce!`](macro.cc_coerce.html).
+    ///
+    /// # Safety
+    ///
+    /// `coerce` must only change the pointer metadata, like an unsizing
+    /// coercion from `*const T` to `*const dyn MyTrait`.
+    #[doc(hidden)]
+    pub unsafe fn __coerce_unsized<U: ?Sized>(
+        self,
+        coerce: impl FnOnce(*const T) -> *const U,
+    ) -> RawCc<U, O> {
+        let ptr: *const RawCcBox<T, O> = self.0.as_ptr();
+        // Do not read the value. It might be dropped.
+        let value = UnsafeCell::raw_get(std::ptr::addr_of!((*ptr).value)) as *const T;
+        let offset = (value as *const u8).offset_from(ptr as *const u8);
+        let coerced = coerce(value);
+        assert!(
+            std::ptr::eq(coerced as *const u8, value as *const u8),
+            "bug: coerce should not change the address"
+        );
+        // The value offset of `RawCcBox<U>` is the same, since `U` has the
+        // alignment of `T` at runtime.
+        let ptr = coerced.byte_offset(-offset) as *mut RawCcBox<U, O>;
+        // The reference count is moved to the result.
+        mem::forget(self);
+        RawCc(NonNull::new_unchecked(ptr))
+    }
+}
+
+/// Convert a [`Cc<T>`](type.Cc.html) or other [`RawCc<T>`](struct.RawCc.html)
+/// to a `RawCc<U>` by an unsizing coercion, ex. to `Cc<dyn MyTrait>` for a
+/// user trait with a `Trace` supertrait. This works on stable Rust, unlike
+/// the implicit coercion enabled by the `nightly` feature.
+///
+/// `Trace` is only implemented for `Cc<dyn Trace>` among the trait objects.
+/// To trace `Cc<dyn MyTrait>` fields, use the inherent
+/// [`RawCc::trace`](struct.RawCc.html#method.trace) method.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{cc_coerce, Cc, Trace, Tracer};
+/// use std::cell::RefCell;
+///
+/// trait Shape: Trace {
+///     fn area(&self) -> f64;
+/// }
+///
+/// struct Square(f64);
+/// impl Trace for Square {}
+/// impl Shape for Square {
+///     fn area(&self) -> f64 {
+///         self.0 * self.0
+///     }
+/// }
+///
+/// struct Group(RefCell<Vec<Cc<dyn Shape>>>);
+/// impl Trace for Group {
+///     fn trace(&self, tracer: &mut Tracer) {
+///         self.0.borrow().iter().for_each(|s| s.trace(tracer));
+///     }
+/// }
+/// impl Shape for Group {
+///     fn area(&self) -> f64 {
+///         self.0.borrow().iter().map(|s| s.area()).sum()
+///     }
+/// }
+///
+/// let square: Cc<dyn Shape> = cc_coerce!(Cc::new(Square(2.0)), dyn Shape);
+/// let group = Cc::new(Group(RefCell::new(vec![square.clone(), square])));
+/// let shape: Cc<dyn Shape> = cc_coerce!(group, dyn Shape);
+/// assert_eq!(shape.area(), 8.0);
+/// ```
+#[macro_export]
+macro_rules! cc_coerce {
+    ($cc:expr, $t:ty) => {{
+        let cc = $cc;
+        let coerce = $crate::RawCc::__coerce_fn(&cc, |ptr| -> *const $t { ptr });
+        // safety: Implicit coercions of raw pointers only change metadata.
+        unsafe { $crate::RawCc::__coerce_unsized(cc, coerce) }
+    }};
+}
+
+impl<O: AbstractObjectSpace> RawCc<dyn Trace, O> {
+    /// Attempt to downcast to a concrete type.
+    ///
+    /// Return `Err(self)` if the value is not a `T`. The allocation is
+    /// reused. The value is not cloned.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace};
+    ///
+    /// let v: Cc<dyn Trace> = Cc::new(String::from("a")).into_dyn();
+    /// let v = v.downcast::<i32>().unwrap_err();
+    /// let v: Cc<String> = v.downcast().ok().unwrap();
+    /// assert_eq!(v.as_str(), "a");
+    /// ```
+    pub fn downcast<T: Trace>(self) -> Result<RawCc<T, O>, Self> {
+        if self.inner().deref().__gcmodule_type_id() != TypeId::of::<T>() {
+            return Err(self);
+        }
+        let ptr = self.0.cast::<RawCcBox<T, O>>();
+        // The reference count is moved to the result.
+        mem::forget(self);
+        Ok(RawCc(ptr))
+    }
+}
+
+impl Cc<dyn Trace> {
+    /// Returns a reference to the value if it is a `T`, or `None` if it
+    /// is not. Unlike [`downcast`](struct.RawCc.html#method.downcast), this
+    /// does not consume the `Cc`.
+    ///
+    /// See also [`downcast_ref`](trait.Trace.html#method.downcast_ref) for
+    /// other `dyn Trace` values, like `Box<dyn Trace>`.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace};
+    ///
+    /// let v: Cc<dyn Trace> = Cc::new(String::from("a")).into_dyn();
+    /// assert_eq!(v.downcast_any::<String>().unwrap(), "a");
+    /// assert!(v.downcast_any::<i32>().is_none());
+    /// ```
+    #[inline]
+    pub fn downcast_any<T: Trace>(&self) -> Option<&T> {
+        self.deref().downcast_ref()
+    }
+}
+
+impl<T: Trace, O: AbstractObjectSpace> RawCc<[T], O> {
+    /// Constructs a new `RawCc<[T]>` by moving `values` into it.
+    ///
+    /// Panics if `T` is tracked by the collector. The `GcHeader` needs a
+    /// vtable of a sized type to operate on the object, which does not
+    /// exist for slices. Use `Cc<Vec<T>>` instead.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn new_slice_in_space(mut values: Vec<T>, space: &O) -> Self {
+        assert!(
+            !T::is_type_tracked(),
+            "Cc<[T]> does not support tracked T (use Cc<Vec<T>> instead)"
+        );
+        let len = values.len();
+        // `RawCcBox` is `repr(C)`. `[T; 0]` has the same offset and
+        // alignment as `[T]`.
+        let value_offset = mem::offset_of!(RawCcBox<[T; 0], O>, value);
+        let align = mem::align_of::<RawCcBox<[T; 0], O>>();
+        let size = mem::size_of::<T>()
+            .checked_mul(len)
+            .and_then(|n| n.checked_add(value_offset))
+            .expect("slice is too large");
+        let layout = Layout::from_size_align(size, align)
+            .expect("slice is too large")
+            .pad_to_align();
+        let prefix = RawCcBox::<[T; 0], O> {
+            ref_count: space.new_ref_count(false),
+            value: UnsafeCell::new(ManuallyDrop::new([])),
+            #[cfg(all(test, feature = "std"))]
+            name: debug::NEXT_DEBUG_NAME.with(|n| n.get().to_string()),
+            #[cfg(feature = "versioning")]
+            version: AtomicU64::new(0),
+        };
+        // safety: The layout matches `Layout::for_value` of the resulting
+        // `RawCcBox<[T], O>` so `drop_ccbox` can deallocate it. Values are
+        // moved out of the `Vec`.
+        let ptr: *mut RawCcBox<[T], O> = unsafe {
+            let mem = alloc(layout);
+            if mem.is_null() {
+                handle_alloc_error(layout);
+            }
+            std::ptr::write(mem as *mut RawCcBox<[T; 0], O>, prefix);
+            let data = mem.add(value_offset) as *mut T;
+            std::ptr::copy_nonoverlapping(values.as_ptr(), data, len);
+            values.set_len(0);
+            std::ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut RawCcBox<[T], O>
+        };
+        // safety: ptr is not null.
+        let result = Self(unsafe { NonNull::new_unchecked(ptr) });
+        debug::log(|| (result.debug_name(), "new (CcBox)"));
+        alloc_hook::on_new(|| result.inner().alloc_info());
+        result
+    }
+}
+
+impl<O: AbstractObjectSpace> RawCc<str, O> {
+    /// Constructs a new `RawCc<str>` by copying `value`.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn new_str_in_space(value: &str, space: &O) -> Self {
+        let bytes = RawCc::<[u8], O>::new_slice_in_space(value.as_bytes().to_vec(), space);
+        let ptr = bytes.0.as_ptr() as *mut RawCcBox<str, O>;
+        mem::forget(bytes);
+        // safety: The bytes are valid UTF-8. `str` and `[u8]` have the
+        // same layout.
+        Self(unsafe { NonNull::new_unchecked(ptr) })
+    }
+}
+
+#[cfg(feature = "std")]
 impl<T: Trace + Clone> Cc<T> {
     /// Update the value `T` in a copy-on-write way.
     ///
     /// If the ref count is 1, the value is updated in-place.
-    /// Otherwise a new `Cc<T>` will be created.
-    pub fn update_with(&mut self, mut update_func: impl FnMut(&mut T)) {
+    /// Otherwise a new `Cc<T>` will be created in the same space.
+    ///
+    /// With the `versioning` feature, the [`version`](struct.RawCc.html#method.version)
+    /// is increased by 1 either way.
+    pub fn update_with(&mut self, update_func: impl FnMut(&mut T)) {
+        self.update(update_func)
+    }
+
+    /// Like [`update_with`](struct.RawCc.html#method.update_with), but
+    /// returns the value returned by `update_func`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::Cc;
+    ///
+    /// let mut a = Cc::new(vec![1, 2]);
+    /// let b = a.clone();
+    /// assert_eq!(a.update(|v| v.pop()), Some(2));
+    /// assert_eq!((a.len(), b.len()), (1, 2));
+    /// ```
+    pub fn update<R>(&mut self, update_func: impl FnOnce(&mut T) -> R) -> R {
+        match self.try_update(|value| Ok::<R, Infallible>(update_func(value))) {
+            Ok(result) => result,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`update`](struct.RawCc.html#method.update), but fallible.
+    ///
+    /// If the ref count is 1, the value is updated in-place, and changes
+    /// made by `update_func` are kept even if it returns `Err`. Otherwise,
+    /// `update_func` operates on a copy, and `self` is only replaced by the
+    /// copy if `update_func` returns `Ok`.
+    ///
+    /// With the `versioning` feature, the [`version`](struct.RawCc.html#method.version)
+    /// is increased by 1 unless `self` is unchanged.
+    pub fn try_update<R, E>(
+        &mut self,
+        update_func: impl FnOnce(&mut T) -> Result<R, E>,
+    ) -> Result<R, E> {
         let need_clone = self.ref_count() > 1;
-        if need_clone {
+        let result = if need_clone {
             let mut value = <Cc<T>>::deref(self).clone();
-            update_func(&mut value);
-            *self = Cc::new(value);
+            let result = update_func(&mut value);
+            if result.is_ok() {
+                *self = self.new_copy(value);
+            }
+            result
         } else {
             let value_ptr: *mut ManuallyDrop<T> = self.inner().value.get();
             let value_mut: &mut T = unsafe { &mut *value_ptr }.deref_mut();
-            update_func(value_mut);
+            update_func(value_mut)
+        };
+        #[cfg(feature = "versioning")]
+        if !need_clone || result.is_ok() {
+            self.touch();
+        }
+        result
+    }
+
+    /// Makes a mutable reference to the value, similar to `Rc::make_mut`.
+    ///
+    /// If there are other strong or weak references to the same object,
+    /// the value is cloned to a new object first, and `this` is replaced by
+    /// it. The new object belongs to the same
+    /// [`ObjectSpace`](struct.ObjectSpace.html) as the old one. Weak
+    /// references keep pointing to the old object.
+    ///
+    /// With the `versioning` feature, the [`version`](struct.RawCc.html#method.version)
+    /// is increased by 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::Cc;
+    ///
+    /// let mut a = Cc::new(vec![1]);
+    /// Cc::make_mut(&mut a).push(2);
+    /// let b = a.clone();
+    /// Cc::make_mut(&mut a).push(3);
+    /// assert_eq!((a.len(), b.len()), (3, 2));
+    /// ```
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if this.ref_count() != 1 || this.weak_count() != 0 {
+            *this = this.new_copy(<Cc<T>>::deref(this).clone());
+        }
+        #[cfg(feature = "versioning")]
+        this.touch();
+        let value_ptr: *mut ManuallyDrop<T> = this.inner().value.get();
+        // safety: The object is only reachable via `this`, which is
+        // borrowed mutably.
+        unsafe { &mut *value_ptr }.deref_mut()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Trace> Cc<T> {
+    /// Create an object with `value` in the same space as `self`. The
+    /// version is copied.
+    pub(crate) fn new_copy(&self, value: T) -> Self {
+        let inner = self.inner();
+        let result = match inner.tracked_header() {
+            None => Cc::new(value),
+            Some(header) => {
+                let boxed: RawCcBoxWithGcHeader<T, ObjectSpace> = RawCcBoxWithGcHeader {
+                    header: header.sibling(),
+                    cc_box: RawCcBox::new(value, SingleThreadRefCount::new(true)),
+                };
+                let layout = Layout::for_value(&boxed);
+                let ptr = boxed.header.alloc(layout).as_ptr() as *mut _;
+                // safety: The memory is allocated with the layout of `boxed`.
+                unsafe { std::ptr::write(ptr, boxed) };
+                // safety: `ptr` is valid.
+                let boxed = unsafe { &mut *ptr };
+                // Fix-up fields in GcHeader after the allocation, like
+                // `new_in_space`. Objects untracked by `untrack` are linked
+                // to themselves. Track the copy in the list of the space
+                // instead, like `retrack`.
+                let prev = if header.is_untracked() {
+                    header.space_list()
+                } else {
+                    Some(header)
+                };
+                boxed
+                    .header
+                    .insert_after(prev.unwrap_or(header), &boxed.cc_box);
+                if prev.is_none() {
+                    // The space was dropped or leaked, or is unknown without
+                    // the `space_pointer` feature. Keep the copy untracked.
+                    boxed.header.untrack();
+                }
+                // safety: `ptr` is not null.
+                let result = Self(unsafe { NonNull::new_unchecked(&mut boxed.cc_box) });
+                debug::log(|| (result.debug_name(), "new (CcBoxWithGcHeader)"));
+                alloc_hook::on_new(|| result.inner().alloc_info());
+                result.init_self_ref(None);
+                result
+            }
+        };
+        #[cfg(feature = "versioning")]
+        result.inner().version.store(self.version(), Relaxed);
+        result
+    }
+}
+
+impl<T: ?Sized> Cc<T> {
+    /// Returns a mutable reference to the value, if there are no other
+    /// strong or weak references to the same object.
+    ///
+    /// With the `versioning` feature, the
+    /// [`version`](struct.RawCc.html#method.version) is increased by 1 if
+    /// a reference is returned.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::Cc;
+    ///
+    /// let mut a = Cc::new(1);
+    /// *Cc::get_mut(&mut a).unwrap() += 1;
+    /// let b = a.clone();
+    /// assert!(Cc::get_mut(&mut a).is_none());
+    /// drop(b);
+    /// assert_eq!(*a, 2);
+    /// ```
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.ref_count() != 1 || this.weak_count() != 0 {
+            return None;
+        }
+        #[cfg(feature = "versioning")]
+        this.touch();
+        let value_ptr: *mut ManuallyDrop<T> = this.inner().value.get();
+        // safety: The object is only reachable via `this`, which is
+        // borrowed mutably.
+        Some(unsafe { &mut *value_ptr }.deref_mut())
+    }
+}
+
+impl<T, O: AbstractObjectSpace> RawCcBox<T, O> {
+    fn new(value: T, ref_count: O::RefCount) -> Self {
+        Self {
+            ref_count,
+            value: UnsafeCell::new(ManuallyDrop::new(value)),
+            #[cfg(all(test, feature = "std"))]
+            name: debug::NEXT_DEBUG_NAME.with(|n| n.get().to_string()),
+            #[cfg(feature = "versioning")]
+            version: AtomicU64::new(0),
         }
     }
 }
@@ -260,6 +894,16 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCcBox<T, O> {
         self.ref_count.is_tracked()
     }
 
+    /// The `GcHeader`, or `None` for untracked objects.
+    #[inline]
+    pub(crate) fn tracked_header(&self) -> Option<&O::Header> {
+        if self.is_tracked() {
+            Some(self.header())
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn is_dropped(&self) -> bool {
         self.ref_count.is_dropped()
@@ -295,12 +939,42 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCcBox<T, O> {
         let already_dropped = self.set_dropped();
         if !already_dropped {
             debug::log(|| (self.debug_name(), "drop (T)"));
+            alloc_hook::on_drop(|| self.alloc_info());
             // safety: is_dropped() check ensures T is only dropped once. Other
             // places (ex. gc collector) ensure that T is no longer accessed.
             unsafe { ManuallyDrop::drop(&mut *(self.value.get())) };
         }
     }
 
+    /// Call `Trace::finalize` if T is not dropped.
+    pub(crate) fn finalize(&self)
+    where
+        T: Trace,
+    {
+        if !self.is_dropped() {
+            self.deref().finalize();
+        }
+    }
+
+    /// Create a `RawCc` pointing to this `CcBox` without changing the
+    /// reference count. The caller is responsible for increasing it.
+    pub(crate) fn to_cc(&self) -> RawCc<T, O> {
+        // safety: The pointer is compatible. The mutability is different only
+        // to satisfy NonNull (NonNull::new requires &mut). The returned value
+        // is still "immutable". &self can also never be nonnull.
+        let ptr: NonNull<RawCcBox<T, O>> =
+            unsafe { NonNull::new_unchecked(self as *const _ as *mut _) };
+        RawCc(ptr)
+    }
+
+    /// Create a new strong reference to this `CcBox`.
+    #[cfg(feature = "relocation")]
+    pub(crate) fn new_ref(&self) -> RawCc<T, O> {
+        self.inc_ref();
+        debug::log(|| (self.debug_name(), format!("clone ({})", self.ref_count())));
+        self.to_cc()
+    }
+
     pub(crate) fn trace_t(&self, tracer: &mut Tracer) {
         if !self.is_tracked() {
             return;
@@ -311,18 +985,30 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCcBox<T, O> {
         // `trace` stops here, is non-recursive, and does apply `tracer`
         // to the actual `GcHeader`. It's expected that the upper layer
         // calls `gc_traverse` on everything (not just roots).
-        tracer(self.header_ptr());
+        tracer.visit(self.header_ptr());
+    }
+
+    /// Type name, debug name and id reported to the alloc hook.
+    fn alloc_info(&self) -> (&'static str, String, usize) {
+        let id = self.value.get() as *const () as usize;
+        (std::any::type_name::<T>(), self.debug_name(), id)
     }
 
     pub(crate) fn debug_name(&self) -> String {
-        #[cfg(test)]
+        self.debug_name_with_type(std::any::type_name::<T>())
+    }
+
+    /// Like `debug_name`, with the given type name.
+    fn debug_name_with_type(&self, type_name: &str) -> String {
+        #[cfg(all(test, feature = "std"))]
         {
+            let _ = type_name;
             self.name.clone()
         }
-        #[cfg(not(test))]
+        #[cfg(not(all(test, feature = "std")))]
         {
             #[allow(unused_mut)]
-            let mut result = format!("{} at {:p}", std::any::type_name::<T>(), &self.value);
+            let mut result = format!("{} at {:p}", type_name, &self.value);
 
             #[cfg(all(feature = "debug", feature = "nightly"))]
             {
@@ -334,7 +1020,19 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCcBox<T, O> {
                 }
             }
 
-            return result;
+            result
+        }
+    }
+}
+
+impl<T: Trace + ?Sized, O: AbstractObjectSpace> RawCcBox<T, O> {
+    /// [`Trace::type_name`] of the value. Fall back to
+    /// `std::any::type_name` if the value was dropped.
+    fn value_type_name(&self) -> &'static str {
+        if self.is_dropped() {
+            std::any::type_name::<T>()
+        } else {
+            self.deref().type_name()
         }
     }
 }
@@ -358,6 +1056,86 @@ impl<T: std::fmt::Debug + ?Sized> OptionalDebug for T {
     }
 }
 
+impl<T: ?Sized> Cc<T> {
+    /// Sequence id assigned when the object was created, or `None` if `T`
+    /// is not tracked.
+    ///
+    /// Sequence ids increase monotonically and are never reused in a
+    /// process. Unlike addresses, they can identify objects across
+    /// [heap snapshots](analysis/fn.snapshot.html) and logs.
+    /// Requires the `seqid` feature.
+    #[cfg(feature = "seqid")]
+    pub fn sequence_id(&self) -> Option<u64> {
+        let inner = self.inner();
+        if inner.is_tracked() {
+            Some(inner.header().seq)
+        } else {
+            None
+        }
+    }
+
+    /// Time when the object was created, or `None` if `T` is not tracked.
+    /// Requires the `age` feature.
+    #[cfg(feature = "age")]
+    pub fn creation_time(&self) -> Option<std::time::Instant> {
+        let inner = self.inner();
+        if inner.is_tracked() {
+            Some(inner.header().created)
+        } else {
+            None
+        }
+    }
+
+    /// Remove the object from the linked list of its
+    /// [`ObjectSpace`](struct.ObjectSpace.html), so collections no longer
+    /// scan it. This is similar to cpython's `PyObject_GC_UnTrack`.
+    ///
+    /// This is useful for objects that cannot be part of a cycle after
+    /// construction, ex. they become logically immutable. The object is
+    /// still released when its reference count drops to zero, and objects
+    /// it refers to are considered reachable. If it is part of a cycle, the
+    /// cycle is not collected until [`retrack`](#method.retrack) is called.
+    /// This is a no-op for untracked types (ex. `Cc<i32>`).
+    ///
+    /// Without the `space_pointer` feature, the object does not know its
+    /// space after `untrack`. It cannot be retracked, and copies made by
+    /// [`make_mut`](#method.make_mut) are not tracked either.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace};
+    ///
+    /// let a: Cc<Vec<Box<dyn Trace>>> = Cc::new(vec![Box::new(1)]);
+    /// assert_eq!(gcmodule::count_thread_tracked(), 1);
+    /// a.untrack();
+    /// assert_eq!(gcmodule::count_thread_tracked(), 0);
+    /// ```
+    pub fn untrack(&self) {
+        if let Some(header) = self.inner().tracked_header() {
+            header.untrack();
+        }
+    }
+
+    /// Insert the object removed by [`untrack`](#method.untrack) back to
+    /// its [`ObjectSpace`](struct.ObjectSpace.html), so collections scan it
+    /// again. Objects frozen before `untrack` are not frozen after
+    /// `retrack`.
+    ///
+    /// Return `false` if the space was dropped or leaked. The object stays
+    /// untracked in that case.
+    ///
+    /// Requires the `space_pointer` feature.
+    #[cfg(feature = "space_pointer")]
+    pub fn retrack(&self) -> bool {
+        match self.inner().tracked_header() {
+            Some(header) => header.retrack(),
+            None => true,
+        }
+    }
+}
+
 impl<T: ?Sized, O: AbstractObjectSpace> RawCc<T, O> {
     /// Obtains a "weak reference", a non-owning pointer.
     pub fn downgrade(&self) -> RawWeak<T, O> {
@@ -377,6 +1155,139 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCc<T, O> {
     pub fn strong_count(&self) -> usize {
         self.ref_count()
     }
+
+    /// Returns `true` if the two `Cc`s point to the same allocation, similar
+    /// to `Rc::ptr_eq`.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.0.as_ptr() as *const () == other.0.as_ptr() as *const ()
+    }
+
+    /// Returns a raw pointer to the value, similar to `Rc::as_ptr`.
+    ///
+    /// The value is not accessed, so this does not need
+    /// [`ThreadedCc::borrow`](type.ThreadedCc.html#method.borrow). The
+    /// pointer is valid while the object is alive.
+    #[inline]
+    pub fn as_ptr(this: &Self) -> *const T {
+        let ptr: *const RawCcBox<T, O> = this.0.as_ptr();
+        // safety: `ptr` is valid. Do not read the value. It might be dropped.
+        unsafe { UnsafeCell::raw_get(std::ptr::addr_of!((*ptr).value)) as *const T }
+    }
+
+    /// Like [`as_ptr`](struct.RawCc.html#method.as_ptr), but returns a
+    /// `NonNull`.
+    #[inline]
+    pub fn as_non_null(this: &Self) -> NonNull<T> {
+        // safety: `as_ptr` points into the allocation, and is not null.
+        unsafe { NonNull::new_unchecked(Self::as_ptr(this) as *mut T) }
+    }
+
+    /// An id of the object based on its address. It is the same for clones
+    /// of this reference, and differs from ids of other live objects, so
+    /// it can be used for hashing and interning by identity. See also
+    /// [`PtrIdentity`](struct.PtrIdentity.html).
+    ///
+    /// Ids can be reused after the object is released. With the `seqid`
+    /// feature, [`sequence_id`](type.Cc.html#method.sequence_id) is never
+    /// reused.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::Cc;
+    ///
+    /// let a = Cc::new(());
+    /// let b = Cc::new(());
+    /// assert_eq!(a.id(), a.clone().id());
+    /// assert_ne!(a.id(), b.id());
+    /// ```
+    #[inline]
+    pub fn id(&self) -> usize {
+        Self::as_ptr(self) as *const () as usize
+    }
+
+    /// Gets the user-defined tag. It is 0 for new objects.
+    ///
+    /// See [`set_tag`](struct.RawCc.html#method.set_tag).
+    #[inline]
+    pub fn tag(&self) -> u8 {
+        self.inner().ref_count.tag()
+    }
+
+    /// Sets a user-defined tag for the shared value. The tag is visible
+    /// from all clones of this reference.
+    ///
+    /// Tags use spare bits in the reference count so they take no extra
+    /// memory. They can be used by external marking algorithms, for
+    /// example, marking objects visited during serialization, without
+    /// building a set keyed by addresses. The collector does not use tags.
+    ///
+    /// Panics if `tag` does not fit in [`TAG_BITS`](constant.TAG_BITS.html)
+    /// bits.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::Cc;
+    ///
+    /// let a = Cc::new(1);
+    /// let b = a.clone();
+    /// a.set_tag(3);
+    /// assert_eq!(b.tag(), 3);
+    /// ```
+    #[inline]
+    pub fn set_tag(&self, tag: u8) {
+        assert!(
+            (tag as u32) < (1 << crate::TAG_BITS),
+            "tag {} does not fit in {} bits",
+            tag,
+            crate::TAG_BITS
+        );
+        self.inner().ref_count.set_tag(tag)
+    }
+}
+
+#[cfg(feature = "versioning")]
+impl<T: ?Sized, O: AbstractObjectSpace> RawCc<T, O> {
+    /// Mark the value as changed by increasing its
+    /// [`version`](struct.RawCc.html#method.version).
+    ///
+    /// The collector does not know about mutations through interior
+    /// mutability. Call this after mutating the value so incremental
+    /// computations can detect changes without comparing or hashing the
+    /// full value.
+    ///
+    /// Requires the `versioning` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::Cc;
+    /// use std::cell::RefCell;
+    ///
+    /// let cc = Cc::new(RefCell::new(1));
+    /// let seen = cc.version();
+    /// *cc.borrow_mut() = 2;
+    /// cc.touch();
+    /// assert_ne!(cc.version(), seen);
+    /// ```
+    #[inline]
+    pub fn touch(&self) {
+        self.inner().version.fetch_add(1, Relaxed);
+    }
+
+    /// Gets the mutation version. It starts from 0 and is increased by
+    /// [`touch`](struct.RawCc.html#method.touch) and
+    /// [`update_with`](struct.RawCc.html#method.update_with).
+    ///
+    /// Requires the `versioning` feature.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.inner().version.load(Relaxed)
+    }
 }
 
 impl<T: ?Sized, O: AbstractObjectSpace> RawWeak<T, O> {
@@ -384,7 +1295,7 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawWeak<T, O> {
     ///
     /// Returns `None` if the value has already been dropped.
     pub fn upgrade(&self) -> Option<RawCc<T, O>> {
-        let inner = self.inner();
+        let inner = self.inner()?;
         // Make the below operation "atomic".
         let _locked = inner.ref_count.locked();
         if inner.is_dropped() {
@@ -404,13 +1315,148 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawWeak<T, O> {
     /// Gets the reference count not considering weak references.
     #[inline]
     pub fn strong_count(&self) -> usize {
-        self.inner().ref_count()
+        self.inner().map_or(0, |inner| inner.ref_count())
+    }
+
+    /// Returns `true` if the value has been dropped, or the weak reference
+    /// was created by [`RawWeak::new`](struct.RawWeak.html#method.new).
+    /// [`upgrade`](struct.RawWeak.html#method.upgrade) fails in this case.
+    ///
+    /// Unlike `upgrade`, this does not take the collector lock of
+    /// [`ThreadedCc`](type.ThreadedCc.html). A dropped value never comes
+    /// back, so `true` is final. `false` can be outdated immediately if
+    /// other threads are dropping the value.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Weak};
+    ///
+    /// let a = Cc::new(1);
+    /// let weak = a.downgrade();
+    /// assert!(!weak.is_dangling());
+    /// drop(a);
+    /// assert!(weak.is_dangling());
+    /// assert!(Weak::<i32>::new().is_dangling());
+    /// ```
+    #[inline]
+    pub fn is_dangling(&self) -> bool {
+        self.inner().is_none_or(|inner| inner.is_dropped())
     }
 
     /// Get the weak (non-owning) reference count.
+    ///
+    /// Returns 0 for weak references created by
+    /// [`RawWeak::new`](struct.RawWeak.html#method.new).
     #[inline]
     pub fn weak_count(&self) -> usize {
-        self.inner().weak_count()
+        self.inner().map_or(0, |inner| inner.weak_count())
+    }
+}
+
+/// Attempts to obtain strong references for all weak references.
+///
+/// Returns `None` if any of the values has already been dropped. In that
+/// case, no strong references are kept.
+///
+/// For [`ThreadedCc`](type.ThreadedCc.html), the collector lock is acquired
+/// once for all upgrades, so a collection cannot drop some values in the
+/// middle. The weak references are expected to belong to the same space.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{Cc, Weak};
+///
+/// let a = Cc::new(1);
+/// let b = Cc::new(2);
+/// let weaks: Vec<Weak<i32>> = vec![a.downgrade(), b.downgrade()];
+/// let strongs = gcmodule::upgrade_all(&weaks).unwrap();
+/// assert_eq!(*strongs[1], 2);
+///
+/// drop((a, strongs));
+/// assert!(gcmodule::upgrade_all(&weaks).is_none());
+/// assert_eq!(b.strong_count(), 1);
+/// ```
+pub fn upgrade_all<T: ?Sized, O: AbstractObjectSpace>(
+    weaks: &[RawWeak<T, O>],
+) -> Option<Vec<RawCc<T, O>>> {
+    let _locked = match weaks.first() {
+        Some(weak) => Some(weak.inner()?.ref_count.locked()),
+        None => None,
+    };
+    weaks.iter().map(|weak| weak.upgrade()).collect()
+}
+
+impl<T, O: AbstractObjectSpace> RawWeak<T, O> {
+    /// Constructs a weak reference without an object. It always fails to
+    /// [`upgrade`](struct.RawWeak.html#method.upgrade).
+    pub fn new() -> Self {
+        // Like `std::rc::Weak::new`, use an address that no allocation can
+        // have. It is never dereferenced.
+        let ptr = usize::MAX as *mut RawCcBox<T, O>;
+        // safety: usize::MAX is not null.
+        Self(unsafe { NonNull::new_unchecked(ptr) })
+    }
+}
+
+impl<T, O: AbstractObjectSpace> Default for RawWeak<T, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Trace, O: AbstractObjectSpace> RawWeak<T, O> {
+    /// Convert to `RawWeak<dyn Trace>`.
+    ///
+    /// Unlike [`RawCc::into_dyn`](struct.RawCc.html#method.into_dyn), this
+    /// works after the value was dropped.
+    pub fn into_dyn(self) -> RawWeak<dyn Trace, O> {
+        // Unsizing `NonNull` does not need CoerceUnsized on `RawWeak`, and
+        // does not read the value.
+        let ptr: NonNull<RawCcBox<dyn Trace, O>> = self.0;
+        // The weak count is moved to the result.
+        mem::forget(self);
+        RawWeak(ptr)
+    }
+}
+
+impl<O: AbstractObjectSpace> RawWeak<dyn Trace, O> {
+    /// Attempt to downcast to a concrete type.
+    ///
+    /// Return `Err(self)` if the value is not a `T`, or the type cannot be
+    /// checked because the value was dropped.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace, Weak};
+    ///
+    /// let v: Cc<dyn Trace> = Cc::new(String::from("a")).into_dyn();
+    /// let weak: Weak<dyn Trace> = v.downgrade();
+    /// let weak = weak.downcast::<i32>().unwrap_err();
+    /// let weak: Weak<String> = weak.downcast().ok().unwrap();
+    /// assert_eq!(weak.upgrade().unwrap().as_str(), "a");
+    /// ```
+    pub fn downcast<T: Trace>(self) -> Result<RawWeak<T, O>, Self> {
+        let matched = match self.inner() {
+            Some(inner) => {
+                // Prevent the value from being dropped by other threads.
+                let _locked = inner.ref_count.locked();
+                !inner.is_dropped() && inner.deref().__gcmodule_type_id() == TypeId::of::<T>()
+            }
+            None => false,
+        };
+        if !matched {
+            return Err(self);
+        }
+        let ptr = self.0.cast::<RawCcBox<T, O>>();
+        // The weak count is moved to the result.
+        mem::forget(self);
+        Ok(RawWeak(ptr))
     }
 }
 
@@ -458,10 +1504,14 @@ impl<T: ?Sized, O: AbstractObjectSpace> RawCc<T, O> {
 }
 
 impl<T: ?Sized, O: AbstractObjectSpace> RawWeak<T, O> {
+    /// Returns `None` if created by `RawWeak::new`.
     #[inline]
-    fn inner(&self) -> &RawCcBox<T, O> {
+    fn inner(&self) -> Option<&RawCcBox<T, O>> {
+        if self.0.as_ptr() as *mut u8 as usize == usize::MAX {
+            return None;
+        }
         // safety: CcBox lifetime maintained by ref count. Pointer is valid.
-        unsafe { self.0.as_ref() }
+        Some(unsafe { self.0.as_ref() })
     }
 }
 
@@ -472,6 +1522,7 @@ impl<T: ?Sized, O: AbstractObjectSpace> Clone for RawCc<T, O> {
         // Practically this is an atomic operation that cannot be split so locking
         // becomes optional.
         // let _locked = self.inner().ref_count.locked();
+        self.inner().ref_count.check_thread();
         self.inc_ref();
         debug::log(|| (self.debug_name(), format!("clone ({})", self.ref_count())));
         Self(self.0)
@@ -481,7 +1532,10 @@ impl<T: ?Sized, O: AbstractObjectSpace> Clone for RawCc<T, O> {
 impl<T: ?Sized, O: AbstractObjectSpace> Clone for RawWeak<T, O> {
     #[inline]
     fn clone(&self) -> Self {
-        let inner = self.inner();
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return Self(self.0),
+        };
         let ref_count = &inner.ref_count;
         ref_count.inc_weak();
         debug::log(|| {
@@ -499,6 +1553,7 @@ impl<T: ?Sized> Deref for Cc<T> {
 
     #[inline]
     fn deref(&self) -> &Self::Target {
+        self.inner().ref_count.check_thread();
         self.inner().deref()
     }
 }
@@ -523,22 +1578,54 @@ impl<T: ?Sized, O: AbstractObjectSpace> Deref for RawCcBox<T, O> {
     }
 }
 
+/// Call the function on drop, unless forgotten. Used by `new_cyclic`.
+struct LinkOnPanic<F: Fn()>(F);
+
+impl<F: Fn()> Drop for LinkOnPanic<F> {
+    fn drop(&mut self) {
+        (self.0)();
+    }
+}
+
+/// Move a tracked object to memory allocated by `space.alloc_tracked`.
+fn alloc_tracked<T, O: AbstractObjectSpace>(
+    space: &O,
+    value: RawCcBoxWithGcHeader<T, O>,
+) -> *mut RawCcBoxWithGcHeader<T, O> {
+    let layout = Layout::new::<RawCcBoxWithGcHeader<T, O>>();
+    let ptr = space.alloc_tracked(&value.header, layout).as_ptr() as *mut _;
+    // safety: The memory is allocated with the layout of `value`.
+    unsafe { std::ptr::write(ptr, value) };
+    ptr
+}
+
 fn drop_ccbox<T: ?Sized, O: AbstractObjectSpace>(cc_box: *mut RawCcBox<T, O>) {
-    // safety: See Cc::new. The pointer was created by Box::into_raw.
-    let cc_box: Box<RawCcBox<T, O>> = unsafe { Box::from_raw(cc_box) };
-    let is_tracked = cc_box.is_tracked();
+    // safety: See Cc::new. The pointer is valid until deallocated below.
+    let is_tracked = unsafe { &*cc_box }.is_tracked();
     if is_tracked {
         // The real object is CcBoxWithGcHeader. Drop that instead.
         // safety: See Cc::new for CcBoxWithGcHeader.
-        let gc_box: Box<RawCcBoxWithGcHeader<T, O>> = unsafe { cast_box(cc_box) };
-        O::remove(&gc_box.header);
-        // Drop T if it hasn't been dropped yet.
-        // This needs to be after O::remove so the collector won't have a
-        // chance to read dropped content.
-        gc_box.cc_box.drop_t();
-        debug::log(|| (gc_box.cc_box.debug_name(), "drop (CcBoxWithGcHeader)"));
-        drop(gc_box);
+        let gc_box: *mut RawCcBoxWithGcHeader<T, O> = unsafe { cast_box(cc_box) };
+        let layout = {
+            // safety: gc_box is valid.
+            let gc_box = unsafe { &*gc_box };
+            O::remove(&gc_box.header);
+            // Drop T if it hasn't been dropped yet.
+            // This needs to be after O::remove so the collector won't have a
+            // chance to read dropped content.
+            gc_box.cc_box.drop_t();
+            debug::log(|| (gc_box.cc_box.debug_name(), "drop (CcBoxWithGcHeader)"));
+            Layout::for_value(gc_box)
+        };
+        // safety: The memory was allocated by `alloc_tracked` with the same
+        // layout. It is not used after this.
+        unsafe {
+            std::ptr::drop_in_place(&mut (*gc_box).cc_box);
+            O::dealloc_tracked(&mut (*gc_box).header, layout);
+        }
     } else {
+        // safety: See Cc::new. The pointer was created by Box::into_raw.
+        let cc_box: Box<RawCcBox<T, O>> = unsafe { Box::from_raw(cc_box) };
         // Drop T if it hasn't been dropped yet.
         cc_box.drop_t();
         debug::log(|| (cc_box.debug_name(), "drop (CcBox)"));
@@ -570,7 +1657,10 @@ impl<T: ?Sized, O: AbstractObjectSpace> Drop for RawCc<T, O> {
 impl<T: ?Sized, O: AbstractObjectSpace> Drop for RawWeak<T, O> {
     fn drop(&mut self) {
         let ptr: *mut RawCcBox<T, O> = self.0.as_ptr();
-        let inner = self.inner();
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
         let ref_count = &inner.ref_count;
         // Block threaded collector to "freeze" the ref count, for safety.
         let _locked = ref_count.locked();
@@ -595,8 +1685,17 @@ impl<T: Trace + ?Sized, O: AbstractObjectSpace> CcDyn for RawCcBox<T, O> {
         self.ref_count()
     }
 
+    fn gc_is_dropped(&self) -> bool {
+        self.is_dropped()
+    }
+
     fn gc_traverse(&self, tracer: &mut Tracer) {
         debug::log(|| (self.debug_name(), "gc_traverse"));
+        // Objects revived by `Drop` after their `T` was dropped are kept
+        // by strong references. See `set_resurrection_tolerant`.
+        if self.is_dropped() {
+            return;
+        }
         T::trace(self.deref(), tracer)
     }
 
@@ -606,18 +1705,59 @@ impl<T: Trace + ?Sized, O: AbstractObjectSpace> CcDyn for RawCcBox<T, O> {
             let msg = format!("gc_clone ({})", self.ref_count());
             (self.debug_name(), msg)
         });
-        // safety: The pointer is compatible. The mutability is different only
-        // to satisfy NonNull (NonNull::new requires &mut). The returned value
-        // is still "immutable". &self can also never be nonnull.
-        let ptr: NonNull<RawCcBox<T, O>> =
-            unsafe { NonNull::new_unchecked(self as *const _ as *mut _) };
-        let cc = RawCc::<T, O>(ptr);
-        Box::new(cc)
+        Box::new(self.to_cc())
+    }
+
+    fn gc_inc_ref(&self) {
+        self.inc_ref();
+        debug::log(|| {
+            (
+                self.debug_name(),
+                format!("gc_inc_ref ({})", self.ref_count()),
+            )
+        });
+    }
+
+    fn gc_dec_ref(&self) {
+        // Dropping the `RawCc` decreases the ref count, and releases the
+        // object if needed.
+        drop(self.to_cc());
+    }
+
+    fn gc_inc_weak(&self) {
+        self.ref_count.inc_weak();
+    }
+
+    fn gc_dec_weak(&self) {
+        // Dropping the `RawWeak` decreases the weak count, and releases the
+        // object if needed.
+        let cc = ManuallyDrop::new(self.to_cc());
+        drop(RawWeak::<T, O>(cc.0));
+    }
+
+    fn gc_drop_t(&self) {
+        self.drop_t()
+    }
+
+    fn gc_finalize(&self) {
+        self.finalize()
+    }
+
+    fn gc_type_name(&self) -> &'static str {
+        self.value_type_name()
+    }
+
+    fn gc_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn gc_alloc_size(&self) -> usize {
+        mem::size_of::<O::Header>() + mem::size_of_val(self)
     }
 
     #[cfg(feature = "debug")]
     fn gc_debug_name(&self) -> String {
-        self.debug_name()
+        self.debug_name_with_type(self.value_type_name())
     }
 }
 
@@ -629,11 +1769,21 @@ impl<T: Trace + ?Sized, O: AbstractObjectSpace> GcClone for RawCc<T, O> {
     fn gc_drop_t(&self) {
         self.inner().drop_t()
     }
+
+    fn gc_finalize(&self) {
+        self.inner().finalize()
+    }
+
+    fn gc_type_name(&self) -> &'static str {
+        self.inner().value_type_name()
+    }
 }
 
-impl<T: Trace> Trace for Cc<T> {
+// Implemented for all spaces, so types generic over the space can derive
+// `Trace`.
+impl<T: Trace, O: AbstractObjectSpace> Trace for RawCc<T, O> {
     fn trace(&self, tracer: &mut Tracer) {
-        Cc::<T>::trace(self, tracer)
+        RawCc::<T, O>::trace(self, tracer)
     }
 
     #[inline]
@@ -642,15 +1792,36 @@ impl<T: Trace> Trace for Cc<T> {
     }
 }
 
-impl Trace for Cc<dyn Trace> {
-    fn trace(&self, tracer: &mut Tracer) {
-        Cc::<dyn Trace>::trace(self, tracer)
+macro_rules! impl_trace_for_dyn_cc {
+    ($($t:ty),*) => {$(
+        impl<O: AbstractObjectSpace> Trace for RawCc<$t, O> {
+            fn trace(&self, tracer: &mut Tracer) {
+                RawCc::<$t, O>::trace(self, tracer)
+            }
+
+            #[inline]
+            fn is_type_tracked() -> bool {
+                // Trait objects can be anything.
+                true
+            }
+        }
+    )*};
+}
+
+impl_trace_for_dyn_cc!(dyn Trace, dyn Trace + Send, dyn Trace + Send + Sync);
+
+impl<T: Trace, O: AbstractObjectSpace> Trace for RawCc<[T], O> {
+    #[inline]
+    fn is_type_tracked() -> bool {
+        // See `RawCc::new_slice_in_space`.
+        false
     }
+}
 
+impl<O: AbstractObjectSpace> Trace for RawCc<str, O> {
     #[inline]
     fn is_type_tracked() -> bool {
-        // Trait objects can be anything.
-        true
+        false
     }
 }
 
@@ -670,20 +1841,19 @@ unsafe fn cast_ref<T: ?Sized, R>(value: &T, offset_bytes: isize) -> &R {
 
 #[inline]
 unsafe fn cast_box<T: ?Sized, O: AbstractObjectSpace>(
-    value: Box<RawCcBox<T, O>>,
-) -> Box<RawCcBoxWithGcHeader<T, O>> {
-    let mut ptr: *const RawCcBox<T, O> = Box::into_raw(value);
+    value: *mut RawCcBox<T, O>,
+) -> *mut RawCcBoxWithGcHeader<T, O> {
+    let mut ptr: *const RawCcBox<T, O> = value;
 
     // ptr can be "thin" (1 pointer) or "fat" (2 pointers).
     // Change the first byte to point to the GcHeader.
     let pptr: *mut *const RawCcBox<T, O> = &mut ptr;
     let pptr: *mut *const O::Header = pptr as _;
     *pptr = (*pptr).offset(-1);
-    let ptr: *mut RawCcBoxWithGcHeader<T, O> = mem::transmute(ptr);
-    Box::from_raw(ptr)
+    mem::transmute(ptr)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use crate::collect::Linked;
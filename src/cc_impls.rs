@@ -1,14 +1,22 @@
 //! Additional impls about `AbstractCc<T, O>` to make it easier to use.
 
 use crate::cc::RawCc;
+use crate::cc::RawWeak;
+#[cfg(feature = "std")]
+use crate::collect::with_current_space;
+use crate::collect::AbstractObjectSpace;
 use crate::collect::ObjectSpace as O;
+#[cfg(feature = "std")]
 use crate::Cc;
 use crate::Trace;
+use crate::Tracer;
+use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::fmt;
 use std::hash;
 use std::ops::Deref;
 
+#[cfg(feature = "std")]
 impl<T: Default + Trace> Default for Cc<T> {
     #[inline]
     fn default() -> Cc<T> {
@@ -16,15 +24,48 @@ impl<T: Default + Trace> Default for Cc<T> {
     }
 }
 
-impl<T: PartialEq + ?Sized> PartialEq for RawCc<T, O> {
+#[cfg(feature = "std")]
+impl<T: Trace> From<T> for Cc<T> {
     #[inline]
-    fn eq(&self, other: &RawCc<T, O>) -> bool {
-        **self == **other
+    fn from(value: T) -> Cc<T> {
+        Self::new(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Trace> From<Vec<T>> for Cc<[T]> {
+    /// Panics if `T` is tracked by the collector. Use `Cc<Vec<T>>` instead.
+    fn from(values: Vec<T>) -> Cc<[T]> {
+        with_current_space(|space| RawCc::new_slice_in_space(values, space))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Trace + Clone> From<&[T]> for Cc<[T]> {
+    /// Panics if `T` is tracked by the collector. Use `Cc<Vec<T>>` instead.
+    fn from(values: &[T]) -> Cc<[T]> {
+        values.to_vec().into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&str> for Cc<str> {
+    fn from(value: &str) -> Cc<str> {
+        with_current_space(|space| RawCc::new_str_in_space(value, space))
     }
+}
+
+#[cfg(feature = "std")]
+impl From<String> for Cc<str> {
+    fn from(value: String) -> Cc<str> {
+        value.as_str().into()
+    }
+}
 
+impl<T: PartialEq + ?Sized> PartialEq for RawCc<T, O> {
     #[inline]
-    fn ne(&self, other: &RawCc<T, O>) -> bool {
-        **self != **other
+    fn eq(&self, other: &RawCc<T, O>) -> bool {
+        **self == **other
     }
 }
 
@@ -77,6 +118,14 @@ impl<T: fmt::Debug + ?Sized> fmt::Debug for RawCc<T, O> {
     }
 }
 
+impl<T: ?Sized> fmt::Debug for RawWeak<T, O> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Like `std::rc::Weak`, do not show the value, which might be dropped.
+        write!(f, "(Weak)")
+    }
+}
+
 impl<T: fmt::Display + ?Sized> fmt::Display for RawCc<T, O> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -89,3 +138,184 @@ impl<T: ?Sized> fmt::Pointer for RawCc<T, O> {
         fmt::Pointer::fmt(&self.inner().deref(), f)
     }
 }
+
+/// Compares and hashes a [`RawCc`](struct.RawCc.html) by the address of
+/// its value, instead of the value. This works for both
+/// [`Cc`](type.Cc.html) and [`ThreadedCc`](type.ThreadedCc.html), and can
+/// be used as keys of identity maps, like `is` in Python.
+///
+/// Maps keyed by `PtrIdentity` can be queried by an
+/// [`Address`](struct.Address.html), ex. from a raw pointer returned by
+/// [`RawCc::as_ptr`](struct.RawCc.html#method.as_ptr), without a
+/// `RawCc`.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{Cc, PtrIdentity};
+/// use std::collections::HashSet;
+///
+/// let a = Cc::new(1);
+/// let b = Cc::new(1);
+/// assert!(a == b);
+/// let mut set = HashSet::new();
+/// set.insert(PtrIdentity(a.clone()));
+/// assert!(set.contains(&PtrIdentity(a)));
+/// assert!(!set.contains(&PtrIdentity(b)));
+/// ```
+#[derive(Clone, Debug, Default)]
+#[doc(alias = "ByAddress")]
+pub struct PtrIdentity<P>(pub P);
+
+impl<T: ?Sized, O: AbstractObjectSpace> PtrIdentity<RawCc<T, O>> {
+    /// Address of the value, without metadata of unsized types.
+    #[inline]
+    fn addr(&self) -> *const () {
+        RawCc::as_ptr(&self.0) as *const ()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> PartialEq for PtrIdentity<RawCc<T, O>> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> Eq for PtrIdentity<RawCc<T, O>> {}
+
+impl<T: ?Sized, O: AbstractObjectSpace> hash::Hash for PtrIdentity<RawCc<T, O>> {
+    #[inline]
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.addr().hash(state)
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> PartialOrd for PtrIdentity<RawCc<T, O>> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> Ord for PtrIdentity<RawCc<T, O>> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.addr().cmp(&other.addr())
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> Borrow<Address<T>> for PtrIdentity<RawCc<T, O>> {
+    #[inline]
+    fn borrow(&self) -> &Address<T> {
+        // safety: The value is not accessed. See `Address::from_ptr`.
+        unsafe { Address::from_ptr(RawCc::as_ptr(&self.0)) }
+    }
+}
+
+impl<P> Deref for PtrIdentity<P> {
+    type Target = P;
+
+    #[inline]
+    fn deref(&self) -> &P {
+        &self.0
+    }
+}
+
+impl<P: Trace> Trace for PtrIdentity<P> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.0.trace(tracer)
+    }
+
+    #[inline]
+    fn is_type_tracked() -> bool {
+        P::is_type_tracked()
+    }
+}
+
+/// The address of a value, compared and hashed like
+/// [`PtrIdentity`](struct.PtrIdentity.html). Use it to look up maps keyed by
+/// `PtrIdentity` with a reference or a raw pointer to the value.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{Address, Cc, PtrIdentity};
+/// use std::collections::HashMap;
+///
+/// let a = Cc::new(1);
+/// let ptr: *const i32 = Cc::as_ptr(&a);
+/// let mut map = HashMap::new();
+/// map.insert(PtrIdentity(a.clone()), "a");
+/// assert_eq!(map[Address::new(&*a)], "a");
+/// // safety: `a` is alive.
+/// assert_eq!(map[unsafe { Address::from_ptr(ptr) }], "a");
+/// assert!(!map.contains_key(Address::new(&1)));
+/// ```
+#[repr(transparent)]
+pub struct Address<T: ?Sized>(T);
+
+impl<T: ?Sized> Address<T> {
+    /// Get the address of `value`.
+    #[inline]
+    pub fn new(value: &T) -> &Self {
+        // safety: `Address<T>` is a transparent wrapper of `T`.
+        unsafe { Self::from_ptr(value) }
+    }
+
+    /// Get the address of the value `ptr` points to.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null and aligned, like pointers returned by
+    /// [`RawCc::as_ptr`](struct.RawCc.html#method.as_ptr) while the object
+    /// is alive. The value is never accessed via the returned reference.
+    #[inline]
+    pub unsafe fn from_ptr<'a>(ptr: *const T) -> &'a Self {
+        &*(ptr as *const Self)
+    }
+
+    /// Address of the value, without metadata of unsized types.
+    #[inline]
+    fn addr(&self) -> *const () {
+        self as *const Self as *const ()
+    }
+}
+
+impl<T: ?Sized> PartialEq for Address<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl<T: ?Sized> Eq for Address<T> {}
+
+impl<T: ?Sized> hash::Hash for Address<T> {
+    #[inline]
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.addr().hash(state)
+    }
+}
+
+impl<T: ?Sized> PartialOrd for Address<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: ?Sized> Ord for Address<T> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.addr().cmp(&other.addr())
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Address<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.addr(), f)
+    }
+}
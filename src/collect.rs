@@ -4,20 +4,49 @@
 
 // NOTE: Consider adding generation support if necessary. It won't be too hard.
 
+#[cfg(feature = "space_pointer")]
+use crate::arena::Arena;
 use crate::cc::CcDummy;
 use crate::cc::CcDyn;
 use crate::cc::GcClone;
 use crate::debug;
+use crate::events::EventKind;
+use crate::events::LocalEvents;
+use crate::leak_detector::LeakDetector;
+use crate::leak_detector::SuspectedLeak;
+use crate::profile;
+use crate::reclaimed;
+use crate::reclaimed::ReclaimedType;
 use crate::ref_count::RefCount;
 use crate::ref_count::SingleThreadRefCount;
+use crate::roots::Roots;
+#[cfg(feature = "watchdog")]
+use crate::watchdog::Watchdog;
 use crate::Cc;
 use crate::Trace;
+use crate::Weak;
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+#[cfg(not(feature = "debug"))]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
 use std::cell::Cell;
 use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
 use std::pin::Pin;
+use std::ptr::NonNull;
+use std::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 /// Provides advanced explicit control about where to store [`Cc`](type.Cc.html)
 /// objects.
@@ -33,7 +62,8 @@ use std::pin::Pin;
 /// create new objects within the space.
 ///
 /// Objects within a space should not refer to objects in a different space.
-/// Failing to do so might cause memory leak.
+/// Failing to do so might cause memory leak. With the `debug` feature,
+/// such references found by collections are logged.
 ///
 /// # Example
 ///
@@ -59,27 +89,257 @@ pub struct ObjectSpace {
     /// Linked list to the tracked objects.
     pub(crate) list: RefCell<Pin<Box<GcHeader>>>,
 
+    /// Number of active `defer_collection` scopes. Collection is skipped if
+    /// it is non-zero.
+    defer_depth: Cell<usize>,
+
+    /// Optional leak detector updated after each collection.
+    leak_detector: RefCell<Option<LeakDetector>>,
+
+    /// Recent events. Zero-sized without the `events` feature.
+    events: LocalEvents,
+
+    /// Identity and memory usage of the space, shared with headers of
+    /// tracked objects. Replaced by `leak`.
+    state: RefCell<Rc<SpaceState>>,
+
+    /// States of spaces merged into this space by `merge`, sorted by id.
+    /// Their objects keep the old states.
+    merged: RefCell<Vec<Rc<SpaceState>>>,
+
+    /// Linked list of objects moved by `freeze`. Not scanned by
+    /// collections.
+    frozen: RefCell<Pin<Box<GcHeader>>>,
+
+    /// Collection counters.
+    counters: Cell<Counters>,
+
+    /// If set, release unreachable objects in batches of this size.
+    batch_size: Cell<Option<usize>>,
+
+    /// Keep objects revived by `finalize` or `Drop` instead of panicking.
+    resurrection_tolerant: Cell<bool>,
+
+    /// Call `leak` instead of collecting cycles on drop.
+    leak_on_drop: Cell<bool>,
+
+    /// Whether this is the thread-local space, whose drop is controlled by
+    /// `set_exit_behavior`.
+    #[cfg(feature = "std")]
+    is_thread_space: Cell<bool>,
+
+    /// Placeholder linked before the object to start the next
+    /// `collect_window` from. Linked to itself to start from the beginning.
+    window_cursor: Pin<Box<GcHeader>>,
+
+    /// Optional watchdog checking growth without collection.
+    #[cfg(feature = "watchdog")]
+    watchdog: RefCell<Option<Watchdog>>,
+
+    /// Relocation passes run after each collection.
+    #[cfg(feature = "relocation")]
+    relocation_hooks: RefCell<Vec<RelocationHook>>,
+
+    /// Optional callbacks around each collection.
+    collect_hooks: RefCell<Option<Rc<CollectHooks>>>,
+
+    /// Optional callback when the number of objects reaches watermarks.
+    #[cfg(feature = "space_pointer")]
+    pressure_handler: RefCell<Option<Rc<PressureHandler>>>,
+
     /// Mark `ObjectSpace` as `!Send` and `!Sync`. This enforces thread-exclusive
     /// access to the linked list so methods can use `&self` instead of
     /// `&mut self`, together with usage of interior mutability.
     _phantom: PhantomData<Cc<()>>,
 }
 
-/// This is a private type.
+/// A relocation pass on the linked list. Returns the number of objects forwarded.
+#[cfg(feature = "relocation")]
+type RelocationHook = Box<dyn Fn(&GcHeader) -> usize>;
+
+/// State shared by an `ObjectSpace` and headers of objects it creates.
+/// Spaces own it by `Rc`. With the `space_pointer` feature, headers keep a
+/// pointer to it, so creating and releasing objects does not touch the `Rc`
+/// counts. A state whose space is
+/// dropped or leaked is kept alive by `orphan` until `count` reaches 0.
+pub(crate) struct SpaceState {
+    /// Identity of the space. Not reused.
+    id: usize,
+
+    /// Identity of the space owning objects with this state. Differs from
+    /// `id` after `merge`. 0 after `leak`.
+    owner: Cell<usize>,
+
+    /// Bytes of tracked objects created by the space and not released,
+    /// including the `GcHeader`s.
+    bytes: Cell<usize>,
+
+    /// Number of tracked objects created by the space and not deallocated.
+    /// Headers of these objects point to this state.
+    count: Cell<usize>,
+
+    /// Allocator of tracked objects. `None` uses the global allocator.
+    /// Objects keep the allocator alive via their headers.
+    allocator: Option<Rc<dyn GlobalAlloc>>,
+
+    /// Dummy head of the linked list of the owning space, used by
+    /// `Cc::retrack`. Null after the space is dropped or leaked.
+    list: Cell<*const GcHeader>,
+}
+
+impl SpaceState {
+    fn new(allocator: Option<Rc<dyn GlobalAlloc>>, list: &GcHeader) -> Rc<Self> {
+        let id = next_space_id();
+        Rc::new(Self {
+            id,
+            owner: Cell::new(id),
+            bytes: Cell::new(0),
+            count: Cell::new(0),
+            allocator,
+            list: Cell::new(list),
+        })
+    }
+
+    /// Stop using `state` by its space, which is dropped or leaked. Objects
+    /// still pointing to it keep it alive. See `release_object`.
+    fn orphan(state: Rc<Self>) {
+        state.list.set(std::ptr::null());
+        if state.count.get() > 0 {
+            // Freed by `release_object` of the last object.
+            let _ = Rc::into_raw(state);
+        }
+    }
+
+    /// Count an object with a header pointing to `state` as deallocated.
+    /// Free the state if it was orphaned and this is the last object.
+    ///
+    /// # Safety
+    ///
+    /// `state` must be valid and counted for the object.
+    unsafe fn release_object(state: *const Self) {
+        let count = (*state).count.get() - 1;
+        (*state).count.set(count);
+        if count == 0 && (*state).list.get().is_null() {
+            drop(Rc::from_raw(state));
+        }
+    }
+}
+
+/// Callback set by `ObjectSpace::set_pressure_handler`.
+#[cfg(feature = "space_pointer")]
+struct PressureHandler {
+    /// Sorted watermarks.
+    watermarks: Vec<usize>,
+    handler: Box<dyn Fn(usize) -> bool>,
+}
+
+/// Callbacks set by `ObjectSpace::set_collect_hooks`.
+struct CollectHooks {
+    on_start: Box<dyn Fn()>,
+    on_end: Box<dyn Fn(CollectStats)>,
+}
+
+/// A space tracking objects, implemented by
+/// [`ObjectSpace`](struct.ObjectSpace.html) and
+/// [`ThreadedObjectSpace`](struct.ThreadedObjectSpace.html).
+///
+/// Use it as a bound to write types generic over the space, which work with
+/// both [`Cc<T>`](type.Cc.html) and [`ThreadedCc<T>`](type.ThreadedCc.html).
+/// [`RawCc<T, O>`](struct.RawCc.html) implements [`Trace`](trait.Trace.html)
+/// for all spaces, so such types can derive `Trace`:
+///
+#[cfg_attr(feature = "derive", doc = " ```")]
+#[cfg_attr(not(feature = "derive"), doc = " ```ignore")]
+/// use gcmodule::{AbstractObjectSpace, ObjectSpace, RawCc, Trace};
+///
+/// #[derive(Trace)]
+/// struct Node<O: AbstractObjectSpace> {
+///     next: Option<RawCc<u32, O>>,
+/// }
+///
+/// let space = ObjectSpace::default();
+/// let node = space.create(Node { next: Some(space.create(1)) });
+/// ```
+///
+/// The items of this trait are private. It cannot be implemented outside
+/// this crate.
 pub trait AbstractObjectSpace: 'static + Sized {
+    #[doc(hidden)]
     type RefCount: RefCount;
+    #[doc(hidden)]
     type Header;
 
+    /// Run `func` with [`Roots`](struct.Roots.html), which keep objects
+    /// alive until `func` returns.
+    ///
+    /// Use it when native code holds raw pointers to values, for example,
+    /// during a call from a VM to a native function. Raw pointers do not
+    /// keep objects alive. Objects might otherwise be released by dropping
+    /// the last reference, or by collections in other threads.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{AbstractObjectSpace, ObjectSpace};
+    ///
+    /// let space = ObjectSpace::default();
+    /// let value = space.create("native".to_string());
+    /// let len = space.with_roots(|roots| {
+    ///     let ptr = roots.root(&value);
+    ///     drop(value);
+    ///     // safety: `ptr` is rooted.
+    ///     unsafe { ptr.as_ref() }.len()
+    /// });
+    /// assert_eq!(len, 6);
+    /// ```
+    fn with_roots<R>(&self, func: impl FnOnce(&mut Roots<Self>) -> R) -> R {
+        let mut roots = Roots::new();
+        func(&mut roots)
+    }
+
     /// Insert "header" and "value" to the linked list.
+    #[doc(hidden)]
     fn insert(&self, header: &mut Self::Header, value: &dyn CcDyn);
 
     /// Remove from linked list.
+    #[doc(hidden)]
     fn remove(header: &Self::Header);
 
     /// Create a `RefCount` object.
+    #[doc(hidden)]
     fn new_ref_count(&self, tracked: bool) -> Self::RefCount;
 
+    #[doc(hidden)]
     fn empty_header(&self) -> Self::Header;
+
+    /// Allocate memory for a tracked object, including its `header`, which
+    /// is created by `empty_header` and not inserted yet.
+    #[doc(hidden)]
+    fn alloc_tracked(&self, header: &Self::Header, layout: Layout) -> NonNull<u8> {
+        let _ = header;
+        alloc_global(layout)
+    }
+
+    /// Drop the header of a tracked object and deallocate its memory. The
+    /// rest of the object has been dropped.
+    ///
+    /// # Safety
+    ///
+    /// `header` must be allocated by `alloc_tracked` with `layout`.
+    #[doc(hidden)]
+    unsafe fn dealloc_tracked(header: *mut Self::Header, layout: Layout) {
+        std::ptr::drop_in_place(header);
+        dealloc(header as *mut u8, layout);
+    }
+}
+
+/// Allocate memory using the global allocator.
+fn alloc_global(layout: Layout) -> NonNull<u8> {
+    // safety: Tracked objects have non-zero sizes.
+    match NonNull::new(unsafe { alloc(layout) }) {
+        Some(ptr) => ptr,
+        None => handle_alloc_error(layout),
+    }
 }
 
 impl AbstractObjectSpace for ObjectSpace {
@@ -87,24 +347,22 @@ impl AbstractObjectSpace for ObjectSpace {
     type Header = GcHeader;
 
     fn insert(&self, header: &mut Self::Header, value: &dyn CcDyn) {
-        let prev: &GcHeader = &self.list.borrow();
-        debug_assert!(header.next.get().is_null());
-        let next = prev.next.get();
-        header.prev.set(prev.deref());
-        header.next.set(next);
-        unsafe {
-            // safety: The linked list is maintained, and pointers are valid.
-            (&*next).prev.set(header);
-            // safety: To access vtable pointer. Test by test_gc_header_value.
-            let fat_ptr: [*mut (); 2] = mem::transmute(value);
-            header.ccdyn_vptr = fat_ptr[1];
+        {
+            let prev: &GcHeader = &self.list.borrow();
+            header.insert_after(prev, value);
+            #[cfg(feature = "watchdog")]
+            self.check_watchdog(prev);
         }
-        prev.next.set(header);
+        #[cfg(feature = "space_pointer")]
+        self.check_pressure();
     }
 
     #[inline]
     fn remove(header: &Self::Header) {
-        let header: &GcHeader = &header;
+        let header: &GcHeader = header;
+        header.events.record(|| EventKind::Drop {
+            type_name: header.value().gc_type_name(),
+        });
         debug_assert!(!header.next.get().is_null());
         debug_assert!(!header.prev.get().is_null());
         let next = header.next.get();
@@ -115,6 +373,11 @@ impl AbstractObjectSpace for ObjectSpace {
             (*next).prev.set(prev);
         }
         header.next.set(std::ptr::null_mut());
+        if let Some(space) = header.state() {
+            space
+                .bytes
+                .set(space.bytes.get() - header.value().gc_alloc_size());
+        }
     }
 
     #[inline]
@@ -124,35 +387,1230 @@ impl AbstractObjectSpace for ObjectSpace {
 
     #[inline]
     fn empty_header(&self) -> Self::Header {
-        GcHeader::empty()
+        GcHeader::empty(self.events.clone(), Rc::as_ptr(&self.state.borrow()))
+    }
+
+    #[inline]
+    fn alloc_tracked(&self, header: &Self::Header, layout: Layout) -> NonNull<u8> {
+        header.alloc(layout)
+    }
+
+    unsafe fn dealloc_tracked(header: *mut Self::Header, layout: Layout) {
+        // The state might own the allocator. Keep it alive until the memory
+        // is released.
+        let space = (*header).space_ptr();
+        std::ptr::drop_in_place(header);
+        let ptr = header as *mut u8;
+        match space.as_ref().and_then(|s| s.allocator.as_ref()) {
+            Some(allocator) => allocator.dealloc(ptr, layout),
+            None => dealloc(ptr, layout),
+        }
+        if !space.is_null() {
+            SpaceState::release_object(space);
+        }
     }
 }
 
 impl Default for ObjectSpace {
     /// Constructs an empty [`ObjectSpace`](struct.ObjectSpace.html).
     fn default() -> Self {
-        let header = new_gc_list();
+        let events = LocalEvents::default();
+        let header = new_gc_list(events.clone());
+        let state = SpaceState::new(None, &header);
         Self {
             list: RefCell::new(header),
+            frozen: RefCell::new(new_gc_list(events.clone())),
+            window_cursor: new_gc_cursor(events.clone()),
+            defer_depth: Cell::new(0),
+            leak_detector: RefCell::new(None),
+            events,
+            state: RefCell::new(state),
+            merged: Default::default(),
+            counters: Default::default(),
+            batch_size: Cell::new(None),
+            resurrection_tolerant: Cell::new(false),
+            leak_on_drop: Cell::new(false),
+            #[cfg(feature = "std")]
+            is_thread_space: Cell::new(false),
+            #[cfg(feature = "watchdog")]
+            watchdog: RefCell::new(None),
+            #[cfg(feature = "relocation")]
+            relocation_hooks: Default::default(),
+            collect_hooks: Default::default(),
+            #[cfg(feature = "space_pointer")]
+            pressure_handler: Default::default(),
             _phantom: PhantomData,
         }
     }
 }
 
 impl ObjectSpace {
+    /// Constructs an empty [`ObjectSpace`](struct.ObjectSpace.html) that
+    /// allocates tracked objects from an arena.
+    ///
+    /// Objects are bump-allocated from 64KB chunks. A chunk is freed when
+    /// all objects in it are released, so memory is not reused until then.
+    /// This makes allocation cheaper for workloads creating many objects
+    /// that are released together, at the cost of memory held by chunks
+    /// with a few long-lived objects. Untracked objects (ex. `Cc<i32>`) and
+    /// objects larger than 8KB use the global allocator.
+    ///
+    /// Requires the `space_pointer` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::with_arena();
+    /// for _ in 0..1000 {
+    ///     let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    ///     a.borrow_mut().push(Box::new(a.clone()));
+    /// }
+    /// assert_eq!(space.collect_cycles(), 1000);
+    /// ```
+    #[cfg(feature = "space_pointer")]
+    pub fn with_arena() -> Self {
+        Self::with_allocator(Arena::new())
+    }
+
+    /// Constructs an empty [`ObjectSpace`](struct.ObjectSpace.html) that
+    /// allocates tracked objects using `allocator`.
+    ///
+    /// This can route allocations of tracked objects, including their
+    /// `GcHeader`s, to a custom pool, or measure them separately. Untracked
+    /// objects (ex. `Cc<i32>`) use the global allocator. The allocator is
+    /// dropped after the space and all objects allocated by it are dropped.
+    ///
+    /// Requires the `space_pointer` feature, so objects can find the
+    /// allocator when they are released.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::cell::{Cell, RefCell};
+    /// use std::rc::Rc;
+    ///
+    /// #[derive(Default)]
+    /// struct Counting(Rc<Cell<usize>>);
+    ///
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         self.0.set(self.0.get() + layout.size());
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         self.0.set(self.0.get() - layout.size());
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// let allocator = Counting::default();
+    /// let allocated = allocator.0.clone();
+    /// let space = ObjectSpace::with_allocator(allocator);
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// assert_eq!(allocated.get(), space.bytes_allocated());
+    /// drop(a);
+    /// assert_eq!(allocated.get(), 0);
+    /// ```
+    #[cfg(feature = "space_pointer")]
+    pub fn with_allocator(allocator: impl GlobalAlloc + 'static) -> Self {
+        let space = Self::default();
+        let state = SpaceState::new(Some(Rc::new(allocator)), &space.list.borrow());
+        *space.state.borrow_mut() = state;
+        space
+    }
+
+    /// Test whether `cc` is tracked by this
+    /// [`ObjectSpace`](struct.ObjectSpace.html). Untracked objects (ex.
+    /// `Cc<i32>`) do not belong to any space. With the `space_pointer`
+    /// feature, the time complexity is `O(log m)`, `m` being the number of
+    /// spaces merged into this space. Otherwise, this walks the tracked
+    /// objects, and objects removed by [`Cc::untrack`](type.Cc.html#method.untrack)
+    /// are not found.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let b = Cc::new(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// assert!(space.contains(&a));
+    /// assert!(!space.contains(&b));
+    /// assert!(!space.contains(&space.create(1)));
+    /// ```
+    pub fn contains<T: ?Sized>(&self, cc: &Cc<T>) -> bool {
+        let header = match cc.inner().tracked_header() {
+            Some(header) => header,
+            None => return false,
+        };
+        if !cfg!(feature = "space_pointer") {
+            let mut found = false;
+            self.visit_allocated(|h| found |= std::ptr::eq(h, header));
+            return found;
+        }
+        header.state().is_some_and(|space| {
+            std::ptr::eq(space, Rc::as_ptr(&self.state.borrow()))
+                || self
+                    .merged
+                    .borrow()
+                    .binary_search_by_key(&space.id, |s| s.id)
+                    .is_ok()
+        })
+    }
+
     /// Count objects tracked by this [`ObjectSpace`](struct.ObjectSpace.html).
+    /// Objects frozen by [`freeze`](struct.ObjectSpace.html#method.freeze)
+    /// are not counted.
     pub fn count_tracked(&self) -> usize {
         let list: &GcHeader = &self.list.borrow();
-        let mut count = 0;
-        visit_list(list, |_| count += 1);
-        count
+        count_list(list)
+    }
+
+    /// Count objects tracked by this [`ObjectSpace`](struct.ObjectSpace.html)
+    /// by type names, as returned by [`Trace::type_name`]. Walks the
+    /// tracked objects.
+    ///
+    /// This can be used to find which types dominate the tracked objects
+    /// when hunting leaks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let b = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let counts = space.count_tracked_by_type();
+    /// assert_eq!(counts.len(), 1);
+    /// assert_eq!(counts.values().sum::<usize>(), 2);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn count_tracked_by_type(&self) -> HashMap<&'static str, usize> {
+        let list: &GcHeader = &self.list.borrow();
+        let mut counts = HashMap::new();
+        visit_list(list, |header| {
+            *counts.entry(header.value().gc_type_name()).or_insert(0) += 1;
+        });
+        counts
+    }
+
+    /// Find cycles that the next
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles)
+    /// would collect, without collecting them.
+    ///
+    /// Return strongly connected components of the unreachable objects
+    /// that form cycles. Objects are described by their debug names with the
+    /// `debug` feature, or their type names otherwise. Unreachable objects not in a cycle, ex. only referred by
+    /// a cycle, are not reported. Frozen objects are not scanned.
+    ///
+    /// This is useful for tests asserting that no cycles leaked. Values are
+    /// traced but not changed. The time complexity is
+    /// `O(tracked + edges)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let b = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(b.clone()));
+    /// b.borrow_mut().push(Box::new(a.clone()));
+    /// assert!(space.find_cycles().is_empty());
+    /// drop((a, b));
+    /// assert_eq!(space.find_cycles().len(), 1);
+    /// assert_eq!(space.find_cycles()[0].len(), 2);
+    /// assert_eq!(space.collect_cycles(), 2);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn find_cycles(&self) -> Vec<Vec<String>> {
+        let list: &GcHeader = &self.list.borrow();
+
+        // Like `update_refs` and `subtract_refs`, with counts in a side
+        // table so headers are not changed.
+        let mut headers: Vec<&GcHeader> = Vec::new();
+        let mut index: HashMap<*const GcHeader, usize> = HashMap::new();
+        visit_list(list, |header| {
+            // Objects with ref count 0 might have their values dropped.
+            if header.value().gc_ref_count() > 0 {
+                index.insert(header, headers.len());
+                headers.push(header);
+            }
+        });
+        let edges: Vec<Vec<usize>> = headers
+            .iter()
+            .map(|header| {
+                let mut edges = Vec::new();
+                header.value().gc_traverse(&mut |ptr: *const ()| {
+                    if let Some(&i) = index.get(&(ptr as *const GcHeader)) {
+                        edges.push(i);
+                    }
+                });
+                edges
+            })
+            .collect();
+        let mut refs: Vec<usize> = headers.iter().map(|h| h.value().gc_ref_count()).collect();
+        for &i in edges.iter().flatten() {
+            refs[i] -= 1;
+        }
+
+        // Like `mark_reachable`.
+        let mut reachable = vec![false; headers.len()];
+        let mut stack: Vec<usize> = (0..headers.len()).filter(|&i| refs[i] > 0).collect();
+        while let Some(i) = stack.pop() {
+            if !mem::replace(&mut reachable[i], true) {
+                stack.extend(edges[i].iter().copied().filter(|&j| !reachable[j]));
+            }
+        }
+
+        // Tarjan's algorithm on unreachable objects, without recursion.
+        const UNVISITED: usize = usize::MAX;
+        let mut order = vec![UNVISITED; headers.len()];
+        let mut low = vec![0; headers.len()];
+        let mut on_stack = vec![false; headers.len()];
+        let mut scc_stack = Vec::new();
+        let mut next_order = 0;
+        let mut result = Vec::new();
+        for root in 0..headers.len() {
+            if reachable[root] || order[root] != UNVISITED {
+                continue;
+            }
+            // (node, position of the next edge to visit)
+            let mut call_stack = vec![(root, 0)];
+            while let Some(&mut (i, ref mut pos)) = call_stack.last_mut() {
+                if *pos == 0 {
+                    order[i] = next_order;
+                    low[i] = next_order;
+                    next_order += 1;
+                    scc_stack.push(i);
+                    on_stack[i] = true;
+                }
+                if let Some(&j) = edges[i].get(*pos) {
+                    *pos += 1;
+                    if order[j] == UNVISITED {
+                        // Edges from unreachable objects only point to
+                        // unreachable objects.
+                        call_stack.push((j, 0));
+                    } else if on_stack[j] {
+                        low[i] = low[i].min(order[j]);
+                    }
+                    continue;
+                }
+                call_stack.pop();
+                if let Some(&(parent, _)) = call_stack.last() {
+                    low[parent] = low[parent].min(low[i]);
+                }
+                if low[i] == order[i] {
+                    let mut scc = Vec::new();
+                    while let Some(j) = scc_stack.pop() {
+                        on_stack[j] = false;
+                        scc.push(j);
+                        if j == i {
+                            break;
+                        }
+                    }
+                    if scc.len() > 1 || edges[i].contains(&i) {
+                        scc.reverse();
+                        let name = |j: usize| {
+                            #[cfg(feature = "debug")]
+                            return headers[j].value().gc_debug_name();
+                            #[cfg(not(feature = "debug"))]
+                            return headers[j].value().gc_type_name().to_string();
+                        };
+                        result.push(scc.iter().map(|&j| name(j)).collect());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Creation metadata of objects tracked by this
+    /// [`ObjectSpace`](struct.ObjectSpace.html), oldest first. Frozen
+    /// objects are not included. Requires the `age` feature.
+    ///
+    /// This is useful to find old objects that keep accumulating. The
+    /// metadata is copied, so the space can be changed while iterating.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let b = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let info: Vec<_> = space.tracked_info().collect();
+    /// assert_eq!(info.len(), 2);
+    /// assert_eq!(Some(info[0].sequence_id), a.sequence_id());
+    /// assert!(info[0].age() >= info[1].age());
+    /// ```
+    #[cfg(feature = "age")]
+    pub fn tracked_info(&self) -> impl Iterator<Item = TrackedInfo> {
+        let list: &GcHeader = &self.list.borrow();
+        let mut result = Vec::new();
+        visit_list(list, |header| {
+            let value = header.value();
+            result.push(TrackedInfo {
+                sequence_id: header.seq,
+                created: header.created,
+                type_name: value.gc_type_name(),
+                size: value.gc_alloc_size(),
+                ref_count: value.gc_ref_count(),
+            });
+        });
+        // New objects are inserted at the front.
+        result.sort_unstable_by_key(|info| info.sequence_id);
+        result.into_iter()
+    }
+
+    /// Statistics of this space. Walks the tracked objects.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn stats(&self) -> ThreadGcStats {
+        let (tracked, tracked_bytes) = self.tracked_and_bytes();
+        let list: &GcHeader = &self.list.borrow();
+        let mut zombies = 0;
+        visit_list(list, |header| {
+            if header.value().gc_is_dropped() {
+                zombies += 1;
+            }
+        });
+        let counters = self.counters.get();
+        ThreadGcStats {
+            tracked,
+            zombies,
+            tracked_bytes,
+            collections: counters.collections,
+            collected: counters.collected,
+            last_collected: counters.last_collected,
+            last_duration: counters.last_duration,
+        }
     }
 
     /// Collect cyclic garbage tracked by this [`ObjectSpace`](struct.ObjectSpace.html).
     /// Return the number of objects collected.
+    ///
+    /// Return 0 without collecting anything if called inside
+    /// [`ObjectSpace::defer_collection`](struct.ObjectSpace.html#method.defer_collection).
+    ///
+    /// The order in which collected objects are finalized and dropped is
+    /// unspecified. With the `ordered_drop` feature, they are finalized and
+    /// dropped in creation order, oldest first. With
+    /// [`set_resurrection_tolerant`](struct.ObjectSpace.html#method.set_resurrection_tolerant),
+    /// only dropping is ordered. Batched collections, ex. with
+    /// [`set_collect_batch_size`](struct.ObjectSpace.html#method.set_collect_batch_size),
+    /// and [`FixedObjectSpace`](struct.FixedObjectSpace.html) do not
+    /// allocate a list to sort, and are not ordered.
     pub fn collect_cycles(&self) -> usize {
+        self.collect_cycles_with(|list| self.collect_configured(list))
+    }
+
+    /// Collect `list` using the mode set by `set_resurrection_tolerant` and
+    /// `set_collect_batch_size`.
+    fn collect_configured(&self, list: &GcHeader) -> usize {
+        match self.batch_size.get() {
+            _ if self.resurrection_tolerant.get() => collect_list_resurrectable(list),
+            None => collect_list(list, ()),
+            Some(batch_size) => collect_list_chunked(list, batch_size, &mut |_| {}),
+        }
+    }
+
+    /// Like [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles),
+    /// but return statistics of the collection.
+    ///
+    /// This walks the tracked objects before and after the collection.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// let b = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// drop(a);
+    /// let stats = space.collect_cycles_with_stats();
+    /// assert_eq!(stats.scanned, 2);
+    /// assert_eq!(stats.collected, 1);
+    /// assert!(stats.released_bytes > 0);
+    /// assert_eq!(stats.reclaimed.len(), 1);
+    /// assert_eq!(stats.reclaimed[0].count, 1);
+    /// ```
+    pub fn collect_cycles_with_stats(&self) -> CollectStats {
+        let (scanned, bytes_before) = self.tracked_and_bytes();
+        let start = Instant::now();
+        let (collected, reclaimed) =
+            self.collect_cycles_recording(|list| self.collect_configured(list), true);
+        let duration = start.elapsed();
+        let bytes_after = self.bytes_allocated();
+        CollectStats {
+            scanned,
+            collected,
+            released_bytes: bytes_before.saturating_sub(bytes_after),
+            bytes_allocated: bytes_after,
+            duration,
+            reclaimed,
+        }
+    }
+
+    /// Count tracked objects and their bytes. Walks the tracked objects
+    /// to count them.
+    fn tracked_and_bytes(&self) -> (usize, usize) {
+        (self.count_tracked(), self.bytes_allocated())
+    }
+
+    /// Bytes used by objects tracked by this
+    /// [`ObjectSpace`](struct.ObjectSpace.html), including the `GcHeader`s.
+    /// Memory owned indirectly (ex. by a `Vec`) is not included. Objects of
+    /// untracked types (ex. `Cc<i32>`) are not included either. They have
+    /// no `GcHeader` to find the space when they are dropped, and cannot
+    /// form cycles, so collections do not release them.
+    ///
+    /// With the `space_pointer` feature, the count is updated when tracked
+    /// objects are created and released, and the time complexity is `O(m)`,
+    /// `m` being the number of spaces merged into this space. This can be
+    /// used to collect by memory pressure. Otherwise, this walks the tracked
+    /// objects, and objects removed by [`Cc::untrack`](type.Cc.html#method.untrack)
+    /// are not included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// assert_eq!(space.bytes_allocated(), 0);
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// assert!(space.bytes_allocated() > 0);
+    /// drop(a);
+    /// if space.bytes_allocated() > 0 {
+    ///     space.collect_cycles();
+    /// }
+    /// assert_eq!(space.bytes_allocated(), 0);
+    /// ```
+    pub fn bytes_allocated(&self) -> usize {
+        if !cfg!(feature = "space_pointer") {
+            let mut bytes = 0;
+            self.visit_allocated(|header| bytes += header.value().gc_alloc_size());
+            return bytes;
+        }
+        let merged = self.merged.borrow();
+        self.state.borrow().bytes.get() + merged.iter().map(|s| s.bytes.get()).sum::<usize>()
+    }
+
+    /// Whether collections are deferred by `defer_collection`, or by
+    /// outstanding mutable borrows of `GcCell`s.
+    fn is_deferred(&self) -> bool {
+        #[cfg(feature = "std")]
+        if crate::gc_cell::is_mut_borrowed() {
+            return true;
+        }
+        self.defer_depth.get() > 0
+    }
+
+    /// Collect cycles using `collect`. Update counters, events, etc.
+    pub(crate) fn collect_cycles_with(&self, collect: impl FnOnce(&GcHeader) -> usize) -> usize {
+        self.collect_cycles_recording(collect, false).0
+    }
+
+    /// Like `collect_cycles_with`. Also return the number of objects
+    /// released per type, if `record_types` is set or they are recorded
+    /// anyway.
+    fn collect_cycles_recording(
+        &self,
+        collect: impl FnOnce(&GcHeader) -> usize,
+        record_types: bool,
+    ) -> (usize, Vec<ReclaimedType>) {
+        if self.is_deferred() {
+            debug::log(|| ("collect", "deferred"));
+            return (0, Vec::new());
+        }
+        // The cursor is not an object. Keep it out of the collection.
+        self.reset_window_cursor();
+        // Cloned so hooks can replace themselves.
+        let hooks = self.collect_hooks.borrow().clone();
+        let before = hooks.as_ref().map(|hooks| {
+            (hooks.on_start)();
+            self.tracked_and_bytes()
+        });
         let list: &GcHeader = &self.list.borrow();
-        collect_list(list, ())
+        let start = Instant::now();
+        let scope = reclaimed::Scope::begin(record_types || hooks.is_some());
+        let result = collect(list);
+        let reclaimed = scope.finish();
+        self.counters
+            .set(self.counters.get().add(result, start.elapsed()));
+        #[cfg(feature = "relocation")]
+        for hook in self.relocation_hooks.borrow().iter() {
+            hook(list);
+        }
+        if let Some(detector) = self.leak_detector.borrow_mut().as_mut() {
+            detector.update(list);
+        }
+        #[cfg(feature = "watchdog")]
+        if let Some(watchdog) = self.watchdog.borrow_mut().as_mut() {
+            watchdog.on_collect(count_list(list));
+        }
+        self.events.record(|| EventKind::Collect {
+            collected: result,
+            reclaimed: reclaimed.clone(),
+        });
+        if let (Some(hooks), Some((scanned, bytes_before))) = (hooks, before) {
+            let bytes_after = self.bytes_allocated();
+            (hooks.on_end)(CollectStats {
+                scanned,
+                collected: result,
+                released_bytes: bytes_before.saturating_sub(bytes_after),
+                bytes_allocated: bytes_after,
+                duration: start.elapsed(),
+                reclaimed: reclaimed.clone(),
+            });
+        }
+        (result, reclaimed)
+    }
+
+    /// Collect cycles like
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles),
+    /// releasing unreachable objects in batches of `batch_size` objects,
+    /// and calling `yielder` between batches.
+    ///
+    /// This allows a host, like a single-threaded event loop, to run other
+    /// tasks during a large collection. Finding unreachable objects is not
+    /// interrupted. `yielder` can create objects. Collections inside
+    /// `yielder` are skipped.
+    ///
+    /// Panics if `batch_size` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// for _ in 0..10 {
+    ///     let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    ///     a.borrow_mut().push(Box::new(a.clone()));
+    /// }
+    /// let mut yields = Vec::new();
+    /// let collected = space.collect_cycles_yielding(4, |released| yields.push(released));
+    /// assert_eq!(collected, 10);
+    /// assert_eq!(yields, [4, 8]);
+    /// ```
+    pub fn collect_cycles_yielding(
+        &self,
+        batch_size: usize,
+        mut yielder: impl CollectYield,
+    ) -> usize {
+        assert!(batch_size > 0, "batch_size should be positive");
+        self.collect_cycles_with(|list| {
+            collect_list_chunked(list, batch_size, &mut |released| {
+                let _guard = DeferGuard::new(&self.defer_depth);
+                yielder.yield_now(released)
+            })
+        })
+    }
+
+    /// Collect cycles among the next `budget` tracked objects. Resume from
+    /// where the previous window stopped.
+    ///
+    /// This bounds the pause of a single call for large spaces. It is not an
+    /// incremental collection: no mark state is kept across windows. Each
+    /// window is a complete collection of its objects, and objects referred
+    /// by objects outside the window are considered reachable. As a result,
+    /// a window only finds cycles whose objects are all in the window.
+    /// Objects created together are scanned together. Objects can be
+    /// created, changed, or released between windows.
+    ///
+    /// Windows do not replace [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles).
+    /// A cycle with objects created far apart (more than `budget` tracked
+    /// objects in between) is never found by windows, even after a window
+    /// [reaches the end](struct.CollectProgress.html#structfield.reached_end).
+    /// Call `collect_cycles` occasionally, like when idle. It also makes the
+    /// next window start from the beginning.
+    ///
+    /// Between windows, the space keeps a placeholder in the list of tracked
+    /// objects to resume from. It does not keep references to objects. Leak
+    /// detection, relocation and the watchdog are only updated by
+    /// `collect_cycles`.
+    ///
+    /// Panics if `budget` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// for _ in 0..10 {
+    ///     let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    ///     a.borrow_mut().push(Box::new(a.clone()));
+    /// }
+    /// let mut collected = 0;
+    /// loop {
+    ///     let progress = space.collect_window(4);
+    ///     collected += progress.collected;
+    ///     if progress.reached_end {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(collected, 10);
+    /// ```
+    pub fn collect_window(&self, budget: usize) -> CollectProgress {
+        assert!(budget > 0, "budget should be positive");
+        if self.is_deferred() {
+            debug::log(|| ("collect", "deferred"));
+            return CollectProgress::default();
+        }
+        let list: &GcHeader = &self.list.borrow();
+        let cursor: &GcHeader = &self.window_cursor;
+        let start = Instant::now();
+
+        let mut next: *const GcHeader = if cursor.is_untracked() {
+            list.next.get()
+        } else {
+            cursor.next.get()
+        };
+        self.reset_window_cursor();
+
+        // Move objects to a separate list to collect them.
+        let window = new_gc_list(list.events.clone());
+        let mut scanned = 0;
+        while scanned < budget && !std::ptr::eq(next, list) {
+            // safety: The linked list is maintained. Pointers in it are valid.
+            let header = unsafe { &*next };
+            next = header.next.get();
+            // Objects with ref count 0 were dropped. Skip them.
+            if header.value().gc_ref_count() > 0 {
+                header.unlink();
+                // safety: The linked list is maintained. Pointers in it are valid.
+                header.link_after(unsafe { &*window.prev.get() });
+                scanned += 1;
+            }
+        }
+        let reached_end = std::ptr::eq(next, list);
+        let resume: &GcHeader = if reached_end {
+            list
+        } else {
+            // Mark the position until the next window. Unlike `next`, the
+            // cursor is not released by the collection.
+            // safety: The linked list is maintained. Pointers in it are valid.
+            cursor.link_after(unsafe { &*(*next).prev.get() });
+            cursor
+        };
+
+        // Move the remaining objects back before `resume` on drop, including
+        // on panic.
+        let window = WindowList {
+            window,
+            list,
+            resume,
+        };
+        let scope = reclaimed::Scope::begin(false);
+        let collected = if self.resurrection_tolerant.get() {
+            collect_list_resurrectable(&window.window)
+        } else {
+            collect_list(&*window.window, ())
+        };
+        let reclaimed = scope.finish();
+        drop(window);
+
+        self.counters
+            .set(self.counters.get().add(collected, start.elapsed()));
+        self.events.record(|| EventKind::Collect {
+            collected,
+            reclaimed,
+        });
+        CollectProgress {
+            scanned,
+            collected,
+            reached_end,
+        }
+    }
+
+    /// Make the next `collect_window` start from the beginning.
+    fn reset_window_cursor(&self) {
+        self.window_cursor.untrack();
+    }
+
+    /// Release unreachable objects in batches of `batch_size` objects in
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles).
+    /// `None` restores the default mode.
+    ///
+    /// By default, the collector keeps a handle to every unreachable object
+    /// while releasing them. The memory used by the handles is proportional
+    /// to the number of unreachable objects. In the batch mode, unreachable
+    /// objects are moved to a separate linked list instead, and at most
+    /// `batch_size` handles are kept at a time.
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn set_collect_batch_size(&self, batch_size: Option<usize>) {
+        assert_ne!(batch_size, Some(0), "batch size should be positive");
+        self.batch_size.set(batch_size);
+    }
+
+    /// Tolerate objects revived (resurrected) during collection in
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles)
+    /// and [`collect_window`](struct.ObjectSpace.html#method.collect_window).
+    ///
+    /// By default, the collector panics if [`Trace::finalize`] or [`Drop`]
+    /// of a collected object keeps a new reference to another collected
+    /// object. If enabled, similar to Python:
+    ///
+    /// - After finalizing, unreachable objects are scanned again. Objects
+    ///   revived by `finalize`, and objects they refer to, are kept alive.
+    /// - Then `T`s are dropped one by one. Before dropping a `T`, its
+    ///   references to other queued objects are subtracted. A queued object
+    ///   with more references than expected was revived by a previous
+    ///   `Drop`. It is kept alive, together with queued objects it refers
+    ///   to. Its references to objects dropped earlier are unusable.
+    /// - An object revived by `Drop` after its own `T` was dropped cannot
+    ///   be used. It is released when the last reference goes away.
+    ///
+    /// Revived objects are moved back to this space. `finalize` is called
+    /// again if they become unreachable later. The batch size set by
+    /// [`set_collect_batch_size`](struct.ObjectSpace.html#method.set_collect_batch_size)
+    /// is ignored in this mode.
+    ///
+    /// [`Trace::finalize`]: trait.Trace.html#method.finalize
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{Cc, ObjectSpace, Trace, Tracer};
+    /// use std::cell::RefCell;
+    ///
+    /// thread_local!(static STASH: RefCell<Vec<Cc<Node>>> = RefCell::new(Vec::new()));
+    ///
+    /// struct Node(RefCell<Option<Cc<Node>>>);
+    /// impl Trace for Node {
+    ///     fn trace(&self, tracer: &mut Tracer) {
+    ///         self.0.trace(tracer);
+    ///     }
+    ///     fn finalize(&self) {
+    ///         // Resurrect the object pointed by this node.
+    ///         let next = self.0.borrow().clone();
+    ///         STASH.with(|s| s.borrow_mut().extend(next));
+    ///     }
+    /// }
+    ///
+    /// let space = ObjectSpace::default();
+    /// space.set_resurrection_tolerant(true);
+    /// let a = space.create(Node(RefCell::new(None)));
+    /// *a.0.borrow_mut() = Some(a.clone());
+    /// drop(a);
+    /// assert_eq!(space.collect_cycles(), 0);
+    /// assert_eq!(space.count_tracked(), 1);
+    ///
+    /// // Break the cycle explicitly.
+    /// let a = STASH.with(|s| s.borrow_mut().pop()).unwrap();
+    /// a.0.borrow_mut().take();
+    /// drop(a);
+    /// assert_eq!(space.count_tracked(), 0);
+    /// ```
+    pub fn set_resurrection_tolerant(&self, tolerant: bool) {
+        self.resurrection_tolerant.set(tolerant);
+    }
+
+    /// Call [`leak`](struct.ObjectSpace.html#method.leak) instead of
+    /// collecting cycles when this [`ObjectSpace`](struct.ObjectSpace.html)
+    /// is dropped.
+    ///
+    /// By default, dropping a space collects all its cycles, including
+    /// frozen ones. That can be slow for a large object graph, or unsafe,
+    /// ex. in a forked child process where `Drop` implementations might
+    /// touch resources owned by the parent. Leaked objects are not dropped
+    /// unless their reference counts reach 0.
+    ///
+    /// For the thread-local space, this takes precedence over
+    /// [`set_exit_behavior`](fn.set_exit_behavior.html) if set to `true`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// space.set_leak_on_drop(true);
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// let weak = a.downgrade();
+    /// drop((a, space));
+    /// assert!(weak.upgrade().is_some());
+    /// ```
+    pub fn set_leak_on_drop(&self, leak: bool) {
+        self.leak_on_drop.set(leak);
+    }
+
+    /// Stop tracking objects in this [`ObjectSpace`](struct.ObjectSpace.html).
+    ///
+    /// Cycles among the existing objects are never collected. Objects are
+    /// still dropped when their reference counts reach 0. This is useful to
+    /// skip collection at shutdown. Objects created afterwards are tracked
+    /// as usual.
+    ///
+    /// Configuration, including the leak detector, watchdog, relocation
+    /// hooks, pressure handler, batch size, resurrection tolerance, leak on
+    /// drop and event capacity, is kept. Collection counters keep counting.
+    /// Use [`reset_stats`](struct.ObjectSpace.html#method.reset_stats) to
+    /// reset them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// space.leak();
+    /// assert!(!space.contains(&a));
+    /// drop(a);
+    /// assert_eq!(space.count_tracked(), 0);
+    /// assert_eq!(space.collect_cycles(), 0);
+    /// ```
+    pub fn leak(&self) {
+        self.reset_window_cursor();
+        let list = mem::replace(
+            &mut *self.list.borrow_mut(),
+            new_gc_list(self.events.clone()),
+        );
+        let frozen = mem::replace(
+            &mut *self.frozen.borrow_mut(),
+            new_gc_list(self.events.clone()),
+        );
+        // Leaked objects are still linked to the old lists. Keep them alive.
+        mem::forget((list, frozen));
+        // Leaked objects keep the old state, so `contains` returns false,
+        // and they are not counted by `bytes_allocated`.
+        let allocator = self.state.borrow().allocator.clone();
+        let new_state = SpaceState::new(allocator, &self.list.borrow());
+        let state = mem::replace(&mut *self.state.borrow_mut(), new_state);
+        for state in self.merged.take().into_iter().chain(Some(state)) {
+            state.owner.set(0);
+            SpaceState::orphan(state);
+        }
+    }
+
+    /// Move objects tracked by `other` to this
+    /// [`ObjectSpace`](struct.ObjectSpace.html).
+    ///
+    /// This is useful to create objects in a temporary space, collect
+    /// cycles there, then keep the survivors in a long-lived space. After
+    /// merging, cycles between objects from both spaces can be collected
+    /// by this space. The linked list of `other` is spliced into this
+    /// space in `O(1)`. Objects are not copied or visited. Existing `Cc<T>`s
+    /// stay valid.
+    ///
+    /// Configuration and counters of `other` are discarded. With the
+    /// `events` feature, drops of the moved objects are still recorded to
+    /// the event log of `other`, which is no longer accessible.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let temp = ObjectSpace::default();
+    /// let a = temp.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let b = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// temp.collect_cycles();
+    /// space.merge(temp);
+    /// assert!(space.contains(&a));
+    ///
+    /// a.borrow_mut().push(Box::new(b.clone()));
+    /// b.borrow_mut().push(Box::new(a.clone()));
+    /// drop((a, b));
+    /// assert_eq!(space.collect_cycles(), 2);
+    /// ```
+    pub fn merge(&self, other: ObjectSpace) {
+        other.reset_window_cursor();
+        let list: &GcHeader = &self.list.borrow();
+        let other_list: &GcHeader = &other.list.borrow();
+        list.splice_all(other_list);
+        let frozen: &GcHeader = &self.frozen.borrow();
+        let other_frozen: &GcHeader = &other.frozen.borrow();
+        frozen.splice_all(other_frozen);
+        let mut merged = self.merged.borrow_mut();
+        merged.push(other.state.borrow().clone());
+        merged.extend(other.merged.borrow().iter().cloned());
+        let owner = self.state.borrow().id;
+        for state in merged.iter() {
+            state.owner.set(owner);
+            state.list.set(list);
+        }
+        merged.sort_unstable_by_key(|s| s.id);
+    }
+
+    /// Move all objects tracked by this
+    /// [`ObjectSpace`](struct.ObjectSpace.html) to a frozen list, which is
+    /// not scanned by collections until
+    /// [`unfreeze`](struct.ObjectSpace.html#method.unfreeze). This is similar
+    /// to Python's `gc.freeze()`.
+    ///
+    /// This reduces pause times if there is a large long-lived object graph,
+    /// ex. created at startup. Frozen objects are still released when their
+    /// reference counts drop to zero, but cycles among them are not
+    /// collected. Objects only referred by frozen objects are considered
+    /// reachable. Frozen objects are not counted by
+    /// [`count_tracked`](struct.ObjectSpace.html#method.count_tracked) and
+    /// other functions walking tracked objects, but are still counted by
+    /// [`bytes_allocated`](struct.ObjectSpace.html#method.bytes_allocated).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// drop(a);
+    /// space.freeze();
+    /// assert_eq!(space.count_tracked(), 0);
+    /// assert_eq!(space.count_frozen(), 1);
+    /// assert_eq!(space.collect_cycles(), 0);
+    ///
+    /// space.unfreeze();
+    /// assert_eq!(space.collect_cycles(), 1);
+    /// ```
+    pub fn freeze(&self) {
+        // The cursor of `collect_window` should not be frozen.
+        self.reset_window_cursor();
+        let list: &GcHeader = &self.list.borrow();
+        let frozen: &GcHeader = &self.frozen.borrow();
+        frozen.splice_all(list);
+    }
+
+    /// Move objects frozen by
+    /// [`freeze`](struct.ObjectSpace.html#method.freeze) back, so they are
+    /// scanned by collections again.
+    pub fn unfreeze(&self) {
+        let list: &GcHeader = &self.list.borrow();
+        let frozen: &GcHeader = &self.frozen.borrow();
+        list.splice_all(frozen);
+    }
+
+    /// Count objects frozen by
+    /// [`freeze`](struct.ObjectSpace.html#method.freeze).
+    pub fn count_frozen(&self) -> usize {
+        let frozen: &GcHeader = &self.frozen.borrow();
+        count_list(frozen)
+    }
+
+    /// Call `on_start` before, and `on_end` after each collection of this
+    /// [`ObjectSpace`](struct.ObjectSpace.html). Replace hooks set
+    /// previously.
+    ///
+    /// This allows profilers and tracing spans to attribute pause time to
+    /// the collector. Hooks are called by
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles) and
+    /// its variants, but not by
+    /// [`collect_window`](struct.ObjectSpace.html#method.collect_window), or
+    /// collections skipped by
+    /// [`defer_collection`](struct.ObjectSpace.html#method.defer_collection).
+    /// With hooks set, each collection walks the tracked objects before and
+    /// after to calculate the [`CollectStats`](struct.CollectStats.html)
+    /// passed to `on_end`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::{Cell, RefCell};
+    /// use std::rc::Rc;
+    ///
+    /// let space = ObjectSpace::default();
+    /// let collected = Rc::new(Cell::new(0));
+    /// let collected2 = collected.clone();
+    /// space.set_collect_hooks(
+    ///     || println!("collect start"),
+    ///     move |stats| collected2.set(stats.collected),
+    /// );
+    /// let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// drop(a);
+    /// space.collect_cycles();
+    /// assert_eq!(collected.get(), 1);
+    /// ```
+    pub fn set_collect_hooks(
+        &self,
+        on_start: impl Fn() + 'static,
+        on_end: impl Fn(CollectStats) + 'static,
+    ) {
+        let hooks = CollectHooks {
+            on_start: Box::new(on_start),
+            on_end: Box::new(on_end),
+        };
+        *self.collect_hooks.borrow_mut() = Some(Rc::new(hooks));
+    }
+
+    /// Remove hooks set by
+    /// [`set_collect_hooks`](struct.ObjectSpace.html#method.set_collect_hooks).
+    pub fn clear_collect_hooks(&self) {
+        *self.collect_hooks.borrow_mut() = None;
+    }
+
+    /// Call `handler` with the number of objects when it rises to one of
+    /// `watermarks`, as objects are created in this
+    /// [`ObjectSpace`](struct.ObjectSpace.html). If `handler` returns
+    /// `true`, collect cycles right after it. Replace the handler set
+    /// previously.
+    ///
+    /// This is a middle ground between manual and automatic collection:
+    /// collect only when the space grows large, ex. to react to memory
+    /// pressure. Unlike [`count_tracked`](struct.ObjectSpace.html#method.count_tracked),
+    /// the number is maintained as objects are created and released, so
+    /// checking it is cheap. Like
+    /// [`bytes_allocated`](struct.ObjectSpace.html#method.bytes_allocated),
+    /// it includes frozen objects, and dropped objects kept by weak
+    /// references. A watermark is reached again after the number drops
+    /// below it, so pick watermarks above the number of live objects.
+    ///
+    /// Requires the `space_pointer` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// space.set_pressure_handler(vec![100, 1000], |count| {
+    ///     eprintln!("{} objects, collecting", count);
+    ///     true
+    /// });
+    /// for _ in 0..250 {
+    ///     let a = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    ///     a.borrow_mut().push(Box::new(a.clone()));
+    /// }
+    /// assert!(space.count_tracked() < 100);
+    /// ```
+    #[cfg(feature = "space_pointer")]
+    pub fn set_pressure_handler(
+        &self,
+        watermarks: impl IntoIterator<Item = usize>,
+        handler: impl Fn(usize) -> bool + 'static,
+    ) {
+        let mut watermarks: Vec<usize> = watermarks.into_iter().collect();
+        watermarks.sort_unstable();
+        let handler = PressureHandler {
+            watermarks,
+            handler: Box::new(handler),
+        };
+        *self.pressure_handler.borrow_mut() = Some(Rc::new(handler));
+    }
+
+    /// Remove the handler set by
+    /// [`set_pressure_handler`](struct.ObjectSpace.html#method.set_pressure_handler).
+    #[cfg(feature = "space_pointer")]
+    pub fn clear_pressure_handler(&self) {
+        *self.pressure_handler.borrow_mut() = None;
+    }
+
+    /// Called after a tracked object was inserted.
+    #[cfg(feature = "space_pointer")]
+    fn check_pressure(&self) {
+        // Cloned so the handler can replace itself.
+        let pressure = match &*self.pressure_handler.borrow() {
+            Some(pressure) => pressure.clone(),
+            None => return,
+        };
+        let count = self.count_allocated();
+        if pressure.watermarks.binary_search(&count).is_ok() && (pressure.handler)(count) {
+            self.collect_cycles();
+        }
+    }
+
+    /// Number of tracked objects created by this space, or spaces merged
+    /// into it, and not released. Unlike `count_tracked`, this includes
+    /// frozen objects. With the `space_pointer` feature, this does not walk
+    /// the objects, and includes objects removed by `Cc::untrack`.
+    pub(crate) fn count_allocated(&self) -> usize {
+        if !cfg!(feature = "space_pointer") {
+            let mut count = 0;
+            self.visit_allocated(|_| count += 1);
+            return count;
+        }
+        let merged = self.merged.borrow();
+        self.state.borrow().count.get() + merged.iter().map(|s| s.count.get()).sum::<usize>()
+    }
+
+    /// Visit tracked and frozen objects. Used to count objects without the
+    /// `space_pointer` feature.
+    fn visit_allocated(&self, mut func: impl FnMut(&GcHeader)) {
+        let list: &GcHeader = &self.list.borrow();
+        let frozen: &GcHeader = &self.frozen.borrow();
+        visit_list(list, &mut func);
+        visit_list(frozen, &mut func);
+    }
+
+    /// Reset collection counters reported by
+    /// [`thread_stats`](fn.thread_stats.html).
+    pub fn reset_stats(&self) {
+        self.counters.set(Counters::default());
+    }
+
+    /// Make [`Cc::new`](type.Cc.html#method.new) and other constructors
+    /// using the thread-local space create objects in this
+    /// [`ObjectSpace`](struct.ObjectSpace.html) instead, in the current
+    /// thread, until the returned guard is dropped. Guards can be nested.
+    ///
+    /// This confines libraries calling `Cc::new` internally to a space
+    /// controlled by the caller. Functions like
+    /// [`collect_thread_cycles`](fn.collect_thread_cycles.html) still use
+    /// the thread-local space. The guard keeps the space alive, so leaking
+    /// it redirects `Cc::new` for the rest of the thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{Cc, ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// let space = Rc::new(ObjectSpace::default());
+    /// let guard = space.enter();
+    /// let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Cc::new(Default::default());
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// drop(guard);
+    ///
+    /// assert!(space.contains(&a));
+    /// drop(a);
+    /// assert_eq!(gcmodule::collect_thread_cycles(), 0);
+    /// assert_eq!(space.collect_cycles(), 1);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn enter(self: &Rc<Self>) -> SpaceGuard {
+        ENTERED_SPACES.with(|spaces| spaces.borrow_mut().push(self.clone()));
+        ENTERED_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        SpaceGuard {
+            space: self.clone(),
+        }
     }
 
     /// Constructs a new [`Cc<T>`](type.Cc.html) in this
@@ -165,13 +1623,413 @@ impl ObjectSpace {
         Cc::new_in_space(value, self)
     }
 
-    // TODO: Consider implementing "merge" or method to collect multiple spaces
-    // together, to make it easier to support generational collection.
+    /// Constructs a new [`Cc<T>`](type.Cc.html) in this
+    /// [`ObjectSpace`](struct.ObjectSpace.html), while giving `data_fn` a
+    /// [`Weak<T>`](type.Weak.html) to the allocation.
+    ///
+    /// See [`Cc::new_cyclic`](type.Cc.html#method.new_cyclic).
+    pub fn create_cyclic<T: Trace>(&self, data_fn: impl FnOnce(&Weak<T>) -> T) -> Cc<T> {
+        Cc::new_cyclic_in_space(data_fn, self, || ())
+    }
+
+    /// Constructs a new [`Cc<T>`](type.Cc.html) with the default value of
+    /// `T` in this [`ObjectSpace`](struct.ObjectSpace.html).
+    pub fn create_default<T: Trace + Default>(&self) -> Cc<T> {
+        self.create(T::default())
+    }
+
+    /// Run `func` with collection disabled for this
+    /// [`ObjectSpace`](struct.ObjectSpace.html).
+    ///
+    /// This is useful for multi-step mutations that temporarily break the
+    /// invariants [`Trace::trace`](trait.Trace.html#method.trace) relies on.
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles)
+    /// calls within `func` are no-ops. Calls can be nested. Collection is
+    /// enabled again once the outermost `func` returns or panics.
+    pub fn defer_collection<R>(&self, func: impl FnOnce() -> R) -> R {
+        let _guard = DeferGuard::new(&self.defer_depth);
+        func()
+    }
+
+    /// Start recording reference counts of tracked objects after each
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles).
+    ///
+    /// Objects surviving `threshold` collections with reference counts
+    /// that grew but never decreased are reported by
+    /// [`suspected_leaks`](struct.ObjectSpace.html#method.suspected_leaks).
+    /// They are likely kept alive by forgotten clones, which the collector
+    /// cannot free by design.
+    ///
+    /// This has a runtime cost proportional to the number of tracked objects
+    /// per collection. Calling it again resets the recorded history.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// let space = ObjectSpace::default();
+    /// space.enable_leak_detector(2);
+    /// let value = space.create(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+    /// let mut forgotten = Vec::new();
+    /// for _ in 0..3 {
+    ///     forgotten.push(value.clone());
+    ///     space.collect_cycles();
+    /// }
+    /// let leaks = space.suspected_leaks();
+    /// assert_eq!(leaks.len(), 1);
+    /// assert_eq!(leaks[0].ref_count, 4);
+    /// ```
+    pub fn enable_leak_detector(&self, threshold: usize) {
+        *self.leak_detector.borrow_mut() = Some(LeakDetector::new(threshold));
+    }
+
+    /// Stop recording reference counts for leak detection.
+    pub fn disable_leak_detector(&self) {
+        *self.leak_detector.borrow_mut() = None;
+    }
+
+    /// Objects suspected to be leaked by forgotten clones, sorted by
+    /// reference count, highest first.
+    ///
+    /// Returns an empty list if
+    /// [`enable_leak_detector`](struct.ObjectSpace.html#method.enable_leak_detector)
+    /// was not called.
+    pub fn suspected_leaks(&self) -> Vec<SuspectedLeak> {
+        match self.leak_detector.borrow().as_ref() {
+            Some(detector) => detector.suspected_leaks(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Recent events of this [`ObjectSpace`](struct.ObjectSpace.html),
+    /// oldest first.
+    ///
+    /// Creation and release of tracked objects, and collections are
+    /// recorded in a bounded buffer. By default, the last 128 events are
+    /// kept. Untracked objects are not recorded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{GcEventKind, ObjectSpace, Trace};
+    ///
+    /// let space = ObjectSpace::default();
+    /// let value: Box<dyn Trace> = Box::new(1);
+    /// drop(space.create(value));
+    /// space.collect_cycles();
+    /// let kinds: Vec<_> = space.recent_events().into_iter().map(|e| e.kind).collect();
+    /// assert!(matches!(kinds[0], GcEventKind::Create { .. }));
+    /// assert!(matches!(kinds[1], GcEventKind::Drop { .. }));
+    /// assert!(matches!(kinds[2], GcEventKind::Collect { collected: 0, .. }));
+    /// ```
+    #[cfg(feature = "events")]
+    pub fn recent_events(&self) -> Vec<crate::GcEvent> {
+        self.events.to_vec()
+    }
+
+    /// Set how many events are kept for
+    /// [`recent_events`](struct.ObjectSpace.html#method.recent_events).
+    /// Setting it to 0 disables recording.
+    #[cfg(feature = "events")]
+    pub fn set_event_capacity(&self, capacity: usize) {
+        self.events.set_capacity(capacity)
+    }
+
+    /// Call `callback` when the number of tracked objects grew
+    /// monotonically by at least `threshold`, and
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles)
+    /// was not called for at least `duration`.
+    ///
+    /// This catches the bug of never collecting a space. The count is
+    /// sampled every `threshold / 4` created objects, which walks the
+    /// tracked objects. A decreasing sample restarts the growth tracking.
+    /// `callback` is called at most once between collections, while an
+    /// object is being created. Collections inside `callback` are skipped.
+    /// Calling this again replaces the previous watchdog.
+    ///
+    /// This requires the `watchdog` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{ObjectSpace, Trace};
+    /// use std::time::Duration;
+    ///
+    /// let space = ObjectSpace::default();
+    /// space.enable_watchdog(100, Duration::from_secs(0), |warning| {
+    ///     eprintln!("{} objects without collection", warning.tracked);
+    /// });
+    /// let objects: Vec<_> = (0..100)
+    ///     .map(|_| space.create(Box::new(1) as Box<dyn Trace>))
+    ///     .collect();
+    /// ```
+    #[cfg(feature = "watchdog")]
+    pub fn enable_watchdog(
+        &self,
+        threshold: usize,
+        duration: Duration,
+        callback: impl Fn(&crate::WatchdogWarning) + 'static,
+    ) {
+        let tracked = self.count_tracked();
+        let watchdog = Watchdog::new(threshold, duration, Rc::new(callback), tracked);
+        *self.watchdog.borrow_mut() = Some(watchdog);
+    }
+
+    /// Stop the watchdog enabled by
+    /// [`enable_watchdog`](struct.ObjectSpace.html#method.enable_watchdog).
+    #[cfg(feature = "watchdog")]
+    pub fn disable_watchdog(&self) {
+        *self.watchdog.borrow_mut() = None;
+    }
+
+    /// Called after a tracked object was inserted to `list`.
+    #[cfg(feature = "watchdog")]
+    fn check_watchdog(&self, list: &GcHeader) {
+        let warning = match self.watchdog.try_borrow_mut() {
+            Ok(mut watchdog) => watchdog.as_mut().and_then(|watchdog| {
+                if watchdog.on_create() {
+                    watchdog.sample(count_list(list))
+                } else {
+                    None
+                }
+            }),
+            // Objects created by the callback.
+            Err(_) => None,
+        };
+        if let Some((callback, warning)) = warning {
+            // The object being created is not fully initialized.
+            self.defer_collection(|| callback(&warning));
+        }
+    }
+
+    /// Replace values of [`Relocatable<T>`](struct.Relocatable.html)
+    /// objects in this space with forwarded handles to equivalent values.
+    ///
+    /// `try_merge(a, b)` decides whether `b` can be replaced by `a`. Each
+    /// value is compared with values kept so far, so this is `O(n^2)` in
+    /// the worst case. Older objects are kept. Objects being accessed are
+    /// skipped. Return the number of objects forwarded.
+    ///
+    /// This is experimental. It requires the `relocation` feature.
+    #[cfg(feature = "relocation")]
+    pub fn relocate<T: Trace>(&self, try_merge: impl Fn(&T, &T) -> bool) -> usize {
+        let list: &GcHeader = &self.list.borrow();
+        crate::relocate::relocate_list(list, &try_merge)
+    }
+
+    /// Run [`relocate`](struct.ObjectSpace.html#method.relocate) with
+    /// `try_merge` after each
+    /// [`collect_cycles`](struct.ObjectSpace.html#method.collect_cycles).
+    ///
+    /// This is experimental. It requires the `relocation` feature.
+    #[cfg(feature = "relocation")]
+    pub fn add_relocation_hook<T: Trace>(&self, try_merge: fn(&T, &T) -> bool) {
+        let hook = move |list: &GcHeader| crate::relocate::relocate_list(list, &try_merge);
+        self.relocation_hooks.borrow_mut().push(Box::new(hook));
+    }
 }
 
 impl Drop for ObjectSpace {
     fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        let exit_leak = self.is_thread_space.get()
+            && EXIT_BEHAVIOR_LEAK.load(std::sync::atomic::Ordering::Acquire);
+        #[cfg(not(feature = "std"))]
+        let exit_leak = false;
+        if self.leak_on_drop.get() || exit_leak {
+            self.leak();
+            return;
+        }
+        self.reset_window_cursor();
+        self.unfreeze();
         self.collect_cycles();
+        // States moved to another space by `merge` are owned by that space.
+        let id = self.state.borrow().id;
+        for state in self
+            .merged
+            .take()
+            .into_iter()
+            .chain(Some(self.state.borrow().clone()))
+        {
+            if state.owner.get() == id {
+                SpaceState::orphan(state);
+            }
+        }
+    }
+}
+
+/// Cumulative collection counters of a space.
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) struct Counters {
+    pub(crate) collections: usize,
+    pub(crate) collected: usize,
+    pub(crate) last_collected: usize,
+    pub(crate) last_duration: Duration,
+}
+
+impl Counters {
+    /// Count a collection.
+    pub(crate) fn add(self, collected: usize, duration: Duration) -> Self {
+        Self {
+            collections: self.collections + 1,
+            collected: self.collected + collected,
+            last_collected: collected,
+            last_duration: duration,
+        }
+    }
+}
+
+/// Garbage collection statistics of the current thread.
+///
+/// Returned by [`thread_stats`](fn.thread_stats.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThreadGcStats {
+    /// Number of objects tracked by the collector, including zombies.
+    pub tracked: usize,
+
+    /// Number of tracked objects whose values were dropped, while their
+    /// allocations are kept alive by weak references. They are released
+    /// with the last weak reference. Weak references to them are
+    /// [dangling](struct.RawWeak.html#method.is_dangling).
+    pub zombies: usize,
+
+    /// Bytes used by tracked objects, including the `GcHeader`s. Memory
+    /// owned indirectly (ex. by a `Vec`) is not included.
+    pub tracked_bytes: usize,
+
+    /// Number of collections.
+    pub collections: usize,
+
+    /// Number of objects collected by all collections.
+    pub collected: usize,
+
+    /// Number of objects collected by the last collection.
+    pub last_collected: usize,
+
+    /// Time spent by the last collection.
+    pub last_duration: Duration,
+}
+
+/// Called by
+/// [`ObjectSpace::collect_cycles_yielding`](struct.ObjectSpace.html#method.collect_cycles_yielding)
+/// between batches of released objects.
+///
+/// Implemented for `FnMut(usize)`.
+pub trait CollectYield {
+    /// Give the host a chance to run other tasks. `released` is the number
+    /// of unreachable objects released so far.
+    fn yield_now(&mut self, released: usize);
+}
+
+impl<F: FnMut(usize)> CollectYield for F {
+    fn yield_now(&mut self, released: usize) {
+        self(released)
+    }
+}
+
+/// Result of a bounded collection. Returned by
+/// [`ObjectSpace::collect_window`](struct.ObjectSpace.html#method.collect_window).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollectProgress {
+    /// Number of objects scanned by the window.
+    pub scanned: usize,
+
+    /// Number of unreachable objects collected by the window.
+    pub collected: usize,
+
+    /// Whether the window reached the end of the tracked objects. The next
+    /// window starts from the beginning.
+    ///
+    /// This does not mean all cyclic garbage was collected. Cycles whose
+    /// objects were scanned by different windows are not found by windows.
+    /// Use [`ObjectSpace::collect_cycles`](struct.ObjectSpace.html#method.collect_cycles)
+    /// for them.
+    pub reached_end: bool,
+}
+
+/// Statistics of a collection.
+///
+/// Returned by
+/// [`ObjectSpace::collect_cycles_with_stats`](struct.ObjectSpace.html#method.collect_cycles_with_stats)
+/// and [`collect_thread_cycles_with_stats`](fn.collect_thread_cycles_with_stats.html).
+/// Passed to hooks set by
+/// [`ObjectSpace::set_collect_hooks`](struct.ObjectSpace.html#method.set_collect_hooks).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollectStats {
+    /// Number of tracked objects scanned by the collection.
+    pub scanned: usize,
+
+    /// Number of unreachable objects collected.
+    pub collected: usize,
+
+    /// Bytes of tracked objects released, including the `GcHeader`s.
+    /// Memory owned indirectly (ex. by a `Vec`) is not included.
+    ///
+    /// This is the difference of tracked bytes before and after the
+    /// collection. Objects created by `Drop` during the collection reduce it.
+    pub released_bytes: usize,
+
+    /// Bytes of tracked objects after the collection, including the
+    /// `GcHeader`s. See
+    /// [`ObjectSpace::bytes_allocated`](struct.ObjectSpace.html#method.bytes_allocated).
+    pub bytes_allocated: usize,
+
+    /// Time spent by the collection.
+    pub duration: Duration,
+
+    /// Number of objects released per type, most released first. Objects
+    /// released by `Drop` of collected objects are included. Empty without
+    /// the `std` feature.
+    pub reclaimed: Vec<ReclaimedType>,
+}
+
+/// Creation metadata of a tracked object. Returned by
+/// [`ObjectSpace::tracked_info`](struct.ObjectSpace.html#method.tracked_info).
+/// Requires the `age` feature.
+#[cfg(feature = "age")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedInfo {
+    /// [Sequence id](type.Cc.html#method.sequence_id) of the object.
+    pub sequence_id: u64,
+
+    /// When the object was created.
+    pub created: Instant,
+
+    /// Type name of the object.
+    pub type_name: &'static str,
+
+    /// Size of the allocation, including the `GcHeader`.
+    pub size: usize,
+
+    /// Strong reference count. 0 means the value was dropped, and the
+    /// object is kept alive by weak references.
+    pub ref_count: usize,
+}
+
+#[cfg(feature = "age")]
+impl TrackedInfo {
+    /// Time elapsed since the object was created.
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+}
+
+/// Increases the defer depth. Decreases it on drop, including on panic.
+struct DeferGuard<'a>(&'a Cell<usize>);
+
+impl<'a> DeferGuard<'a> {
+    fn new(depth: &'a Cell<usize>) -> Self {
+        depth.set(depth.get() + 1);
+        Self(depth)
+    }
+}
+
+impl<'a> Drop for DeferGuard<'a> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
     }
 }
 
@@ -182,6 +2040,31 @@ pub trait Linked {
 
     /// Get the trait object to operate on the actual `CcBox`.
     fn value(&self) -> &dyn CcDyn;
+
+    /// Test whether `other` belongs to a different space. Used to report
+    /// references across spaces, which keep objects alive and leak cycles.
+    #[cfg(feature = "debug")]
+    fn is_other_space(&self, _other: &Self) -> bool {
+        false
+    }
+
+    /// Whether this marks a position in the list, like the cursor of
+    /// `collect_window`, instead of an object. Skipped by `visit_list`.
+    fn is_placeholder(&self) -> bool {
+        false
+    }
+
+    /// Key to sort objects before dropping them. Objects with the same key
+    /// are dropped in the list order.
+    #[cfg(feature = "ordered_drop")]
+    fn drop_order(&self) -> u64 {
+        0
+    }
+
+    /// Whether `EphemeronMap`s might have keys of this type. If so, the
+    /// collection steps call the `ephemeron` module.
+    #[cfg(feature = "std")]
+    const EPHEMERONS: bool = false;
 }
 
 /// Internal metadata used by the cycle collector.
@@ -192,9 +2075,28 @@ pub struct GcHeader {
 
     /// Vtable of (`&CcBox<T> as &dyn CcDyn`)
     pub(crate) ccdyn_vptr: *const (),
+
+    /// Event log of the space. Zero-sized without the `events` feature.
+    pub(crate) events: LocalEvents,
+
+    /// State of the `ObjectSpace` creating the object. Null for list heads.
+    /// Valid while the object is allocated. See `SpaceState::count`.
+    #[cfg(feature = "space_pointer")]
+    pub(crate) space: *const SpaceState,
+
+    /// Sequence id assigned at creation. 0 for list heads.
+    #[cfg(feature = "seqid")]
+    pub(crate) seq: u64,
+
+    /// Creation time.
+    #[cfg(feature = "age")]
+    pub(crate) created: Instant,
 }
 
 impl Linked for GcHeader {
+    #[cfg(feature = "std")]
+    const EPHEMERONS: bool = true;
+
     #[inline]
     fn next(&self) -> *const Self {
         self.next.get()
@@ -212,20 +2114,203 @@ impl Linked for GcHeader {
         // safety: To build trait object from self and vtable pointer.
         // Test by test_gc_header_value_consistency().
         unsafe {
-            let fat_ptr: (*const (), *const ()) =
-                ((self as *const Self).offset(1) as _, self.ccdyn_vptr);
-            mem::transmute(fat_ptr)
+            let fat_ptr: (*const (), *const ()) =
+                ((self as *const Self).offset(1) as _, self.ccdyn_vptr);
+            mem::transmute(fat_ptr)
+        }
+    }
+    #[inline]
+    fn is_placeholder(&self) -> bool {
+        self.ccdyn_vptr.is_null()
+    }
+    #[cfg(feature = "debug")]
+    fn is_other_space(&self, other: &Self) -> bool {
+        match (self.state(), other.state()) {
+            // Leaked objects are not collected by any space.
+            (Some(a), Some(b)) => b.owner.get() != 0 && a.owner.get() != b.owner.get(),
+            _ => false,
+        }
+    }
+    #[cfg(feature = "ordered_drop")]
+    fn drop_order(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl GcHeader {
+    /// Create an empty header.
+    #[cfg_attr(not(feature = "space_pointer"), allow(unused_variables))]
+    pub(crate) fn empty(events: LocalEvents, space: *const SpaceState) -> Self {
+        Self {
+            next: Cell::new(std::ptr::null()),
+            prev: Cell::new(std::ptr::null()),
+            ccdyn_vptr: CcDummy::ccdyn_vptr(),
+            events,
+            #[cfg(feature = "space_pointer")]
+            space,
+            #[cfg(feature = "seqid")]
+            seq: 0,
+            #[cfg(feature = "age")]
+            created: Instant::now(),
+        }
+    }
+
+    /// Create a header for a new object in the same space as the object of
+    /// this header. The new object is inserted by `insert_after` this
+    /// header, so it is in the same linked list, even after `merge`.
+    #[cfg(feature = "std")]
+    pub(crate) fn sibling(&self) -> Self {
+        Self::empty(self.events.clone(), self.space_ptr())
+    }
+
+    /// State of the space creating the object. Null for list heads, and
+    /// without the `space_pointer` feature.
+    fn space_ptr(&self) -> *const SpaceState {
+        #[cfg(feature = "space_pointer")]
+        return self.space;
+        #[cfg(not(feature = "space_pointer"))]
+        std::ptr::null()
+    }
+
+    /// State of the space creating the object. `None` for list heads, and
+    /// without the `space_pointer` feature.
+    fn state(&self) -> Option<&SpaceState> {
+        // safety: The state is alive while objects pointing to it are
+        // allocated, and list heads have null states.
+        unsafe { self.space_ptr().as_ref() }
+    }
+
+    /// Allocate memory for the object of this header, which is not
+    /// inserted yet, using the allocator of its space.
+    pub(crate) fn alloc(&self, layout: Layout) -> NonNull<u8> {
+        match self.state().and_then(|s| s.allocator.as_ref()) {
+            // safety: Tracked objects have non-zero sizes.
+            Some(allocator) => match NonNull::new(unsafe { allocator.alloc(layout) }) {
+                Some(ptr) => ptr,
+                None => handle_alloc_error(layout),
+            },
+            None => alloc_global(layout),
+        }
+    }
+
+    /// Insert a new object to a linked list after `prev`. `value` is the
+    /// object of this header.
+    pub(crate) fn insert_after(&mut self, prev: &GcHeader, value: &dyn CcDyn) {
+        debug_assert!(self.next.get().is_null());
+        #[cfg(feature = "seqid")]
+        {
+            self.seq = next_seq();
+        }
+        let next = prev.next.get();
+        self.prev.set(prev);
+        self.next.set(next);
+        unsafe {
+            // safety: The linked list is maintained, and pointers are valid.
+            (&*next).prev.set(self);
+            // safety: To access vtable pointer. Test by test_gc_header_value.
+            let fat_ptr: [*mut (); 2] = mem::transmute(value);
+            self.ccdyn_vptr = fat_ptr[1];
+        }
+        prev.next.set(self);
+        if let Some(space) = self.state() {
+            space.bytes.set(space.bytes.get() + value.gc_alloc_size());
+            space.count.set(space.count.get() + 1);
+        }
+        self.events.record(|| EventKind::Create {
+            type_name: value.gc_type_name(),
+        });
+    }
+
+    /// Remove from the current linked list. Unlike `remove`, this does not
+    /// record events, and is used to move objects between lists.
+    fn unlink(&self) {
+        let next = self.next.get();
+        let prev = self.prev.get();
+        // safety: The linked list is maintained. Pointers in it are valid.
+        unsafe {
+            (*prev).next.set(next);
+            (*next).prev.set(prev);
+        }
+    }
+
+    /// Insert after `list`, the dummy head of a linked list.
+    fn link_after(&self, list: &GcHeader) {
+        let next = list.next.get();
+        self.prev.set(list);
+        self.next.set(next);
+        // safety: The linked list is maintained. Pointers in it are valid.
+        unsafe { (*next).prev.set(self) };
+        list.next.set(self);
+    }
+
+    /// Move all objects from the `other` list to the front of this list in
+    /// `O(1)`. Unlike `take_all`, the order of moved objects is kept.
+    fn splice_all(&self, other: &GcHeader) {
+        let first = other.next.get();
+        if std::ptr::eq(first, other) {
+            return;
+        }
+        let last = other.prev.get();
+        let next = self.next.get();
+        // safety: The linked lists are maintained. Pointers in them are valid.
+        unsafe {
+            (*first).prev.set(self);
+            (*last).next.set(next);
+            (*next).prev.set(last);
         }
+        self.next.set(first);
+        other.next.set(other);
+        other.prev.set(other);
     }
-}
 
-impl GcHeader {
-    /// Create an empty header.
-    pub(crate) fn empty() -> Self {
-        Self {
-            next: Cell::new(std::ptr::null()),
-            prev: Cell::new(std::ptr::null()),
-            ccdyn_vptr: CcDummy::ccdyn_vptr(),
+    /// Remove from the current linked list, and link to itself so `remove`
+    /// still works. Used by `Cc::untrack`.
+    pub(crate) fn untrack(&self) {
+        if !self.is_untracked() {
+            self.unlink();
+            self.prev.set(self);
+            self.next.set(self);
+        }
+    }
+
+    /// Insert the object removed by `untrack` back to the linked list of its
+    /// space. Return `false` if the space was dropped or leaked.
+    #[cfg(feature = "space_pointer")]
+    pub(crate) fn retrack(&self) -> bool {
+        if !self.is_untracked() {
+            return true;
+        }
+        match self.space_list() {
+            Some(list) => {
+                self.link_after(list);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The list head of the space creating the object. `None` if the space
+    /// was dropped or leaked, and without the `space_pointer` feature.
+    #[cfg(any(feature = "std", feature = "space_pointer"))]
+    pub(crate) fn space_list(&self) -> Option<&GcHeader> {
+        let list = self.state()?.list.get();
+        // safety: The list head is alive until the space is dropped or
+        // leaked, which resets `list` to null.
+        unsafe { list.as_ref() }
+    }
+
+    /// Whether the object was removed from linked lists by `untrack`.
+    pub(crate) fn is_untracked(&self) -> bool {
+        std::ptr::eq(self.next.get(), self)
+    }
+
+    /// Move all objects from the `other` list to this list.
+    fn take_all(&self, other: &GcHeader) {
+        while !std::ptr::eq(other.next.get(), other) {
+            // safety: The linked list is maintained. Pointers in it are valid.
+            let header = unsafe { &*other.next.get() };
+            header.unlink();
+            header.link_after(self);
         }
     }
 }
@@ -233,55 +2318,290 @@ impl GcHeader {
 /// Collect cyclic garbage in the current thread created by
 /// [`Cc::new`](type.Cc.html#method.new).
 /// Return the number of objects collected.
+#[cfg(feature = "std")]
 pub fn collect_thread_cycles() -> usize {
     debug::log(|| ("collect", "collect_thread_cycles"));
     THREAD_OBJECT_SPACE.with(|list| list.collect_cycles())
 }
 
+/// Like [`collect_thread_cycles`](fn.collect_thread_cycles.html), but
+/// return statistics of the collection. See
+/// [`ObjectSpace::collect_cycles_with_stats`](struct.ObjectSpace.html#method.collect_cycles_with_stats).
+#[cfg(feature = "std")]
+pub fn collect_thread_cycles_with_stats() -> CollectStats {
+    debug::log(|| ("collect", "collect_thread_cycles"));
+    THREAD_OBJECT_SPACE.with(|list| list.collect_cycles_with_stats())
+}
+
 /// Count number of objects tracked by the collector in the current thread
 /// created by [`Cc::new`](type.Cc.html#method.new).
 /// Return the number of objects tracked.
+#[cfg(feature = "std")]
 pub fn count_thread_tracked() -> usize {
     THREAD_OBJECT_SPACE.with(|list| list.count_tracked())
 }
 
-thread_local!(pub(crate) static THREAD_OBJECT_SPACE: ObjectSpace = ObjectSpace::default());
+/// Run `func` with collection disabled for the current thread's objects
+/// created by [`Cc::new`](type.Cc.html#method.new).
+///
+/// [`collect_thread_cycles`](fn.collect_thread_cycles.html) calls within
+/// `func` are no-ops and return 0.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// gcmodule::defer_collection(|| {
+///     let a: List = Default::default();
+///     a.borrow_mut().push(Box::new(a.clone()));
+///     drop(a);
+///     assert_eq!(gcmodule::collect_thread_cycles(), 0); // Deferred.
+/// });
+/// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+/// ```
+#[cfg(feature = "std")]
+pub fn defer_collection<R>(func: impl FnOnce() -> R) -> R {
+    THREAD_OBJECT_SPACE.with(|space| space.defer_collection(func))
+}
+
+/// Get garbage collection statistics about objects created by
+/// [`Cc::new`](type.Cc.html#method.new) in the current thread.
+///
+/// This walks all tracked objects. The time complexity is `O(tracked)`.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, Trace};
+///
+/// let value: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+/// gcmodule::collect_thread_cycles();
+/// let stats = gcmodule::thread_stats();
+/// assert_eq!(stats.tracked, 1);
+/// assert!(stats.tracked_bytes > 0);
+/// assert_eq!(stats.collections, 1);
+/// ```
+#[cfg(feature = "std")]
+pub fn thread_stats() -> ThreadGcStats {
+    THREAD_OBJECT_SPACE.with(|space| space.stats())
+}
+
+/// Run `func` with the [`ObjectSpace`](struct.ObjectSpace.html) used by
+/// [`Cc::new`](type.Cc.html#method.new) in the current thread.
+///
+/// `space.create(value)` is equivalent to `Cc::new(value)`.
+#[cfg(feature = "std")]
+pub fn with_thread_object_space<R>(func: impl FnOnce(&ObjectSpace) -> R) -> R {
+    THREAD_OBJECT_SPACE.with(func)
+}
+
+#[cfg(feature = "std")]
+thread_local!(pub(crate) static THREAD_OBJECT_SPACE: ObjectSpace = {
+    let space = ObjectSpace::default();
+    space.is_thread_space.set(true);
+    space
+});
+
+/// What to do with objects in the thread-local space when a thread exits.
+/// See [`set_exit_behavior`](fn.set_exit_behavior.html).
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitBehavior {
+    /// Collect cycles, so `Drop` of objects in cycles runs. This is the
+    /// default.
+    #[default]
+    Collect,
+
+    /// Leak the objects like
+    /// [`ObjectSpace::leak`](struct.ObjectSpace.html#method.leak). Objects
+    /// in cycles are not dropped.
+    Leak,
+}
+
+#[cfg(feature = "std")]
+static EXIT_BEHAVIOR_LEAK: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+/// Set what to do with objects created by
+/// [`Cc::new`](type.Cc.html#method.new) when a thread exits, for all
+/// threads in the process.
+///
+/// Collecting cycles when a thread exits can be slow, and is often
+/// unnecessary for command line applications that are about to exit
+/// anyway. In a forked child process, `Drop` implementations might also
+/// touch resources owned by the parent. Use
+/// [`ExitBehavior::Leak`](enum.ExitBehavior.html#variant.Leak) to skip it.
+/// [`ObjectSpace::set_leak_on_drop`](struct.ObjectSpace.html#method.set_leak_on_drop)
+/// can be used to leak the space of a single thread.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, ExitBehavior, Trace};
+/// use std::cell::RefCell;
+/// use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+///
+/// static DROPPED: AtomicBool = AtomicBool::new(false);
+/// struct SetOnDrop;
+/// impl Trace for SetOnDrop {}
+/// impl Drop for SetOnDrop {
+///     fn drop(&mut self) {
+///         DROPPED.store(true, SeqCst);
+///     }
+/// }
+///
+/// gcmodule::set_exit_behavior(ExitBehavior::Leak);
+/// std::thread::spawn(|| {
+///     let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+///     a.borrow_mut().push(Box::new(a.clone()));
+///     a.borrow_mut().push(Box::new(SetOnDrop));
+/// })
+/// .join()
+/// .unwrap();
+/// assert!(!DROPPED.load(SeqCst));
+/// ```
+#[cfg(feature = "std")]
+pub fn set_exit_behavior(behavior: ExitBehavior) {
+    let leak = behavior == ExitBehavior::Leak;
+    EXIT_BEHAVIOR_LEAK.store(leak, std::sync::atomic::Ordering::Release);
+}
+
+/// Guard returned by [`ObjectSpace::enter`](struct.ObjectSpace.html#method.enter).
+/// [`Cc::new`](type.Cc.html#method.new) stops using the space when it is
+/// dropped.
+#[cfg(feature = "std")]
+pub struct SpaceGuard {
+    space: Rc<ObjectSpace>,
+}
+
+#[cfg(feature = "std")]
+impl Drop for SpaceGuard {
+    fn drop(&mut self) {
+        // Guards might be dropped out of order. Remove the last entry of
+        // this space.
+        let _ = ENTERED_SPACES.try_with(|spaces| {
+            let mut spaces = spaces.borrow_mut();
+            if let Some(i) = spaces.iter().rposition(|s| Rc::ptr_eq(s, &self.space)) {
+                spaces.remove(i);
+                let _ = ENTERED_DEPTH.try_with(|depth| depth.set(depth.get() - 1));
+            }
+        });
+    }
+}
+
+// Spaces entered by `ObjectSpace::enter`. The last one is used by
+// `Cc::new`.
+#[cfg(feature = "std")]
+thread_local!(static ENTERED_SPACES: RefCell<Vec<Rc<ObjectSpace>>> = const { RefCell::new(Vec::new()) });
+
+// Length of `ENTERED_SPACES`. Checked first so `Cc::new` outside entered
+// spaces does not borrow or clone.
+#[cfg(feature = "std")]
+thread_local!(static ENTERED_DEPTH: Cell<usize> = const { Cell::new(0) });
+
+/// Run `func` with the space used by `Cc::new` in the current thread: the
+/// last space entered by `ObjectSpace::enter`, or the thread-local space.
+#[cfg(feature = "std")]
+pub(crate) fn with_current_space<R>(func: impl FnOnce(&ObjectSpace) -> R) -> R {
+    if ENTERED_DEPTH.with(Cell::get) == 0 {
+        return THREAD_OBJECT_SPACE.with(func);
+    }
+    // Cloned so `func` can enter or exit spaces.
+    match ENTERED_SPACES.with(|spaces| spaces.borrow().last().cloned()) {
+        Some(space) => func(&space),
+        None => THREAD_OBJECT_SPACE.with(func),
+    }
+}
+
+/// Next `ObjectSpace` id. Ids are not reused, so a header cannot match a
+/// space created after its space is dropped.
+static NEXT_SPACE_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(1);
+
+fn next_space_id() -> usize {
+    NEXT_SPACE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Next sequence id. Shared by all spaces so ids are unique in a process.
+#[cfg(feature = "seqid")]
+static NEXT_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+#[cfg(feature = "seqid")]
+fn next_seq() -> u64 {
+    NEXT_SEQ.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Without `std`, there is no clock. Durations in statistics are zero.
+#[cfg(not(feature = "std"))]
+struct Instant;
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    fn now() -> Self {
+        Instant
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
 
 /// Create an empty linked list with a dummy GcHeader.
-pub(crate) fn new_gc_list() -> Pin<Box<GcHeader>> {
-    let pinned = Box::pin(GcHeader::empty());
+pub(crate) fn new_gc_list(events: LocalEvents) -> Pin<Box<GcHeader>> {
+    let pinned = Box::pin(GcHeader::empty(events, std::ptr::null()));
     let header: &GcHeader = pinned.deref();
     header.prev.set(header);
     header.next.set(header);
     pinned
 }
 
+/// Create a placeholder to mark a position in a linked list. It has no
+/// value. It is not linked to a list yet.
+fn new_gc_cursor(events: LocalEvents) -> Pin<Box<GcHeader>> {
+    let mut pinned = new_gc_list(events);
+    pinned.ccdyn_vptr = std::ptr::null();
+    pinned
+}
+
 /// Scan the specified linked list. Collect cycles.
 pub(crate) fn collect_list<L: Linked, K>(list: &L, lock: K) -> usize {
+    profile::begin();
     update_refs(list);
     subtract_refs(list);
+    mark_reachable(list);
     release_unreachable(list, lock)
 }
 
+/// Count objects in a linked list.
+pub(crate) fn count_list<L: Linked>(list: &L) -> usize {
+    let mut count = 0;
+    visit_list(list, |_| count += 1);
+    count
+}
+
 /// Visit the linked list.
 pub(crate) fn visit_list<'a, L: Linked>(list: &'a L, mut func: impl FnMut(&'a L)) {
     // Skip the first dummy entry.
     let mut ptr = list.next();
-    while ptr as *const _ != list as *const _ {
+    while !std::ptr::eq(ptr, list) {
         // The linked list is maintained so the pointer is valid.
         let header: &L = unsafe { &*ptr };
         ptr = header.next();
-        func(header);
+        if !header.is_placeholder() {
+            func(header);
+        }
     }
 }
 
-const PREV_MASK_COLLECTING: usize = 1;
-const PREV_MASK_VISITED: usize = 2;
-const PREV_SHIFT: u32 = 2;
+pub(crate) const PREV_MASK_COLLECTING: usize = 1;
+pub(crate) const PREV_MASK_VISITED: usize = 2;
+pub(crate) const PREV_SHIFT: u32 = 2;
 
 /// Temporarily use `GcHeader.prev` as `gc_ref_count`.
 /// Idea comes from https://bugs.python.org/issue33597.
-fn update_refs<L: Linked>(list: &L) {
+pub(crate) fn update_refs<L: Linked>(list: &L) {
     visit_list(list, |header| {
         let ref_count = header.value().gc_ref_count();
         // It's possible that the ref_count becomes 0 in a multi-thread context:
@@ -307,55 +2627,80 @@ fn update_refs<L: Linked>(list: &L) {
 /// to 0. If vertexes in a connected component _all_ have ref count 0,
 /// they are unreachable and can be released.
 fn subtract_refs<L: Linked>(list: &L) {
-    let mut tracer = |header: *const ()| {
-        // safety: The type is known to be GcHeader.
-        let header = unsafe { &*(header as *const L) };
+    visit_list(list, |header| {
+        // Objects not marked by `update_refs` have ref count 0. Their values
+        // might be dropped, ex. kept alive by weak references.
         if is_collecting(header) {
-            debug_assert!(
-                !is_unreachable(header),
-                "bug: object {} becomes unreachable while trying to dec_ref (is Trace impl correct?)",
-                debug_name(header)
-            );
-            edit_gc_ref_count(header, -1);
+            set_visited(header);
+            profile::gc_traverse(header.value(), &mut |referent: *const ()| {
+                // safety: The type is known to be GcHeader.
+                let referent = unsafe { &*(referent as *const L) };
+                if is_collecting(referent) {
+                    debug_assert!(
+                        !is_unreachable(referent),
+                        "bug: object {} becomes unreachable while trying to dec_ref (is Trace impl correct?)",
+                        debug_name(referent)
+                    );
+                    edit_gc_ref_count(referent, -1);
+                } else {
+                    #[cfg(feature = "debug")]
+                    if header.is_other_space(referent) {
+                        debug::log(|| {
+                            let message = format!(
+                                "{} refers to {} in a different space",
+                                debug_name(header),
+                                debug_name(referent)
+                            );
+                            ("collect", message)
+                        });
+                    }
+                }
+            });
         }
-    };
-    visit_list(list, |header| {
-        set_visited(header);
-        header.value().gc_traverse(&mut tracer);
     });
+    #[cfg(feature = "std")]
+    if L::EPHEMERONS {
+        crate::ephemeron::subtract_refs();
+    }
 }
 
 /// Mark objects as reachable recursively. So ref count 0 means unreachable
 /// values. This also removes the COLLECTING flag for reachable objects so
 /// unreachable objects all have the COLLECTING flag set.
 fn mark_reachable<L: Linked>(list: &L) {
-    fn revive<L: Linked>(header: *const ()) {
-        // safety: The type is known to be GcHeader.
-        let header = unsafe { &*(header as *const L) };
-        // hasn't visited?
-        if is_collecting(header) {
-            unset_collecting(header);
-            if is_unreachable(header) {
-                edit_gc_ref_count(header, 1); // revive
-            }
-            header.value().gc_traverse(&mut revive::<L>); // revive recursively
-        }
-    }
     visit_list(list, |header| {
         if is_collecting(header) && !is_unreachable(header) {
             unset_collecting(header);
-            header.value().gc_traverse(&mut revive::<L>)
+            profile::gc_traverse(header.value(), &mut revive::<L>)
         }
     });
+    #[cfg(feature = "std")]
+    if L::EPHEMERONS {
+        crate::ephemeron::mark_reachable();
+    }
 }
 
-/// Release unreachable objects in the linked list.
-fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
-    // Mark reachable objects. For example, A refers B. A's gc_ref_count
-    // is 1 while B's gc_ref_count is 0. In this case B should be revived
-    // by A's non-zero gc_ref_count.
-    mark_reachable(list);
+/// Mark an object and objects it refers as reachable. Used by
+/// `mark_reachable`.
+pub(crate) fn revive<L: Linked>(header: *const ()) {
+    // safety: The type is known to be GcHeader.
+    let header = unsafe { &*(header as *const L) };
+    // hasn't visited?
+    if is_collecting(header) {
+        unset_collecting(header);
+        if is_unreachable(header) {
+            edit_gc_ref_count(header, 1); // revive
+        }
+        profile::gc_traverse(header.value(), &mut revive::<L>); // revive recursively
+    }
+}
 
+/// Release unreachable objects in the linked list.
+///
+/// Reachable objects should be marked by `mark_reachable`. For example, A
+/// refers B. A's gc_ref_count is 1 while B's gc_ref_count is 0. In this
+/// case B should be revived by A's non-zero gc_ref_count.
+pub(crate) fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
     let mut count = 0;
 
     // Count unreachable objects. This is an optimization to avoid realloc.
@@ -373,12 +2718,28 @@ fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
     // Here we keep extra references to the `CcBox<T>` to keep them alive. This
     // ensures metadata fields like `ref_count` is available.
     let mut to_drop: Vec<Box<dyn GcClone>> = Vec::with_capacity(count);
+    #[cfg(not(feature = "ordered_drop"))]
     visit_list(list, |header| {
         if is_unreachable(header) {
             to_drop.push(header.value().gc_clone());
         }
     });
 
+    // Finalize and drop objects in creation order so side effects of
+    // `Drop` are deterministic.
+    #[cfg(feature = "ordered_drop")]
+    {
+        let mut keyed: Vec<(u64, Box<dyn GcClone>)> = Vec::with_capacity(count);
+        visit_list(list, |header| {
+            if is_unreachable(header) {
+                keyed.push((header.drop_order(), header.value().gc_clone()));
+            }
+        });
+        // Stable sort keeps the list order for objects without keys.
+        keyed.sort_by_key(|(key, _)| *key);
+        to_drop.extend(keyed.into_iter().map(|(_, value)| value));
+    }
+
     // Restore "prev" so deleting nodes from the linked list can work.
     restore_prev(list);
 
@@ -389,7 +2750,14 @@ fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
     // used.
     drop(lock);
     // Drop the reference to the list so we don't reuse it.
-    drop(list);
+    let _ = list;
+
+    // Finalize all objects before dropping any of them.
+    for value in to_drop.iter() {
+        value.gc_finalize();
+    }
+    #[cfg(feature = "std")]
+    crate::ephemeron::drop_pending();
 
     #[cfg(feature = "debug")]
     {
@@ -400,7 +2768,9 @@ fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
     // recursive drops of other `Cc<T>`. `CcBox<T>` need to stay alive so
     // `Cc<T>::drop` can read the ref count metadata.
     for value in to_drop.iter() {
-        value.gc_drop_t();
+        let type_name = value.gc_type_name();
+        reclaimed::record(type_name);
+        profile::drop_t(type_name, || value.gc_drop_t());
     }
 
     // At this point the only references to the `CcBox<T>`s are inside the
@@ -424,6 +2794,393 @@ fn release_unreachable<L: Linked, K>(list: &L, lock: K) -> usize {
     count
 }
 
+/// Scan the specified linked list. Collect cycles. Unlike `collect_list`,
+/// only keep `batch_size` handles of unreachable objects at a time. Call
+/// `yield_now` with the number of released objects between batches.
+fn collect_list_chunked(
+    list: &GcHeader,
+    batch_size: usize,
+    yield_now: &mut dyn FnMut(usize),
+) -> usize {
+    profile::begin();
+    update_refs(list);
+    subtract_refs(list);
+    mark_reachable(list);
+
+    let lists = ChunkLists::new(list);
+    let count = move_unreachable(list, &lists.unreachable);
+    finalize_list(&lists.unreachable);
+    #[cfg(feature = "std")]
+    crate::ephemeron::drop_pending();
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(true));
+    }
+
+    // Objects can be released by other objects' `Drop` before they are
+    // visited here. Released objects remove themselves from the linked list
+    // so they won't be visited. Objects in a batch are moved to the
+    // "dropping" list. They might stay alive after the batch because
+    // objects in a later batch still refer to them.
+    let mut batch: Vec<Box<dyn GcClone>> = Vec::with_capacity(batch_size.min(count));
+    let mut released = 0;
+    loop {
+        while batch.len() < batch_size {
+            let next = lists.unreachable.next.get();
+            if std::ptr::eq(next, &*lists.unreachable) {
+                break;
+            }
+            // safety: The linked list is maintained. Pointers in it are valid.
+            let header = unsafe { &*next };
+            header.unlink();
+            header.link_after(&lists.dropping);
+            let value = header.value();
+            // Objects with ref count 0 have `T` dropped, and are kept alive
+            // by weak references.
+            if value.gc_ref_count() > 0 {
+                batch.push(value.gc_clone());
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        for value in batch.iter() {
+            let type_name = value.gc_type_name();
+            reclaimed::record(type_name);
+            profile::drop_t(type_name, || value.gc_drop_t());
+        }
+        released += batch.len();
+        batch.clear();
+        if !std::ptr::eq(lists.unreachable.next.get(), &*lists.unreachable) {
+            yield_now(released);
+        }
+    }
+
+    // At this point, remaining objects should only be kept alive by weak
+    // references.
+    let mut leaked = false;
+    visit_list(&*lists.dropping, |header| {
+        leaked |= header.value().gc_ref_count() > 0;
+    });
+    drop(lists);
+    assert!(
+        !leaked,
+        concat!(
+            "bug: unexpected ref-count after dropping cycles\n",
+            "This usually indicates a buggy Trace or Drop implementation."
+        )
+    );
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(false));
+    }
+
+    count
+}
+
+/// Scan the specified linked list. Collect cycles. Unlike `collect_list`,
+/// this does not allocate memory. `unreachable` should be an empty list. It
+/// is used to hold unreachable objects temporarily.
+pub(crate) fn collect_list_in_place(list: &GcHeader, unreachable: &GcHeader) -> usize {
+    debug_assert!(std::ptr::eq(unreachable.next.get(), unreachable));
+    profile::begin();
+    update_refs(list);
+    subtract_refs(list);
+    mark_reachable(list);
+    let count = move_unreachable(list, unreachable);
+    let _guard = MoveBack {
+        list,
+        other: unreachable,
+    };
+
+    // Keep objects alive so their ref counts can be checked after dropping
+    // `T`. Objects are not released so the `unreachable` list is stable.
+    visit_list(unreachable, |header| header.value().gc_inc_ref());
+
+    // Finalize all objects before dropping any of them.
+    visit_list(unreachable, |header| header.value().gc_finalize());
+    #[cfg(feature = "std")]
+    crate::ephemeron::drop_pending();
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(true));
+    }
+
+    visit_list(unreachable, |header| {
+        let value = header.value();
+        let type_name = value.gc_type_name();
+        reclaimed::record(type_name);
+        profile::drop_t(type_name, || value.gc_drop_t());
+    });
+
+    // Release the objects. Objects kept alive by weak references are moved
+    // back to the main list.
+    let mut leaked = false;
+    while !std::ptr::eq(unreachable.next.get(), unreachable) {
+        // safety: The linked list is maintained. Pointers in it are valid.
+        let header = unsafe { &*unreachable.next.get() };
+        header.unlink();
+        header.link_after(list);
+        let value = header.value();
+        leaked |= value.gc_ref_count() != 1;
+        value.gc_dec_ref();
+    }
+    assert!(
+        !leaked,
+        concat!(
+            "bug: unexpected ref-count after dropping cycles\n",
+            "This usually indicates a buggy Trace or Drop implementation."
+        )
+    );
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(false));
+    }
+
+    count
+}
+
+/// Scan the specified linked list. Collect cycles. Unlike `collect_list`,
+/// objects revived by `finalize` or `Drop` are kept alive instead of
+/// causing a panic. See `ObjectSpace::set_resurrection_tolerant`.
+fn collect_list_resurrectable(list: &GcHeader) -> usize {
+    profile::begin();
+    update_refs(list);
+    subtract_refs(list);
+    mark_reachable(list);
+
+    let lists = ChunkLists::new(list);
+    move_unreachable(list, &lists.unreachable);
+    finalize_list(&lists.unreachable);
+
+    // Values of unreachable ephemeron keys are dropped before the second
+    // scan, so they do not revive objects. Objects released by them remove
+    // themselves from `unreachable`.
+    #[allow(unused_mut)]
+    let mut dropped = 0;
+    #[cfg(feature = "std")]
+    {
+        let before = count_list(&*lists.unreachable);
+        crate::ephemeron::drop_pending();
+        dropped += before - count_list(&*lists.unreachable);
+    }
+
+    // Find objects that are still unreachable after finalizing, like
+    // `collect_list` but only among the unreachable objects. Objects
+    // referred by others, including the main list, are revived and stay in
+    // `unreachable`.
+    update_refs(&*lists.unreachable);
+    subtract_refs(&*lists.unreachable);
+    mark_reachable(&*lists.unreachable);
+    let count = move_unreachable(&lists.unreachable, &lists.dropping);
+
+    // The drop queue. Keep objects alive so the list is stable, and ref
+    // counts can be checked after `Drop`. `expected` is the ref count
+    // without revival. It is the queue's reference, plus references from
+    // queued objects whose `T` has not been dropped.
+    let mut queue: Vec<*const GcHeader> = Vec::with_capacity(count);
+    visit_list(&*lists.dropping, |header| {
+        header.value().gc_inc_ref();
+        queue.push(header);
+    });
+    // safety: Objects are kept alive by the queue.
+    #[cfg(feature = "ordered_drop")]
+    queue.sort_by_key(|&header| unsafe { &*header }.seq);
+    let mut expected: Vec<usize> = queue
+        .iter()
+        // safety: Objects are kept alive by the queue.
+        .map(|&header| unsafe { &*header }.value().gc_ref_count())
+        .collect();
+    let mut state: Vec<QueueState> = alloc::vec![QueueState::Pending; queue.len()];
+    let mut sorted: Vec<(*const GcHeader, usize)> =
+        queue.iter().enumerate().map(|(i, &h)| (h, i)).collect();
+    sorted.sort_unstable();
+    let index_of = |header: *const ()| -> Option<usize> {
+        let header = header as *const GcHeader;
+        let i = sorted.binary_search_by(|&(h, _)| h.cmp(&header)).ok()?;
+        Some(sorted[i].1)
+    };
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(true));
+    }
+
+    let mut revived = 0;
+    for i in 0..queue.len() {
+        if state[i] != QueueState::Pending {
+            continue;
+        }
+        // safety: Objects are kept alive by the queue.
+        let value = unsafe { &*queue[i] }.value();
+        if value.gc_ref_count() > expected[i] {
+            // Revived by a previous `Drop`. Keep it, and queued objects it
+            // refers to.
+            let mut to_revive = alloc::vec![i];
+            while let Some(j) = to_revive.pop() {
+                if state[j] != QueueState::Pending {
+                    continue;
+                }
+                state[j] = QueueState::Revived;
+                revived += 1;
+                // safety: Objects are kept alive by the queue.
+                let value = unsafe { &*queue[j] }.value();
+                profile::gc_traverse(value, &mut |header| {
+                    to_revive.extend(index_of(header));
+                });
+            }
+            continue;
+        }
+        // References from `T` go away with it.
+        profile::gc_traverse(value, &mut |header| {
+            if let Some(j) = index_of(header) {
+                expected[j] = expected[j].saturating_sub(1);
+            }
+        });
+        state[i] = QueueState::Dropped;
+        dropped += 1;
+        let type_name = value.gc_type_name();
+        reclaimed::record(type_name);
+        profile::drop_t(type_name, || value.gc_drop_t());
+    }
+
+    #[cfg(feature = "debug")]
+    {
+        crate::debug::GC_DROPPING.with(|d| d.set(false));
+    }
+
+    debug::log(|| {
+        // Objects kept by weak references are also counted as revived by
+        // finalize.
+        let finalized = count_list(&*lists.unreachable);
+        (
+            "collect",
+            format!("revived {} by finalize, {} by drop", finalized, revived),
+        )
+    });
+
+    // Move objects back to the main list. Release them unless they are
+    // revived.
+    drop(lists);
+    for header in queue {
+        // safety: Objects are kept alive by the queue.
+        unsafe { &*header }.value().gc_dec_ref();
+    }
+    // Entries of keys released by finalizers.
+    #[cfg(feature = "std")]
+    crate::ephemeron::drop_pending();
+
+    dropped
+}
+
+/// State of an object in the drop queue of `collect_list_resurrectable`.
+#[derive(Clone, Copy, PartialEq)]
+enum QueueState {
+    Pending,
+    Dropped,
+    Revived,
+}
+
+/// Call `Trace::finalize` on objects in `list`. Objects might be released
+/// by finalizers. Released objects remove themselves from the linked list.
+fn finalize_list(list: &GcHeader) {
+    // Keep objects alive so the list is stable while finalizing.
+    visit_list(list, |header| header.value().gc_inc_ref());
+    visit_list(list, |header| header.value().gc_finalize());
+    // Releasing an object only releases objects that were visited, since
+    // objects after it are still kept alive.
+    visit_list(list, |header| header.value().gc_dec_ref());
+}
+
+/// Objects moved out of the main list by `collect_window`. Moved back before
+/// `resume`, in order, on drop. Moved to the end of `list` instead if
+/// `resume` was unlinked, ex. by a collection during the window.
+struct WindowList<'a> {
+    window: Pin<Box<GcHeader>>,
+    list: &'a GcHeader,
+    resume: &'a GcHeader,
+}
+
+impl<'a> Drop for WindowList<'a> {
+    fn drop(&mut self) {
+        let window: &GcHeader = &self.window;
+        let resume = if self.resume.is_untracked() {
+            self.list
+        } else {
+            self.resume
+        };
+        while !std::ptr::eq(window.next.get(), window) {
+            // safety: The linked list is maintained. Pointers in it are valid.
+            let header = unsafe { &*window.next.get() };
+            header.unlink();
+            // safety: The linked list is maintained. Pointers in it are valid.
+            header.link_after(unsafe { &*resume.prev.get() });
+        }
+    }
+}
+
+/// Move objects in `other` back to `list` on drop, including on panic.
+struct MoveBack<'a> {
+    list: &'a GcHeader,
+    other: &'a GcHeader,
+}
+
+impl<'a> Drop for MoveBack<'a> {
+    fn drop(&mut self) {
+        self.list.take_all(self.other);
+    }
+}
+
+/// Move unreachable objects from `list` to `unreachable`. Restore "prev"
+/// of reachable objects. Return the number of unreachable objects.
+fn move_unreachable(list: &GcHeader, unreachable: &GcHeader) -> usize {
+    let mut count = 0;
+    let mut prev: &GcHeader = list;
+    visit_list(list, |header| {
+        if is_unreachable(header) {
+            count += 1;
+            header.link_after(unreachable);
+        } else {
+            header.prev.set(prev);
+            prev.next.set(header);
+            prev = header;
+        }
+    });
+    prev.next.set(list);
+    list.prev.set(prev);
+    debug::log(|| ("collect", format!("{} unreachable objects", count)));
+    count
+}
+
+/// Temporary linked lists used by `collect_list_chunked`. Objects in them are
+/// moved back to the main list on drop, including on panic.
+struct ChunkLists<'a> {
+    list: &'a GcHeader,
+    unreachable: Pin<Box<GcHeader>>,
+    dropping: Pin<Box<GcHeader>>,
+}
+
+impl<'a> ChunkLists<'a> {
+    fn new(list: &'a GcHeader) -> Self {
+        Self {
+            list,
+            unreachable: new_gc_list(list.events.clone()),
+            dropping: new_gc_list(list.events.clone()),
+        }
+    }
+}
+
+impl<'a> Drop for ChunkLists<'a> {
+    fn drop(&mut self) {
+        self.list.take_all(&self.dropping);
+        self.list.take_all(&self.unreachable);
+    }
+}
+
 /// Restore `GcHeader.prev` as a pointer used in the linked list.
 fn restore_prev<L: Linked>(list: &L) {
     let mut prev = list;
@@ -434,17 +3191,17 @@ fn restore_prev<L: Linked>(list: &L) {
 }
 
 fn is_unreachable<L: Linked>(header: &L) -> bool {
-    let prev = header.prev() as *const L as usize;
+    let prev = header.prev() as usize;
     is_collecting(header) && (prev >> PREV_SHIFT) == 0
 }
 
 pub(crate) fn is_collecting<L: Linked>(header: &L) -> bool {
-    let prev = header.prev() as *const L as usize;
+    let prev = header.prev() as usize;
     (prev & PREV_MASK_COLLECTING) != 0
 }
 
 fn set_visited<L: Linked>(header: &L) -> bool {
-    let prev = header.prev() as *const L as usize;
+    let prev = header.prev() as usize;
     let visited = (prev & PREV_MASK_VISITED) != 0;
     debug_assert!(
         !visited,
@@ -457,26 +3214,26 @@ fn set_visited<L: Linked>(header: &L) -> bool {
 }
 
 fn unset_collecting<L: Linked>(header: &L) {
-    let prev = header.prev() as *const L as usize;
+    let prev = header.prev() as usize;
     let new_prev = (prev & PREV_MASK_COLLECTING) ^ prev;
     header.set_prev(new_prev as _);
 }
 
-fn edit_gc_ref_count<L: Linked>(header: &L, delta: isize) {
-    let prev = header.prev() as *const L as isize;
+pub(crate) fn edit_gc_ref_count<L: Linked>(header: &L, delta: isize) {
+    let prev = header.prev() as isize;
     let new_prev = prev + (1 << PREV_SHIFT) * delta;
     header.set_prev(new_prev as _);
 }
 
 #[allow(unused_variables)]
-fn debug_name<L: Linked>(header: &L) -> String {
+pub(crate) fn debug_name<L: Linked>(header: &L) -> String {
     #[cfg(feature = "debug")]
     {
-        return header.value().gc_debug_name();
+        header.value().gc_debug_name()
     }
 
     #[cfg(not(feature = "debug"))]
     {
-        return "(enable gcmodule \"debug\" feature for debugging)".to_string();
+        "(enable gcmodule \"debug\" feature for debugging)".to_string()
     }
 }
@@ -8,6 +8,7 @@ thread_local!(pub(crate) static LOG: RefCell<String> = Default::default());
 thread_local!(pub(crate) static LAST_NAME: RefCell<String> = Default::default());
 thread_local!(pub(crate) static ENABLED: Cell<bool> = Default::default());
 thread_local!(pub(crate) static NEXT_DEBUG_NAME: Cell<usize> = Default::default());
+thread_local!(pub(crate) static GC_DROPPING: Cell<bool> = const { Cell::new(false) });
 thread_local!(pub(crate) static VERBOSE: bool = std::env::var("VERBOSE").is_ok());
 
 /// Enable debug log for the given scope. Return the debug log.
@@ -38,7 +39,7 @@ pub(crate) fn log<S1: ToString, S2: ToString>(func: impl Fn() -> (S1, S2)) {
                     log.push_str(", ");
                     log.push_str(&message);
                 } else {
-                    log.push_str("\n");
+                    log.push('\n');
                     log.push_str(&name);
                     log.push_str(": ");
                     log.push_str(&message);
@@ -50,6 +51,6 @@ pub(crate) fn log<S1: ToString, S2: ToString>(func: impl Fn() -> (S1, S2)) {
         let (name, message) = func();
         let t = std::thread::current().id();
         let name = format!("{:?}-{}", t, name.to_string());
-        eprintln!("debug::log {} {}", name.to_string(), message.to_string());
+        eprintln!("debug::log {} {}", name, message.to_string());
     }
 }
@@ -0,0 +1,288 @@
+use crate::Cc;
+use crate::Trace;
+use std::any::Any;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Clone a value, and objects reachable from it, recursively.
+///
+/// Unlike [`Clone`](https://doc.rust-lang.org/std/clone/trait.Clone.html)
+/// on [`Cc<T>`](type.Cc.html), which only increases the reference count,
+/// `DeepClone` creates new objects. An object reachable via different
+/// paths is cloned once, so shared structure and cycles are preserved in
+/// the copy. This is useful for snapshotting states.
+///
+/// New objects are created in the same spaces as the original objects.
+///
+/// `DeepClone` can be derived along with `Trace`. Fields with
+/// `#[trace(skip)]` are cloned by `Clone`.
+///
+/// # Cycles
+///
+/// A cycle of `Cc<T>`s must go through interior mutability. To clone a
+/// cycle, `Cc<T>` creates an object with a placeholder value from
+/// [`deep_clone_placeholder`](#method.deep_clone_placeholder) first, so
+/// objects in the cycle can refer to it, then fills the value by
+/// [`deep_clone_into`](#method.deep_clone_into). `RefCell<T>` implements
+/// them if `T: Default`. Cloning a cycle without placeholders panics.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, DeepClone, Trace};
+/// use std::cell::RefCell;
+///
+/// #[derive(DeepClone, Trace, Default)]
+/// struct Node {
+///     name: String,
+///     next: Option<Cc<RefCell<Node>>>,
+/// }
+///
+/// let a: Cc<RefCell<Node>> = Default::default();
+/// a.borrow_mut().next = Some(a.clone());
+/// let b = gcmodule::deep_clone(&a);
+/// assert!(!Cc::ptr_eq(&a, &b));
+/// assert!(Cc::ptr_eq(&b, b.borrow().next.as_ref().unwrap()));
+/// # a.borrow_mut().next = None;
+/// # b.borrow_mut().next = None;
+/// ```
+pub trait DeepClone: Sized {
+    /// Clone `self`. Use `cloner` to clone fields.
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self;
+
+    /// Create a value to be filled by
+    /// [`deep_clone_into`](#method.deep_clone_into) later. Return `None`
+    /// if the type does not support placeholders.
+    fn deep_clone_placeholder(&self) -> Option<Self> {
+        None
+    }
+
+    /// Clone `self` into `placeholder` created by
+    /// [`deep_clone_placeholder`](#method.deep_clone_placeholder).
+    fn deep_clone_into(&self, placeholder: &Self, cloner: &mut DeepCloner) {
+        let _ = (placeholder, cloner);
+        unreachable!("deep_clone_into is called without a placeholder")
+    }
+}
+
+/// Maps objects to their clones during [`DeepClone`](trait.DeepClone.html).
+///
+/// Values cloned by the same `DeepCloner` share cloned objects. For
+/// example, cloning two values pointing to the same object produces two
+/// values pointing to the same new object.
+///
+/// ```
+/// use gcmodule::{Cc, DeepCloner};
+///
+/// let shared = Cc::new(1);
+/// let mut cloner = DeepCloner::new();
+/// let a = cloner.clone_value(&shared);
+/// let b = cloner.clone_value(&vec![shared.clone()]);
+/// assert!(Cc::ptr_eq(&a, &b[0]));
+/// assert!(!Cc::ptr_eq(&a, &shared));
+/// ```
+#[derive(Default)]
+pub struct DeepCloner {
+    /// Cloned `Cc<T>`s, by ids of the original objects.
+    cloned: HashMap<usize, Box<dyn Any>>,
+
+    /// Ids of objects being cloned without placeholders.
+    in_progress: HashSet<usize>,
+}
+
+impl DeepCloner {
+    /// Create an empty `DeepCloner`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clone `value` using this `DeepCloner`.
+    pub fn clone_value<T: DeepClone>(&mut self, value: &T) -> T {
+        value.deep_clone(self)
+    }
+
+    /// Clone of the object with `id`.
+    fn get<T: Trace>(&self, id: usize) -> Option<Cc<T>> {
+        let cloned = self.cloned.get(&id)?;
+        cloned.downcast_ref::<Cc<T>>().cloned()
+    }
+}
+
+/// Clone `value`, and objects reachable from it. See
+/// [`DeepClone`](trait.DeepClone.html).
+pub fn deep_clone<T: DeepClone>(value: &T) -> T {
+    DeepCloner::new().clone_value(value)
+}
+
+impl<T: DeepClone + Trace> DeepClone for Cc<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        let id = self.id();
+        if let Some(cloned) = cloner.get::<T>(id) {
+            return cloned;
+        }
+        let value: &T = self;
+        match value.deep_clone_placeholder() {
+            Some(placeholder) => {
+                let result = self.new_copy(placeholder);
+                cloner.cloned.insert(id, Box::new(result.clone()));
+                value.deep_clone_into(&result, cloner);
+                result
+            }
+            None => {
+                if !cloner.in_progress.insert(id) {
+                    panic!(
+                        "cannot deep clone a cycle without placeholders ({})",
+                        std::any::type_name::<T>()
+                    );
+                }
+                let result = self.new_copy(value.deep_clone(cloner));
+                cloner.in_progress.remove(&id);
+                cloner.cloned.insert(id, Box::new(result.clone()));
+                result
+            }
+        }
+    }
+}
+
+impl<T: DeepClone + Default> DeepClone for RefCell<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        RefCell::new(self.borrow().deep_clone(cloner))
+    }
+
+    fn deep_clone_placeholder(&self) -> Option<Self> {
+        Some(Default::default())
+    }
+
+    fn deep_clone_into(&self, placeholder: &Self, cloner: &mut DeepCloner) {
+        let value = self.borrow().deep_clone(cloner);
+        *placeholder.borrow_mut() = value;
+    }
+}
+
+impl<T: Copy> DeepClone for Cell<T> {
+    fn deep_clone(&self, _cloner: &mut DeepCloner) -> Self {
+        Cell::new(self.get())
+    }
+}
+
+impl<T: DeepClone> DeepClone for Option<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        self.as_ref().map(|v| v.deep_clone(cloner))
+    }
+}
+
+impl<T: DeepClone, E: DeepClone> DeepClone for Result<T, E> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        match self {
+            Ok(v) => Ok(v.deep_clone(cloner)),
+            Err(e) => Err(e.deep_clone(cloner)),
+        }
+    }
+}
+
+impl<T: DeepClone> DeepClone for Box<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        Box::new(T::deep_clone(self, cloner))
+    }
+}
+
+impl<T: DeepClone> DeepClone for Vec<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        self.iter().map(|v| v.deep_clone(cloner)).collect()
+    }
+}
+
+impl<T: DeepClone> DeepClone for VecDeque<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        self.iter().map(|v| v.deep_clone(cloner)).collect()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: DeepClone, S: BuildHasher + Clone> DeepClone for HashMap<K, V, S> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        let mut result = HashMap::with_capacity_and_hasher(self.len(), self.hasher().clone());
+        result.extend(self.iter().map(|(k, v)| (k.clone(), v.deep_clone(cloner))));
+        result
+    }
+}
+
+impl<K: Clone + Ord, V: DeepClone> DeepClone for BTreeMap<K, V> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        self.iter()
+            .map(|(k, v)| (k.clone(), v.deep_clone(cloner)))
+            .collect()
+    }
+}
+
+/// `Rc<T>` is not tracked. Its value is cloned like `Box<T>`, without
+/// preserving sharing.
+impl<T: DeepClone> DeepClone for Rc<T> {
+    fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+        Rc::new(T::deep_clone(self, cloner))
+    }
+}
+
+impl<T: ?Sized> DeepClone for PhantomData<T> {
+    fn deep_clone(&self, _cloner: &mut DeepCloner) -> Self {
+        PhantomData
+    }
+}
+
+macro_rules! impl_by_clone {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl DeepClone for $t {
+                fn deep_clone(&self, _cloner: &mut DeepCloner) -> Self {
+                    self.clone()
+                }
+            }
+        )*
+    };
+}
+
+impl_by_clone!(
+    (),
+    bool,
+    char,
+    f32,
+    f64,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    String,
+    &'static str,
+    std::path::PathBuf,
+    std::time::Duration
+);
+
+macro_rules! impl_tuple {
+    ( $( $name:ident $index:tt ),* ) => {
+        impl< $( $name: DeepClone ),* > DeepClone for ( $( $name, )* ) {
+            fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+                ( $( self.$index.deep_clone(cloner), )* )
+            }
+        }
+    };
+}
+
+impl_tuple!(A 0);
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+impl_tuple!(A 0, B 1, C 2, D 3);
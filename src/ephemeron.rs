@@ -0,0 +1,312 @@
+//! Ephemeron tables. See `EphemeronMap`.
+//!
+//! Values in the tables are not visited by `Trace`. Instead, collections
+//! of `ObjectSpace` call the functions here after the usual steps:
+//!
+//! - `subtract_refs`: References from values whose keys are collecting are
+//!   internal, like references from keys.
+//! - `mark_reachable`: Values whose keys are reachable revive objects they
+//!   refer to, until no more keys become reachable. Then entries whose keys
+//!   are unreachable are moved to `State.pending`.
+//! - `drop_pending`: Called by the release steps after finalizing. Values
+//!   are dropped before the keys.
+
+use crate::collect;
+use crate::collect::GcHeader;
+use crate::Cc;
+use crate::Trace;
+use crate::Tracer;
+use crate::Weak;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc;
+use std::rc::Rc;
+
+thread_local! {
+    static STATE: RefCell<State> = const {
+        RefCell::new(State {
+            tables: Vec::new(),
+            pending: Vec::new(),
+        })
+    };
+}
+
+/// Ephemeron states of a thread. Collections in thread local destructors
+/// see no tables after this is destroyed.
+struct State {
+    /// Ephemeron tables in the current thread.
+    tables: Vec<rc::Weak<dyn AnyTable>>,
+
+    /// Entries removed by `mark_reachable`, to be dropped by `drop_pending`.
+    pending: Vec<Box<dyn Any>>,
+}
+
+/// A map with keys held weakly. A value is kept alive only while its key is
+/// alive, even if the value refers to the key.
+///
+/// Keys are compared by identity. Entries are removed when their keys are
+/// released, including by
+/// [`ObjectSpace::collect_cycles`](struct.ObjectSpace.html#method.collect_cycles).
+/// Values of keys released by the collector are dropped after finalizers,
+/// before the keys. Values of keys released otherwise are dropped on later
+/// accesses, or by the next collection.
+///
+/// A value is considered reachable if its key is reachable, whether or not
+/// the map is reachable. Values are not visited by `Trace`. Collections in
+/// [`ThreadedObjectSpace`](struct.ThreadedObjectSpace.html) do not know about
+/// ephemerons.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, EphemeronMap, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let cache: EphemeronMap<RefCell<Vec<Box<dyn Trace>>>, List> = EphemeronMap::new();
+/// let key: List = Default::default();
+/// // The value refers to the key. This does not keep the key alive.
+/// cache.insert(&key, key.clone());
+/// assert!(cache.contains_key(&key));
+///
+/// drop(key);
+/// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+/// assert!(cache.is_empty());
+/// ```
+pub struct EphemeronMap<K: Trace + 'static, V: Trace + 'static> {
+    table: Rc<Table<K, V>>,
+}
+
+struct Table<K: 'static, V: 'static> {
+    /// Entries by key ids.
+    entries: RefCell<HashMap<usize, Entry<K, V>>>,
+}
+
+struct Entry<K: 'static, V: 'static> {
+    /// Keeps the memory of the key so the id is not reused.
+    key: Weak<K>,
+
+    /// Header of the key. Null if the key is not tracked.
+    header: *const GcHeader,
+
+    value: V,
+}
+
+/// Operations used by collections.
+trait AnyTable {
+    /// Subtract references from values whose keys are collecting.
+    fn subtract_refs(&self);
+
+    /// Revive objects referred by values whose keys are reachable. Return
+    /// `true` if any object is revived.
+    fn revive(&self) -> bool;
+
+    /// Move entries whose keys are unreachable or released to `pending`.
+    fn take_unreachable(&self, pending: &mut Vec<Box<dyn Any>>);
+}
+
+impl<K: Trace + 'static, V: Trace + 'static> EphemeronMap<K, V> {
+    /// Constructs an empty map.
+    pub fn new() -> Self {
+        let table = Rc::new(Table {
+            entries: Default::default(),
+        });
+        let weak: rc::Weak<dyn AnyTable> = Rc::downgrade(&table) as _;
+        STATE.with(|state| {
+            let tables = &mut state.borrow_mut().tables;
+            tables.retain(|table| table.strong_count() > 0);
+            tables.push(weak);
+        });
+        Self { table }
+    }
+
+    /// Insert a value for `key`. Return the old value, if any.
+    pub fn insert(&self, key: &Cc<K>, value: V) -> Option<V> {
+        let mut header: *const GcHeader = std::ptr::null();
+        key.trace(&mut |ptr: *const ()| header = ptr as *const GcHeader);
+        let entry = Entry {
+            key: key.downgrade(),
+            header,
+            value,
+        };
+        let old = self.table.entries.borrow_mut().insert(key.id(), entry);
+        old.map(|entry| entry.value)
+    }
+
+    /// Return a clone of the value for `key`, if any.
+    pub fn get(&self, key: &Cc<K>) -> Option<V>
+    where
+        V: Clone,
+    {
+        let entries = self.table.entries.borrow();
+        entries.get(&key.id()).map(|entry| entry.value.clone())
+    }
+
+    /// Whether there is a value for `key`.
+    pub fn contains_key(&self, key: &Cc<K>) -> bool {
+        self.table.entries.borrow().contains_key(&key.id())
+    }
+
+    /// Remove the value for `key`, and return it.
+    pub fn remove(&self, key: &Cc<K>) -> Option<V> {
+        let entry = self.table.entries.borrow_mut().remove(&key.id());
+        entry.map(|entry| entry.value)
+    }
+
+    /// Number of entries whose keys are alive. Entries of released keys are
+    /// removed.
+    pub fn len(&self) -> usize {
+        self.purge();
+        self.table.entries.borrow().len()
+    }
+
+    /// Whether there are no entries whose keys are alive.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove entries whose keys are released.
+    pub fn purge(&self) {
+        loop {
+            let removed: Vec<Entry<K, V>> = {
+                let mut entries = self.table.entries.borrow_mut();
+                let ids: Vec<usize> = entries
+                    .iter()
+                    .filter(|(_, entry)| entry.key.strong_count() == 0)
+                    .map(|(&id, _)| id)
+                    .collect();
+                ids.into_iter()
+                    .filter_map(|id| entries.remove(&id))
+                    .collect()
+            };
+            if removed.is_empty() {
+                break;
+            }
+            // Values are dropped without borrowing entries. They might
+            // release other keys.
+            drop(removed);
+        }
+    }
+}
+
+impl<K: Trace + 'static, V: Trace + 'static> Default for EphemeronMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Values are handled by collections directly. See the module document.
+impl<K: Trace + 'static, V: Trace + 'static> Trace for EphemeronMap<K, V> {
+    #[inline]
+    fn is_type_tracked() -> bool {
+        false
+    }
+}
+
+impl<K: Trace + 'static, V: Trace + 'static> fmt::Debug for EphemeronMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EphemeronMap")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<K: 'static, V: 'static> Entry<K, V> {
+    /// Whether the key is collecting, or unreachable after `mark_reachable`.
+    fn is_key_collecting(&self) -> bool {
+        // safety: The memory of the key is kept by `self.key`.
+        !self.header.is_null() && collect::is_collecting(unsafe { &*self.header })
+    }
+}
+
+impl<K: 'static, V: Trace + 'static> AnyTable for Table<K, V> {
+    fn subtract_refs(&self) {
+        for entry in self.entries.borrow().values() {
+            if entry.is_key_collecting() {
+                entry.value.trace(&mut |referent: *const ()| {
+                    // safety: The type is known to be GcHeader.
+                    let referent = unsafe { &*(referent as *const GcHeader) };
+                    if collect::is_collecting(referent) {
+                        collect::edit_gc_ref_count(referent, -1);
+                    }
+                });
+            }
+        }
+    }
+
+    fn revive(&self) -> bool {
+        let mut revived = false;
+        let mut visit = |referent: *const ()| {
+            // safety: The type is known to be GcHeader.
+            if collect::is_collecting(unsafe { &*(referent as *const GcHeader) }) {
+                revived = true;
+                collect::revive::<GcHeader>(referent);
+            }
+        };
+        let tracer: &mut Tracer = &mut visit;
+        for entry in self.entries.borrow().values() {
+            if !entry.is_key_collecting() {
+                entry.value.trace(tracer);
+            }
+        }
+        revived
+    }
+
+    fn take_unreachable(&self, pending: &mut Vec<Box<dyn Any>>) {
+        let mut entries = self.entries.borrow_mut();
+        let ids: Vec<usize> = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_key_collecting() || entry.key.strong_count() == 0)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            if let Some(entry) = entries.remove(&id) {
+                pending.push(Box::new(entry));
+            }
+        }
+    }
+}
+
+/// Call `func` with each live table in the current thread, and entries to
+/// be dropped by `drop_pending`. Tables are visited in place, so
+/// collections without ephemerons do not allocate.
+fn for_each_table(mut func: impl FnMut(&dyn AnyTable, &mut Vec<Box<dyn Any>>)) {
+    let _ = STATE.try_with(|state| {
+        let state = &mut *state.borrow_mut();
+        state.tables.retain(|table| table.strong_count() > 0);
+        for table in state.tables.iter() {
+            if let Some(table) = table.upgrade() {
+                func(&*table, &mut state.pending);
+            }
+        }
+    });
+}
+
+/// Called by `collect::subtract_refs`.
+pub(crate) fn subtract_refs() {
+    for_each_table(|table, _| table.subtract_refs());
+}
+
+/// Called by `collect::mark_reachable`, after objects with external
+/// references are marked.
+pub(crate) fn mark_reachable() {
+    // Reviving objects might make more keys reachable.
+    loop {
+        let mut revived = false;
+        for_each_table(|table, _| revived |= table.revive());
+        if !revived {
+            break;
+        }
+    }
+    for_each_table(|table, pending| table.take_unreachable(pending));
+}
+
+/// Drop values removed by `mark_reachable`.
+pub(crate) fn drop_pending() {
+    let pending = STATE
+        .try_with(|state| std::mem::take(&mut state.borrow_mut().pending))
+        .unwrap_or_default();
+    drop(pending);
+}
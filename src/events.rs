@@ -0,0 +1,189 @@
+//! Bounded log of recent GC events, kept per space.
+//!
+//! With the `events` feature disabled, the handles are zero-sized and
+//! recording is a no-op.
+
+pub(crate) use imp::LocalEvents;
+#[cfg(feature = "sync")]
+pub(crate) use imp::SharedEvents;
+#[cfg(feature = "events")]
+pub use imp::{GcEvent, GcEventKind};
+
+#[cfg(feature = "events")]
+mod imp {
+    use crate::reclaimed::ReclaimedType;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    #[cfg(feature = "sync")]
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    /// Default number of events kept per space.
+    const DEFAULT_CAPACITY: usize = 128;
+
+    /// An event recorded by a space. See
+    /// [`ObjectSpace::recent_events`](struct.ObjectSpace.html#method.recent_events).
+    #[derive(Clone, Debug)]
+    pub struct GcEvent {
+        /// When the event happened.
+        pub time: SystemTime,
+
+        /// What happened.
+        pub kind: GcEventKind,
+    }
+
+    /// Kinds of [`GcEvent`](struct.GcEvent.html).
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum GcEventKind {
+        /// A tracked object was created.
+        Create {
+            /// Type name of the object.
+            type_name: &'static str,
+        },
+
+        /// A tracked object was released.
+        Drop {
+            /// Type name of the object.
+            type_name: &'static str,
+        },
+
+        /// A collection finished.
+        Collect {
+            /// Number of objects collected.
+            collected: usize,
+
+            /// Number of objects collected per type, highest first.
+            reclaimed: Vec<ReclaimedType>,
+        },
+    }
+
+    pub(crate) struct EventLog {
+        capacity: usize,
+        events: VecDeque<GcEvent>,
+    }
+
+    impl Default for EventLog {
+        fn default() -> Self {
+            Self {
+                capacity: DEFAULT_CAPACITY,
+                events: VecDeque::with_capacity(DEFAULT_CAPACITY),
+            }
+        }
+    }
+
+    impl EventLog {
+        fn push(&mut self, kind: GcEventKind) {
+            if self.capacity == 0 {
+                return;
+            }
+            while self.events.len() >= self.capacity {
+                self.events.pop_front();
+            }
+            let time = SystemTime::now();
+            self.events.push_back(GcEvent { time, kind });
+        }
+
+        fn set_capacity(&mut self, capacity: usize) {
+            self.capacity = capacity;
+            while self.events.len() > capacity {
+                self.events.pop_front();
+            }
+        }
+
+        fn to_vec(&self) -> Vec<GcEvent> {
+            self.events.iter().cloned().collect()
+        }
+    }
+
+    /// Event log shared by an `ObjectSpace` and its objects.
+    #[derive(Clone, Default)]
+    pub(crate) struct LocalEvents(Rc<RefCell<EventLog>>);
+
+    impl LocalEvents {
+        #[inline]
+        pub(crate) fn record(&self, kind: impl FnOnce() -> GcEventKind) {
+            self.0.borrow_mut().push(kind());
+        }
+
+        pub(crate) fn set_capacity(&self, capacity: usize) {
+            self.0.borrow_mut().set_capacity(capacity);
+        }
+
+        pub(crate) fn to_vec(&self) -> Vec<GcEvent> {
+            self.0.borrow().to_vec()
+        }
+    }
+
+    /// Event log shared by a `ThreadedObjectSpace` and its objects.
+    #[cfg(feature = "sync")]
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedEvents(Arc<parking_lot::Mutex<EventLog>>);
+
+    #[cfg(feature = "sync")]
+    impl SharedEvents {
+        #[inline]
+        pub(crate) fn record(&self, kind: impl FnOnce() -> GcEventKind) {
+            let kind = kind();
+            self.0.lock().push(kind);
+        }
+
+        pub(crate) fn set_capacity(&self, capacity: usize) {
+            self.0.lock().set_capacity(capacity);
+        }
+
+        pub(crate) fn to_vec(&self) -> Vec<GcEvent> {
+            self.0.lock().to_vec()
+        }
+
+        /// Reset the lock after `fork()`.
+        ///
+        /// safety: No other threads can use the lock.
+        pub(crate) unsafe fn reset_lock(&self) {
+            crate::sync::collect::reset_mutex(&self.0);
+        }
+    }
+}
+
+#[cfg(not(feature = "events"))]
+mod imp {
+    use crate::reclaimed::ReclaimedType;
+    use alloc::vec::Vec;
+
+    /// Placeholder of `GcEventKind`. Never constructed.
+    #[allow(dead_code)]
+    pub(crate) enum GcEventKind {
+        Create {
+            type_name: &'static str,
+        },
+        Drop {
+            type_name: &'static str,
+        },
+        Collect {
+            collected: usize,
+            reclaimed: Vec<ReclaimedType>,
+        },
+    }
+
+    #[derive(Clone, Default)]
+    pub(crate) struct LocalEvents(());
+
+    impl LocalEvents {
+        #[inline]
+        pub(crate) fn record(&self, _kind: impl FnOnce() -> GcEventKind) {}
+    }
+
+    #[cfg(feature = "sync")]
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedEvents(());
+
+    #[cfg(feature = "sync")]
+    impl SharedEvents {
+        #[inline]
+        pub(crate) fn record(&self, _kind: impl FnOnce() -> GcEventKind) {}
+
+        pub(crate) unsafe fn reset_lock(&self) {}
+    }
+}
+
+pub(crate) use imp::GcEventKind as EventKind;
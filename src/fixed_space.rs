@@ -0,0 +1,103 @@
+use crate::collect::collect_list_in_place;
+use crate::collect::new_gc_list;
+use crate::collect::GcHeader;
+use crate::Cc;
+use crate::ObjectSpace;
+use crate::Trace;
+use alloc::boxed::Box;
+use std::pin::Pin;
+
+/// An [`ObjectSpace`](struct.ObjectSpace.html) that tracks at most `N`
+/// objects, and collects cycles without allocating memory.
+///
+/// This is useful for allocation-free critical sections. Creating an
+/// object allocates exactly once, for the value and its `GcHeader`
+/// together. [`collect_cycles`](struct.FixedObjectSpace.html#method.collect_cycles)
+/// does not allocate memory, except for:
+/// - `Drop` implementations of the collected values.
+/// - Entries of [`EphemeronMap`](struct.EphemeronMap.html)s whose keys
+///   are collected.
+/// - The `debug`, `events` and `profiling` features.
+///
+/// Untracked objects (ex. `Cc<i32>`) do not count towards the capacity.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, FixedObjectSpace, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let space = FixedObjectSpace::<2>::default();
+/// let a: List = space.try_create(Default::default()).ok().unwrap();
+/// let b: List = space.try_create(Default::default()).ok().unwrap();
+/// assert!(space.try_create(RefCell::new(Vec::<Box<dyn Trace>>::new())).is_err());
+///
+/// a.borrow_mut().push(Box::new(b.clone()));
+/// b.borrow_mut().push(Box::new(a.clone()));
+/// drop((a, b));
+/// assert_eq!(space.collect_cycles(), 2);
+/// assert_eq!(space.count_tracked(), 0);
+/// ```
+pub struct FixedObjectSpace<const N: usize> {
+    space: ObjectSpace,
+
+    /// Empty list used by collection. Allocated ahead of time so collection
+    /// does not allocate.
+    unreachable: Pin<Box<GcHeader>>,
+}
+
+impl<const N: usize> Default for FixedObjectSpace<N> {
+    /// Constructs an empty [`FixedObjectSpace`](struct.FixedObjectSpace.html).
+    fn default() -> Self {
+        let space = ObjectSpace::default();
+        let events = space.list.borrow().events.clone();
+        Self {
+            space,
+            unreachable: new_gc_list(events),
+        }
+    }
+}
+
+impl<const N: usize> FixedObjectSpace<N> {
+    /// Maximum number of tracked objects.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Count objects tracked by this space.
+    pub fn count_tracked(&self) -> usize {
+        self.space.count_tracked()
+    }
+
+    /// Constructs a new [`Cc<T>`](type.Cc.html) in this space.
+    ///
+    /// Return `Err(value)` if `T` is tracked, and the space already has
+    /// [`capacity`](struct.FixedObjectSpace.html#method.capacity) tracked
+    /// objects. With the `space_pointer` feature, objects removed by
+    /// [`Cc::untrack`](type.Cc.html#method.untrack) still count until they
+    /// are released, and the check is `O(1)`. Otherwise, the check walks the
+    /// tracked objects, which are about `N`.
+    ///
+    /// The returned object should only refer to objects in the same space.
+    /// Otherwise the collector might fail to collect cycles.
+    pub fn try_create<T: Trace>(&self, value: T) -> Result<Cc<T>, T> {
+        if T::is_type_tracked() && self.space.count_allocated() >= N {
+            return Err(value);
+        }
+        Ok(self.space.create(value))
+    }
+
+    /// Collect cyclic garbage tracked by this space. Return the number of
+    /// objects collected.
+    pub fn collect_cycles(&self) -> usize {
+        self.space
+            .collect_cycles_with(|list| collect_list_in_place(list, &self.unreachable))
+    }
+}
+
+impl<const N: usize> Drop for FixedObjectSpace<N> {
+    fn drop(&mut self) {
+        self.collect_cycles();
+    }
+}
@@ -0,0 +1,163 @@
+//! `Cc<T>` with automatic collection of the thread-local space.
+
+use crate::collect;
+use crate::{Cc, Trace, Tracer, Weak};
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Deref;
+
+/// Default of [`set_auto_collect_threshold`](fn.set_auto_collect_threshold.html).
+const DEFAULT_THRESHOLD: usize = 700;
+
+thread_local! {
+    /// Collect after creating this many tracked `Gc<T>`s. 0: disabled.
+    static THRESHOLD: Cell<usize> = const { Cell::new(DEFAULT_THRESHOLD) };
+
+    /// Tracked `Gc<T>`s created since the last automatic collection.
+    static CREATED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A [`Cc<T>`](type.Cc.html) that collects cycles automatically.
+///
+/// `Gc<T>` is for programs that do not want to decide when to collect.
+/// The mental model is like Python: create values with `Gc::new`, clone
+/// them to share ownership, and form cycles freely. Every 700 tracked
+/// values created by `Gc::new` in a thread, cycles in the thread-local
+/// space are collected. See
+/// [`set_auto_collect_threshold`](fn.set_auto_collect_threshold.html).
+///
+/// `Gc<T>` dereferences to `T`. Use [`Gc::as_cc`](struct.Gc.html#method.as_cc)
+/// for other `Cc<T>` APIs. Objects created by `Cc::new` are in the same
+/// space, but do not trigger collection.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Gc, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = Gc<RefCell<Vec<Box<dyn Trace>>>>;
+/// gcmodule::set_auto_collect_threshold(2);
+/// let a: List = Default::default();
+/// a.borrow_mut().push(Box::new(a.clone()));
+/// drop(a);
+/// assert_eq!(gcmodule::count_thread_tracked(), 1);
+///
+/// let b: List = Default::default(); // Collects `a`.
+/// assert_eq!(gcmodule::count_thread_tracked(), 1);
+/// # drop(b);
+/// ```
+pub struct Gc<T: ?Sized>(Cc<T>);
+
+impl<T: Trace> Gc<T> {
+    /// Constructs a new `Gc<T>` in the thread-local space. Collect cycles if
+    /// the threshold is reached.
+    pub fn new(value: T) -> Self {
+        let cc = Cc::new(value);
+        if T::is_type_tracked() {
+            maybe_collect();
+        }
+        Self(cc)
+    }
+}
+
+impl<T: ?Sized> Gc<T> {
+    /// The underlying `Cc<T>`.
+    #[inline]
+    pub fn as_cc(this: &Self) -> &Cc<T> {
+        &this.0
+    }
+
+    /// Convert to the underlying `Cc<T>`.
+    #[inline]
+    pub fn into_cc(this: Self) -> Cc<T> {
+        this.0
+    }
+
+    /// Create a weak reference. See
+    /// [`Cc::downgrade`](struct.RawCc.html#method.downgrade).
+    #[inline]
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        this.0.downgrade()
+    }
+
+    /// Returns `true` if the two `Gc`s point to the same allocation.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Cc::ptr_eq(&this.0, &other.0)
+    }
+}
+
+/// Set the number of tracked objects created by
+/// [`Gc::new`](struct.Gc.html#method.new) in the current thread between
+/// automatic collections. 0 disables automatic collection. The default is
+/// 700.
+pub fn set_auto_collect_threshold(threshold: usize) {
+    THRESHOLD.with(|t| t.set(threshold));
+    CREATED.with(|c| c.set(0));
+}
+
+fn maybe_collect() {
+    let threshold = THRESHOLD.with(|t| t.get());
+    if threshold == 0 {
+        return;
+    }
+    let created = CREATED.with(|c| c.get()) + 1;
+    if created >= threshold {
+        CREATED.with(|c| c.set(0));
+        collect::collect_thread_cycles();
+    } else {
+        CREATED.with(|c| c.set(created));
+    }
+}
+
+impl<T: ?Sized> Clone for Gc<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for Gc<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Trace> Trace for Gc<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.0.trace(tracer)
+    }
+
+    #[inline]
+    fn is_type_tracked() -> bool {
+        T::is_type_tracked()
+    }
+}
+
+impl<T: Default + Trace> Default for Gc<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> From<Cc<T>> for Gc<T> {
+    fn from(cc: Cc<T>) -> Self {
+        Self(cc)
+    }
+}
+
+impl<T: fmt::Debug + ?Sized> fmt::Debug for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: fmt::Display + ?Sized> fmt::Display for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,167 @@
+use crate::Trace;
+use crate::Tracer;
+use std::cell::BorrowError;
+use std::cell::BorrowMutError;
+use std::cell::Cell;
+use std::cell::Ref;
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::fmt;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+thread_local! {
+    /// Number of outstanding `GcRefMut`s in the current thread.
+    static MUT_BORROWS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether there are outstanding mutable borrows of `GcCell`s in the
+/// current thread. Collections are deferred if so.
+pub(crate) fn is_mut_borrowed() -> bool {
+    MUT_BORROWS.with(|n| n.get() > 0)
+}
+
+/// A mutable memory location like `RefCell<T>` that cooperates with the
+/// collector.
+///
+/// Tracing a `RefCell` skips it if it is mutably borrowed, since its value
+/// cannot be read. Objects referred by the value are then considered
+/// reachable, and cycles through them leak if the collection happens to
+/// run at that time. With `GcCell`, collections in the current thread,
+/// including [`collect_thread_cycles`](fn.collect_thread_cycles.html) and
+/// [`ObjectSpace::collect_cycles`](struct.ObjectSpace.html#method.collect_cycles),
+/// are deferred while any `GcCell` is mutably borrowed, so the collector
+/// always sees the values. Deferred collections return 0 like
+/// [`defer_collection`](fn.defer_collection.html). Leaking a
+/// [`GcRefMut`](struct.GcRefMut.html) disables collection in the thread.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, GcCell, Trace};
+///
+/// type List = Cc<GcCell<Vec<Box<dyn Trace>>>>;
+/// let a: List = Default::default();
+/// a.borrow_mut().push(Box::new(a.clone()));
+/// let b = a.clone();
+/// drop(a);
+///
+/// let mut borrowed = b.borrow_mut();
+/// assert_eq!(gcmodule::collect_thread_cycles(), 0); // Deferred.
+/// borrowed.clear();
+/// drop(borrowed);
+/// ```
+#[derive(Default)]
+pub struct GcCell<T: ?Sized>(RefCell<T>);
+
+/// A wrapper type for a mutably borrowed value from a
+/// [`GcCell<T>`](struct.GcCell.html). Collections in the current thread
+/// are deferred while it is alive.
+pub struct GcRefMut<'a, T: ?Sized>(RefMut<'a, T>);
+
+impl<T> GcCell<T> {
+    /// Creates a new `GcCell` containing `value`.
+    pub const fn new(value: T) -> Self {
+        Self(RefCell::new(value))
+    }
+
+    /// Consumes the `GcCell`, returning the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+
+    /// Replaces the wrapped value with a new one, returning the old value.
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.borrow_mut(), value)
+    }
+}
+
+impl<T: ?Sized> GcCell<T> {
+    /// Immutably borrows the wrapped value. Collections are not affected.
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+
+    /// Immutably borrows the wrapped value, returning an error if the value
+    /// is currently mutably borrowed.
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        self.0.try_borrow()
+    }
+
+    /// Mutably borrows the wrapped value. Collections in the current thread
+    /// are deferred until the returned [`GcRefMut`](struct.GcRefMut.html)
+    /// is dropped.
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut(&self) -> GcRefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(value) => value,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Mutably borrows the wrapped value, returning an error if the value
+    /// is currently borrowed.
+    pub fn try_borrow_mut(&self) -> Result<GcRefMut<'_, T>, BorrowMutError> {
+        let value = self.0.try_borrow_mut()?;
+        MUT_BORROWS.with(|n| n.set(n.get() + 1));
+        Ok(GcRefMut(value))
+    }
+
+    /// Returns a mutable reference to the wrapped value. No borrow is
+    /// recorded since `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+}
+
+impl<T: Trace> Trace for GcCell<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        // Collections are deferred while any `GcCell` is mutably borrowed,
+        // so this does not fail.
+        if let Ok(x) = self.0.try_borrow() {
+            x.trace(tracer);
+        }
+    }
+
+    #[inline]
+    fn is_type_tracked() -> bool {
+        T::is_type_tracked()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GcCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("GcCell").field(&self.0).finish()
+    }
+}
+
+impl<T: ?Sized> Deref for GcRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> DerefMut for GcRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: ?Sized> Drop for GcRefMut<'_, T> {
+    fn drop(&mut self) {
+        MUT_BORROWS.with(|n| n.set(n.get() - 1));
+    }
+}
+
+impl<T: fmt::Debug + ?Sized> fmt::Debug for GcRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,91 @@
+//! Names that ease porting code written for the `gc` crate.
+//!
+//! The `gc` crate marks reachable objects from roots, while this crate
+//! visits references via [`Tracer`](../type.Tracer.html). `gc::Trace`
+//! implementations cannot be reused, or bridged:
+//!
+//! - `gc::Trace::trace` marks `gc::Gc` pointers directly. It has no
+//!   visitor argument to redirect to `Cc<T>`s.
+//! - A blanket `impl<T: gc::Trace> Trace for T` conflicts with the
+//!   implementations of this crate for `Vec<T>`, `Option<T>`, etc., and
+//!   implementing `Trace` for types of other crates is not allowed by the
+//!   orphan rules.
+//!
+//! Code using the derive macros can usually be ported by changing imports:
+//!
+//! | `gc` | `gcmodule` |
+//! |------|------------|
+//! | `gc::Gc<T>` | [`Gc<T>`](type.Gc.html), an alias of `Cc<T>` |
+//! | `gc::GcCell<T>` | [`GcCell<T>`](../struct.GcCell.html) |
+//! | `#[derive(Trace, Finalize)]` | unchanged |
+//! | `#[unsafe_ignore_trace]` | unchanged, or `#[trace(skip)]` |
+//! | `impl Finalize for T { fn finalize(&self) { ... } }` | unchanged, plus `#[trace(finalize)]` on `T` |
+//! | `unsafe impl Trace` with `custom_trace!` | [`trace_fields!`](../macro.trace_fields.html) or a manual [`Trace`](../trait.Trace.html) implementation |
+//! | `gc::force_collect()` | [`collect_thread_cycles()`](../fn.collect_thread_cycles.html) |
+//!
+//! Without `#[trace(finalize)]`, the derived `Trace` does not call
+//! [`Finalize::finalize`](trait.Finalize.html#method.finalize).
+//!
+//! Recursive types, like `struct Node { next: Option<Gc<Node>> }`, need a
+//! manual `Trace` implementation. See
+//! [`Trace::is_type_tracked`](../trait.Trace.html#method.is_type_tracked).
+//!
+//! This requires the `gc_compat` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use gcmodule::gc_compat::{Finalize, Gc, GcCell, Trace};
+//! use std::cell::Cell;
+//!
+//! #[derive(Trace, Finalize)]
+//! struct Node {
+//!     children: GcCell<Vec<Box<dyn Trace>>>,
+//! }
+//!
+//! #[derive(Trace)]
+//! #[trace(finalize)]
+//! struct Logged {
+//!     node: Gc<Node>,
+//!     #[unsafe_ignore_trace]
+//!     finalized: Gc<Cell<bool>>,
+//! }
+//!
+//! impl Finalize for Logged {
+//!     fn finalize(&self) {
+//!         self.finalized.set(true);
+//!     }
+//! }
+//!
+//! let node = Gc::new(Node { children: GcCell::new(Vec::new()) });
+//! let finalized = Gc::new(Cell::new(false));
+//! let logged = Gc::new(Logged { node: node.clone(), finalized: finalized.clone() });
+//! node.children.borrow_mut().push(Box::new(logged));
+//! drop(node);
+//!
+//! assert_eq!(gcmodule::collect_thread_cycles(), 2);
+//! assert!(finalized.get());
+//! ```
+
+use crate::Cc;
+
+pub use crate::GcCell;
+pub use crate::Trace;
+
+/// Counterpart of `gc::Gc<T>`.
+pub type Gc<T> = Cc<T>;
+
+/// Counterpart of `gc::Finalize`.
+///
+/// Types with the `#[trace(finalize)]` attribute call
+/// [`finalize`](trait.Finalize.html#method.finalize) from
+/// [`Trace::finalize`](../trait.Trace.html#method.finalize). See there for
+/// when it is called.
+pub trait Finalize {
+    /// Called before the collector drops the value as part of unreachable
+    /// cycles.
+    fn finalize(&self) {}
+}
+
+/// Derive an empty [`Finalize`](trait.Finalize.html) implementation.
+pub use gcmodule_derive::Finalize;
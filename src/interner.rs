@@ -0,0 +1,201 @@
+use crate::Cc;
+use crate::ObjectSpace;
+use crate::Trace;
+use crate::Weak;
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::rc::Rc;
+
+/// A pool of deduplicated values, like a symbol table.
+///
+/// Interning equal values returns the same [`Cc<T>`](type.Cc.html), so
+/// they can be compared by [`ptr_eq`](struct.RawCc.html#method.ptr_eq)
+/// or [`id`](struct.RawCc.html#method.id). The pool only keeps weak
+/// references. Values are released, including by the collector, once they
+/// are no longer used elsewhere. Interning a released value creates a new
+/// object.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, Interner};
+///
+/// let symbols: Interner<String> = Interner::new();
+/// let a = symbols.intern("foo".to_string());
+/// let b = symbols.intern_borrowed("foo");
+/// assert!(Cc::ptr_eq(&a, &b));
+/// assert!(symbols.get("bar").is_none());
+///
+/// drop((a, b));
+/// assert!(symbols.get("foo").is_none());
+/// ```
+pub struct Interner<T> {
+    /// Weak references, by hashes of the values.
+    buckets: RefCell<HashMap<u64, Vec<Weak<T>>>>,
+
+    hasher: RandomState,
+
+    /// Space of new objects. `None` uses `Cc::new`.
+    space: Option<Rc<ObjectSpace>>,
+
+    /// Number of weak references in `buckets`, including released ones.
+    pub(crate) entries: Cell<usize>,
+
+    /// `entries` after the last `purge`. Used to purge automatically.
+    purged_entries: Cell<usize>,
+}
+
+impl<T: Trace + Hash + Eq> Interner<T> {
+    /// Constructs an empty pool. New objects are created by
+    /// [`Cc::new`](type.Cc.html#method.new).
+    pub fn new() -> Self {
+        Self::new_with_space(None)
+    }
+
+    /// Constructs an empty pool that creates objects in `space`.
+    pub fn with_space(space: Rc<ObjectSpace>) -> Self {
+        Self::new_with_space(Some(space))
+    }
+
+    fn new_with_space(space: Option<Rc<ObjectSpace>>) -> Self {
+        Self {
+            buckets: Default::default(),
+            hasher: Default::default(),
+            space,
+            entries: Cell::new(0),
+            purged_entries: Cell::new(0),
+        }
+    }
+
+    /// Return the interned value equal to `value`, or intern `value`.
+    pub fn intern(&self, value: T) -> Cc<T> {
+        let hash = self.hasher.hash_one(&value);
+        if let Some(found) = self.find(hash, &value) {
+            return found;
+        }
+        let result = match &self.space {
+            Some(space) => space.create(value),
+            None => Cc::new(value),
+        };
+        self.insert(hash, &result);
+        result
+    }
+
+    /// Like [`intern`](struct.Interner.html#method.intern), but only
+    /// converts `key` to `T` if it is not interned.
+    pub fn intern_borrowed<Q>(&self, key: &Q) -> Cc<T>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = T> + ?Sized,
+    {
+        match self.get(key) {
+            Some(found) => found,
+            None => self.intern(key.to_owned()),
+        }
+    }
+
+    /// Return the interned value equal to `key`, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<Cc<T>>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.hasher.hash_one(key), key)
+    }
+
+    /// Number of interned values that are not released. The time
+    /// complexity is `O(n)`.
+    pub fn len(&self) -> usize {
+        let buckets = self.buckets.borrow();
+        buckets
+            .values()
+            .flatten()
+            .filter(|weak| weak.upgrade().is_some())
+            .count()
+    }
+
+    /// Whether there are no interned values that are not released.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove weak references to released values. This is done
+    /// automatically when the number of weak references doubles.
+    pub fn purge(&self) {
+        let mut buckets = self.buckets.borrow_mut();
+        buckets.retain(|_, bucket| {
+            bucket.retain(|weak| weak.upgrade().is_some());
+            !bucket.is_empty()
+        });
+        let entries = buckets.values().map(|bucket| bucket.len()).sum();
+        self.entries.set(entries);
+        self.purged_entries.set(entries);
+    }
+
+    /// Find an interned value by hash. Remove released values in the
+    /// bucket.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<Cc<T>>
+    where
+        T: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut buckets = self.buckets.borrow_mut();
+        let bucket = buckets.get_mut(&hash)?;
+        let mut found = None;
+        let len = bucket.len();
+        bucket.retain(|weak| match weak.upgrade() {
+            Some(value) => {
+                if found.is_none() && <T as Borrow<Q>>::borrow(&value) == key {
+                    found = Some(value);
+                }
+                true
+            }
+            None => false,
+        });
+        self.entries.set(self.entries.get() - (len - bucket.len()));
+        if bucket.is_empty() {
+            buckets.remove(&hash);
+        }
+        found
+    }
+
+    fn insert(&self, hash: u64, value: &Cc<T>) {
+        self.buckets
+            .borrow_mut()
+            .entry(hash)
+            .or_default()
+            .push(value.downgrade());
+        self.entries.set(self.entries.get() + 1);
+        if self.entries.get() > self.purged_entries.get().max(16) * 2 {
+            self.purge();
+        }
+    }
+}
+
+impl<T: Trace + Hash + Eq> Default for Interner<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Only weak references are kept. They do not keep values alive.
+impl<T: 'static> Trace for Interner<T> {
+    #[inline]
+    fn is_type_tracked() -> bool {
+        false
+    }
+}
+
+impl<T: Trace + Hash + Eq> fmt::Debug for Interner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("len", &self.len())
+            .finish()
+    }
+}
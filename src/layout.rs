@@ -0,0 +1,84 @@
+use crate::cc::RawCcBox;
+use crate::cc::RawCcBoxWithGcHeader;
+use crate::collect::AbstractObjectSpace;
+use crate::collect::ObjectSpace;
+use crate::Trace;
+use std::mem;
+
+/// Memory layout of the allocation of a [`Cc<T>`](type.Cc.html) or
+/// [`ThreadedCc<T>`](type.ThreadedCc.html).
+///
+/// Tracked objects (see [`Trace::is_type_tracked`](trait.Trace.html#method.is_type_tracked))
+/// have a `GcHeader` linking them to their space. Untracked objects only
+/// have the reference counts.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::CcLayout;
+///
+/// let tracked = CcLayout::of::<u64>(true);
+/// let untracked = CcLayout::of::<u64>(false);
+/// assert_eq!(untracked.header_size, 0);
+/// assert_eq!(tracked.size, untracked.size + tracked.header_size);
+/// assert_eq!(untracked.overhead, untracked.size - 8);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CcLayout {
+    /// Size of the allocation, including the value.
+    pub size: usize,
+
+    /// Size of the allocation, excluding the value.
+    pub overhead: usize,
+
+    /// Size of the `GcHeader`. 0 for untracked objects.
+    pub header_size: usize,
+}
+
+impl CcLayout {
+    /// Layout of [`Cc<T>`](type.Cc.html), tracked or not.
+    pub const fn of<T>(tracked: bool) -> Self {
+        Self::in_space::<T, ObjectSpace>(tracked)
+    }
+
+    /// Layout of [`ThreadedCc<T>`](type.ThreadedCc.html), tracked or not.
+    #[cfg(feature = "sync")]
+    pub const fn threaded<T>(tracked: bool) -> Self {
+        Self::in_space::<T, crate::ThreadedObjectSpace>(tracked)
+    }
+
+    const fn in_space<T, O: AbstractObjectSpace>(tracked: bool) -> Self {
+        let (size, header_size) = if tracked {
+            (
+                mem::size_of::<RawCcBoxWithGcHeader<T, O>>(),
+                mem::size_of::<O::Header>(),
+            )
+        } else {
+            (mem::size_of::<RawCcBox<T, O>>(), 0)
+        };
+        Self {
+            size,
+            overhead: size - mem::size_of::<T>(),
+            header_size,
+        }
+    }
+}
+
+/// Size of the allocation of [`Cc::new(value)`](type.Cc.html#method.new)
+/// for a value of type `T`. Whether `T` is tracked is decided by
+/// [`Trace::is_type_tracked`](trait.Trace.html#method.is_type_tracked).
+///
+/// See [`CcLayout`](struct.CcLayout.html) for details.
+pub fn sizeof_cc<T: Trace>() -> usize {
+    CcLayout::of::<T>(T::is_type_tracked()).size
+}
+
+/// Size of the allocation of
+/// [`ThreadedObjectSpace::create(value)`](struct.ThreadedObjectSpace.html#method.create)
+/// for a value of type `T`.
+///
+/// See [`sizeof_cc`](fn.sizeof_cc.html).
+#[cfg(feature = "sync")]
+pub fn sizeof_threaded_cc<T: Trace>() -> usize {
+    CcLayout::threaded::<T>(T::is_type_tracked()).size
+}
@@ -0,0 +1,110 @@
+//! Detect objects that are likely leaked by forgotten clones.
+//!
+//! The cycle collector cannot free objects that are kept alive by extra
+//! (non-cyclic) references. A common cause is cloning a `Cc<T>` somewhere and
+//! forgetting to drop it. Such objects usually have reference counts that
+//! grow over time but never go down. The detector records the reference
+//! count of every tracked object after each collection and reports objects
+//! whose count did not decrease for a number of collections.
+
+use crate::collect::visit_list;
+use crate::collect::Linked;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// An object that survived collections with a reference count that never
+/// decreased. Returned by
+/// [`ObjectSpace::suspected_leaks`](struct.ObjectSpace.html#method.suspected_leaks).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuspectedLeak {
+    /// Type name of the object.
+    pub type_name: &'static str,
+
+    /// Reference count observed at the last collection.
+    pub ref_count: usize,
+
+    /// Reference count observed when the object was first seen, or last
+    /// seen decreasing.
+    pub initial_ref_count: usize,
+
+    /// Number of collections survived without the reference count
+    /// decreasing.
+    pub collections: usize,
+}
+
+/// Per-object history.
+struct Entry {
+    type_name: &'static str,
+    initial_ref_count: usize,
+    ref_count: usize,
+    collections: usize,
+}
+
+pub(crate) struct LeakDetector {
+    /// Report objects after surviving this many collections.
+    threshold: usize,
+
+    /// Keyed by the address of the object's header.
+    entries: BTreeMap<usize, Entry>,
+}
+
+impl LeakDetector {
+    pub(crate) fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Record reference counts of objects in the linked list. Should be
+    /// called after a collection. Objects no longer in the list are forgotten.
+    pub(crate) fn update<L: Linked>(&mut self, list: &L) {
+        let mut entries = BTreeMap::new();
+        visit_list(list, |header| {
+            let value = header.value();
+            let ref_count = value.gc_ref_count();
+            let type_name = value.gc_type_name();
+            let key = header as *const L as usize;
+            let entry = match self.entries.remove(&key) {
+                // The address might be reused by a different object. Detect
+                // that by comparing type names.
+                Some(mut entry) if entry.type_name == type_name => {
+                    if ref_count < entry.ref_count {
+                        entry.initial_ref_count = ref_count;
+                        entry.collections = 0;
+                    } else {
+                        entry.collections += 1;
+                    }
+                    entry.ref_count = ref_count;
+                    entry
+                }
+                _ => Entry {
+                    type_name,
+                    initial_ref_count: ref_count,
+                    ref_count,
+                    collections: 0,
+                },
+            };
+            entries.insert(key, entry);
+        });
+        self.entries = entries;
+    }
+
+    /// Objects that survived at least `threshold` collections, and whose
+    /// reference count grew during that time.
+    pub(crate) fn suspected_leaks(&self) -> Vec<SuspectedLeak> {
+        let mut result: Vec<SuspectedLeak> = self
+            .entries
+            .values()
+            .filter(|e| e.collections >= self.threshold && e.ref_count > e.initial_ref_count)
+            .map(|e| SuspectedLeak {
+                type_name: e.type_name,
+                ref_count: e.ref_count,
+                initial_ref_count: e.initial_ref_count,
+                collections: e.collections,
+            })
+            .collect();
+        result.sort_by_key(|e| std::cmp::Reverse(e.ref_count));
+        result
+    }
+}
@@ -1,4 +1,5 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "nightly", feature(coerce_unsized), feature(unsize))]
 #![cfg_attr(all(feature = "debug", feature = "nightly"), feature(specialization))]
 
@@ -15,7 +16,8 @@
 //!
 //! Similar to `Rc<T>`, use `clone()` to get cloned references.
 //!
-//! ```
+#![cfg_attr(feature = "std", doc = " ```")]
+#![cfg_attr(not(feature = "std"), doc = " ```ignore")]
 //! use gcmodule::Cc;
 //! let foo = Cc::new(vec![1, 2, 3]);
 //! let foo_cloned = foo.clone();
@@ -32,7 +34,8 @@
 //! Use [`count_thread_tracked()`](fn.count_thread_tracked.html) to count how
 //! many objects are tracked by the collector.
 //!
-//! ```
+#![cfg_attr(feature = "std", doc = " ```")]
+#![cfg_attr(not(feature = "std"), doc = " ```ignore")]
 //! use gcmodule::{Cc, Trace};
 //! use std::cell::RefCell;
 //! {
@@ -59,7 +62,8 @@
 //! and [`ThreadedCc`](type.ThreadedCc.html) for multi-thread usecases. Beware
 //! they take more memory, are slower, and a bit harder to use.
 //!
-//! ```
+#![cfg_attr(feature = "sync", doc = " ```")]
+#![cfg_attr(not(feature = "sync"), doc = " ```ignore")]
 //! use gcmodule::{ThreadedObjectSpace, ThreadedCc, Trace};
 //! use std::sync::Mutex;
 //!
@@ -79,6 +83,23 @@
 //! assert_eq!(space.count_tracked(), 0);
 //! ```
 //!
+//! ## `no_std` support
+//!
+//! Without the default `std` feature, this crate only depends on `core` and
+//! `alloc`. There is no thread-local space, so `Cc::new` and functions like
+//! [`collect_thread_cycles`](fn.collect_thread_cycles.html) are unavailable.
+//! Use an explicit [`ObjectSpace`](struct.ObjectSpace.html) instead:
+//!
+//! ```
+//! let space = gcmodule::ObjectSpace::default();
+//! let value = space.create(1);
+//! assert_eq!(*value, 1);
+//! space.collect_cycles();
+//! ```
+//!
+//! Durations in collection statistics are zero without `std`. Features like
+//! `sync`, `debug`, `events` and `age` require `std`.
+//!
 //! ## Defining new types
 //!
 //! [`Cc<T>`](type.Cc.html) requires [`Trace`](trait.Trace.html) implemented
@@ -90,7 +111,8 @@
 //! If a type is acyclic (cannot form reference circles about [`Cc`](type.Cc.html)),
 //! [`Trace::is_type_tracked()`](trait.Trace.html#method.is_type_tracked) will return `false`.
 //!
-//! ```
+#![cfg_attr(all(feature = "derive", feature = "std"), doc = " ```")]
+#![cfg_attr(not(all(feature = "derive", feature = "std")), doc = " ```ignore")]
 //! use gcmodule::{Cc, Trace};
 //!
 //! #[derive(Trace)]
@@ -116,7 +138,8 @@
 //! Whether a container type is acyclic or not depends on its fields. Usually,
 //! types without referring to trait objects or itself are considered acyclic.
 //!
-//! ```
+#![cfg_attr(all(feature = "derive", feature = "std"), doc = " ```")]
+#![cfg_attr(not(all(feature = "derive", feature = "std")), doc = " ```ignore")]
 //! use gcmodule::{Cc, Trace};
 //!
 //! #[derive(Trace)]
@@ -134,7 +157,8 @@
 //! The `#[trace(skip)]` attribute can be used to skip tracking specified fields
 //! in a structure.
 //!
-//! ```
+#![cfg_attr(all(feature = "derive", feature = "std"), doc = " ```")]
+#![cfg_attr(not(all(feature = "derive", feature = "std")), doc = " ```ignore")]
 //! use gcmodule::{Cc, Trace};
 //!
 //! struct AlienStruct; // Does not implement Trace
@@ -154,7 +178,8 @@
 //! to create weak references. Use [`Weak::upgrade`](struct.RawWeak.html#method.upgrade)
 //! to test if the value is still alive and to access the value. For example:
 //!
-//! ```
+#![cfg_attr(feature = "std", doc = " ```")]
+#![cfg_attr(not(feature = "std"), doc = " ```ignore")]
 //! use gcmodule::{Cc, Weak};
 //!
 //! let value = Cc::new("foo");
@@ -259,27 +284,125 @@
 //! undefined behavior. Again, the UB can only happen if the [`Trace::trace`](trait.Trace.html#method.trace)
 //! is implemented wrong, and panic will happen before the UB.
 
+extern crate alloc;
+// Most `std` paths used by this crate are re-exports of `core`.
+#[cfg(not(feature = "std"))]
+extern crate core as std;
+
+mod alloc_hook;
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "space_pointer")]
+mod arena;
 mod cc;
 mod cc_impls;
 mod collect;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod debug;
+#[cfg(feature = "std")]
+mod deep_clone;
+#[cfg(feature = "std")]
+mod ephemeron;
+mod events;
+mod fixed_space;
+#[cfg(feature = "std")]
+mod gc;
+#[cfg(feature = "std")]
+mod gc_cell;
+#[cfg(feature = "gc_compat")]
+pub mod gc_compat;
+#[cfg(feature = "std")]
+mod interner;
+mod layout;
+mod leak_detector;
+#[cfg(feature = "sync")]
+mod migrate;
+mod profile;
+pub mod rc_compat;
+mod reclaimed;
 mod ref_count;
+#[cfg(feature = "relocation")]
+mod relocate;
+mod roots;
+#[cfg(feature = "std")]
+mod self_ref;
+#[cfg(feature = "serde")]
+mod serde_impls;
 #[cfg(feature = "sync")]
 mod sync;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
-#[cfg(any(test, feature = "testutil"))]
+#[cfg(any(all(test, feature = "std"), feature = "testutil"))]
 pub mod testutil;
 mod trace;
 mod trace_impls;
+mod traced_rc;
+#[cfg(feature = "watchdog")]
+mod watchdog;
+#[cfg(feature = "std")]
+mod weak_map;
 
-pub use cc::{Cc, RawCc, RawWeak, Weak};
-pub use collect::{collect_thread_cycles, count_thread_tracked, ObjectSpace};
-pub use trace::{Trace, Tracer};
+#[cfg(feature = "alloc_hook")]
+pub use alloc_hook::{clear_alloc_hook, set_alloc_hook, AllocEvent, AllocKind};
+#[cfg(feature = "std")]
+pub use analysis::{is_reachable, reachable_count};
+pub use cc::{upgrade_all, Cc, RawCc, RawWeak, Weak};
+pub use cc_impls::{Address, PtrIdentity};
+#[cfg(feature = "age")]
+pub use collect::TrackedInfo;
+#[cfg(feature = "std")]
+pub use collect::{
+    collect_thread_cycles, collect_thread_cycles_with_stats, count_thread_tracked,
+    defer_collection, set_exit_behavior, thread_stats, with_thread_object_space, ExitBehavior,
+    SpaceGuard,
+};
+pub use collect::{
+    AbstractObjectSpace, CollectProgress, CollectStats, CollectYield, ObjectSpace, ThreadGcStats,
+};
+#[cfg(feature = "std")]
+pub use deep_clone::{deep_clone, DeepClone, DeepCloner};
+#[cfg(feature = "std")]
+pub use ephemeron::EphemeronMap;
+#[cfg(feature = "events")]
+pub use events::{GcEvent, GcEventKind};
+pub use fixed_space::FixedObjectSpace;
+#[cfg(feature = "std")]
+pub use gc::{set_auto_collect_threshold, Gc};
+#[cfg(feature = "std")]
+pub use gc_cell::{GcCell, GcRefMut};
+#[cfg(feature = "std")]
+pub use interner::Interner;
+#[cfg(feature = "sync")]
+pub use layout::sizeof_threaded_cc;
+pub use layout::{sizeof_cc, CcLayout};
+pub use leak_detector::SuspectedLeak;
+#[cfg(feature = "profiling")]
+pub use profile::{last_collection_profile, TypeProfile};
+pub use reclaimed::ReclaimedType;
+#[cfg(feature = "relocation")]
+pub use relocate::Relocatable;
+pub use roots::Roots;
+#[cfg(feature = "std")]
+pub use self_ref::SelfRef;
+#[cfg(all(feature = "serde", feature = "std"))]
+pub use serde_impls::{deserialize_shared, deserialize_shared_in, serialize_shared};
+pub use trace::{Trace, Tracer, Visitor};
+pub use traced_rc::{TracedArc, TracedRc};
+#[cfg(feature = "watchdog")]
+pub use watchdog::WatchdogWarning;
+#[cfg(feature = "std")]
+pub use weak_map::{WeakSet, WeakValueMap};
 
 #[cfg(feature = "sync")]
-pub use sync::{collect::ThreadedObjectSpace, ThreadedCc, ThreadedCcRef};
+pub use migrate::SpaceMigration;
+#[cfg(feature = "sync")]
+pub use sync::{
+    collect::{BackgroundCollector, ThreadedObjectSpace},
+    ThreadedCc, ThreadedCcRef, ThreadedWeak,
+};
+
+#[cfg(all(feature = "derive", feature = "std"))]
+pub use gcmodule_derive::DeepClone;
 
 /// Derive [`Trace`](trait.Trace.html) implementation for a structure.
 ///
@@ -308,18 +431,37 @@ pub use sync::{collect::ThreadedObjectSpace, ThreadedCc, ThreadedCcRef};
 #[cfg(feature = "derive")]
 pub use gcmodule_derive::Trace;
 
-#[cfg(not(test))]
+#[cfg(not(all(test, feature = "std")))]
 mod debug {
+    use alloc::string::ToString;
+    #[cfg(feature = "std")]
     use std::cell::Cell;
+    #[cfg(feature = "std")]
     thread_local!(pub(crate) static NEXT_DEBUG_NAME: Cell<usize> = Default::default());
-    thread_local!(pub(crate) static GC_DROPPING: Cell<bool> = Cell::new(false));
+    #[cfg(feature = "std")]
+    thread_local!(pub(crate) static GC_DROPPING: Cell<bool> = const { Cell::new(false) });
     pub(crate) fn log<S1: ToString, S2: ToString>(func: impl Fn() -> (S1, S2)) {
-        if cfg!(feature = "debug") {
+        #[cfg(feature = "debug")]
+        {
             let (name, message) = func();
             eprintln!("[gc] {} {}", name.to_string(), message.to_string());
         }
+        let _ = func;
     }
 }
 
+/// Number of bits available for [`RawCc::set_tag`](struct.RawCc.html#method.set_tag).
+pub const TAG_BITS: u32 = 4;
+
 /// Whether the `debug` feature is enabled.
 pub const DEBUG_ENABLED: bool = cfg!(feature = "debug");
+
+/// Log a field visited by `#[derive(Trace)]`. Does nothing without the
+/// `debug` feature.
+#[doc(hidden)]
+#[inline]
+pub fn __debug_visit(type_name: &str, field: &str) {
+    #[cfg(feature = "debug")]
+    eprintln!("[gc] Trace({}): visit .{}", type_name, field);
+    let _ = (type_name, field);
+}
@@ -0,0 +1,157 @@
+//! Copy object graphs between [`ObjectSpace`] and [`ThreadedObjectSpace`].
+
+use crate::cc::RawCc;
+use crate::collect::AbstractObjectSpace;
+use crate::ref_count::RefCount;
+use crate::{ObjectSpace, ThreadedObjectSpace, Trace};
+use std::any::Any;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Deref;
+
+/// Copies objects into a destination space, preserving sharing.
+///
+/// Objects are converted by user-provided functions. Each source object is
+/// converted at most once. Converting the same object again returns the
+/// previously created handle, so shared references in the source graph are
+/// still shared in the copied graph.
+///
+/// Converting from [`Cc`](type.Cc.html) to [`ThreadedCc`](type.ThreadedCc.html)
+/// requires the converted values to be `Send + Sync`.
+///
+/// # Cycles
+///
+/// A conversion function cannot recursively convert an object that is
+/// still being converted. To copy cycles, create the destination object
+/// with interior mutability first, register it via
+/// [`insert`](struct.SpaceMigration.html#method.insert), then fill in the
+/// fields.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, SpaceMigration, ThreadedCc, ThreadedObjectSpace};
+///
+/// let shared = Cc::new(1u32);
+/// let pair = Cc::new((shared.clone(), shared));
+///
+/// let space = ThreadedObjectSpace::default();
+/// let mut migration = SpaceMigration::new(&space);
+/// let copied: ThreadedCc<(ThreadedCc<u32>, ThreadedCc<u32>)> =
+///     migration.migrate(&pair, |(a, b), m| {
+///         let convert = |v: &u32, _: &mut SpaceMigration<_>| *v;
+///         (m.migrate(a, convert), m.migrate(b, convert))
+///     });
+/// let copied = copied.borrow();
+/// assert!(std::ptr::eq(&*copied.0.borrow(), &*copied.1.borrow()));
+/// ```
+pub struct SpaceMigration<'a, D: AbstractObjectSpace> {
+    space: &'a D,
+
+    /// Source allocation address to the converted `RawCc<U, D>`.
+    mapped: HashMap<usize, Box<dyn Any>>,
+
+    /// Source objects being converted.
+    converting: HashSet<usize>,
+}
+
+impl<'a, D: AbstractObjectSpace> SpaceMigration<'a, D> {
+    /// Start a migration that creates objects in `space`.
+    pub fn new(space: &'a D) -> Self {
+        Self {
+            space,
+            mapped: HashMap::new(),
+            converting: HashSet::new(),
+        }
+    }
+
+    /// Number of objects converted so far.
+    pub fn len(&self) -> usize {
+        self.mapped.len()
+    }
+
+    /// Whether no objects were converted.
+    pub fn is_empty(&self) -> bool {
+        self.mapped.is_empty()
+    }
+
+    /// Register `to` as the converted object of `from`.
+    ///
+    /// Later [`migrate`](struct.SpaceMigration.html#method.migrate) calls
+    /// on `from` return `to`. This is useful for copying cycles.
+    pub fn insert<T: ?Sized, S: AbstractObjectSpace, U: Trace>(
+        &mut self,
+        from: &RawCc<T, S>,
+        to: RawCc<U, D>,
+    ) {
+        self.mapped.insert(source_key(from), Box::new(to));
+    }
+
+    /// Get the converted object of `from`, if it was converted.
+    ///
+    /// Panics if `from` was converted to a different type.
+    pub fn get<T: ?Sized, S: AbstractObjectSpace, U: Trace>(
+        &self,
+        from: &RawCc<T, S>,
+    ) -> Option<RawCc<U, D>> {
+        self.mapped.get(&source_key(from)).map(|to| {
+            to.downcast_ref::<RawCc<U, D>>()
+                .expect("object was migrated to a different type")
+                .clone()
+        })
+    }
+
+    fn migrate_with<T: ?Sized, S: AbstractObjectSpace, U: Trace>(
+        &mut self,
+        from: &RawCc<T, S>,
+        convert: impl FnOnce(&T, &mut Self) -> U,
+        create: impl FnOnce(&D, U) -> RawCc<U, D>,
+    ) -> RawCc<U, D> {
+        if let Some(to) = self.get(from) {
+            return to;
+        }
+        let key = source_key(from);
+        assert!(
+            self.converting.insert(key),
+            "object was migrated recursively (use SpaceMigration::insert to copy cycles)"
+        );
+        let value = {
+            // Prevent a threaded collector from running while reading.
+            let inner = from.inner();
+            let _locked = inner.ref_count.locked();
+            convert(inner.deref(), self)
+        };
+        self.converting.remove(&key);
+        let to = create(self.space, value);
+        self.insert(from, to.clone());
+        to
+    }
+}
+
+impl<'a> SpaceMigration<'a, ThreadedObjectSpace> {
+    /// Convert `from` to a [`ThreadedCc`](type.ThreadedCc.html) using
+    /// `convert`, or return the previously converted object.
+    pub fn migrate<T: ?Sized, S: AbstractObjectSpace, U: Trace + Send + Sync>(
+        &mut self,
+        from: &RawCc<T, S>,
+        convert: impl FnOnce(&T, &mut Self) -> U,
+    ) -> RawCc<U, ThreadedObjectSpace> {
+        self.migrate_with(from, convert, |space, value| space.create(value))
+    }
+}
+
+impl<'a> SpaceMigration<'a, ObjectSpace> {
+    /// Convert `from` to a [`Cc`](type.Cc.html) using `convert`, or return
+    /// the previously converted object.
+    pub fn migrate<T: ?Sized, S: AbstractObjectSpace, U: Trace>(
+        &mut self,
+        from: &RawCc<T, S>,
+        convert: impl FnOnce(&T, &mut Self) -> U,
+    ) -> RawCc<U, ObjectSpace> {
+        self.migrate_with(from, convert, |space, value| space.create(value))
+    }
+}
+
+fn source_key<T: ?Sized, S: AbstractObjectSpace>(cc: &RawCc<T, S>) -> usize {
+    cc.inner() as *const _ as *const () as usize
+}
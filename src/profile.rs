@@ -0,0 +1,167 @@
+//! Per-type timing of `Trace` and `Drop` implementations during collection.
+//!
+//! With the `profiling` feature disabled, the wrappers call the underlying
+//! methods directly.
+
+use crate::cc::CcDyn;
+use crate::Tracer;
+
+#[cfg(feature = "profiling")]
+pub use imp::{last_collection_profile, TypeProfile};
+
+/// Start profiling a collection. Forget the previous profile.
+#[inline]
+pub(crate) fn begin() {
+    #[cfg(feature = "profiling")]
+    imp::begin()
+}
+
+/// `value.gc_traverse(tracer)` with timing.
+#[inline]
+pub(crate) fn gc_traverse(value: &dyn CcDyn, tracer: &mut Tracer) {
+    #[cfg(feature = "profiling")]
+    {
+        imp::measure(value.gc_type_name(), imp::Kind::Trace, || {
+            value.gc_traverse(tracer)
+        })
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    value.gc_traverse(tracer)
+}
+
+/// `drop_t()`, which drops a value of `type_name`, with timing.
+#[inline]
+pub(crate) fn drop_t(type_name: &'static str, drop_t: impl FnOnce()) {
+    #[cfg(feature = "profiling")]
+    {
+        imp::measure(type_name, imp::Kind::Drop, drop_t)
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    {
+        let _ = type_name;
+        drop_t()
+    }
+}
+
+#[cfg(feature = "profiling")]
+mod imp {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    /// Time spent by `Trace` and `Drop` implementations of a type during
+    /// the last collection. Returned by
+    /// [`last_collection_profile`](fn.last_collection_profile.html).
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct TypeProfile {
+        /// Name of the type.
+        pub type_name: &'static str,
+
+        /// Number of `Trace::trace` calls by the collector.
+        pub trace_calls: usize,
+
+        /// Time spent in `Trace::trace`, excluding objects visited
+        /// recursively by the collector.
+        pub trace_time: Duration,
+
+        /// Number of values dropped by the collector.
+        pub drop_calls: usize,
+
+        /// Time spent dropping the values, including values released by
+        /// their `Drop`.
+        pub drop_time: Duration,
+    }
+
+    pub(crate) enum Kind {
+        Trace,
+        Drop,
+    }
+
+    #[derive(Default)]
+    struct Profile {
+        by_type: HashMap<&'static str, TypeProfile>,
+
+        /// Time spent by nested measurements, per active measurement.
+        nested: Vec<Duration>,
+    }
+
+    thread_local!(static PROFILE: RefCell<Profile> = RefCell::new(Profile::default()));
+
+    // `try_with` is used since collection can run when thread-local storage
+    // is being destroyed.
+
+    pub(crate) fn begin() {
+        let _ = PROFILE.try_with(|p| {
+            let mut p = p.borrow_mut();
+            // A collection triggered by `Drop` during a collection extends
+            // the outer profile.
+            if p.nested.is_empty() {
+                p.by_type.clear();
+            }
+        });
+    }
+
+    pub(crate) fn measure(type_name: &'static str, kind: Kind, func: impl FnOnce()) {
+        if PROFILE
+            .try_with(|p| p.borrow_mut().nested.push(Duration::default()))
+            .is_err()
+        {
+            return func();
+        }
+        let start = Instant::now();
+        func();
+        let elapsed = start.elapsed();
+        let _ = PROFILE.try_with(|p| {
+            let mut p = p.borrow_mut();
+            let nested = p.nested.pop().unwrap_or_default();
+            if let Some(parent) = p.nested.last_mut() {
+                *parent += elapsed;
+            }
+            let entry = p.by_type.entry(type_name).or_insert_with(|| TypeProfile {
+                type_name,
+                ..Default::default()
+            });
+            match kind {
+                Kind::Trace => {
+                    entry.trace_calls += 1;
+                    entry.trace_time += elapsed.saturating_sub(nested);
+                }
+                Kind::Drop => {
+                    entry.drop_calls += 1;
+                    entry.drop_time += elapsed;
+                }
+            }
+        });
+    }
+
+    /// Per-type timing of the last collection on the current thread, sorted
+    /// by time spent, highest first.
+    ///
+    /// Covers collections of all spaces run by the current thread. Requires
+    /// the `profiling` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use gcmodule::{Cc, Trace};
+    /// use std::cell::RefCell;
+    ///
+    /// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    /// let a: List = Default::default();
+    /// a.borrow_mut().push(Box::new(a.clone()));
+    /// drop(a);
+    /// gcmodule::collect_thread_cycles();
+    /// let profile = gcmodule::last_collection_profile();
+    /// assert_eq!(profile.len(), 1);
+    /// assert_eq!(profile[0].drop_calls, 1);
+    /// ```
+    pub fn last_collection_profile() -> Vec<TypeProfile> {
+        let mut result: Vec<TypeProfile> =
+            PROFILE.with(|p| p.borrow().by_type.values().cloned().collect());
+        result.sort_by_key(|p| std::cmp::Reverse(p.trace_time + p.drop_time));
+        result
+    }
+}
@@ -0,0 +1,80 @@
+//! Names that ease migrating `Rc<RefCell<T>>` code to [`Cc`](../type.Cc.html).
+//!
+//! Replacing `Rc<RefCell<T>>` with [`GcRc<T>`](type.GcRc.html) keeps most
+//! code compiling as-is, since `borrow` and `borrow_mut` come from
+//! `RefCell` via `Deref`:
+//!
+//! | `std::rc` | `gcmodule` |
+//! |-----------|------------|
+//! | `Rc<RefCell<T>>` | [`GcRc<T>`](type.GcRc.html) |
+//! | `Weak<RefCell<T>>` | [`GcWeak<T>`](type.GcWeak.html) |
+//! | `Rc::new(RefCell::new(v))` | [`gc_rc!(v)`](../macro.gc_rc.html) |
+//! | `Rc::clone(&a)` | `Cc::clone(&a)` |
+//! | `Rc::ptr_eq(&a, &b)` | [`Cc::ptr_eq(&a, &b)`](../struct.RawCc.html#method.ptr_eq) |
+//! | `Rc::downgrade(&a)` | [`Cc::downgrade(&a)`](../struct.RawCc.html#method.downgrade) |
+//! | `Rc::strong_count(&a)` | [`Cc::strong_count(&a)`](../struct.RawCc.html#method.strong_count) |
+//! | `Rc::weak_count(&a)` | [`Cc::weak_count(&a)`](../struct.RawCc.html#method.weak_count) |
+//! | `a.borrow()`, `a.borrow_mut()` | unchanged |
+//! | `w.upgrade()` | unchanged |
+//!
+//! `T` must implement [`Trace`](../trait.Trace.html). Types can be
+//! migrated incrementally:
+//!
+//! 1. Mark types with [`trace_acyclic!`](../macro.trace_acyclic.html).
+//!    They behave like `Rc`. Cycles are not collected.
+//! 2. Replace `trace_acyclic!` with `#[derive(Trace)]` for types that can
+//!    form cycles. Recursive types need a manual implementation. See
+//!    [`Trace::is_type_tracked`](../trait.Trace.html#method.is_type_tracked).
+//!    Then call
+//!    [`collect_thread_cycles`](../fn.collect_thread_cycles.html) to
+//!    collect them.
+//!
+//! # Example
+//!
+#![cfg_attr(feature = "std", doc = " ```")]
+#![cfg_attr(not(feature = "std"), doc = " ```ignore")]
+//! use gcmodule::gc_rc;
+//! use gcmodule::rc_compat::{GcRc, GcWeak};
+//! use gcmodule::{Cc, Trace};
+//!
+//! type List = Vec<Box<dyn Trace>>;
+//! let a: GcRc<List> = gc_rc!(Vec::new());
+//! let weak: GcWeak<List> = Cc::downgrade(&a);
+//! a.borrow_mut().push(Box::new(Cc::clone(&a)));
+//! assert!(Cc::ptr_eq(&weak.upgrade().unwrap(), &a));
+//!
+//! drop(a);
+//! assert_eq!(gcmodule::collect_thread_cycles(), 1);
+//! ```
+
+use crate::Cc;
+use crate::Weak;
+use std::cell::RefCell;
+
+/// Shared mutable value. Counterpart of `Rc<RefCell<T>>`.
+pub type GcRc<T> = Cc<RefCell<T>>;
+
+/// Weak reference to a [`GcRc<T>`](type.GcRc.html). Counterpart of
+/// `Weak<RefCell<T>>`.
+pub type GcWeak<T> = Weak<RefCell<T>>;
+
+/// Constructs a new [`GcRc<T>`](rc_compat/type.GcRc.html), like
+/// `Rc::new(RefCell::new(value))`.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::gc_rc;
+/// use gcmodule::rc_compat::GcRc;
+///
+/// let v: GcRc<Vec<u8>> = gc_rc!(vec![1]);
+/// v.borrow_mut().push(2);
+/// assert_eq!(*v.borrow(), [1, 2]);
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! gc_rc {
+    ( $value: expr ) => {
+        $crate::Cc::new(::std::cell::RefCell::new($value))
+    };
+}
@@ -0,0 +1,121 @@
+//! Number of objects released per type by a collection.
+//!
+//! Aggregated with the `debug` or `events` feature, and reported to the
+//! debug log and [`GcEventKind::Collect`](enum.GcEventKind.html#variant.Collect).
+//! Also aggregated for [`CollectStats`](struct.CollectStats.html) when it
+//! is requested. Otherwise, recording is a no-op.
+
+pub(crate) use imp::{record, Scope};
+
+/// Number of objects of a type released by a collection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReclaimedType {
+    /// Name of the type.
+    pub type_name: &'static str,
+
+    /// Number of objects released.
+    pub count: usize,
+}
+
+#[cfg(feature = "std")]
+mod imp {
+    use super::ReclaimedType;
+    use crate::debug;
+    use std::cell::RefCell;
+    use std::mem;
+
+    // One frame per active collection. A collection triggered by `Drop`
+    // during a collection has its own frame. `None` frames do not record.
+    thread_local!(static FRAMES: RefCell<Vec<Option<Vec<ReclaimedType>>>> = const { RefCell::new(Vec::new()) });
+
+    /// A collection in progress. Objects released are counted until
+    /// `finish`, or drop on panic.
+    pub(crate) struct Scope {
+        active: bool,
+    }
+
+    impl Scope {
+        /// Start counting. Counts are only recorded if `record` is set, or
+        /// the `debug` or `events` feature is enabled.
+        pub(crate) fn begin(record: bool) -> Self {
+            let record = record || cfg!(any(test, feature = "debug", feature = "events"));
+            let frame = if record { Some(Vec::new()) } else { None };
+            // `try_with` is used since collection can run when thread-local
+            // storage is being destroyed.
+            let active = FRAMES.try_with(|f| f.borrow_mut().push(frame)).is_ok();
+            Self { active }
+        }
+
+        /// Stop counting. Return the counts, highest first. Write them to
+        /// the debug log.
+        pub(crate) fn finish(mut self) -> Vec<ReclaimedType> {
+            let mut result = self.pop();
+            result.sort_by(|a, b| b.count.cmp(&a.count).then(a.type_name.cmp(b.type_name)));
+            for entry in result.iter() {
+                debug::log(|| {
+                    (
+                        "collect",
+                        format!("reclaimed {} {}", entry.count, entry.type_name),
+                    )
+                });
+            }
+            result
+        }
+
+        fn pop(&mut self) -> Vec<ReclaimedType> {
+            if !mem::replace(&mut self.active, false) {
+                return Vec::new();
+            }
+            FRAMES
+                .try_with(|f| f.borrow_mut().pop())
+                .ok()
+                .flatten()
+                .flatten()
+                .unwrap_or_default()
+        }
+    }
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            self.pop();
+        }
+    }
+
+    /// Count an object of `type_name` released by the current collection.
+    pub(crate) fn record(type_name: &'static str) {
+        let _ = FRAMES.try_with(|f| {
+            if let Some(Some(frame)) = f.borrow_mut().last_mut() {
+                match frame.iter_mut().find(|e| e.type_name == type_name) {
+                    Some(entry) => entry.count += 1,
+                    None => frame.push(ReclaimedType {
+                        type_name,
+                        count: 1,
+                    }),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    use super::ReclaimedType;
+    use alloc::vec::Vec;
+
+    pub(crate) struct Scope;
+
+    impl Scope {
+        #[inline]
+        pub(crate) fn begin(_record: bool) -> Self {
+            Scope
+        }
+
+        #[inline]
+        pub(crate) fn finish(self) -> Vec<ReclaimedType> {
+            Vec::new()
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record(_type_name: &'static str) {}
+}
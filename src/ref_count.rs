@@ -1,4 +1,6 @@
 use std::cell::Cell;
+#[cfg(feature = "debug")]
+use std::thread::ThreadId;
 
 /// Whether a `GcHeader` exists before the `CcBox<T>`.
 pub(crate) const REF_COUNT_MASK_TRACKED: usize = 0b1;
@@ -6,8 +8,14 @@ pub(crate) const REF_COUNT_MASK_TRACKED: usize = 0b1;
 /// Whether `T` in the `CcBox<T>` has been dropped.
 pub(crate) const REF_COUNT_MASK_DROPPED: usize = 0b10;
 
+/// User-defined tag bits. See `RawCc::set_tag`.
+pub(crate) const REF_COUNT_MASK_TAG: usize = 0b111100;
+
+/// Position of the tag bits.
+pub(crate) const REF_COUNT_TAG_SHIFT: i32 = 2;
+
 /// Number of bits used for metadata.
-pub(crate) const REF_COUNT_SHIFT: i32 = 2;
+pub(crate) const REF_COUNT_SHIFT: i32 = 6;
 
 pub trait RefCount: 'static {
     fn is_tracked(&self) -> bool;
@@ -16,14 +24,22 @@ pub trait RefCount: 'static {
     fn dec_ref(&self) -> usize;
     fn ref_count(&self) -> usize;
     fn set_dropped(&self) -> bool;
+    fn clear_dropped(&self);
+    fn tag(&self) -> u8;
+    fn set_tag(&self, tag: u8);
+
+    /// Panic if accessed from a thread that does not own the object.
+    /// Only checked with the `debug` feature.
+    #[inline]
+    fn check_thread(&self) {}
 
     // Ideally this can be "type Locked<'a> = ..." so there is no need to
     // duplicate the function to make parking_lot optional. However it's not in
     // stable Rust yet. See https://github.com/rust-lang/rust/issues/44265.
     #[cfg(not(feature = "sync"))]
     #[inline]
-    fn locked(&self) -> () {
-        ()
+    fn locked(&self) -> Option<()> {
+        None
     }
 
     #[cfg(feature = "sync")]
@@ -34,22 +50,60 @@ pub trait RefCount: 'static {
         None
     }
 
-    // Weakref support.
+    // Weakref support. Weak counts are separate from the strong count and
+    // its metadata bits. `RawCcBox` is released when both reach 0.
+
+    /// Increase the weak count. Return the old count.
     fn inc_weak(&self) -> usize;
+
+    /// Decrease the weak count. Return the old count.
     fn dec_weak(&self) -> usize;
+
+    /// Get the weak count.
     fn weak_count(&self) -> usize;
 }
 
-pub struct SingleThreadRefCount(Cell<usize>, Cell<usize>);
+pub struct SingleThreadRefCount(
+    Cell<usize>,
+    Cell<usize>,
+    /// The creating thread.
+    #[cfg(feature = "debug")]
+    Option<ThreadId>,
+);
 
 impl SingleThreadRefCount {
     pub fn new(tracked: bool) -> Self {
         let value = (1 << REF_COUNT_SHIFT) | if tracked { REF_COUNT_MASK_TRACKED } else { 0 };
-        Self(Cell::new(value), Cell::new(0))
+        Self(
+            Cell::new(value),
+            Cell::new(0),
+            #[cfg(feature = "debug")]
+            current_thread_id(),
+        )
     }
 }
 
+#[cfg(feature = "debug")]
+thread_local!(static THREAD_ID: ThreadId = std::thread::current().id());
+
+/// `ThreadId` of the current thread. `None` if the thread is exiting.
+#[cfg(feature = "debug")]
+fn current_thread_id() -> Option<ThreadId> {
+    THREAD_ID.try_with(|id| *id).ok()
+}
+
 impl RefCount for SingleThreadRefCount {
+    #[cfg(feature = "debug")]
+    fn check_thread(&self) {
+        if let (Some(owner), Some(current)) = (self.2, current_thread_id()) {
+            assert_eq!(
+                owner, current,
+                "bug: Cc created by thread {:?} is accessed by thread {:?}",
+                owner, current
+            );
+        }
+    }
+
     #[inline]
     fn is_tracked(&self) -> bool {
         Cell::get(&self.0) & REF_COUNT_MASK_TRACKED != 0
@@ -67,11 +121,28 @@ impl RefCount for SingleThreadRefCount {
         value & REF_COUNT_MASK_DROPPED != 0
     }
 
+    #[inline]
+    fn clear_dropped(&self) {
+        let value = Cell::get(&self.0);
+        self.0.set(value & !REF_COUNT_MASK_DROPPED);
+    }
+
     #[inline]
     fn ref_count(&self) -> usize {
         self.0.get() >> REF_COUNT_SHIFT
     }
 
+    #[inline]
+    fn tag(&self) -> u8 {
+        ((self.0.get() & REF_COUNT_MASK_TAG) >> REF_COUNT_TAG_SHIFT) as u8
+    }
+
+    #[inline]
+    fn set_tag(&self, tag: u8) {
+        let value = self.0.get() & !REF_COUNT_MASK_TAG;
+        self.0.set(value | ((tag as usize) << REF_COUNT_TAG_SHIFT));
+    }
+
     #[inline]
     fn inc_ref(&self) -> usize {
         let value = Cell::get(&self.0);
@@ -89,7 +160,8 @@ impl RefCount for SingleThreadRefCount {
     #[inline]
     fn inc_weak(&self) -> usize {
         let value = Cell::get(&self.1);
-        self.1.set(value + 1);
+        self.1
+            .set(value.checked_add(1).expect("weak count overflow"));
         value
     }
 
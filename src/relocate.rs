@@ -0,0 +1,155 @@
+//! Experimental deduplication of immutable values during collection.
+//!
+//! A [`Relocatable<T>`](struct.Relocatable.html) either owns a value, or
+//! forwards to another `Relocatable<T>` with an equivalent value. A
+//! relocation pass finds objects whose values can be merged, and replaces
+//! all but one of them with forwarded handles, releasing the duplicated
+//! values.
+
+use crate::cc::CcDyn;
+use crate::cc::RawCcBox;
+use crate::collect::visit_list;
+use crate::collect::GcHeader;
+use crate::collect::Linked;
+use crate::debug;
+use crate::Cc;
+use crate::ObjectSpace;
+use crate::Trace;
+use crate::Tracer;
+use alloc::format;
+use alloc::vec::Vec;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::mem;
+
+/// A value that can be replaced by a forwarded handle to an equivalent
+/// value by [`ObjectSpace::relocate`](struct.ObjectSpace.html#method.relocate).
+///
+/// Values are only accessible via
+/// [`with`](struct.Relocatable.html#method.with), which follows forwarded
+/// handles. Since relocation might happen at any collection, `T` should be
+/// treated as immutable.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{ObjectSpace, Relocatable};
+///
+/// let space = ObjectSpace::default();
+/// let a = space.create(Relocatable::new(String::from("x")));
+/// let b = space.create(Relocatable::new(String::from("x")));
+/// assert_eq!(space.relocate::<String>(|a, b| a == b), 1);
+/// assert!(b.is_forwarded());
+/// assert_eq!(b.with(|s| s.clone()), "x");
+/// # drop(a);
+/// ```
+pub struct Relocatable<T: Trace>(RefCell<Slot<T>>);
+
+enum Slot<T: Trace> {
+    Value(T),
+    Forwarded(Cc<Relocatable<T>>),
+}
+
+impl<T: Trace> Relocatable<T> {
+    /// Wrap `value` so it can be relocated.
+    pub fn new(value: T) -> Self {
+        Self(RefCell::new(Slot::Value(value)))
+    }
+
+    /// Call `func` with the value, following forwarded handles.
+    ///
+    /// Objects being accessed are not relocated.
+    pub fn with<R>(&self, func: impl FnOnce(&T) -> R) -> R {
+        match &*self.0.borrow() {
+            Slot::Value(value) => func(value),
+            Slot::Forwarded(target) => target.with(func),
+        }
+    }
+
+    /// Whether the value was replaced by a forwarded handle.
+    pub fn is_forwarded(&self) -> bool {
+        matches!(&*self.0.borrow(), Slot::Forwarded(_))
+    }
+
+    fn slot(&self) -> &RefCell<Slot<T>> {
+        &self.0
+    }
+}
+
+impl<T: Trace> Trace for Relocatable<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(slot) = self.0.try_borrow() {
+            match &*slot {
+                Slot::Value(value) => value.trace(tracer),
+                Slot::Forwarded(target) => target.trace(tracer),
+            }
+        }
+    }
+
+    #[inline]
+    fn is_type_tracked() -> bool {
+        // Forwarded handles are `Cc`s.
+        true
+    }
+}
+
+/// Merge `Relocatable<T>` objects in `list` whose values are accepted by
+/// `try_merge`. Return the number of objects forwarded.
+pub(crate) fn relocate_list<T: Trace>(
+    list: &GcHeader,
+    try_merge: &dyn Fn(&T, &T) -> bool,
+) -> usize {
+    let type_id = TypeId::of::<Relocatable<T>>();
+    let mut candidates: Vec<Cc<Relocatable<T>>> = Vec::new();
+    visit_list(list, |header| {
+        let value: &dyn CcDyn = header.value();
+        // Objects with ref count 0 are being dropped, or were dropped but
+        // kept alive by weak references.
+        if value.gc_type_id() == type_id && value.gc_ref_count() > 0 {
+            // safety: The type is checked by TypeId.
+            let cc_box = unsafe {
+                &*(value as *const dyn CcDyn as *const RawCcBox<Relocatable<T>, ObjectSpace>)
+            };
+            candidates.push(cc_box.new_ref());
+        }
+    });
+    // The list is newest first. Prefer keeping older objects.
+    candidates.reverse();
+
+    let mut representatives: Vec<&Cc<Relocatable<T>>> = Vec::new();
+    // Replaced values are dropped after the pass, since their `Drop` might
+    // run arbitrary code.
+    let mut replaced: Vec<Slot<T>> = Vec::new();
+    for candidate in candidates.iter() {
+        let mut slot = match candidate.slot().try_borrow_mut() {
+            Ok(slot) => slot,
+            Err(_) => continue, // Being accessed.
+        };
+        let value = match &*slot {
+            Slot::Value(value) => value,
+            Slot::Forwarded(_) => continue,
+        };
+        let target = representatives
+            .iter()
+            .find(|rep| match rep.slot().try_borrow() {
+                Ok(rep_slot) => match &*rep_slot {
+                    Slot::Value(rep_value) => try_merge(rep_value, value),
+                    Slot::Forwarded(_) => false,
+                },
+                Err(_) => false,
+            });
+        match target {
+            Some(target) => {
+                let forwarded = Slot::Forwarded((*target).clone());
+                replaced.push(mem::replace(&mut *slot, forwarded));
+            }
+            None => representatives.push(candidate),
+        }
+    }
+
+    let count = replaced.len();
+    debug::log(|| ("relocate", format!("{} objects forwarded", count)));
+    drop(representatives);
+    drop(replaced);
+    count
+}
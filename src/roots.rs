@@ -0,0 +1,53 @@
+//! Roots for native frames. See `AbstractObjectSpace::with_roots`.
+
+use crate::collect::AbstractObjectSpace;
+use crate::RawCc;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+/// Objects kept alive during
+/// [`with_roots`](trait.AbstractObjectSpace.html#method.with_roots).
+///
+/// Rooted objects are referred by `Roots`, so they are reachable for the
+/// collector, including collections in other threads.
+pub struct Roots<O: AbstractObjectSpace> {
+    /// Type-erased `RawCc<T, O>`s.
+    objects: Vec<Box<dyn Any>>,
+    _space: PhantomData<O>,
+}
+
+impl<O: AbstractObjectSpace> Roots<O> {
+    pub(crate) fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+            _space: PhantomData,
+        }
+    }
+
+    /// Keep `value` alive until `with_roots` returns. Return a pointer to
+    /// the value that is valid until then.
+    pub fn root<T: ?Sized + 'static>(&mut self, value: &RawCc<T, O>) -> NonNull<T> {
+        self.objects.push(Box::new(value.clone()));
+        RawCc::as_non_null(value)
+    }
+
+    /// Number of rooted objects.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Whether no objects are rooted.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl<O: AbstractObjectSpace> fmt::Debug for Roots<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Roots").field("len", &self.len()).finish()
+    }
+}
@@ -0,0 +1,100 @@
+use crate::collect::AbstractObjectSpace;
+use crate::collect::ObjectSpace;
+use crate::Trace;
+use crate::Tracer;
+use crate::{RawCc, RawWeak};
+use std::any::Any;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// A weak reference from a value to the [`Cc<T>`](type.Cc.html) owning it.
+///
+/// Embed `SelfRef<Self>` as a field of a type deriving
+/// [`Trace`](trait.Trace.html). The field is initialized when the value is
+/// moved into a [`Cc<T>`](type.Cc.html) by [`Cc::new`](type.Cc.html#method.new),
+/// [`Cc::new_cyclic`](type.Cc.html#method.new_cyclic), or the `create`
+/// methods of spaces. Before that, the accessors return `None`.
+///
+/// Fields of nested structures deriving `Trace` are initialized too, if
+/// their type matches the `Cc<T>`. Manual `Trace` implementations do not
+/// initialize `SelfRef` fields.
+///
+/// Use `SelfRef<T, ThreadedObjectSpace>` for
+/// [`ThreadedCc<T>`](type.ThreadedCc.html).
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, SelfRef, Trace};
+///
+/// #[derive(Trace)]
+/// struct Node {
+///     this: SelfRef<Node>,
+///     name: String,
+/// }
+///
+/// let node = Node { this: SelfRef::new(), name: "a".to_string() };
+/// assert!(node.this.get().is_none());
+/// let node = Cc::new(node);
+/// assert!(Cc::ptr_eq(&node, &node.this.get().unwrap()));
+/// ```
+pub struct SelfRef<T: ?Sized, O: AbstractObjectSpace = ObjectSpace> {
+    weak: OnceLock<RawWeak<T, O>>,
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> SelfRef<T, O> {
+    /// Constructs an uninitialized `SelfRef`.
+    pub const fn new() -> Self {
+        Self {
+            weak: OnceLock::new(),
+        }
+    }
+
+    /// Obtain a strong reference to the owning `Cc<T>`.
+    ///
+    /// Return `None` if the value is not owned by a `Cc<T>` yet, or is
+    /// being dropped.
+    pub fn get(&self) -> Option<RawCc<T, O>> {
+        self.weak.get()?.upgrade()
+    }
+
+    /// The weak reference to the owning `Cc<T>`, or `None` if the value is
+    /// not owned by a `Cc<T>` yet.
+    pub fn weak(&self) -> Option<&RawWeak<T, O>> {
+        self.weak.get()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> Default for SelfRef<T, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> fmt::Debug for SelfRef<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelfRef")
+            .field("initialized", &self.weak.get().is_some())
+            .finish()
+    }
+}
+
+impl<T: ?Sized + 'static, O: AbstractObjectSpace> Trace for SelfRef<T, O> {
+    fn trace(&self, _tracer: &mut Tracer) {
+        // Weak references are not traced.
+    }
+
+    fn is_type_tracked() -> bool {
+        false
+    }
+
+    fn __gcmodule_has_self_ref() -> bool {
+        true
+    }
+
+    fn __gcmodule_init_self_ref(&self, weak: &dyn Any) {
+        if let Some(weak) = weak.downcast_ref::<RawWeak<T, O>>() {
+            let _ = self.weak.set(weak.clone());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! `serde` support for [`Cc<T>`](type.Cc.html) and
+//! [`ThreadedCc<T>`](type.ThreadedCc.html).
+//!
+//! By default, a `Cc<T>` is serialized as its inner value. Shared values are
+//! serialized once per reference, and deserialized as separate objects.
+//!
+//! Within [`serialize_shared`](fn.serialize_shared.html) and
+//! [`deserialize_shared`](fn.deserialize_shared.html), a `Cc<T>` is
+//! serialized as a sequence instead: `[id, value]` on the first
+//! occurrence, and `[id]` after that, so sharing is preserved.
+
+use crate::cc::RawCc;
+use crate::collect::AbstractObjectSpace;
+
+use crate::Cc;
+use serde::ser::{Serialize, Serializer};
+use std::ops::Deref;
+
+#[cfg(feature = "std")]
+use crate::collect::ObjectSpace;
+#[cfg(feature = "std")]
+use crate::Trace;
+#[cfg(feature = "std")]
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+#[cfg(feature = "std")]
+use serde::ser::{Error as _, SerializeSeq};
+#[cfg(feature = "std")]
+use std::any::{Any, TypeId};
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::marker::PhantomData;
+
+#[cfg(feature = "sync")]
+use crate::sync::collect::ThreadedObjectSpace;
+#[cfg(feature = "sync")]
+use crate::ThreadedCc;
+
+/// Serializes the inner value. Shared values are serialized once per
+/// reference, unless within [`serialize_shared`](fn.serialize_shared.html).
+impl<T: Serialize + ?Sized> Serialize for Cc<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_cc(self, self.deref(), serializer)
+    }
+}
+
+/// Serializes the inner value. Shared values are serialized once per
+/// reference, unless within [`serialize_shared`](fn.serialize_shared.html).
+#[cfg(feature = "sync")]
+impl<T: Serialize + ?Sized> Serialize for ThreadedCc<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_cc(self, self.borrow().deref(), serializer)
+    }
+}
+
+/// Serialize `value` owned by `cc`.
+#[allow(unused_variables)]
+fn serialize_cc<T, O, S>(cc: &RawCc<T, O>, value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + ?Sized,
+    O: AbstractObjectSpace,
+    S: Serializer,
+{
+    #[cfg(feature = "std")]
+    {
+        let addr = cc.inner() as *const _ as *const u8 as usize;
+        if let Some(seen) = SESSION.with(|s| s.borrow_mut().as_mut().map(|s| s.seen(addr))) {
+            let (id, first) = match seen {
+                Seen::First(id) => (id, true),
+                Seen::Again(id) => (id, false),
+                Seen::InProgress => {
+                    return Err(S::Error::custom("cannot serialize cyclic references"))
+                }
+            };
+            let mut seq = serializer.serialize_seq(Some(if first { 2 } else { 1 }))?;
+            seq.serialize_element(&id)?;
+            if first {
+                seq.serialize_element(value)?;
+                SESSION.with(|s| s.borrow_mut().as_mut().map(|s| s.done(addr)));
+            }
+            return seq.end();
+        }
+    }
+    value.serialize(serializer)
+}
+
+/// Deserializes a value, and moves it into a `Cc<T>` created by
+/// [`Cc::new`](type.Cc.html#method.new), or in the space passed to
+/// [`deserialize_shared_in`](fn.deserialize_shared_in.html).
+#[cfg(feature = "std")]
+impl<'de, T: Deserialize<'de> + Trace> Deserialize<'de> for Cc<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_in_session(deserializer, |value| match session_space::<ObjectSpace>() {
+            // safety: The space outlives the session.
+            Some(space) => Ok(unsafe { &*space }.create(value)),
+            None => Ok(Cc::new(value)),
+        })
+    }
+}
+
+/// Deserializes a value, and moves it into a `ThreadedCc<T>` created in the
+/// space passed to [`deserialize_shared_in`](fn.deserialize_shared_in.html).
+/// Fails outside `deserialize_shared_in`, since there is no default space.
+#[cfg(feature = "sync")]
+impl<'de, T: Deserialize<'de> + Trace + Send + Sync> Deserialize<'de> for ThreadedCc<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_in_session(deserializer, |value| {
+            match session_space::<ThreadedObjectSpace>() {
+                // safety: The space outlives the session.
+                Some(space) => Ok(unsafe { &*space }.create(value)),
+                None => {
+                    Err("ThreadedCc can only be deserialized by deserialize_shared_in".to_string())
+                }
+            }
+        })
+    }
+}
+
+/// Serializes `value` with sharing of [`Cc<T>`](type.Cc.html) and
+/// [`ThreadedCc<T>`](type.ThreadedCc.html) preserved.
+///
+/// A `Cc<T>` referred more than once is serialized once. Use
+/// [`deserialize_shared`](fn.deserialize_shared.html) to restore the
+/// sharing. Cyclic references cannot be serialized and cause an error.
+///
+/// Requires the `serde` feature.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::Cc;
+///
+/// let a = Cc::new(1);
+/// let list = vec![a.clone(), a.clone()];
+///
+/// let mut buf = Vec::new();
+/// gcmodule::serialize_shared(&list, &mut serde_json::Serializer::new(&mut buf)).unwrap();
+/// assert_eq!(buf, b"[[0,1],[0]]");
+///
+/// let list: Vec<Cc<i32>> =
+///     gcmodule::deserialize_shared(&mut serde_json::Deserializer::from_slice(&buf)).unwrap();
+/// assert!(Cc::ptr_eq(&list[0], &list[1]));
+/// ```
+#[cfg(feature = "std")]
+pub fn serialize_shared<T: Serialize + ?Sized, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let _session = SessionGuard::new(Session::default());
+    value.serialize(serializer)
+}
+
+/// Deserializes a value written by
+/// [`serialize_shared`](fn.serialize_shared.html). `Cc<T>`s are created by
+/// [`Cc::new`](type.Cc.html#method.new).
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "std")]
+pub fn deserialize_shared<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let _session = SessionGuard::new(Session::default());
+    T::deserialize(deserializer)
+}
+
+/// Like [`deserialize_shared`](fn.deserialize_shared.html), but create
+/// objects in `space`. `space` can be an
+/// [`ObjectSpace`](struct.ObjectSpace.html) for `Cc<T>`, or a
+/// [`ThreadedObjectSpace`](struct.ThreadedObjectSpace.html) for
+/// `ThreadedCc<T>`.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "std")]
+pub fn deserialize_shared_in<'de, T: Deserialize<'de>, D: Deserializer<'de>, O>(
+    space: &O,
+    deserializer: D,
+) -> Result<T, D::Error>
+where
+    O: AbstractObjectSpace,
+{
+    let _session = SessionGuard::new(Session {
+        space: Some((TypeId::of::<O>(), space as *const O as *const ())),
+        ..Default::default()
+    });
+    T::deserialize(deserializer)
+}
+
+/// States of `serialize_shared` or `deserialize_shared`.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Session {
+    /// Serialization: address of `CcBox` -> (id, in progress).
+    ids: HashMap<usize, (u64, bool)>,
+
+    /// Deserialization: id -> `RawCc<T, O>`.
+    objects: HashMap<u64, Box<dyn Any>>,
+
+    /// Deserialization: the space to create objects in.
+    space: Option<(TypeId, *const ())>,
+}
+
+#[cfg(feature = "std")]
+enum Seen {
+    First(u64),
+    Again(u64),
+    InProgress,
+}
+
+#[cfg(feature = "std")]
+impl Session {
+    fn seen(&mut self, addr: usize) -> Seen {
+        let next_id = self.ids.len() as u64;
+        match self.ids.get(&addr) {
+            Some(&(_, true)) => Seen::InProgress,
+            Some(&(id, false)) => Seen::Again(id),
+            None => {
+                self.ids.insert(addr, (next_id, true));
+                Seen::First(next_id)
+            }
+        }
+    }
+
+    fn done(&mut self, addr: usize) {
+        if let Some(entry) = self.ids.get_mut(&addr) {
+            entry.1 = false;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local!(static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) });
+
+/// Installs a session. Restores the previous session on drop.
+#[cfg(feature = "std")]
+struct SessionGuard(Option<Session>);
+
+#[cfg(feature = "std")]
+impl SessionGuard {
+    fn new(session: Session) -> Self {
+        Self(SESSION.with(|s| s.replace(Some(session))))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let session = SESSION.with(|s| s.replace(previous));
+        // Drop objects outside the `RefCell` borrow.
+        drop(session);
+    }
+}
+
+/// The space passed to `deserialize_shared_in`, if it has type `O`.
+#[cfg(feature = "std")]
+fn session_space<O: AbstractObjectSpace>() -> Option<*const O> {
+    SESSION.with(|s| match s.borrow().as_ref()?.space {
+        Some((type_id, space)) if type_id == TypeId::of::<O>() => Some(space as *const O),
+        _ => None,
+    })
+}
+
+/// Deserialize a `RawCc<T, O>`. Use `create` to move a new value into a
+/// `RawCc<T, O>`.
+#[cfg(feature = "std")]
+fn deserialize_in_session<'de, D, T, O>(
+    deserializer: D,
+    create: impl FnOnce(T) -> Result<RawCc<T, O>, String>,
+) -> Result<RawCc<T, O>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + 'static,
+    O: AbstractObjectSpace,
+{
+    if SESSION.with(|s| s.borrow().is_none()) {
+        let value = T::deserialize(deserializer)?;
+        return create(value).map_err(de::Error::custom);
+    }
+    deserializer.deserialize_seq(SharedVisitor(create, PhantomData))
+}
+
+#[cfg(feature = "std")]
+struct SharedVisitor<F, T>(F, PhantomData<T>);
+
+#[cfg(feature = "std")]
+impl<'de, F, T, O> Visitor<'de> for SharedVisitor<F, T>
+where
+    F: FnOnce(T) -> Result<RawCc<T, O>, String>,
+    T: Deserialize<'de> + 'static,
+    O: AbstractObjectSpace,
+{
+    type Value = RawCc<T, O>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of id and optional value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let id: u64 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        match seq.next_element::<T>()? {
+            Some(value) => {
+                let cc = (self.0)(value).map_err(de::Error::custom)?;
+                let any: Box<dyn Any> = Box::new(cc.clone());
+                let replaced = SESSION.with(|s| {
+                    s.borrow_mut()
+                        .as_mut()
+                        .and_then(|s| s.objects.insert(id, any))
+                });
+                drop(replaced);
+                Ok(cc)
+            }
+            None => SESSION
+                .with(|s| {
+                    let s = s.borrow();
+                    let cc = s
+                        .as_ref()?
+                        .objects
+                        .get(&id)?
+                        .downcast_ref::<RawCc<T, O>>()?;
+                    Some(cc.clone())
+                })
+                .ok_or_else(|| de::Error::custom(format!("unknown shared id {}", id))),
+        }
+    }
+}
@@ -1,13 +1,14 @@
 pub(crate) mod collect;
+#[cfg(feature = "parallel")]
+mod parallel;
 mod ref_count;
 
 #[cfg(test)]
 mod tests;
 
 use crate::cc::RawCc;
+use crate::cc::RawWeak;
 use crate::ref_count::RefCount;
-use crate::Trace;
-use crate::Tracer;
 use collect::ThreadedObjectSpace;
 use parking_lot::lock_api::RwLockReadGuard;
 use parking_lot::RawRwLock;
@@ -25,6 +26,9 @@ use std::ops::Deref;
 /// [`ThreadedObjectSpace::create`](struct.ThreadedObjectSpace.html#method.create).
 pub type ThreadedCc<T> = RawCc<T, ThreadedObjectSpace>;
 
+/// Weak reference of [`ThreadedCc`](type.ThreadedCc.html).
+pub type ThreadedWeak<T> = RawWeak<T, ThreadedObjectSpace>;
+
 /// Wraps a borrowed reference to [`ThreadedCc`](type.ThreadedCc.html).
 ///
 /// The wrapper automatically takes a lock that prevents the collector from
@@ -48,6 +52,10 @@ pub struct ThreadedCcRef<'a, T: ?Sized> {
 unsafe impl<T: Send + Sync + ?Sized> Send for ThreadedCc<T> {}
 unsafe impl<T: Send + Sync + ?Sized> Sync for ThreadedCc<T> {}
 
+// safety: similar to `std::sync::Weak`
+unsafe impl<T: Send + Sync + ?Sized> Send for RawWeak<T, ThreadedObjectSpace> {}
+unsafe impl<T: Send + Sync + ?Sized> Sync for RawWeak<T, ThreadedObjectSpace> {}
+
 impl<T: ?Sized> ThreadedCc<T> {
     /// Immutably borrows the wrapped value.
     ///
@@ -65,54 +73,7 @@ impl<'a, T: ?Sized> Deref for ThreadedCcRef<'a, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        let _ = &self.locked;
+        let _locked = &self.locked;
         self.parent.inner().deref()
     }
 }
-
-impl<T: Trace> Trace for ThreadedCc<T> {
-    fn trace(&self, tracer: &mut Tracer) {
-        self.inner().trace_t(tracer)
-    }
-
-    #[inline]
-    fn is_type_tracked() -> bool {
-        T::is_type_tracked()
-    }
-}
-
-impl Trace for ThreadedCc<dyn Trace> {
-    fn trace(&self, tracer: &mut Tracer) {
-        self.inner().trace_t(tracer)
-    }
-
-    #[inline]
-    fn is_type_tracked() -> bool {
-        // Trait objects can be anything.
-        true
-    }
-}
-
-impl Trace for ThreadedCc<dyn Trace + Send> {
-    fn trace(&self, tracer: &mut Tracer) {
-        self.inner().trace_t(tracer)
-    }
-
-    #[inline]
-    fn is_type_tracked() -> bool {
-        // Trait objects can be anything.
-        true
-    }
-}
-
-impl Trace for ThreadedCc<dyn Trace + Send + Sync> {
-    fn trace(&self, tracer: &mut Tracer) {
-        self.inner().trace_t(tracer)
-    }
-
-    #[inline]
-    fn is_type_tracked() -> bool {
-        // Trait objects can be anything.
-        true
-    }
-}
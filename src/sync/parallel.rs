@@ -0,0 +1,149 @@
+//! Parallel marking for `ThreadedObjectSpace`, enabled by the `parallel`
+//! feature.
+//!
+//! The algorithm is the same as `collect::collect_list`. `subtract_refs`
+//! and `mark_reachable` run in the current rayon thread pool. The caller
+//! holds the collector lock, so values cannot be mutated via
+//! `ThreadedCcRef` while they are traced by other threads.
+
+use super::collect::Header;
+use crate::collect;
+use crate::collect::Linked;
+use crate::collect::PREV_MASK_COLLECTING;
+use crate::collect::PREV_MASK_VISITED;
+use crate::collect::PREV_SHIFT;
+use crate::debug;
+use alloc::vec::Vec;
+use rayon::prelude::*;
+use std::mem;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Lists shorter than this are collected in the current thread. Parallel
+/// marking does not pay off for them.
+pub(crate) const MIN_PARALLEL_LEN: usize = 4096;
+
+/// Number of headers handled by a task before work is split.
+const CHUNK_LEN: usize = 1024;
+
+// `Header.prev` is accessed as `AtomicUsize`.
+const _: () = assert!(mem::size_of::<AtomicUsize>() == mem::size_of::<*const Header>());
+const _: () = assert!(mem::align_of::<AtomicUsize>() == mem::align_of::<*const Header>());
+
+/// Pointer to a `Header` that can be sent to other threads.
+#[derive(Clone, Copy)]
+struct HeaderPtr(*const Header);
+
+// safety: Values in `ThreadedObjectSpace` are `Send + Sync`. The linked
+// list is locked so headers stay alive.
+unsafe impl Send for HeaderPtr {}
+unsafe impl Sync for HeaderPtr {}
+
+impl HeaderPtr {
+    fn get(&self) -> &Header {
+        // safety: The linked list is locked. Pointers in it are valid.
+        unsafe { &*self.0 }
+    }
+}
+
+/// Scan the specified linked list like `collect::collect_list`, with
+/// marking done by multiple threads.
+pub(crate) fn collect_list<K>(list: &Header, lock: K) -> usize {
+    let mut headers = Vec::new();
+    collect::visit_list(list, |header| headers.push(HeaderPtr(header)));
+    if headers.len() < MIN_PARALLEL_LEN {
+        return collect::collect_list(list, lock);
+    }
+    debug::log(|| ("collect", "parallel marking"));
+    collect::update_refs(list);
+    // Accesses to `prev` are atomic until the pool finishes. The pool
+    // joins the current thread so plain accesses can be used again.
+    subtract_refs(&headers);
+    mark_reachable(&headers);
+    collect::release_unreachable(list, lock)
+}
+
+/// `Header.prev` as an atomic integer. See `collect::update_refs` for
+/// the bits.
+fn prev(header: &Header) -> &AtomicUsize {
+    // safety: Size and alignment are checked above. During parallel
+    // marking, `prev` is only accessed via this.
+    unsafe { AtomicUsize::from_ptr(header.prev.as_ptr() as *mut usize) }
+}
+
+/// Parallel version of `collect::subtract_refs`.
+fn subtract_refs(headers: &[HeaderPtr]) {
+    headers.par_iter().with_min_len(CHUNK_LEN).for_each(|header| {
+        let header = header.get();
+        if prev(header).load(Relaxed) & PREV_MASK_COLLECTING == 0 {
+            return;
+        }
+        let old = prev(header).fetch_or(PREV_MASK_VISITED, Relaxed);
+        debug_assert!(
+            old & PREV_MASK_VISITED == 0,
+            "bug: double visit: {} (is Trace impl correct?)",
+            collect::debug_name(header)
+        );
+        header.value().gc_traverse(&mut |ptr: *const ()| {
+            // safety: The type is known to be Header.
+            let header = unsafe { &*(ptr as *const Header) };
+            if prev(header).load(Relaxed) & PREV_MASK_COLLECTING != 0 {
+                let old = prev(header).fetch_sub(1 << PREV_SHIFT, Relaxed);
+                debug_assert!(
+                    old >> PREV_SHIFT > 0,
+                    "bug: object {} becomes unreachable while trying to dec_ref (is Trace impl correct?)",
+                    collect::debug_name(header)
+                );
+            }
+        });
+    });
+}
+
+/// Parallel version of `collect::mark_reachable`. Objects with non-zero
+/// ref counts and objects they refer to have the COLLECTING flag unset.
+fn mark_reachable(headers: &[HeaderPtr]) {
+    rayon::scope(|scope| {
+        for chunk in headers.chunks(CHUNK_LEN) {
+            scope.spawn(move |scope| {
+                let stack = chunk
+                    .iter()
+                    .copied()
+                    .filter(|header| {
+                        let prev = prev(header.get()).load(Relaxed);
+                        prev & PREV_MASK_COLLECTING != 0 && prev >> PREV_SHIFT > 0
+                    })
+                    .filter(|header| claim(header.get()))
+                    .collect();
+                revive(scope, stack);
+            });
+        }
+    });
+}
+
+/// Unset the COLLECTING flag. Return `true` if it was set, in which case
+/// the current thread should traverse the object.
+fn claim(header: &Header) -> bool {
+    // Check first to avoid writing to objects that are not collecting,
+    // ex. objects in other spaces.
+    prev(header).load(Relaxed) & PREV_MASK_COLLECTING != 0
+        && prev(header).fetch_and(!PREV_MASK_COLLECTING, Relaxed) & PREV_MASK_COLLECTING != 0
+}
+
+/// Mark objects referred by `stack` as reachable recursively. Unlike
+/// `collect::revive`, ref counts are not updated, since objects without
+/// the COLLECTING flag are not unreachable regardless of their counts.
+fn revive<'s>(scope: &rayon::Scope<'s>, mut stack: Vec<HeaderPtr>) {
+    while let Some(header) = stack.pop() {
+        header.get().value().gc_traverse(&mut |ptr: *const ()| {
+            // safety: The type is known to be Header.
+            let header = unsafe { &*(ptr as *const Header) };
+            if claim(header) {
+                stack.push(HeaderPtr(header));
+            }
+        });
+        if stack.len() >= CHUNK_LEN {
+            let rest = stack.split_off(stack.len() / 2);
+            scope.spawn(move |scope| revive(scope, rest));
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::ref_count::{RefCount, REF_COUNT_MASK_DROPPED, REF_COUNT_MASK_TRACKED, REF_COUNT_SHIFT};
+use crate::ref_count::{
+    RefCount, REF_COUNT_MASK_DROPPED, REF_COUNT_MASK_TAG, REF_COUNT_MASK_TRACKED, REF_COUNT_SHIFT,
+    REF_COUNT_TAG_SHIFT,
+};
 use parking_lot::lock_api::RwLockReadGuard;
 use parking_lot::RawRwLock;
 use parking_lot::RwLock;
@@ -6,6 +9,12 @@ use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed};
 use std::sync::Arc;
 
+/// Reference counts of a `ThreadedCc`.
+///
+/// With the `padded_ref_count` feature, the counts occupy a cache line, so
+/// threads updating counts of different objects do not contend on the same
+/// cache line.
+#[cfg_attr(feature = "padded_ref_count", repr(align(64)))]
 pub struct ThreadedRefCount {
     ref_count: AtomicUsize,
     weak_count: AtomicUsize,
@@ -16,7 +25,7 @@ impl ThreadedRefCount {
     #[inline]
     pub(crate) fn new(tracked: bool, collector_lock: Arc<RwLock<()>>) -> Self {
         Self {
-            collector_lock: collector_lock,
+            collector_lock,
             ref_count: AtomicUsize::new(
                 (1 << REF_COUNT_SHIFT) | if tracked { REF_COUNT_MASK_TRACKED } else { 0 },
             ),
@@ -42,11 +51,29 @@ impl RefCount for ThreadedRefCount {
         old_value & REF_COUNT_MASK_DROPPED != 0
     }
 
+    #[inline]
+    fn clear_dropped(&self) {
+        self.ref_count.fetch_and(!REF_COUNT_MASK_DROPPED, AcqRel);
+    }
+
     #[inline]
     fn ref_count(&self) -> usize {
         self.ref_count.load(Acquire) >> REF_COUNT_SHIFT
     }
 
+    #[inline]
+    fn tag(&self) -> u8 {
+        ((self.ref_count.load(Acquire) & REF_COUNT_MASK_TAG) >> REF_COUNT_TAG_SHIFT) as u8
+    }
+
+    #[inline]
+    fn set_tag(&self, tag: u8) {
+        let bits = (tag as usize) << REF_COUNT_TAG_SHIFT;
+        let _ = self.ref_count.fetch_update(AcqRel, Acquire, |value| {
+            Some((value & !REF_COUNT_MASK_TAG) | bits)
+        });
+    }
+
     #[inline]
     fn inc_ref(&self) -> usize {
         self.ref_count.fetch_add(1 << REF_COUNT_SHIFT, AcqRel) >> REF_COUNT_SHIFT
@@ -64,7 +91,12 @@ impl RefCount for ThreadedRefCount {
 
     #[inline]
     fn inc_weak(&self) -> usize {
-        self.weak_count.fetch_add(1, AcqRel)
+        let value = self.weak_count.fetch_add(1, AcqRel);
+        if value == usize::MAX {
+            self.weak_count.fetch_sub(1, AcqRel);
+            panic!("weak count overflow");
+        }
+        value
     }
 
     #[inline]
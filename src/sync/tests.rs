@@ -4,7 +4,9 @@ use crate::Trace;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::thread::spawn;
+use std::time::Duration;
 
 type List = ThreadedCc<Mutex<Vec<Box<dyn Trace + Send + Sync>>>>;
 
@@ -33,7 +35,7 @@ fn test_cross_thread_cycle(n: usize) {
         })
     };
 
-    let threads: Vec<_> = (0..n).map(|i| spawn_thread(i)).collect();
+    let threads: Vec<_> = (0..n).map(spawn_thread).collect();
     for thread in threads {
         thread.join().unwrap();
     }
@@ -97,9 +99,9 @@ fn test_racy_threads(
                             }
                         }
                         if (create_cycles_bits >> i) & 1 == 1 {
-                            for j in 0..thread_count {
+                            for (j, tx) in tx_list.iter().enumerate() {
                                 if j % (i + 1) == 0 {
-                                    let _ = tx_list[j].send(Box::new(acc.clone()));
+                                    let _ = tx.send(Box::new(acc.clone()));
                                 }
                             }
                         }
@@ -143,3 +145,442 @@ fn test_racy_threads_collects() {
 fn test_racy_threads_mixed_collects() {
     test_racy_threads(8, 100, 0b11110000, 0b10101010);
 }
+
+#[test]
+fn test_defer_collection() {
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let (tx, rx) = channel();
+    let thread = space.defer_collection(|| {
+        {
+            let a: List = space.create(Mutex::new(Vec::new()));
+            a.borrow().lock().unwrap().push(Box::new(a.clone()));
+        }
+        let space = space.clone();
+        let thread = spawn(move || tx.send(space.collect_cycles()).unwrap());
+        // The collector is blocked by defer_collection.
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        thread
+    });
+    thread.join().unwrap();
+    assert_eq!(rx.recv().unwrap(), 1);
+}
+
+#[test]
+fn test_create_default() {
+    let space = ThreadedObjectSpace::default();
+    {
+        let a: List = space.create_default();
+        a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    }
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[test]
+fn test_migrate_cycle_from_local_space() {
+    use crate::{Cc, SpaceMigration};
+    use std::cell::RefCell;
+
+    type LocalList = Cc<RefCell<Vec<Cc<dyn Trace>>>>;
+
+    let a: LocalList = Default::default();
+    let b: LocalList = Default::default();
+    a.borrow_mut().push(b.clone().into_dyn());
+    b.borrow_mut().push(a.clone().into_dyn());
+
+    let space = ThreadedObjectSpace::default();
+    {
+        let mut migration = SpaceMigration::new(&space);
+        let a2: List = space.create(Mutex::new(Vec::new()));
+        migration.insert(&a, a2.clone());
+        let b2: List = migration.migrate(&b, |_, _| Mutex::new(Vec::new()));
+        b2.borrow().lock().unwrap().push(Box::new(a2.clone()));
+        a2.borrow().lock().unwrap().push(Box::new(b2));
+        assert_eq!(migration.len(), 2);
+        let a3: List = migration.get(&a).unwrap();
+        assert!(std::ptr::eq(&*a2.borrow(), &*a3.borrow()));
+    }
+    assert_eq!(space.count_tracked(), 2);
+    assert_eq!(space.collect_cycles(), 2);
+
+    drop((a, b));
+    assert_eq!(crate::collect_thread_cycles(), 2);
+}
+
+#[cfg(feature = "padded_ref_count")]
+#[test]
+fn test_padded_ref_count() {
+    let space = ThreadedObjectSpace::default();
+    let a: ThreadedCc<usize> = space.create(1);
+    let b: List = space.create(Mutex::new(Vec::new()));
+    let c: List = space.create(Mutex::new(Vec::new()));
+    for cc in &[b.clone(), c.clone()] {
+        let addr = &cc.inner().ref_count as *const _ as usize;
+        assert_eq!(addr % 64, 0);
+    }
+    assert_eq!(&a.inner().ref_count as *const _ as usize % 64, 0);
+    b.borrow().lock().unwrap().push(Box::new(c.clone()));
+    c.borrow().lock().unwrap().push(Box::new(b.clone()));
+    drop((b, c));
+    assert_eq!(space.collect_cycles(), 2);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_spawn_collector() {
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let collector = space.spawn_collector(Duration::from_millis(1));
+    for _ in 0..3 {
+        let a: List = space.create(Mutex::new(Vec::new()));
+        a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    }
+    while collector.collected() < 3 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(space.count_tracked(), 0);
+
+    // Dropping the collector stops the thread, which releases the space.
+    drop(collector);
+    assert_eq!(Arc::strong_count(&space), 1);
+}
+
+#[test]
+fn test_space_contains() {
+    let space1 = ThreadedObjectSpace::default();
+    let space2 = ThreadedObjectSpace::default();
+    let a: List = space1.create(Mutex::new(Vec::new()));
+    let b: List = space2.create(Mutex::new(Vec::new()));
+    assert!(space1.contains(&a));
+    assert!(!space1.contains(&b));
+    assert!(space2.contains(&b));
+    assert!(!space1.contains(&space1.create(1)));
+}
+
+#[test]
+fn test_create_cyclic() {
+    use crate::RawWeak;
+    type Weak = RawWeak<Mutex<Vec<Box<dyn Trace + Send + Sync>>>, ThreadedObjectSpace>;
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create_cyclic(|weak: &Weak| {
+        assert!(weak.upgrade().is_none());
+        Mutex::new(Vec::new())
+    });
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    assert_eq!(space.count_tracked(), 1);
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[test]
+fn test_weak_count() {
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create(Mutex::new(Vec::new()));
+    let weak: ThreadedWeak<_> = a.downgrade();
+    let b = a.clone();
+    assert_eq!((a.strong_count(), a.weak_count()), (2, 1));
+    assert_eq!((weak.strong_count(), weak.weak_count()), (2, 1));
+
+    // Counts are shared across threads.
+    let weak2 = spawn(move || {
+        let weak2 = b.downgrade();
+        assert_eq!((b.strong_count(), b.weak_count()), (2, 2));
+        weak2
+    })
+    .join()
+    .unwrap();
+    assert_eq!((a.strong_count(), a.weak_count()), (1, 2));
+    drop(weak2);
+    assert_eq!(a.weak_count(), 1);
+
+    drop(a);
+    assert_eq!((weak.strong_count(), weak.weak_count()), (0, 1));
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn test_upgrade_all() {
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let a: List = space.create(Mutex::new(Vec::new()));
+    let b: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(b.clone()));
+    b.borrow().lock().unwrap().push(Box::new(a.clone()));
+    let weaks = vec![a.downgrade(), b.downgrade()];
+    drop((a, b));
+
+    let collector = {
+        let space = space.clone();
+        spawn(move || space.collect_cycles())
+    };
+    // Either both values are alive, or both are collected.
+    while let Some(strongs) = crate::upgrade_all(&weaks) {
+        assert_eq!(strongs.len(), 2);
+        drop(strongs);
+        if collector.is_finished() {
+            break;
+        }
+    }
+    let collected = collector.join().unwrap() + space.collect_cycles();
+    assert_eq!(collected, 2);
+    assert!(crate::upgrade_all(&weaks).is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let space = ThreadedObjectSpace::default();
+    let a = space.create(1u8);
+    let pair = (a.clone(), a);
+    let mut buf = Vec::new();
+    crate::serialize_shared(&pair, &mut serde_json::Serializer::new(&mut buf)).unwrap();
+    assert_eq!(buf, b"[[0,1],[0]]");
+
+    let mut deserializer = serde_json::Deserializer::from_slice(&buf);
+    let copy: (ThreadedCc<u8>, ThreadedCc<u8>) =
+        crate::deserialize_shared_in(&space, &mut deserializer).unwrap();
+    assert!(ThreadedCc::ptr_eq(&copy.0, &copy.1));
+
+    // There is no default space for ThreadedCc.
+    let result: Result<ThreadedCc<u8>, _> = serde_json::from_str("1");
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_drop_creates_objects_during_collection() {
+    // Creates a new cycle in `space` on drop.
+    struct D(Arc<ThreadedObjectSpace>, Mutex<Option<ThreadedCc<D>>>);
+    impl Trace for D {
+        fn trace(&self, tracer: &mut crate::Tracer) {
+            self.1.trace(tracer);
+        }
+    }
+    impl Drop for D {
+        fn drop(&mut self) {
+            let a: List = self.0.create(Mutex::new(Vec::new()));
+            a.borrow().lock().unwrap().push(Box::new(a.clone()));
+        }
+    }
+
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let a = space.create(D(space.clone(), Mutex::new(None)));
+    *a.borrow().1.lock().unwrap() = Some(a.clone());
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_collect_hooks() {
+    let space = ThreadedObjectSpace::default();
+    let (sender, receiver) = channel();
+    let start_sender = Mutex::new(sender.clone());
+    let end_sender = Mutex::new(sender);
+    space.set_collect_hooks(
+        move || start_sender.lock().unwrap().send(None).unwrap(),
+        move |stats| end_sender.lock().unwrap().send(Some(stats)).unwrap(),
+    );
+    let a: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    let _b: List = space.create(Mutex::new(Vec::new()));
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+    assert!(receiver.try_recv().unwrap().is_none());
+    let stats = receiver.try_recv().unwrap().unwrap();
+    assert_eq!((stats.scanned, stats.collected), (2, 1));
+    assert!(stats.released_bytes > 0);
+    assert_eq!(stats.reclaimed.len(), 1);
+    assert_eq!(stats.reclaimed[0].count, 1);
+
+    space.clear_collect_hooks();
+    space.collect_cycles();
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_try_collect_cycles() {
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let (sender, receiver) = channel();
+    let end_sender = Mutex::new(sender);
+    space.set_collect_hooks(
+        || {},
+        move |stats| end_sender.lock().unwrap().send(stats).unwrap(),
+    );
+    let a: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    let b = a.clone();
+    drop(a);
+
+    // Another thread holds a `ThreadedCcRef`.
+    let (locked_sender, locked_receiver) = channel();
+    let (unlock_sender, unlock_receiver) = channel::<()>();
+    let thread = spawn(move || {
+        let borrowed = b.borrow();
+        locked_sender.send(()).unwrap();
+        unlock_receiver.recv().unwrap();
+        drop(borrowed);
+    });
+    locked_receiver.recv().unwrap();
+    assert_eq!(space.try_collect_cycles(), None);
+    let timeout = Duration::from_millis(20);
+    let start = std::time::Instant::now();
+    assert_eq!(space.collect_cycles_with_deadline(timeout), None);
+    assert!(start.elapsed() >= timeout);
+    // Hooks are not called for skipped collections.
+    assert!(receiver.try_recv().is_err());
+    unlock_sender.send(()).unwrap();
+    thread.join().unwrap();
+
+    assert_eq!(space.collect_cycles_with_deadline(timeout), Some(1));
+    let stats = receiver.try_recv().unwrap();
+    assert_eq!((stats.scanned, stats.collected), (1, 1));
+    assert_eq!(space.try_collect_cycles(), Some(0));
+}
+
+#[test]
+fn test_global_space() {
+    static GLOBAL: OnceLock<List> = OnceLock::new();
+    let space = ThreadedObjectSpace::global();
+    assert!(Arc::ptr_eq(space, ThreadedObjectSpace::global()));
+    let value = GLOBAL.get_or_init(|| space.create(Mutex::new(Vec::new())));
+    assert!(space.contains(value));
+
+    // Cycles from other threads are collected in the global space.
+    spawn(|| {
+        let a: List = ThreadedObjectSpace::global().create(Mutex::new(Vec::new()));
+        a.borrow().lock().unwrap().push(Box::new(a.clone()));
+        let b = GLOBAL.get().unwrap();
+        b.borrow().lock().unwrap().push(Box::new(a.clone()));
+    })
+    .join()
+    .unwrap();
+    let before = space.count_tracked();
+    value.borrow().lock().unwrap().clear();
+    assert!(space.collect_cycles() >= 1);
+    assert!(space.count_tracked() < before);
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+#[cfg_attr(miri, ignore)]
+fn test_parallel_marking() {
+    let n = super::parallel::MIN_PARALLEL_LEN * 2;
+    let space = ThreadedObjectSpace::default();
+    let push = |from: &List, to: &List| {
+        from.borrow().lock().unwrap().push(Box::new(to.clone()));
+    };
+
+    // A long chain kept alive by `root`, with each node in a cycle.
+    let root: List = space.create(Default::default());
+    let mut last = root.clone();
+    for _ in 0..n {
+        let next: List = space.create(Default::default());
+        push(&last, &next);
+        push(&next, &last);
+        last = next;
+    }
+    drop(last);
+
+    // Unreachable pairs, some referring to the chain.
+    for i in 0..n / 2 {
+        let a: List = space.create(Default::default());
+        let b: List = space.create(Default::default());
+        push(&a, &b);
+        push(&b, &a);
+        if i % 2 == 0 {
+            push(&a, &root);
+        }
+    }
+
+    assert_eq!(space.count_tracked(), n * 2 + 1);
+    assert_eq!(space.collect_cycles(), n);
+    assert_eq!(space.count_tracked(), n + 1);
+    drop(root);
+    assert_eq!(space.collect_cycles(), n + 1);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_ptr_identity() {
+    use crate::PtrIdentity;
+    use std::collections::HashSet;
+
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create(Mutex::new(Vec::new()));
+    let b: List = space.create(Mutex::new(Vec::new()));
+    assert!(ThreadedCc::ptr_eq(&a, &a.clone()));
+    assert!(!ThreadedCc::ptr_eq(&a, &b));
+    assert_eq!(ThreadedCc::as_ptr(&a), &*a.borrow() as *const _);
+
+    let set: HashSet<_> = vec![a.clone(), b.clone(), a]
+        .into_iter()
+        .map(PtrIdentity)
+        .collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&PtrIdentity(b)));
+}
+
+#[test]
+fn test_id() {
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create(Mutex::new(Vec::new()));
+    let b: List = space.create(Mutex::new(Vec::new()));
+    assert_eq!(a.id(), a.clone().id());
+    assert_ne!(a.id(), b.id());
+    let unsized_a = a.clone().into_dyn();
+    assert_eq!(a.id(), unsized_a.id());
+}
+
+#[test]
+fn test_weak_set() {
+    use crate::WeakSet;
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    let mut set = WeakSet::new();
+    assert!(set.insert(&a));
+    assert_eq!(set.len(), 1);
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+    assert!(set.is_empty());
+}
+
+#[test]
+fn test_with_roots() {
+    use crate::AbstractObjectSpace;
+    let space = Arc::new(ThreadedObjectSpace::default());
+    let a: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+    space.with_roots(|roots| {
+        let ptr = roots.root(&a);
+        drop(a);
+        assert_eq!(roots.len(), 1);
+        // Collections in other threads do not release rooted objects.
+        let collected = {
+            let space = space.clone();
+            spawn(move || space.collect_cycles()).join().unwrap()
+        };
+        assert_eq!(collected, 0);
+        // safety: `ptr` is rooted.
+        assert_eq!(unsafe { ptr.as_ref() }.lock().unwrap().len(), 1);
+    });
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[test]
+fn test_reinit_after_fork() {
+    let space = ThreadedObjectSpace::default();
+    let a: List = space.create(Mutex::new(Vec::new()));
+    a.borrow().lock().unwrap().push(Box::new(a.clone()));
+
+    // Simulate a lock held by a thread that does not exist after fork().
+    let b = a.clone();
+    spawn(move || std::mem::forget(b.borrow())).join().unwrap();
+    assert_eq!(space.try_collect_cycles(), None);
+
+    // safety: The locks are not used by other threads.
+    unsafe { space.reinit_after_fork() };
+    drop(a);
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.collect_cycles(), 1);
+}
@@ -109,6 +109,393 @@ fn test_simple_non_trait_cycles() {
     assert_eq!(collect::collect_thread_cycles(), 2);
 }
 
+#[test]
+fn test_defer_collection() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let create_cycle = || {
+        let a: List = Default::default();
+        a.borrow_mut().push(Box::new(a.clone()));
+    };
+    crate::defer_collection(|| {
+        create_cycle();
+        crate::defer_collection(create_cycle);
+        assert_eq!(collect::collect_thread_cycles(), 0);
+    });
+    assert_eq!(collect::collect_thread_cycles(), 2);
+
+    // Collection is enabled again after a panic.
+    let result = panic::catch_unwind(|| {
+        crate::defer_collection(|| {
+            create_cycle();
+            panic!("deferred");
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(collect::collect_thread_cycles(), 1);
+}
+
+#[test]
+fn test_create_default_and_from() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::ObjectSpace::default();
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+
+    let b: Cc<u32> = 3.into();
+    assert_eq!(*b, 3);
+}
+
+#[test]
+fn test_find_cycle_through() {
+    use crate::analysis::find_cycle_through;
+
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    let b: List = Default::default();
+    let c: List = Default::default();
+    let d: Cc<Option<List>> = Cc::new(Some(a.clone()));
+    assert!(find_cycle_through(&Cc::new(1)).is_none());
+
+    // a -> b -> c -> a, a -> c
+    a.borrow_mut().push(Box::new(b.clone()));
+    a.borrow_mut().push(Box::new(c.clone()));
+    b.borrow_mut().push(Box::new(c.clone()));
+    assert!(find_cycle_through(&a).is_none());
+    c.borrow_mut().push(Box::new(a.clone()));
+
+    let cycle = find_cycle_through(&a).unwrap();
+    assert_eq!(cycle.len(), 2);
+    assert_eq!(cycle[0].index, 1);
+    assert_eq!(cycle[0].to_type, cycle[1].from_type);
+    assert_eq!(find_cycle_through(&b).unwrap().len(), 3);
+    assert!(find_cycle_through(&d).is_none());
+
+    drop((a, b, c, d));
+    assert_eq!(collect::collect_thread_cycles(), 3);
+}
+
+#[test]
+fn test_reachable() {
+    use crate::{is_reachable, reachable_count};
+
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    let b: List = Default::default();
+    let c: List = Default::default();
+    let n = Cc::new(1);
+    assert_eq!(reachable_count(&n), 0);
+    assert_eq!(reachable_count(&a), 1);
+    assert!(is_reachable(&n, &n));
+
+    // a -> b -> c -> b, c -> n
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(c.clone()));
+    c.borrow_mut().push(Box::new(b.clone()));
+    c.borrow_mut().push(Box::new(n.clone()));
+    assert_eq!(reachable_count(&a), 3);
+    assert_eq!(reachable_count(&c), 2);
+    assert!(is_reachable(&a, &c));
+    assert!(!is_reachable(&c, &a));
+    assert!(!is_reachable(&a, &n));
+
+    let dyn_a: Cc<dyn Trace> = a.clone().into_dyn();
+    assert_eq!(reachable_count(&dyn_a), 3);
+
+    // Disconnect b -> c.
+    b.borrow_mut().clear();
+    assert_eq!(reachable_count(&a), 2);
+    assert!(!is_reachable(&a, &c));
+    assert!(is_reachable(&c, &b));
+
+    drop((a, b, c, n, dyn_a));
+    assert_eq!(collect::collect_thread_cycles(), 0);
+}
+
+#[test]
+fn test_collect_batch_size() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::ObjectSpace::default();
+    space.set_collect_batch_size(Some(1));
+    let weak = {
+        let a: List = space.create_default();
+        let b: List = space.create_default();
+        let c: List = space.create_default();
+        a.borrow_mut().push(Box::new(b.clone()));
+        b.borrow_mut().push(Box::new(c.clone()));
+        c.borrow_mut().push(Box::new(a.clone()));
+        a.borrow_mut().push(Box::new(c.clone()));
+        c.downgrade()
+    };
+    let e: List = space.create_default();
+    assert_eq!(space.collect_cycles(), 3);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(space.count_tracked(), 2);
+    drop(e);
+    drop(weak);
+    assert_eq!(space.count_tracked(), 0);
+
+    let message =
+        capture_panic_message(|| crate::ObjectSpace::default().set_collect_batch_size(Some(0)));
+    assert!(message.contains("positive"));
+}
+
+#[test]
+fn test_fixed_object_space() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::FixedObjectSpace::<3>::default();
+    assert_eq!(space.capacity(), 3);
+    let weak = {
+        let a: List = space.try_create(Default::default()).ok().unwrap();
+        let b: List = space.try_create(Default::default()).ok().unwrap();
+        let c: List = space.try_create(Default::default()).ok().unwrap();
+        let d: Cc<u32> = space.try_create(1).unwrap(); // Untracked.
+        a.borrow_mut().push(Box::new(b.clone()));
+        b.borrow_mut().push(Box::new(a.clone()));
+        b.borrow_mut().push(Box::new(d));
+        c.borrow_mut().push(Box::new(c.clone()));
+        assert!(space.try_create(List::default()).is_err());
+        drop((a, c));
+        b.downgrade()
+    };
+    assert_eq!(space.collect_cycles(), 3);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(space.count_tracked(), 1);
+    drop(weak);
+    assert_eq!(space.count_tracked(), 0);
+    assert_eq!(space.collect_cycles(), 0);
+
+    // Untracked objects still count towards the capacity with the
+    // `space_pointer` feature.
+    let objects: Vec<List> = (0..3)
+        .map(|_| space.try_create(Default::default()).ok().unwrap())
+        .collect();
+    objects[0].untrack();
+    assert_eq!(space.count_tracked(), 2);
+    assert_eq!(
+        space.try_create(List::default()).is_err(),
+        cfg!(feature = "space_pointer")
+    );
+    drop(objects);
+    assert!(space.try_create(List::default()).is_ok());
+}
+
+#[test]
+fn test_heap_snapshot() {
+    use crate::analysis::{snapshot, EdgeRow};
+
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    let _c: Cc<u32> = space.create(1); // Untracked.
+    a.borrow_mut().push(Box::new(Cc::new(2)));
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(a.clone()));
+    let weak = {
+        let d: List = space.create_default();
+        d.downgrade()
+    };
+
+    let snapshot = snapshot(&space);
+    // Newest first.
+    let ref_counts: Vec<usize> = snapshot.objects.iter().map(|o| o.ref_count).collect();
+    assert_eq!(ref_counts, [0, 2, 2]);
+    let ids: Vec<u64> = snapshot.objects.iter().map(|o| o.id).collect();
+    #[cfg(not(feature = "seqid"))]
+    assert_eq!(ids, [0, 1, 2]);
+    #[cfg(feature = "seqid")]
+    assert_eq!(
+        ids[1..],
+        [b.sequence_id().unwrap(), a.sequence_id().unwrap()]
+    );
+    assert_eq!(
+        snapshot.edges,
+        [
+            EdgeRow {
+                from: ids[1],
+                to: ids[2],
+                index: 0,
+                name: None,
+            },
+            EdgeRow {
+                from: ids[2],
+                to: ids[1],
+                index: 0,
+                name: None,
+            },
+        ]
+    );
+
+    let mut objects = Vec::new();
+    snapshot.write_csv(&mut objects, Vec::new()).unwrap();
+    let objects = String::from_utf8(objects).unwrap();
+    let header = format!("id,type_name,size,ref_count\n{},", ids[0]);
+    assert!(objects.starts_with(&(header + "\"core::cell::RefCell<")));
+    assert_eq!(objects.lines().count(), 4);
+
+    drop(weak);
+    a.borrow_mut().clear();
+}
+
+#[test]
+fn test_heap_snapshot_dot_json() {
+    use crate::analysis::{EdgeRow, HeapSnapshot, ObjectRow};
+
+    let snapshot = HeapSnapshot {
+        objects: vec![ObjectRow {
+            id: 3,
+            type_name: "a\"b\\c",
+            size: 10,
+            ref_count: 1,
+        }],
+        edges: vec![EdgeRow {
+            from: 3,
+            to: 3,
+            index: 0,
+            name: Some("x"),
+        }],
+    };
+    let mut dot = Vec::new();
+    snapshot.write_dot(&mut dot).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "digraph {\n  3 [label=\"a\\\"b\\\\c\"];\n  3 -> 3 [label=\"x\"];\n}\n"
+    );
+    let mut json = Vec::new();
+    snapshot.write_json(&mut json).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        concat!(
+            r#"{"objects":[{"id":3,"type_name":"a\"b\\c","size":10,"ref_count":1}],"#,
+            r#""edges":[{"from":3,"to":3,"index":0,"name":"x"}]}"#,
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn test_thread_stats() {
+    let before = crate::thread_stats();
+    {
+        let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+        a.borrow_mut().push(Box::new(a.clone()));
+        let stats = crate::thread_stats();
+        assert_eq!(stats.tracked, before.tracked + 1);
+        assert!(stats.tracked_bytes > before.tracked_bytes);
+    }
+    assert_eq!(collect::collect_thread_cycles(), 1);
+    let after = crate::thread_stats();
+    assert_eq!(after.tracked, before.tracked);
+    assert_eq!(after.tracked_bytes, before.tracked_bytes);
+    assert_eq!(after.collections, before.collections + 1);
+    assert_eq!(after.collected, before.collected + 1);
+    assert_eq!(after.last_collected, 1);
+}
+
+#[test]
+fn test_zombies_and_dangling_weak() {
+    use crate::Weak;
+    let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    let weak = a.downgrade();
+    let weak_untracked: Weak<u8> = Cc::new(1u8).downgrade();
+    assert!(weak_untracked.is_dangling());
+    assert!(!weak.is_dangling());
+    assert_eq!(crate::thread_stats().zombies, 0);
+
+    // The value is dropped. The allocation is kept by `weak`.
+    drop(a);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+    assert!(weak.is_dangling());
+    let stats = crate::thread_stats();
+    assert_eq!((stats.tracked, stats.zombies), (1, 1));
+
+    drop(weak);
+    let stats = crate::thread_stats();
+    assert_eq!((stats.tracked, stats.zombies), (0, 0));
+}
+
+#[test]
+fn test_collect_thread_cycles_with_stats() {
+    let tracked = collect::count_thread_tracked();
+    let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    let size = crate::thread_stats().tracked_bytes;
+    drop(a);
+
+    let stats = collect::defer_collection(collect::collect_thread_cycles_with_stats);
+    assert_eq!(stats.scanned, tracked + 1);
+    assert_eq!((stats.collected, stats.released_bytes), (0, 0));
+
+    let stats = crate::collect_thread_cycles_with_stats();
+    assert_eq!(stats.scanned, tracked + 1);
+    assert_eq!(stats.collected, 1);
+    assert_eq!(stats.released_bytes, size);
+    assert_eq!(
+        stats.reclaimed,
+        [crate::ReclaimedType {
+            type_name: std::any::type_name::<RefCell<Vec<Box<dyn Trace>>>>(),
+            count: 1,
+        }]
+    );
+}
+
+#[test]
+fn test_leak_detector() {
+    let space = collect::ObjectSpace::default();
+    space.enable_leak_detector(2);
+    let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = space.create(Default::default());
+    let b: Cc<RefCell<Vec<Box<dyn Trace>>>> = space.create(Default::default());
+    let mut clones = Vec::new();
+    for _ in 0..3 {
+        clones.push(a.clone());
+        clones.push(b.clone());
+        space.collect_cycles();
+    }
+    assert_eq!(space.suspected_leaks().len(), 2);
+
+    // Decreasing the reference count resets the history.
+    clones.retain(|c| !std::ptr::eq(c.deref(), b.deref()));
+    space.collect_cycles();
+    let leaks = space.suspected_leaks();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(leaks[0].ref_count, 4);
+    assert_eq!(leaks[0].initial_ref_count, 2);
+    assert_eq!(leaks[0].collections, 3);
+
+    space.disable_leak_detector();
+    assert!(space.suspected_leaks().is_empty());
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn test_recent_events_capacity() {
+    use crate::GcEventKind;
+    let space = collect::ObjectSpace::default();
+    space.set_event_capacity(2);
+    for _ in 0..3 {
+        let value: Box<dyn Trace> = Box::new(1);
+        drop(space.create(value));
+    }
+    space.collect_cycles();
+    let kinds: Vec<_> = space.recent_events().into_iter().map(|e| e.kind).collect();
+    assert_eq!(kinds.len(), 2);
+    assert!(matches!(kinds[0], GcEventKind::Drop { .. }));
+    assert_eq!(
+        kinds[1],
+        GcEventKind::Collect {
+            collected: 0,
+            reclaimed: Vec::new()
+        }
+    );
+
+    space.set_event_capacity(0);
+    space.collect_cycles();
+    assert!(space.recent_events().is_empty());
+}
+
 #[test]
 fn test_weakref_without_cycles() {
     let log = debug::capture_log(|| {
@@ -168,9 +555,8 @@ fn test_weakref_with_cycles() {
         assert_eq!(wa.weak_count(), 2);
         assert_eq!(wa.strong_count(), 0);
     });
-    assert_eq!(
-        log,
-        r#"
+    #[cfg(not(feature = "ordered_drop"))]
+    let expected = r#"
 1: new (CcBoxWithGcHeader)
 2: new (CcBoxWithGcHeader), clone (2)
 1: clone (2), new-weak (1), clone-weak (2)
@@ -190,9 +576,43 @@ collect: 2 unreachable objects
 1: drop (1), drop (T)
 2: drop (1), drop (0)
 1: drop (0)
+collect: reclaimed 2 core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>
 2: clone-weak (2), drop-weak (1), drop-weak (0), drop (CcBoxWithGcHeader)
-1: drop-weak (1), drop-weak (0), drop (CcBoxWithGcHeader)"#
-    );
+1: drop-weak (1), drop-weak (0), drop (CcBoxWithGcHeader)"#;
+    #[cfg(feature = "ordered_drop")]
+    let expected = r#"
+1: new (CcBoxWithGcHeader)
+2: new (CcBoxWithGcHeader), clone (2)
+1: clone (2), new-weak (1), clone-weak (2)
+2: new-weak (1)
+1: drop (1)
+2: drop (1)
+1: new-strong (2), drop (1)
+2: new-strong (2), drop (1)
+collect: collect_thread_cycles
+2: gc_traverse
+1: trace, gc_traverse
+2: trace
+collect: 2 unreachable objects
+2: gc_clone (2)
+1: gc_clone (2), drop (T)
+2: drop (1), drop (T)
+1: drop (1), drop (0)
+2: drop (0)
+collect: reclaimed 2 core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>
+2: clone-weak (2), drop-weak (1), drop-weak (0), drop (CcBoxWithGcHeader)
+1: drop-weak (1), drop-weak (0), drop (CcBoxWithGcHeader)"#;
+    assert_eq!(log, expected);
+}
+
+#[test]
+fn test_weakref_new() {
+    let weak: crate::Weak<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    assert!(weak.upgrade().is_none());
+    let weak2 = weak.clone();
+    assert_eq!((weak2.strong_count(), weak2.weak_count()), (0, 0));
+    drop(weak);
+    assert!(weak2.upgrade().is_none());
 }
 
 #[test]
@@ -221,7 +641,8 @@ fn test_self_referential() {
 collect: collect_thread_cycles
 0: gc_traverse, trace, trace, trace
 collect: 1 unreachable objects
-0: gc_clone (4), drop (T), drop (3), drop (2), drop (1), drop (0), drop (CcBoxWithGcHeader)"#
+0: gc_clone (4), drop (T), drop (3), drop (2), drop (1), drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 1 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#
     );
 }
 
@@ -229,9 +650,8 @@ collect: 1 unreachable objects
 fn test_3_object_cycle() {
     // 0 -> 1 -> 2 -> 0
     let log = debug::capture_log(|| test_small_graph(3, &[0x01, 0x12, 0x20], 0, 0));
-    assert_eq!(
-        log,
-        r#"
+    #[cfg(not(feature = "ordered_drop"))]
+    let expected = r#"
 0: new (CcBoxWithGcHeader)
 1: new (CcBoxWithGcHeader)
 2: new (CcBoxWithGcHeader)
@@ -255,16 +675,45 @@ collect: 3 unreachable objects
 0: drop (1), drop (T)
 2: drop (1), drop (0), drop (CcBoxWithGcHeader)
 1: drop (0), drop (CcBoxWithGcHeader)
-0: drop (0), drop (CcBoxWithGcHeader)"#
-    );
+0: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 3 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    #[cfg(feature = "ordered_drop")]
+    let expected = r#"
+0: new (CcBoxWithGcHeader)
+1: new (CcBoxWithGcHeader)
+2: new (CcBoxWithGcHeader)
+0: clone (2)
+1: clone (2)
+2: clone (2)
+0: drop (1)
+1: drop (1)
+2: drop (1)
+collect: collect_thread_cycles
+2: gc_traverse
+1: trace, gc_traverse
+0: trace, gc_traverse
+2: trace
+collect: 3 unreachable objects
+2: gc_clone (2)
+1: gc_clone (2)
+0: gc_clone (2), drop (T)
+2: drop (1)
+1: drop (T)
+0: drop (1)
+2: drop (T)
+1: drop (1)
+0: drop (0), drop (CcBoxWithGcHeader)
+1: drop (0), drop (CcBoxWithGcHeader)
+2: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 3 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    assert_eq!(log, expected);
 }
 
 #[test]
 fn test_2_object_cycle_with_another_incoming_reference() {
     let log = debug::capture_log(|| test_small_graph(3, &[0x02, 0x20, 0x10], 0, 0));
-    assert_eq!(
-        log,
-        r#"
+    #[cfg(not(feature = "ordered_drop"))]
+    let expected = r#"
 0: new (CcBoxWithGcHeader)
 1: new (CcBoxWithGcHeader)
 2: new (CcBoxWithGcHeader)
@@ -293,16 +742,45 @@ collect: 3 unreachable objects
 1: drop (1)
 2: drop (0), drop (CcBoxWithGcHeader)
 1: drop (0), drop (CcBoxWithGcHeader)
-0: drop (0), drop (CcBoxWithGcHeader)"#
-    );
+0: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 3 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    #[cfg(feature = "ordered_drop")]
+    let expected = r#"
+0: new (CcBoxWithGcHeader)
+1: new (CcBoxWithGcHeader)
+2: new (CcBoxWithGcHeader)
+0: clone (2)
+2: clone (2)
+1: clone (2)
+0: drop (1)
+1: drop (1)
+2: drop (1)
+collect: collect_thread_cycles
+2: gc_traverse
+0: trace
+1: gc_traverse
+0: gc_traverse
+2: trace
+1: trace
+collect: 3 unreachable objects
+2: gc_clone (2)
+1: gc_clone (2)
+0: gc_clone (2), drop (T)
+2: drop (1)
+1: drop (1), drop (T)
+2: drop (T)
+0: drop (1), drop (0), drop (CcBoxWithGcHeader)
+1: drop (0), drop (CcBoxWithGcHeader)
+2: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 3 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    assert_eq!(log, expected);
 }
 
 #[test]
 fn test_2_object_cycle_with_another_outgoing_reference() {
     let log = debug::capture_log(|| test_small_graph(3, &[0x02, 0x20, 0x01], 0, 0));
-    assert_eq!(
-        log,
-        r#"
+    #[cfg(not(feature = "ordered_drop"))]
+    let expected = r#"
 0: new (CcBoxWithGcHeader)
 1: new (CcBoxWithGcHeader)
 2: new (CcBoxWithGcHeader)
@@ -323,8 +801,32 @@ collect: 2 unreachable objects
 2: drop (T)
 0: drop (1), drop (T)
 2: drop (1), drop (0), drop (CcBoxWithGcHeader)
-0: drop (0), drop (CcBoxWithGcHeader)"#
-    );
+0: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 2 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    #[cfg(feature = "ordered_drop")]
+    let expected = r#"
+0: new (CcBoxWithGcHeader)
+1: new (CcBoxWithGcHeader)
+2: new (CcBoxWithGcHeader)
+0: clone (2)
+2: clone (2)
+0: clone (3), drop (2)
+1: drop (0), drop (T)
+0: drop (1)
+1: drop (CcBoxWithGcHeader)
+2: drop (1)
+collect: collect_thread_cycles
+2: gc_traverse
+0: trace, gc_traverse
+2: trace
+collect: 2 unreachable objects
+2: gc_clone (2)
+0: gc_clone (2), drop (T)
+2: drop (1), drop (T)
+0: drop (1), drop (0), drop (CcBoxWithGcHeader)
+2: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 2 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#;
+    assert_eq!(log, expected);
 }
 
 /// Mixed tracked and untracked values.
@@ -341,7 +843,8 @@ fn test_simple_mixed_graph() {
 collect: collect_thread_cycles
 0: gc_traverse, trace, trace
 collect: 1 unreachable objects
-0: gc_clone (3), drop (T), drop (2), drop (1), drop (0), drop (CcBoxWithGcHeader)"#
+0: gc_clone (3), drop (T), drop (2), drop (1), drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 1 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#
     );
 
     let log = debug::capture_log(|| test_small_graph(2, &[0, 0x10], 0b10, 0));
@@ -359,7 +862,8 @@ collect: collect_thread_cycles
 collect: 1 unreachable objects
 0: gc_clone (2), drop (T), drop (1)
 1: drop (0), drop (T), drop (CcBox)
-0: drop (0), drop (CcBoxWithGcHeader)"#
+0: drop (0), drop (CcBoxWithGcHeader)
+collect: reclaimed 1 gcmodule::testutil::DropCounter<core::cell::RefCell<alloc::vec::Vec<alloc::boxed::Box<dyn gcmodule::trace::Trace>>>>"#
     )
 }
 
@@ -400,7 +904,7 @@ fn test_update_with() {
     // Update on a unique value.
     let log = debug::capture_log(|| {
         let mut cc = Cc::new(30);
-        cc.update_with(|i| *i = *i + 1);
+        cc.update_with(|i| *i += 1);
         assert_eq!(cc.deref(), &31);
     });
     assert_eq!(log, "\n0: new (CcBox), drop (0), drop (T), drop (CcBox)");
@@ -411,7 +915,7 @@ fn test_update_with() {
         let cc1 = Cc::new(30);
         let mut cc2 = cc1.clone();
         debug::NEXT_DEBUG_NAME.with(|n| n.set(3));
-        cc2.update_with(|i| *i = *i + 1);
+        cc2.update_with(|i| *i += 1);
         assert_eq!(cc1.deref(), &30);
         assert_eq!(cc2.deref(), &31);
     });
@@ -439,7 +943,7 @@ fn test_update_with() {
         let cc1: Cc<V> = Cc::new(V(30));
         let mut cc2 = cc1.clone();
         debug::NEXT_DEBUG_NAME.with(|n| n.set(3));
-        cc2.update_with(|i| i.0 = i.0 + 1);
+        cc2.update_with(|i| i.0 += 1);
         assert_eq!(cc1.deref().0, 30);
         assert_eq!(cc2.deref().0, 31);
     });
@@ -454,6 +958,247 @@ fn test_update_with() {
     );
 }
 
+#[test]
+fn test_unsized_slice_and_str() {
+    let a: Cc<[String]> = vec!["a".to_string(), "b".to_string()].into();
+    let b = a.clone();
+    assert_eq!(&b[..], ["a", "b"]);
+    drop(a);
+    assert_eq!(b.len(), 2);
+
+    let empty: Cc<[u64]> = Vec::new().into();
+    assert!(empty.is_empty());
+    let units: Cc<[()]> = Cc::from(&[(), ()][..]);
+    assert_eq!(units.len(), 2);
+    let bytes: Cc<[u8]> = Cc::from(&b"abc"[..]);
+    assert_eq!(&*bytes, b"abc");
+
+    let s: Cc<str> = "hello".into();
+    let t: Cc<str> = String::from("hello").into();
+    assert_eq!(s, t);
+    assert_eq!(format!("{} {:?}", s, t), "hello Cc(\"hello\")");
+
+    // Can be used in tracked structures.
+    let list: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    list.borrow_mut().push(Box::new(s));
+    list.borrow_mut().push(Box::new(b));
+    list.borrow_mut().push(Box::new(list.clone()));
+    drop(list);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+
+    let message = capture_panic_message(|| {
+        let values: Vec<Box<dyn Trace>> = vec![Box::new(1)];
+        Cc::<[Box<dyn Trace>]>::from(values)
+    });
+    assert!(message.contains("does not support tracked T"));
+}
+
+#[test]
+fn test_get_mut() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let mut a: List = Default::default();
+    Cc::get_mut(&mut a).unwrap().get_mut().push(Box::new(1));
+    let weak = a.downgrade();
+    assert!(Cc::get_mut(&mut a).is_none());
+    drop(weak);
+
+    let mut b: Cc<dyn Trace> = Cc::new(2).into_dyn();
+    assert!(Cc::get_mut(&mut b).is_some());
+    let c = b.clone();
+    assert!(Cc::get_mut(&mut b).is_none());
+    drop(c);
+    assert!(Cc::get_mut(&mut b).is_some());
+}
+
+#[test]
+fn test_try_update() {
+    let mut a = Cc::new(vec![1]);
+    let b = a.clone();
+
+    // Shared. Unchanged on error.
+    let result: Result<(), &str> = a.try_update(|v| {
+        v.push(2);
+        Err("x")
+    });
+    assert_eq!(result, Err("x"));
+    assert!(std::ptr::eq(&*a, &*b));
+    assert_eq!(a.try_update(|v| v.pop().ok_or("empty")), Ok(1));
+    assert_eq!((a.len(), b.len()), (0, 1));
+
+    // Unique. Updated in place.
+    assert_eq!(a.try_update(|v| v.pop().ok_or("empty")), Err("empty"));
+    assert_eq!(a.update(|v| v.len()), 0);
+}
+
+#[cfg(feature = "versioning")]
+#[test]
+fn test_update_with_version() {
+    let mut cc1 = Cc::new(30);
+    assert_eq!(cc1.version(), 0);
+    cc1.update_with(|i| *i += 1);
+    assert_eq!(cc1.version(), 1);
+
+    // Copy-on-write keeps the version increasing.
+    let cc2 = cc1.clone();
+    cc1.update_with(|i| *i += 1);
+    assert_eq!(cc1.version(), 2);
+    assert_eq!(cc2.version(), 1);
+
+    cc2.touch();
+    assert_eq!(cc2.version(), 2);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_last_collection_profile() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    let b: Cc<RefCell<Option<List>>> = Default::default();
+    a.borrow_mut().push(Box::new(b.clone()));
+    *b.borrow_mut() = Some(a.clone());
+    let _c: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+    drop((a, b));
+    assert_eq!(collect::collect_thread_cycles(), 2);
+
+    let profile = crate::last_collection_profile();
+    assert_eq!(profile.len(), 3);
+    let find = |name| {
+        let found = profile.iter().find(|p| p.type_name.starts_with(name));
+        found.unwrap().clone()
+    };
+    // Unreachable objects are traced once.
+    let a = find("core::cell::RefCell<alloc::vec::Vec");
+    assert_eq!((a.trace_calls, a.drop_calls), (1, 1));
+    let b = find("core::cell::RefCell<core::option::Option");
+    assert_eq!((b.trace_calls, b.drop_calls), (1, 1));
+    // Reachable objects are traced twice, and not dropped.
+    let c = find("alloc::boxed::Box");
+    assert_eq!((c.trace_calls, c.drop_calls), (2, 0));
+}
+
+#[cfg(feature = "alloc_hook")]
+#[test]
+fn test_alloc_hook() {
+    use crate::AllocKind;
+    use std::sync::Mutex;
+
+    // Other tests might run concurrently. Only record `Hooked` objects.
+    struct Hooked(RefCell<Vec<Box<dyn Trace>>>);
+    impl Trace for Hooked {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.0.trace(tracer);
+        }
+    }
+    static EVENTS: Mutex<Vec<(AllocKind, usize)>> = Mutex::new(Vec::new());
+    crate::set_alloc_hook(|event| {
+        if event.type_name.ends_with("Hooked") {
+            // Objects created by the hook are not reported.
+            let _ = Cc::new(Hooked(Default::default()));
+            EVENTS.lock().unwrap().push((event.kind, event.id));
+        }
+    });
+
+    let a = Cc::new(Hooked(Default::default()));
+    let b = Cc::new(Hooked(Default::default()));
+    a.0.borrow_mut().push(Box::new(a.clone()));
+    let (a_id, b_id) = (a.id(), b.id());
+    drop((a, b));
+    assert_eq!(collect::collect_thread_cycles(), 1);
+    crate::clear_alloc_hook();
+    let _c = Cc::new(Hooked(Default::default()));
+
+    let events = EVENTS.lock().unwrap().clone();
+    assert_eq!(
+        events,
+        [
+            (AllocKind::New, a_id),
+            (AllocKind::New, b_id),
+            (AllocKind::Drop, b_id),
+            (AllocKind::Drop, a_id),
+        ]
+    );
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn test_access_from_another_thread() {
+    struct Smuggled(Cc<u32>);
+    unsafe impl Send for Smuggled {}
+
+    let smuggled = Smuggled(Cc::new(1));
+    let messages = std::thread::spawn(move || {
+        let cc = panic::AssertUnwindSafe(&smuggled.0);
+        let messages = [
+            capture_panic_message(|| ***cc),
+            capture_panic_message(|| drop(cc.clone())),
+        ];
+        // Dropping it here would be a real data race.
+        std::mem::forget(smuggled);
+        messages
+    })
+    .join()
+    .unwrap();
+    for message in messages.iter() {
+        assert!(message.contains("is accessed by thread"), "{}", message);
+    }
+}
+
+#[test]
+fn test_tag() {
+    let a: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    a.set_tag(15);
+    let b = a.clone();
+    assert_eq!(b.strong_count(), 3);
+    drop(b);
+    assert_eq!(a.strong_count(), 2);
+    assert_eq!(a.tag(), 15);
+    a.set_tag(1);
+    assert_eq!(a.tag(), 1);
+    drop(a);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+
+    let message = capture_panic_message(|| Cc::new(1).set_tag(16));
+    assert!(message.contains("does not fit"));
+}
+
+#[cfg(feature = "relocation")]
+#[test]
+fn test_relocation_hook() {
+    use crate::{ObjectSpace, Relocatable};
+
+    type Value = Vec<Box<dyn Trace>>;
+    let space = ObjectSpace::default();
+    space.add_relocation_hook::<Value>(|a, b| a.len() == b.len());
+    let a = space.create(Relocatable::new(Value::new()));
+    let b = space.create(Relocatable::new(Value::new()));
+    let c = space.create(Relocatable::new(
+        vec![Box::new(b.clone()) as Box<dyn Trace>],
+    ));
+    let d = space.create(Relocatable::new(
+        vec![Box::new(c.clone()) as Box<dyn Trace>],
+    ));
+
+    // Objects being accessed are not relocated.
+    a.with(|_| assert_eq!(space.collect_cycles(), 0));
+    assert!(!a.is_forwarded());
+    assert!(!b.is_forwarded());
+
+    assert_eq!(space.collect_cycles(), 0);
+    assert!(!a.is_forwarded());
+    assert!(b.is_forwarded());
+    assert!(!c.is_forwarded());
+    assert!(d.is_forwarded());
+    assert_eq!(d.with(|v| v.len()), 1);
+    assert_eq!(space.count_tracked(), 4);
+
+    assert_eq!(a.strong_count(), 2); // Forwarded from b.
+    assert_eq!(d.strong_count(), 1);
+
+    drop((a, b, c, d));
+    assert_eq!(space.count_tracked(), 0);
+}
+
 #[derive(Default)]
 struct DuplicatedVisits {
     a: RefCell<Option<Box<dyn Trace>>>,
@@ -478,9 +1223,9 @@ fn capture_panic_message<R, F: Fn() -> R + panic::UnwindSafe>(func: F) -> String
         Ok(_) => "(no panic happened)".to_string(),
         Err(e) => {
             if let Some(s) = e.downcast_ref::<String>() {
-                return s.clone();
+                s.clone()
             } else if let Some(s) = e.downcast_ref::<&'static str>() {
-                return s.to_string();
+                s.to_string()
             } else {
                 "(panic information is not a string)".to_string()
             }
@@ -494,7 +1239,7 @@ fn test_trace_impl_double_visits() {
     v.extra_times.set(1);
     *(v.a.borrow_mut()) = Some(Box::new(v.clone()));
 
-    let message = capture_panic_message(|| collect::collect_thread_cycles());
+    let message = capture_panic_message(collect::collect_thread_cycles);
     assert!(message.contains("bug: unexpected ref-count after dropping cycles"));
 
     // The `CcBox<_>` was "forced dropped" as a side effect.
@@ -515,4 +1260,1926 @@ quickcheck::quickcheck! {
         test_small_graph(16, &edges, atomic_bits, collect_bits);
         true
     }
+
+    fn test_quickcheck_16_vertex_graph_chunked(edges: Vec<u8>, atomic_bits: u16, collect_bits: u16) -> bool {
+        crate::with_thread_object_space(|space| space.set_collect_batch_size(Some(3)));
+        test_small_graph(16, &edges, atomic_bits, collect_bits);
+        true
+    }
+}
+
+#[test]
+fn test_dyn_downcast() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    let weak = a.downgrade();
+    let v: Cc<dyn Trace> = a.clone().into_dyn();
+    let v = v.downcast::<Cc<i32>>().unwrap_err();
+    assert_eq!(a.ref_count(), 2);
+
+    // The same allocation is reused.
+    let b: List = v.downcast().ok().unwrap();
+    assert!(std::ptr::eq(&*a, &*b));
+    assert_eq!(a.ref_count(), 2);
+    b.borrow_mut().push(Box::new(b.clone()));
+    drop((a, b));
+    assert_eq!(collect::collect_thread_cycles(), 1);
+    assert!(weak.upgrade().is_none());
+
+    // Untracked.
+    let v: Cc<dyn Trace> = Cc::new(3u8).into_dyn();
+    let v: Cc<u8> = v.downcast().ok().unwrap();
+    assert_eq!(*v, 3);
+}
+
+#[test]
+fn test_dyn_weak() {
+    use crate::Weak;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    let v: Cc<dyn Trace> = a.clone().into_dyn();
+    let weak: Weak<dyn Trace> = v.downgrade();
+    assert_eq!(a.weak_count(), 1);
+
+    // Upgrade and downcast.
+    assert!(Cc::ptr_eq(&weak.upgrade().unwrap(), &v));
+    let weak = weak.downcast::<u8>().unwrap_err();
+    let typed: Weak<RefCell<Vec<Box<dyn Trace>>>> = weak.clone().downcast().ok().unwrap();
+    assert!(Cc::ptr_eq(&typed.upgrade().unwrap(), &a));
+    assert_eq!(a.weak_count(), 2);
+
+    // Typed weak to dyn weak.
+    let weak2: Weak<dyn Trace> = a.downgrade().into_dyn();
+    assert_eq!(weak2.strong_count(), 2);
+    a.borrow_mut().push(Box::new(v));
+    drop(a);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+    assert!(weak2.upgrade().is_none());
+    assert!(typed.upgrade().is_none());
+
+    // Cannot check the type after drop.
+    assert!(weak2.downcast::<RefCell<Vec<Box<dyn Trace>>>>().is_err());
+    let empty: Weak<dyn Trace> = Weak::<u8>::new().into_dyn();
+    assert!(empty.upgrade().is_none());
+    assert!(empty.downcast::<u8>().is_err());
+}
+
+#[test]
+fn test_ptr_eq() {
+    let a = Cc::new(1);
+    let b = Cc::new(1);
+    assert!(Cc::ptr_eq(&a, &a.clone()));
+    assert!(!Cc::ptr_eq(&a, &b));
+
+    let c: Cc<dyn Trace> = a.clone().into_dyn();
+    let d: Cc<dyn Trace> = a.into_dyn();
+    assert!(Cc::ptr_eq(&c, &d));
+}
+
+#[cfg(feature = "watchdog")]
+#[test]
+fn test_watchdog() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    let space = ObjectSpace::default();
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    space.enable_watchdog(8, Duration::from_secs(0), {
+        let warnings = warnings.clone();
+        move |w| warnings.borrow_mut().push(w.clone())
+    });
+    let create = || space.create(Box::new(1) as Box<dyn Trace>);
+
+    // Not growing monotonically.
+    for _ in 0..20 {
+        drop((0..4).map(|_| create()).collect::<Vec<_>>());
+    }
+    assert!(warnings.borrow().is_empty());
+
+    // Growing.
+    let mut objects: Vec<_> = (0..20).map(|_| create()).collect();
+    assert_eq!(warnings.borrow().len(), 1);
+    assert_eq!(warnings.borrow()[0].growth, 8);
+    assert_eq!(warnings.borrow()[0].tracked, 10);
+
+    // Reported again after a collection.
+    space.collect_cycles();
+    objects.extend((0..8).map(|_| create()));
+    assert_eq!(warnings.borrow().len(), 2);
+    assert_eq!(warnings.borrow()[1].tracked, 28);
+
+    // Disabled.
+    space.collect_cycles();
+    space.disable_watchdog();
+    objects.extend((0..8).map(|_| create()));
+    assert_eq!(warnings.borrow().len(), 2);
+}
+
+#[test]
+fn test_finalize() {
+    use crate::{FixedObjectSpace, ObjectSpace};
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+    struct F {
+        name: &'static str,
+        peer: RefCell<Option<Cc<F>>>,
+        log: Log,
+        break_cycle: bool,
+    }
+    impl Trace for F {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.peer.trace(tracer);
+        }
+        fn finalize(&self) {
+            // Other objects in the cycle are not dropped yet.
+            let peer = self.peer.borrow().as_ref().map(|p| p.name).unwrap_or("");
+            self.log
+                .borrow_mut()
+                .push(format!("{}->{}", self.name, peer));
+            if self.break_cycle {
+                self.peer.borrow_mut().take();
+            }
+        }
+    }
+    impl Drop for F {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(format!("drop {}", self.name));
+        }
+    }
+
+    let new_cycle = |create: &dyn Fn(F) -> Cc<F>, log: &Log, break_cycle: bool| {
+        let new = |name| F {
+            name,
+            peer: RefCell::new(None),
+            log: log.clone(),
+            break_cycle,
+        };
+        let a = create(new("a"));
+        let b = create(new("b"));
+        *a.peer.borrow_mut() = Some(b.clone());
+        *b.peer.borrow_mut() = Some(a.clone());
+    };
+    let check = |log: &Log| {
+        let mut log = log.borrow_mut();
+        // Finalizers run before dropping.
+        assert_eq!(log.len(), 4);
+        assert!(log[..2].iter().all(|s| !s.starts_with("drop")));
+        assert!(log[2..].iter().all(|s| s.starts_with("drop")));
+        log.sort();
+        assert_eq!(log.concat(), "a->bb->adrop adrop b");
+        log.clear();
+    };
+
+    for &break_cycle in &[false, true] {
+        for &batch_size in &[None, Some(1)] {
+            let log = Log::default();
+            let space = ObjectSpace::default();
+            space.set_collect_batch_size(batch_size);
+            new_cycle(&|v| space.create(v), &log, break_cycle);
+            assert_eq!(space.collect_cycles(), 2);
+            check(&log);
+        }
+
+        let log = Log::default();
+        let space = FixedObjectSpace::<2>::default();
+        new_cycle(&|v| space.try_create(v).ok().unwrap(), &log, break_cycle);
+        assert_eq!(space.collect_cycles(), 2);
+        check(&log);
+    }
+
+    // Not called for values released by reference counting.
+    let log = Log::default();
+    drop(Cc::new(F {
+        name: "c",
+        peer: RefCell::new(None),
+        log: log.clone(),
+        break_cycle: false,
+    }));
+    assert_eq!(log.borrow().concat(), "drop c");
+}
+
+#[test]
+fn test_resurrection_tolerant() {
+    use crate::ObjectSpace;
+
+    thread_local!(static STASH: RefCell<Vec<Cc<N>>> = const { RefCell::new(Vec::new()) });
+    struct N {
+        peer: RefCell<Option<Cc<N>>>,
+        finalized: Cell<usize>,
+        stash_on_finalize: bool,
+        stash_on_drop: bool,
+    }
+    impl Trace for N {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.peer.trace(tracer);
+        }
+        fn finalize(&self) {
+            self.finalized.set(self.finalized.get() + 1);
+            // Revive on the first `finalize` only.
+            if self.stash_on_finalize && self.finalized.get() == 1 {
+                STASH.with(|s| s.borrow_mut().extend(self.peer.borrow().clone()));
+            }
+        }
+    }
+    impl Drop for N {
+        fn drop(&mut self) {
+            if self.stash_on_drop {
+                STASH.with(|s| s.borrow_mut().extend(self.peer.borrow().clone()));
+            }
+        }
+    }
+    let take_stash = || STASH.with(|s| s.take());
+
+    let space = ObjectSpace::default();
+    space.set_resurrection_tolerant(true);
+    let new = |stash_on_finalize, stash_on_drop| {
+        space.create(N {
+            peer: RefCell::new(None),
+            finalized: Cell::new(0),
+            stash_on_finalize,
+            stash_on_drop,
+        })
+    };
+    let new_cycle = |a: &Cc<N>, b: &Cc<N>| {
+        *a.peer.borrow_mut() = Some(b.clone());
+        *b.peer.borrow_mut() = Some(a.clone());
+    };
+
+    // Revived by `finalize`. Objects it refers to are also revived.
+    let (a, b) = (new(true, false), new(false, false));
+    new_cycle(&a, &b);
+    drop((a, b));
+    assert_eq!(space.collect_cycles(), 0);
+    assert_eq!(space.count_tracked(), 2);
+    let stash = take_stash();
+    assert_eq!(stash[0].peer.borrow().as_ref().unwrap().finalized.get(), 1);
+    drop(stash);
+    assert_eq!(space.collect_cycles(), 2);
+    assert_eq!(space.count_tracked(), 0);
+
+    // Revived by `Drop`. The first dropped object stashes its peer, which
+    // is kept alive with a reference to the dropped object.
+    let (a, b) = (new(false, true), new(false, true));
+    new_cycle(&a, &b);
+    let weaks = [a.downgrade(), b.downgrade()];
+    drop((a, b));
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(weaks.iter().filter(|w| w.upgrade().is_some()).count(), 1);
+    assert_eq!(space.count_tracked(), 2);
+    let stash = take_stash();
+    assert!(stash[0].peer.borrow().is_some());
+    assert_eq!(space.collect_cycles(), 0);
+
+    // The dropped object is released with the revived object.
+    drop(stash);
+    drop(take_stash());
+    drop(weaks);
+    assert_eq!(space.count_tracked(), 0);
+
+    // `collect_window` is also tolerant.
+    let (a, b) = (new(true, false), new(false, false));
+    new_cycle(&a, &b);
+    drop((a, b));
+    assert_eq!(space.collect_window(10).collected, 0);
+    drop(take_stash());
+    assert_eq!(space.collect_window(10).collected, 2);
+}
+
+#[test]
+fn test_drop_creates_objects_during_collection() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+
+    // Creates a new cycle in `space` on drop, unless it is created by drop.
+    struct D {
+        peer: RefCell<Option<Cc<D>>>,
+        space: Rc<ObjectSpace>,
+        created_by_drop: bool,
+    }
+    impl Trace for D {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.peer.trace(tracer);
+        }
+    }
+    impl Drop for D {
+        fn drop(&mut self) {
+            if !self.created_by_drop {
+                new_cycle(&self.space, true);
+            }
+        }
+    }
+    fn new_cycle(space: &Rc<ObjectSpace>, created_by_drop: bool) {
+        let new = || {
+            space.create(D {
+                peer: RefCell::new(None),
+                space: space.clone(),
+                created_by_drop,
+            })
+        };
+        let (a, b) = (new(), new());
+        *a.peer.borrow_mut() = Some(b.clone());
+        *b.peer.borrow_mut() = Some(a.clone());
+    }
+
+    let collectors: [&dyn Fn(&ObjectSpace) -> usize; 5] = [
+        &|s| s.collect_cycles(),
+        &|s| {
+            s.set_collect_batch_size(Some(1));
+            s.collect_cycles()
+        },
+        &|s| s.collect_cycles_yielding(1, |_| {}),
+        &|s| s.collect_window(10).collected,
+        &|s| {
+            s.set_resurrection_tolerant(true);
+            s.collect_cycles()
+        },
+    ];
+    for collect in collectors {
+        let space = Rc::new(ObjectSpace::default());
+        new_cycle(&space, false);
+        // The new objects are not collected by the same collection.
+        assert_eq!(collect(&space), 2);
+        assert_eq!(space.count_tracked(), 4);
+        // They are collected by the next collection.
+        assert_eq!(collect(&space), 4);
+        assert_eq!(space.count_tracked(), 0);
+    }
+}
+
+#[cfg(feature = "seqid")]
+#[test]
+fn test_sequence_id() {
+    let a: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+    let b: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+    let c = b.clone();
+    let id = a.sequence_id().unwrap();
+    assert!(b.sequence_id().unwrap() > id);
+    assert_eq!(c.sequence_id(), b.sequence_id());
+    assert_eq!(Cc::new(1).sequence_id(), None);
+
+    // Not reused.
+    drop(a);
+    let d: Cc<Box<dyn Trace>> = Cc::new(Box::new(1));
+    assert!(d.sequence_id().unwrap() > id);
+}
+
+#[cfg(feature = "age")]
+#[test]
+fn test_tracked_info() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    let c: List = space.create_default();
+    assert!(a.creation_time().unwrap() <= b.creation_time().unwrap());
+    assert_eq!(Cc::new(1).creation_time(), None);
+
+    drop(b);
+    let info: Vec<_> = space.tracked_info().collect();
+    let ids: Vec<_> = info.iter().map(|i| Some(i.sequence_id)).collect();
+    assert_eq!(ids, [a.sequence_id(), c.sequence_id()]);
+    assert_eq!(Some(info[0].created), a.creation_time());
+    assert_eq!(info[1].ref_count, 1);
+    assert!(info[1].type_name.contains("RefCell"));
+    assert!(info[0].age() >= info[1].age());
+}
+
+#[test]
+fn test_collect_cycles_yielding() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::ObjectSpace::default();
+    let new_cycle = || {
+        let a: List = space.create_default();
+        a.borrow_mut().push(Box::new(a.clone()));
+    };
+    (0..5).for_each(|_| new_cycle());
+
+    // Objects created by the yielder are kept. Collections are skipped.
+    let mut created = Vec::new();
+    let mut yields = Vec::new();
+    let collected = space.collect_cycles_yielding(2, |released| {
+        yields.push(released);
+        created.push(space.create(RefCell::new(Vec::<Box<dyn Trace>>::new())));
+        assert_eq!(space.collect_cycles(), 0);
+    });
+    assert_eq!(collected, 5);
+    assert_eq!(yields, [2, 4]);
+    assert_eq!(space.count_tracked(), 2);
+
+    // Not yielding if everything fits in a batch.
+    new_cycle();
+    let collected = space.collect_cycles_yielding(2, |_| panic!("should not yield"));
+    assert_eq!(collected, 1);
+}
+
+#[test]
+fn test_collect_window() {
+    use crate::CollectProgress;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = crate::ObjectSpace::default();
+    let new_cycle = || {
+        let a: List = space.create_default();
+        a.borrow_mut().push(Box::new(a.clone()));
+    };
+    let keep: List = space.create_default();
+    (0..4).for_each(|_| new_cycle());
+    let progress = |scanned, collected, reached_end| CollectProgress {
+        scanned,
+        collected,
+        reached_end,
+    };
+
+    assert_eq!(space.collect_window(2), progress(2, 2, false));
+
+    // Objects created between windows are scanned by the next round.
+    new_cycle();
+    assert_eq!(space.collect_window(2), progress(2, 2, false));
+
+    // The object to resume from can be dropped between windows.
+    drop(keep);
+    assert_eq!(space.collect_window(2), progress(0, 0, true));
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.collect_window(2), progress(1, 1, true));
+    assert_eq!(space.count_tracked(), 0);
+
+    // Cycles spanning windows need a full collection.
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(a.clone()));
+    drop((a, b));
+    assert_eq!(space.collect_window(1), progress(1, 0, false));
+    assert_eq!(space.collect_window(1), progress(1, 0, true));
+    assert_eq!(space.collect_window(2), progress(2, 2, true));
+
+    // The cursor does not keep references to objects.
+    let mut a: List = space.create_default();
+    let b: List = space.create_default();
+    assert_eq!(space.collect_window(1), progress(1, 0, false));
+    assert_eq!(a.weak_count(), 0);
+    assert!(Cc::get_mut(&mut a).is_some());
+    assert_eq!(space.count_tracked(), 2);
+    drop(a);
+    assert_eq!(space.collect_window(1), progress(0, 0, true));
+    drop(b);
+
+    // Dropping the space in the middle of a round.
+    (0..3).for_each(|_| new_cycle());
+    assert_eq!(space.collect_window(1), progress(1, 1, false));
+}
+
+#[test]
+fn test_space_contains() {
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space1 = crate::ObjectSpace::default();
+    let space2 = crate::ObjectSpace::default();
+    let a: List = space1.create_default();
+    let b: List = space2.create_default();
+    let c: List = Cc::new(Default::default());
+    assert!(space1.contains(&a));
+    assert!(!space1.contains(&b));
+    assert!(!space1.contains(&c));
+    assert!(space2.contains(&b));
+    assert!(!space2.contains(&a));
+    crate::with_thread_object_space(|space| assert!(space.contains(&c)));
+
+    // Objects keep their space after collect_window.
+    space1.collect_window(1);
+    assert!(space1.contains(&a));
+
+    let d: Cc<dyn Trace> = a.into_dyn();
+    assert!(space1.contains(&d));
+}
+
+#[test]
+fn test_new_cyclic() {
+    use crate::Weak;
+    struct Node {
+        this: Weak<Node>,
+        children: RefCell<Vec<Box<dyn Trace>>>,
+    }
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.children.trace(tracer);
+        }
+    }
+    let space = crate::ObjectSpace::default();
+    let node = space.create_cyclic(|weak: &Weak<Node>| {
+        assert!(weak.upgrade().is_none());
+        assert_eq!(space.count_tracked(), 0);
+        Node {
+            this: weak.clone(),
+            children: Default::default(),
+        }
+    });
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(node.strong_count(), 1);
+    let this = node.this.upgrade().unwrap();
+    assert!(Cc::ptr_eq(&node, &this));
+    node.children.borrow_mut().push(Box::new(this));
+    drop(node);
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.count_tracked(), 0);
+
+    // Panic in `data_fn` with an escaped weak reference.
+    let escaped = RefCell::new(None);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        space.create_cyclic(|weak: &Weak<Node>| {
+            *escaped.borrow_mut() = Some(weak.clone());
+            panic!("data_fn panics");
+        })
+    }));
+    assert!(result.is_err());
+    let escaped = escaped.into_inner().unwrap();
+    assert!(escaped.upgrade().is_none());
+    assert_eq!(space.collect_cycles(), 0);
+    drop(escaped);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_traced_rc() {
+    use crate::{TracedArc, TracedRc};
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    type Shared = RefCell<Vec<Box<dyn Trace>>>;
+    let space = crate::ObjectSpace::default();
+
+    // a -> rc -> a
+    let a: List = space.create_default();
+    let rc: TracedRc<Shared> = Default::default();
+    rc.borrow_mut().push(Box::new(a.clone()));
+    a.borrow_mut().push(Box::new(rc.clone()));
+
+    // Not traced while shared. The cycle is kept.
+    drop(a);
+    assert_eq!(space.collect_cycles(), 0);
+    drop(rc);
+    assert_eq!(space.collect_cycles(), 1);
+
+    // a -> arc -> a
+    let a: List = space.create_default();
+    let arc: TracedArc<Shared> = Default::default();
+    arc.borrow_mut().push(Box::new(a.clone()));
+    a.borrow_mut().push(Box::new(arc));
+    drop(a);
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[test]
+fn test_layout() {
+    use crate::{sizeof_cc, CcLayout};
+    use std::mem;
+    type List = RefCell<Vec<Box<dyn Trace>>>;
+    let space = crate::ObjectSpace::default();
+    let a: Cc<List> = space.create_default();
+    let snapshot = crate::analysis::snapshot(&space);
+    assert_eq!(snapshot.objects[0].size, sizeof_cc::<List>());
+    assert_eq!(sizeof_cc::<List>(), CcLayout::of::<List>(true).size);
+    assert_eq!(sizeof_cc::<u8>(), CcLayout::of::<u8>(false).size);
+    assert_eq!(
+        CcLayout::of::<List>(true).header_size,
+        mem::size_of::<crate::collect::GcHeader>()
+    );
+    // Features adding fields to the header are disabled by default.
+    #[cfg(not(any(feature = "space_pointer", feature = "events", feature = "seqid")))]
+    assert_eq!(
+        mem::size_of::<crate::collect::GcHeader>(),
+        mem::size_of::<usize>() * 3
+    );
+    drop(a);
+
+    #[cfg(feature = "sync")]
+    {
+        use crate::cc::RawCcBoxWithGcHeader;
+        use crate::sync::collect::{Header, ThreadedObjectSpace};
+        const THREADED: CcLayout = CcLayout::threaded::<u32>(true);
+        assert_eq!(THREADED.header_size, mem::size_of::<Header>());
+        assert_eq!(
+            THREADED.size,
+            mem::size_of::<RawCcBoxWithGcHeader<u32, ThreadedObjectSpace>>()
+        );
+        assert_eq!(THREADED.overhead, THREADED.size - mem::size_of::<u32>());
+        assert_eq!(
+            crate::sizeof_threaded_cc::<u32>(),
+            CcLayout::threaded::<u32>(false).size
+        );
+    }
+}
+
+#[test]
+fn test_upgrade_all() {
+    use crate::{upgrade_all, Weak};
+    let space = crate::ObjectSpace::default();
+    let a: Cc<u32> = space.create(1);
+    let b: Cc<u32> = space.create(2);
+    let weaks: Vec<Weak<u32>> = vec![a.downgrade(), b.downgrade(), a.downgrade()];
+    let strongs = upgrade_all(&weaks).unwrap();
+    assert_eq!(strongs.iter().map(|v| **v).collect::<Vec<_>>(), [1, 2, 1]);
+    assert_eq!(a.strong_count(), 3);
+    drop(strongs);
+
+    // Nothing is kept if any value was dropped.
+    drop(b);
+    assert!(upgrade_all(&weaks).is_none());
+    assert_eq!(a.strong_count(), 1);
+    assert!(upgrade_all(&[Weak::<u32>::new()]).is_none());
+    assert_eq!(
+        upgrade_all::<u32, crate::ObjectSpace>(&[]).unwrap().len(),
+        0
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use crate::{deserialize_shared, deserialize_shared_in, serialize_shared};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{Deserializer, Serializer};
+
+    type Pair = (Cc<String>, Cc<String>);
+    let a = Cc::new("a".to_string());
+    let pair: Pair = (a.clone(), a.clone());
+
+    // Without a session, values are duplicated.
+    let json = serde_json::to_string(&pair).unwrap();
+    assert_eq!(json, r#"["a","a"]"#);
+    let copy: Pair = serde_json::from_str(&json).unwrap();
+    assert!(!Cc::ptr_eq(&copy.0, &copy.1));
+
+    // With a session, sharing is preserved.
+    let mut buf = Vec::new();
+    serialize_shared(&pair, &mut Serializer::new(&mut buf)).unwrap();
+    assert_eq!(buf, br#"[[0,"a"],[0]]"#);
+    let copy: Pair = deserialize_shared(&mut Deserializer::from_slice(&buf)).unwrap();
+    assert!(Cc::ptr_eq(&copy.0, &copy.1));
+    assert_eq!(*copy.0, "a");
+
+    // Unknown ids.
+    let result: Result<Pair, _> =
+        deserialize_shared(&mut Deserializer::from_str(r#"[[0,"a"],[1]]"#));
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("unknown shared id 1"));
+
+    struct Node(RefCell<Option<Cc<Node>>>);
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.0.trace(tracer);
+        }
+    }
+    impl Serialize for Node {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.borrow().serialize(serializer)
+        }
+    }
+    impl<'de> Deserialize<'de> for Node {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Ok(Node(RefCell::new(Deserialize::deserialize(deserializer)?)))
+        }
+    }
+
+    // Objects are created in the given space.
+    let space = crate::ObjectSpace::default();
+    let node: Cc<Node> =
+        deserialize_shared_in(&space, &mut Deserializer::from_str("[0,[1,null]]")).unwrap();
+    assert!(space.contains(&node));
+    assert!(space.contains(node.0.borrow().as_ref().unwrap()));
+
+    // Cyclic references cannot be serialized.
+    *node.0.borrow_mut() = Some(node.clone());
+    let result = serialize_shared(&node, &mut Serializer::new(Vec::new()));
+    assert!(result.unwrap_err().to_string().contains("cyclic"));
+    drop(node);
+    assert_eq!(space.collect_cycles(), 1);
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn test_reclaimed_types() {
+    use crate::{GcEventKind, ReclaimedType};
+    type List = RefCell<Vec<Box<dyn Trace>>>;
+    let space = collect::ObjectSpace::default();
+    for _ in 0..2 {
+        let a: Cc<List> = space.create_default();
+        a.borrow_mut().push(Box::new(a.clone()));
+    }
+    let b: Cc<Box<dyn Trace>> = space.create(Box::new(space.create(1)));
+    let c: Cc<RefCell<Option<Box<dyn Trace>>>> = space.create(RefCell::new(None));
+    *c.borrow_mut() = Some(Box::new(c.clone()));
+    drop((b, c));
+    assert_eq!(space.collect_cycles(), 3);
+    let kind = space.recent_events().pop().unwrap().kind;
+    let expected = vec![
+        ReclaimedType {
+            type_name: std::any::type_name::<List>(),
+            count: 2,
+        },
+        ReclaimedType {
+            type_name: std::any::type_name::<RefCell<Option<Box<dyn Trace>>>>(),
+            count: 1,
+        },
+    ];
+    assert_eq!(
+        kind,
+        GcEventKind::Collect {
+            collected: 3,
+            reclaimed: expected,
+        }
+    );
+}
+
+#[test]
+fn test_leak_keeps_config_and_stats() {
+    type List = RefCell<Vec<Box<dyn Trace>>>;
+    collect::with_thread_object_space(|space| {
+        space.enable_leak_detector(1);
+        let a: Cc<List> = space.create_default();
+        a.borrow_mut().push(Box::new(a.clone()));
+        drop(a);
+        assert_eq!(space.collect_cycles(), 1);
+
+        let b: Cc<List> = space.create_default();
+        b.borrow_mut().push(Box::new(b.clone()));
+        space.leak();
+        assert!(!space.contains(&b));
+        drop(b);
+        assert_eq!(space.count_tracked(), 0);
+        assert_eq!(space.collect_cycles(), 0);
+
+        // The leak detector is still enabled.
+        let c: Cc<List> = space.create_default();
+        assert!(space.contains(&c));
+        let mut clones = Vec::new();
+        for _ in 0..3 {
+            clones.push(c.clone());
+            space.collect_cycles();
+        }
+        assert_eq!(space.suspected_leaks().len(), 1);
+    });
+
+    // Counters keep counting until `reset_stats`.
+    let stats = collect::thread_stats();
+    assert_eq!((stats.collections, stats.collected), (5, 1));
+    collect::with_thread_object_space(|space| space.reset_stats());
+    let stats = collect::thread_stats();
+    assert_eq!((stats.collections, stats.collected), (0, 0));
+}
+
+#[test]
+fn test_gc_auto_collect() {
+    use crate::Gc;
+    type List = Gc<RefCell<Vec<Box<dyn Trace>>>>;
+    crate::set_auto_collect_threshold(3);
+
+    let a: List = Default::default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    drop(a);
+
+    // Untracked values do not count.
+    let _n = Gc::new(1);
+    let b: List = Default::default();
+    assert_eq!(collect::count_thread_tracked(), 2);
+
+    // The third tracked value triggers a collection.
+    let c: List = Default::default();
+    assert_eq!(collect::count_thread_tracked(), 2);
+    assert!(Gc::ptr_eq(&c, &c.clone()));
+    assert!(!Gc::ptr_eq(&b, &c));
+
+    // Disabled.
+    crate::set_auto_collect_threshold(0);
+    b.borrow_mut().push(Box::new(b.clone()));
+    drop(b);
+    for _ in 0..3 {
+        let _: List = Default::default();
+    }
+    assert_eq!(collect::count_thread_tracked(), 2);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+}
+
+#[test]
+fn test_merge_spaces() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    let temp = ObjectSpace::default();
+    let mut survivors: Vec<List> = (0..3).map(|_| temp.create_default()).collect();
+    let garbage: List = temp.create_default();
+    garbage.borrow_mut().push(Box::new(garbage.clone()));
+    drop(garbage);
+
+    // The window cursor of `temp` is released.
+    let progress = temp.collect_window(1);
+    assert_eq!((progress.collected, progress.reached_end), (1, false));
+    space.merge(temp);
+    assert_eq!(space.count_tracked(), 4);
+    assert!(survivors.iter().all(|s| space.contains(s)));
+
+    // Cycles across the merged spaces are collected.
+    let b = survivors.pop().unwrap();
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(a.clone()));
+    drop((a, b));
+    assert_eq!(space.collect_cycles(), 2);
+    drop(survivors);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_merge_spaces_nested() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let outer = ObjectSpace::default();
+    let inner = ObjectSpace::default();
+    let a: List = inner.create_default();
+    let b: List = outer.create_default();
+    let c: List = space.create_default();
+    outer.merge(inner);
+    assert!(outer.contains(&a));
+    space.merge(outer);
+    assert_eq!(space.count_tracked(), 3);
+    assert!([&a, &b, &c].iter().all(|x| space.contains(x)));
+
+    // Merging an empty space is fine.
+    space.merge(ObjectSpace::default());
+    assert_eq!(space.count_tracked(), 3);
+
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(c.clone()));
+    c.borrow_mut().push(Box::new(a.clone()));
+    drop((b, c));
+    assert_eq!(space.collect_cycles(), 0);
+
+    space.leak();
+    assert!(!space.contains(&a));
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_count_tracked_by_type() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let _untracked = space.create(1);
+    let a: List = space.create_default();
+    let _b: List = space.create_default();
+    let _c = space.create(RefCell::new(Some(a.clone())));
+    let counts = space.count_tracked_by_type();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(
+        counts[std::any::type_name::<RefCell<Vec<Box<dyn Trace>>>>()],
+        2
+    );
+    assert_eq!(counts[std::any::type_name::<RefCell<Option<List>>>()], 1);
+    assert!(ObjectSpace::default().count_tracked_by_type().is_empty());
+}
+
+#[cfg(feature = "space_pointer")]
+#[test]
+fn test_arena() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::with_arena();
+    let addr = |v: &List| v.inner() as *const _ as usize;
+
+    // Enough objects to use multiple chunks.
+    let values: Vec<List> = (0..5000).map(|_| space.create_default()).collect();
+    for pair in values.chunks(2) {
+        pair[0].borrow_mut().push(Box::new(pair[1].clone()));
+        pair[1].borrow_mut().push(Box::new(pair[0].clone()));
+    }
+    let distance = addr(&values[1]) - addr(&values[0]);
+    assert_eq!(distance, space.bytes_allocated() / values.len());
+    let kept = values[4999].clone();
+    drop(values);
+    assert_eq!(space.collect_cycles(), 4998);
+    assert_eq!(space.count_tracked(), 2);
+
+    // Large and untracked objects use the global allocator.
+    let large = space.create(RefCell::new((None::<List>, [0u8; 100_000])));
+    let untracked = space.create(1);
+    assert_eq!(space.count_tracked(), 3);
+    drop((large, untracked));
+
+    kept.borrow_mut().clear();
+    drop(kept);
+    assert_eq!(space.count_tracked(), 0);
+    assert_eq!(space.bytes_allocated(), 0);
+
+    // Leaked objects keep their memory.
+    let space = ObjectSpace::with_arena();
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    space.leak();
+    drop(a);
+    let b: List = space.create_default();
+    drop(b);
+}
+
+#[cfg(feature = "space_pointer")]
+#[test]
+fn test_with_allocator() {
+    use crate::ObjectSpace;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::rc::Rc;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+
+    /// Counts live allocations. Sets the flag on drop.
+    struct Counting(Rc<Cell<isize>>, Rc<Cell<bool>>);
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.0.set(self.0.get() + 1);
+            System.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            System.dealloc(ptr, layout)
+        }
+    }
+    impl Drop for Counting {
+        fn drop(&mut self) {
+            self.1.set(true);
+        }
+    }
+
+    let live: Rc<Cell<isize>> = Default::default();
+    let dropped: Rc<Cell<bool>> = Default::default();
+    let space = ObjectSpace::with_allocator(Counting(live.clone(), dropped.clone()));
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    let _untracked = space.create(1);
+    assert_eq!(live.get(), 2);
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(a.clone()));
+    drop((a, b));
+    assert_eq!(space.collect_cycles(), 2);
+    assert_eq!(live.get(), 0);
+
+    // Objects created after `leak` use the same allocator.
+    let c: List = space.create_default();
+    c.borrow_mut().push(Box::new(c.clone()));
+    space.leak();
+    let d: List = space.create_default();
+    assert_eq!(live.get(), 2);
+    drop(d);
+    drop(space);
+    assert_eq!(live.get(), 1);
+    assert!(!dropped.get());
+
+    // Leaked objects keep the allocator alive until they are dropped.
+    let dropped: Rc<Cell<bool>> = Default::default();
+    let space = ObjectSpace::with_allocator(Counting(live.clone(), dropped.clone()));
+    let e: List = space.create_default();
+    let f = e.clone();
+    space.leak();
+    drop(space);
+    assert!(!dropped.get());
+    drop(e);
+    assert!(!dropped.get());
+    drop(f);
+    assert_eq!(live.get(), 1);
+    assert!(dropped.get());
+}
+
+#[test]
+fn test_freeze() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let frozen_cycle: List = space.create_default();
+    frozen_cycle
+        .borrow_mut()
+        .push(Box::new(frozen_cycle.clone()));
+    let root: List = space.create_default();
+    space.freeze();
+    assert_eq!(space.count_tracked(), 0);
+    assert_eq!(space.count_frozen(), 2);
+    assert!(space.contains(&root));
+
+    // New objects referred by frozen objects are reachable.
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    root.borrow_mut().push(Box::new(a.clone()));
+    // New objects referring to frozen objects can be collected.
+    let b: List = space.create_default();
+    b.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(frozen_cycle.clone()));
+    drop((a, b, frozen_cycle));
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.count_frozen(), 2);
+
+    // Frozen objects are released by reference counting.
+    drop(root);
+    assert_eq!(space.count_frozen(), 1);
+    assert_eq!(space.collect_cycles(), 1);
+
+    // Frozen objects move along with `merge`.
+    let other = ObjectSpace::default();
+    let c: List = other.create_default();
+    c.borrow_mut().push(Box::new(c.clone()));
+    other.freeze();
+    drop(c);
+    space.merge(other);
+    assert_eq!(space.count_frozen(), 2);
+    space.unfreeze();
+    assert_eq!(space.count_frozen(), 0);
+    assert_eq!(space.collect_cycles(), 2);
+
+    // Frozen cycles are collected when the space is dropped.
+    struct SetOnDrop(std::rc::Rc<Cell<bool>>);
+    impl Trace for SetOnDrop {}
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+    let dropped: std::rc::Rc<Cell<bool>> = Default::default();
+    let d: List = space.create_default();
+    d.borrow_mut().push(Box::new(d.clone()));
+    d.borrow_mut().push(Box::new(SetOnDrop(dropped.clone())));
+    space.freeze();
+    drop(d);
+    drop(space);
+    assert!(dropped.get());
+}
+
+#[test]
+fn test_bytes_allocated() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let walked = |space: &ObjectSpace| space.stats().tracked_bytes;
+    let space = ObjectSpace::default();
+    let untracked = space.create(1);
+    assert_eq!(space.bytes_allocated(), 0);
+
+    let a: List = space.create_default();
+    let size = space.bytes_allocated();
+    assert!(size > 0);
+    let b: List = space.create_default();
+    assert_eq!(space.bytes_allocated(), size * 2);
+    a.borrow_mut().push(Box::new(a.clone()));
+    drop(b);
+    assert_eq!(space.bytes_allocated(), size);
+    drop(a);
+    assert_eq!(space.bytes_allocated(), size);
+    let stats = space.collect_cycles_with_stats();
+    assert_eq!(stats.released_bytes, size);
+    assert_eq!(stats.bytes_allocated, 0);
+    assert_eq!(space.bytes_allocated(), 0);
+
+    // Merged spaces are counted. Leaked objects are not.
+    let other = ObjectSpace::default();
+    let c: List = other.create_default();
+    let _d: List = space.create_default();
+    space.merge(other);
+    assert_eq!(space.bytes_allocated(), size * 2);
+    assert_eq!(space.bytes_allocated(), walked(&space));
+    drop(c);
+    assert_eq!(space.bytes_allocated(), size);
+    space.leak();
+    assert_eq!(space.bytes_allocated(), 0);
+    drop(untracked);
+}
+
+#[cfg(not(miri))]
+quickcheck::quickcheck! {
+    fn test_quickcheck_verify_collect_cycles(edges: Vec<u8>, root_bits: u16) -> bool {
+        use crate::testutil::verify_collect_cycles;
+        type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+        let space = crate::ObjectSpace::default();
+        let values: Vec<List> = (0..16).map(|_| space.create_default()).collect();
+        for edge in edges {
+            let from = &values[(edge >> 4) as usize];
+            let to = &values[(edge & 15) as usize];
+            from.borrow_mut().push(Box::new(to.clone()));
+        }
+        let roots: Vec<List> = values
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| (root_bits >> i) & 1 != 0)
+            .map(|(_, v)| v)
+            .collect();
+        let root_refs: Vec<&dyn Trace> = roots.iter().map(|r| r as &dyn Trace).collect();
+        let result = verify_collect_cycles(&space, &root_refs);
+        assert!(result.is_consistent(), "{:?}", result);
+        drop(root_refs);
+        drop(roots);
+        let result = verify_collect_cycles(&space, &[]);
+        assert!(result.is_consistent(), "{:?}", result);
+        space.count_tracked() == 0
+    }
+}
+
+#[test]
+fn test_verify_collect_cycles_hidden_edge() {
+    use crate::testutil::verify_collect_cycles;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    struct Hidden(List);
+    impl Trace for Hidden {
+        // Does not report the edge to `self.0`.
+    }
+    let space = crate::ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    b.borrow_mut().push(Box::new(b.clone()));
+    let hidden = space.create(Hidden(a));
+    drop(b);
+
+    let result = verify_collect_cycles(&space, &[&hidden]);
+    assert_eq!(result.collected, 1);
+    assert!(result.reachable_released.is_empty());
+    assert_eq!(result.unreachable_kept.len(), 1);
+    assert!(result.unreachable_kept[0].contains("RefCell"));
+
+    hidden.0.borrow_mut().clear();
+    drop(hidden);
+    assert!(verify_collect_cycles(&space, &[]).is_consistent());
+}
+
+#[test]
+fn test_collect_hooks() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = Rc::new(ObjectSpace::default());
+    let log: Rc<RefCell<Vec<String>>> = Default::default();
+    space.set_collect_hooks(
+        {
+            let log = log.clone();
+            move || log.borrow_mut().push("start".to_string())
+        },
+        {
+            let log = log.clone();
+            move |stats| {
+                let line = format!("end {} {}", stats.scanned, stats.collected);
+                log.borrow_mut().push(line);
+            }
+        },
+    );
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    let _b: List = space.create_default();
+    drop(a);
+    space.defer_collection(|| space.collect_cycles());
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.collect_cycles(), 0);
+    assert_eq!(*log.borrow(), ["start", "end 2 1", "start", "end 1 0"]);
+
+    // Hooks can replace themselves.
+    let space2 = space.clone();
+    let log2 = log.clone();
+    space.set_collect_hooks(
+        move || space2.clear_collect_hooks(),
+        move |_| log2.borrow_mut().push("cleared".to_string()),
+    );
+    space.collect_cycles();
+    space.collect_cycles();
+    assert_eq!(log.borrow().len(), 5);
+}
+
+#[test]
+fn test_gc_cell() {
+    use crate::{GcCell, ObjectSpace};
+    type List = Cc<GcCell<Vec<Box<dyn Trace>>>>;
+    let a: List = Default::default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    let b = a.clone();
+    drop(a);
+
+    // Collections are deferred while borrowed mutably.
+    let mut borrowed = b.borrow_mut();
+    assert_eq!(collect::collect_thread_cycles(), 0);
+    let space = ObjectSpace::default();
+    let c: List = space.create_default();
+    c.borrow_mut().push(Box::new(c.clone()));
+    drop(c);
+    assert_eq!(space.collect_cycles(), 0);
+    assert_eq!(space.count_tracked(), 1);
+
+    // Immutable borrows do not defer collections.
+    borrowed.push(Box::new(1));
+    drop(borrowed);
+    let _borrowed = b.borrow();
+    assert_eq!(space.collect_cycles(), 1);
+    drop(_borrowed);
+    drop(b);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn test_cross_space_refs() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let other = ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = other.create_default();
+    let log = debug::capture_log(|| {
+        a.borrow_mut().push(Box::new(b.clone()));
+        space.collect_cycles();
+    });
+    assert!(log.contains("in a different space"), "{}", log);
+
+    // References are fine after merging the spaces.
+    let c: List = space.create_default();
+    c.borrow_mut().push(Box::new(a.clone()));
+    space.merge(other);
+    let log = debug::capture_log(|| {
+        space.collect_cycles();
+    });
+    assert!(!log.contains("in a different space"), "{}", log);
+    drop((a, b, c));
+}
+
+#[test]
+fn test_cc_coerce() {
+    trait Named: Trace {
+        fn name(&self) -> &'static str;
+    }
+    struct Node(&'static str, RefCell<Vec<Cc<dyn Named>>>);
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.1.borrow().iter().for_each(|n| n.trace(tracer));
+        }
+    }
+    impl Named for Node {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+    }
+
+    let a = Cc::new(Node("a", Default::default()));
+    let b: Cc<dyn Named> = crate::cc_coerce!(Cc::new(Node("b", Default::default())), dyn Named);
+    a.1.borrow_mut().push(b.clone());
+    a.1.borrow_mut()
+        .push(crate::cc_coerce!(a.clone(), dyn Named));
+    assert_eq!(a.1.borrow()[1].name(), "a");
+    assert_eq!(b.name(), "b");
+    assert_eq!(a.ref_count(), 2);
+
+    // Coercing to `dyn Trace` keeps the ref count.
+    let c: Cc<dyn Trace> = crate::cc_coerce!(b, dyn Trace);
+    assert_eq!(c.ref_count(), 2);
+    drop(c);
+
+    drop(a);
+    assert_eq!(collect::collect_thread_cycles(), 2);
+}
+
+#[test]
+fn test_enter_space() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let outer = Rc::new(ObjectSpace::default());
+    let inner = Rc::new(ObjectSpace::default());
+    let outer_guard = outer.enter();
+    let a: List = Cc::new(Default::default());
+    let inner_guard = inner.enter();
+    let b: List = Cc::new_cyclic(|_| Default::default());
+    // Guards can be dropped out of order.
+    drop(outer_guard);
+    let c: List = Cc::new(Default::default());
+    drop(inner_guard);
+    let d: List = Cc::new(Default::default());
+
+    assert!(outer.contains(&a));
+    assert!(inner.contains(&b));
+    assert!(inner.contains(&c));
+    assert!(!outer.contains(&d) && !inner.contains(&d));
+    for x in [&a, &b, &c, &d] {
+        x.borrow_mut().push(Box::new(x.clone()));
+    }
+    drop((a, b, c, d));
+    assert_eq!(inner.collect_cycles(), 2);
+    assert_eq!(outer.collect_cycles(), 1);
+    assert_eq!(collect::collect_thread_cycles(), 1);
+}
+
+#[test]
+fn test_trace_untracked_container() {
+    use std::collections::{HashMap, VecDeque};
+    thread_local!(static TRACED: Cell<usize> = const { Cell::new(0) });
+    struct Untracked;
+    impl Trace for Untracked {
+        fn trace(&self, _tracer: &mut Tracer) {
+            TRACED.with(|t| t.set(t.get() + 1));
+        }
+        fn is_type_tracked() -> bool {
+            false
+        }
+    }
+    let mut tracer = |_: *const ()| {};
+    vec![Untracked, Untracked].trace(&mut tracer);
+    VecDeque::from([Untracked]).trace(&mut tracer);
+    [Untracked].trace(&mut tracer);
+    HashMap::from([(1, Untracked)]).trace(&mut tracer);
+    assert_eq!(TRACED.with(|t| t.get()), 0);
+
+    // Tracked values are still traced.
+    let v: Vec<(Untracked, Box<dyn Trace>)> = vec![(Untracked, Box::new(1))];
+    v.trace(&mut tracer);
+    assert_eq!(TRACED.with(|t| t.get()), 1);
+}
+
+#[test]
+fn test_find_cycles() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    let c: List = space.create_default();
+    let d: List = space.create_default();
+    let e: List = space.create_default();
+    // a <-> b -> c (not in a cycle), d -> d, e -> a.
+    a.borrow_mut().push(Box::new(b.clone()));
+    b.borrow_mut().push(Box::new(a.clone()));
+    b.borrow_mut().push(Box::new(c.clone()));
+    d.borrow_mut().push(Box::new(d.clone()));
+    e.borrow_mut().push(Box::new(a.clone()));
+    drop((a, b, c, d));
+    let mut cycles = space.find_cycles();
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(cycles[0].len(), 1);
+
+    drop(e);
+    cycles = space.find_cycles();
+    cycles.sort_by_key(|c| c.len());
+    assert_eq!(cycles.iter().map(|c| c.len()).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(space.count_tracked(), 4);
+    assert_eq!(space.collect_cycles(), 4);
+    assert!(space.find_cycles().is_empty());
+}
+
+#[cfg(feature = "ordered_drop")]
+#[test]
+fn test_ordered_drop() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+    struct N {
+        id: usize,
+        next: RefCell<Option<Cc<N>>>,
+        log: Rc<RefCell<Vec<usize>>>,
+    }
+    impl Trace for N {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.next.trace(tracer);
+        }
+    }
+    impl Drop for N {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.id);
+        }
+    }
+
+    let collectors: [&dyn Fn(&ObjectSpace) -> usize; 2] = [&|s| s.collect_cycles(), &|s| {
+        s.set_resurrection_tolerant(true);
+        s.collect_cycles()
+    }];
+    for collect in collectors {
+        let space = ObjectSpace::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let nodes: Vec<Cc<N>> = (0..5)
+            .map(|id| {
+                space.create(N {
+                    id,
+                    next: RefCell::new(None),
+                    log: log.clone(),
+                })
+            })
+            .collect();
+        // Link in an order unrelated to creation.
+        for (i, j) in [(3, 1), (1, 4), (4, 0), (0, 2), (2, 3)] {
+            *nodes[i].next.borrow_mut() = Some(nodes[j].clone());
+        }
+        drop(nodes);
+        assert_eq!(collect(&space), 5);
+        assert_eq!(*log.borrow(), [0, 1, 2, 3, 4]);
+    }
+}
+
+#[test]
+fn test_ptr_identity() {
+    use crate::{Address, PtrIdentity};
+    use std::collections::{BTreeSet, HashMap};
+
+    let a: Cc<dyn Trace> = Cc::new(1u8).into_dyn();
+    let b = Cc::new(1u8).into_dyn();
+    assert_eq!(
+        Cc::as_ptr(&a) as *const u8,
+        Cc::as_ptr(&a.clone()) as *const u8
+    );
+    assert_ne!(Cc::as_ptr(&a) as *const u8, Cc::as_ptr(&b) as *const u8);
+
+    let mut map = HashMap::new();
+    map.insert(PtrIdentity(a.clone()), "a");
+    map.insert(PtrIdentity(b.clone()), "b");
+    map.insert(PtrIdentity(a.clone()), "a2");
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&PtrIdentity(a.clone())], "a2");
+
+    let set: BTreeSet<_> = vec![a.clone(), b.clone(), a.clone()]
+        .into_iter()
+        .map(PtrIdentity)
+        .collect();
+    assert_eq!(set.len(), 2);
+
+    // Look up by address, without a `Cc`.
+    let ptr = Cc::as_ptr(&b);
+    // safety: `b` is alive.
+    let b_addr = unsafe { Address::from_ptr(ptr) };
+    assert_eq!(map[b_addr], "b");
+    assert!(set.contains(b_addr));
+    assert!(set.contains(Address::new(&*a)));
+    assert!(!set.contains(Address::new(&1u8 as &dyn Trace)));
+}
+
+#[test]
+fn test_make_mut() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Cc<dyn Trace>>>>;
+    let thread_tracked = collect::count_thread_tracked();
+    let space = ObjectSpace::default();
+
+    // Unique. Mutated in place.
+    let mut a: List = space.create_default();
+    let addr = a.id();
+    Cc::make_mut(&mut a).get_mut().push(Cc::new(1).into_dyn());
+    assert_eq!(a.id(), addr);
+
+    // Shared. Copied to the same space.
+    let b = a.clone();
+    Cc::make_mut(&mut a).get_mut().push(Cc::new(2).into_dyn());
+    assert_ne!(a.id(), addr);
+    assert_eq!((a.borrow().len(), b.borrow().len()), (2, 1));
+    assert_eq!(space.count_tracked(), 2);
+
+    // Weak references keep pointing to the old object.
+    let c: List = space.create_default();
+    let mut d = c.clone();
+    drop(c);
+    let weak = d.downgrade();
+    Cc::make_mut(&mut d);
+    d.borrow_mut().push(d.clone().into_dyn());
+    assert!(weak.upgrade().is_none());
+    drop(weak);
+    assert_eq!(space.count_tracked(), 3);
+
+    // Copies of objects in a merged space.
+    let other = ObjectSpace::default();
+    let e: List = other.create_default();
+    let mut f = e.clone();
+    space.merge(other);
+    f.update(|v| v.get_mut().push(Cc::new(3).into_dyn()));
+    assert_eq!(space.count_tracked(), 5);
+
+    // Untracked values.
+    let mut g = space.create(1);
+    let h = g.clone();
+    *Cc::make_mut(&mut g) += 1;
+    assert_eq!((*g, *h), (2, 1));
+
+    assert_eq!(collect::count_thread_tracked(), thread_tracked);
+    drop((a, b, d, e, f));
+    assert_eq!(space.collect_cycles(), 1);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+fn test_interner() {
+    use crate::{Interner, ObjectSpace};
+    use std::hash::{Hash, Hasher};
+    use std::rc::Rc;
+
+    // Tracked. Compared by name.
+    struct Sym(String, RefCell<Option<Cc<dyn Trace>>>);
+    impl Trace for Sym {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.1.trace(tracer);
+        }
+    }
+    impl PartialEq for Sym {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl Eq for Sym {}
+    impl Hash for Sym {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.hash(state)
+        }
+    }
+    let sym = |name: &str| Sym(name.to_string(), Default::default());
+
+    let space = Rc::new(ObjectSpace::default());
+    let interner = Interner::with_space(space.clone());
+    let a = interner.intern(sym("a"));
+    let b = interner.intern(sym("b"));
+    assert!(Cc::ptr_eq(&a, &interner.intern(sym("a"))));
+    assert!(!Cc::ptr_eq(&a, &b));
+    assert_eq!(interner.len(), 2);
+    assert_eq!(space.count_tracked(), 2);
+
+    // Released by the collector.
+    *a.1.borrow_mut() = Some(a.clone().into_dyn());
+    drop(a);
+    assert!(interner.get(&sym("a")).is_some());
+    assert_eq!(space.collect_cycles(), 1);
+    assert!(interner.get(&sym("a")).is_none());
+    assert_eq!(interner.len(), 1);
+
+    // Released by dropping. Interning again creates a new object.
+    drop(b);
+    assert!(interner.is_empty());
+    let b = interner.intern(sym("b"));
+    assert_eq!(interner.len(), 1);
+    drop(b);
+
+    // Released values are purged automatically.
+    let strings: Interner<String> = Interner::new();
+    let kept = strings.intern_borrowed("kept");
+    for i in 0..1000 {
+        strings.intern(i.to_string());
+    }
+    assert!(strings.entries.get() < 100);
+    assert!(Cc::ptr_eq(&kept, &strings.intern_borrowed("kept")));
+    strings.purge();
+    assert_eq!(strings.entries.get(), 1);
+}
+
+#[test]
+fn test_ephemeron_map() {
+    use crate::{EphemeronMap, ObjectSpace};
+    type Inner = RefCell<Vec<Cc<dyn Trace>>>;
+    type List = Cc<Inner>;
+
+    for mode in 0..3 {
+        let space = ObjectSpace::default();
+        match mode {
+            1 => space.set_collect_batch_size(Some(1)),
+            2 => space.set_resurrection_tolerant(true),
+            _ => {}
+        }
+        let map: EphemeronMap<Inner, List> = EphemeronMap::new();
+        let new_list = || -> List { space.create_default() };
+
+        // The value refers to the key. Both are released.
+        let a = new_list();
+        map.insert(&a, a.clone());
+        drop(a);
+        assert_eq!(space.collect_cycles(), 1, "mode {}", mode);
+        assert!(map.is_empty());
+        assert_eq!(space.count_tracked(), 0);
+
+        // The key is alive. Objects referred by the value are kept.
+        let b = new_list();
+        let c = new_list();
+        c.borrow_mut().push(c.clone().into_dyn());
+        map.insert(&b, c.clone());
+        let c_weak = c.downgrade();
+        drop(c);
+        assert_eq!(space.collect_cycles(), 0);
+        assert!(c_weak.upgrade().is_some());
+        assert!(map.get(&b).is_some());
+
+        // Keys made reachable by other values: b -> d -> e -> f.
+        let d = new_list();
+        let e = new_list();
+        let f = new_list();
+        map.insert(&b, d.clone());
+        map.insert(&d, e.clone());
+        map.insert(&e, f.clone());
+        drop((d, e, f));
+        assert_eq!(space.collect_cycles(), 1); // c, replaced by d
+        assert_eq!(map.len(), 3);
+        assert!(c_weak.upgrade().is_none());
+        drop(c_weak);
+        assert_eq!(space.count_tracked(), 4);
+
+        // Releasing the first key releases the chain.
+        drop(b);
+        assert!(map.is_empty());
+        assert_eq!(space.collect_cycles(), 0);
+        assert_eq!(space.count_tracked(), 0);
+
+        // A cycle among values and keys.
+        let g = new_list();
+        let h = new_list();
+        map.insert(&g, h.clone());
+        map.insert(&h, g.clone());
+        drop((g, h));
+        assert_eq!(space.collect_cycles(), 2);
+        assert!(map.is_empty());
+    }
+}
+
+#[test]
+fn test_weak_value_map_and_set() {
+    use crate::{ObjectSpace, WeakSet, WeakValueMap};
+    type List = Cc<RefCell<Vec<Cc<dyn Trace>>>>;
+
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    b.borrow_mut().push(b.clone().into_dyn());
+
+    let mut map = WeakValueMap::new();
+    assert!(map.insert("a", &a).is_none());
+    assert!(map.insert("b", &b).is_none());
+    assert!(Cc::ptr_eq(&map.insert("a", &a).unwrap(), &a));
+    let mut set = WeakSet::new();
+    assert!(set.insert(&a));
+    assert!(set.insert(&b));
+    assert!(!set.insert(&b));
+    assert_eq!((map.len(), set.len()), (2, 2));
+
+    // Released by the collector.
+    drop(b);
+    assert!(map.contains_key("b"));
+    assert_eq!(space.collect_cycles(), 1);
+    assert!(map.get("b").is_none());
+    assert_eq!((map.len(), set.len()), (1, 1));
+    assert!(set.iter().all(|v| Cc::ptr_eq(&v, &a)));
+    assert!(map.remove("b").is_none());
+
+    // Released entries are purged automatically.
+    let mut by_id = WeakValueMap::new();
+    for _ in 0..100 {
+        let value: List = space.create_default();
+        by_id.insert(value.id(), &value);
+        set.insert(&value);
+    }
+    assert!(by_id.entries.len() < 50);
+    assert!(set.entries.len() < 50);
+    assert!(set.remove(&a));
+    assert!(!set.contains(&a));
+    set.purge();
+    by_id.purge();
+    map.purge();
+    assert_eq!(map.entries.len(), 1);
+    assert_eq!((by_id.entries.len(), set.entries.len()), (0, 0));
+}
+
+#[test]
+fn test_option_size_and_non_null() {
+    use crate::{ObjectSpace, RawCc, Weak};
+    use std::mem::size_of;
+
+    assert_eq!(size_of::<Option<Cc<u8>>>(), size_of::<Cc<u8>>());
+    assert_eq!(size_of::<Option<Cc<[u8]>>>(), size_of::<Cc<[u8]>>());
+    assert_eq!(
+        size_of::<Option<Cc<dyn Trace>>>(),
+        size_of::<Cc<dyn Trace>>()
+    );
+    assert_eq!(size_of::<Option<Weak<u8>>>(), size_of::<Weak<u8>>());
+
+    let a: RawCc<String, ObjectSpace> = Cc::new("a".to_string());
+    let ptr = Cc::as_non_null(&a);
+    assert_eq!(ptr.as_ptr() as *const String, Cc::as_ptr(&a));
+    // safety: `a` is alive.
+    assert_eq!(unsafe { ptr.as_ref() }, "a");
+}
+
+#[test]
+fn test_untrack() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    let b: List = space.create_default();
+    b.borrow_mut().push(Box::new(a.clone()));
+    a.untrack();
+    a.untrack();
+    assert_eq!(space.count_tracked(), 1);
+
+    // Objects referred by untracked objects are reachable.
+    a.borrow_mut().push(Box::new(a.clone()));
+    drop(b);
+    assert_eq!(space.collect_cycles(), 0);
+    let weak = a.downgrade();
+    drop(a);
+    assert_eq!(space.collect_cycles(), 0);
+    assert_eq!(weak.strong_count(), 1);
+    weak.upgrade().unwrap().borrow_mut().clear();
+
+    // Untracked objects are released by reference counting.
+    let c: List = space.create_default();
+    c.untrack();
+    drop(c);
+    assert_eq!(space.count_tracked(), 0);
+
+    // collect_window resumes after untracked objects.
+    let d: Vec<List> = (0..3).map(|_| space.create_default()).collect();
+    assert!(!space.collect_window(1).reached_end);
+    d.iter().for_each(|d| d.untrack());
+    assert!(space.collect_window(1).reached_end);
+}
+
+#[cfg(feature = "space_pointer")]
+#[test]
+fn test_retrack() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    a.untrack();
+    assert!(space.contains(&a));
+
+    // Untracked cycles are collected after retrack.
+    let weak = a.downgrade();
+    drop(a);
+    assert_eq!(space.collect_cycles(), 0);
+    assert!(weak.upgrade().unwrap().retrack());
+    assert_eq!(space.count_tracked(), 1);
+    assert_eq!(space.collect_cycles(), 1);
+    drop(weak);
+
+    // Untracked objects are counted until they are released.
+    let c: List = space.create_default();
+    c.untrack();
+    assert!(space.bytes_allocated() > 0);
+    drop(c);
+    assert_eq!(space.bytes_allocated(), 0);
+
+    // Copies of untracked objects by make_mut are tracked.
+    let mut f: Cc<Vec<List>> = space.create(vec![space.create_default()]);
+    let g = f.clone();
+    f.untrack();
+    Cc::make_mut(&mut f).clear();
+    assert_eq!(space.count_tracked(), 2);
+    drop((f, g));
+    assert_eq!(space.bytes_allocated(), 0);
+
+    // Retrack after merge goes to the merged space, and fails after drop.
+    let other = ObjectSpace::default();
+    let e: List = other.create_default();
+    e.untrack();
+    let mut h: Cc<Vec<List>> = other.create(Vec::new());
+    h.untrack();
+    space.merge(other);
+    assert!(e.retrack());
+    assert_eq!(space.count_tracked(), 1);
+    e.untrack();
+    drop(space);
+    assert!(!e.retrack());
+
+    // Copies stay untracked after drop.
+    let h2 = h.clone();
+    Cc::make_mut(&mut h).push(e);
+    assert!(!h.retrack());
+    drop((h, h2));
+}
+
+#[test]
+fn test_leak_on_drop() {
+    use crate::ObjectSpace;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    space.set_leak_on_drop(true);
+    let a: List = space.create_default();
+    a.borrow_mut().push(Box::new(a.clone()));
+    let frozen: List = space.create_default();
+    frozen.borrow_mut().push(Box::new(frozen.clone()));
+    space.freeze();
+    let (weak_a, weak_frozen) = (a.downgrade(), frozen.downgrade());
+    drop((a, frozen, space));
+    assert_eq!(weak_a.strong_count(), 1);
+    assert_eq!(weak_frozen.strong_count(), 1);
+
+    // The thread-local space honors the flag on thread exit.
+    let dropped = std::thread::spawn(|| {
+        crate::with_thread_object_space(|space| space.set_leak_on_drop(true));
+        let a: List = Default::default();
+        a.borrow_mut().push(Box::new(a.clone()));
+        let dropped = std::sync::Arc::new(AtomicBool::new(false));
+        struct SetOnDrop(std::sync::Arc<AtomicBool>);
+        impl Trace for SetOnDrop {}
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, SeqCst);
+            }
+        }
+        a.borrow_mut().push(Box::new(SetOnDrop(dropped.clone())));
+        dropped
+    })
+    .join()
+    .unwrap();
+    assert!(!dropped.load(SeqCst));
+}
+
+#[cfg(feature = "space_pointer")]
+#[test]
+fn test_pressure_handler() {
+    use crate::ObjectSpace;
+    use std::rc::Rc;
+    type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+    let space = ObjectSpace::default();
+    let calls: Rc<RefCell<Vec<usize>>> = Default::default();
+    let calls2 = calls.clone();
+    space.set_pressure_handler(vec![4, 2], move |count| {
+        calls2.borrow_mut().push(count);
+        count >= 4
+    });
+
+    let new_cycle = || {
+        let a: List = space.create_default();
+        a.borrow_mut().push(Box::new(a.clone()));
+    };
+    (0..3).for_each(|_| new_cycle());
+    assert_eq!(*calls.borrow(), [2]);
+    assert_eq!(space.count_tracked(), 3);
+
+    // The object being created is not collected.
+    new_cycle();
+    assert_eq!(*calls.borrow(), [2, 4]);
+    assert_eq!(space.count_tracked(), 1);
+
+    // Objects of merged spaces are counted.
+    let other = ObjectSpace::default();
+    let _b: List = other.create_default();
+    space.merge(other);
+    (0..2).for_each(|_| new_cycle());
+    assert_eq!(*calls.borrow(), [2, 4, 4]);
+    assert_eq!(space.count_tracked(), 2);
+
+    space.clear_pressure_handler();
+    (0..3).for_each(|_| new_cycle());
+    assert_eq!(calls.borrow().len(), 3);
+}
+
+#[test]
+fn test_deep_clone() {
+    use crate::{DeepClone, DeepCloner, ObjectSpace};
+    #[derive(Default)]
+    struct List(Vec<Cc<RefCell<List>>>);
+    impl Trace for List {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.0.trace(tracer);
+        }
+    }
+    impl DeepClone for List {
+        fn deep_clone(&self, cloner: &mut DeepCloner) -> Self {
+            List(self.0.deep_clone(cloner))
+        }
+    }
+
+    let space = ObjectSpace::default();
+    let a: Cc<RefCell<List>> = space.create_default();
+    let b = space.create(RefCell::new(List(vec![a.clone(), a.clone()])));
+    a.borrow_mut().0.push(b.clone());
+
+    let copy = b.deep_clone(&mut DeepCloner::new());
+    assert_eq!(space.count_tracked(), 4);
+    let copy_a = copy.borrow().0[0].clone();
+    assert!(!Cc::ptr_eq(&copy_a, &a));
+    assert!(Cc::ptr_eq(&copy_a, &copy.borrow().0[1]));
+    assert!(Cc::ptr_eq(&copy_a.borrow().0[0], &copy));
+
+    // Changes to the copy do not affect the original.
+    copy_a.borrow_mut().0.clear();
+    assert_eq!(a.borrow().0.len(), 1);
+
+    drop((a, b, copy, copy_a));
+    assert_eq!(space.collect_cycles(), 2);
+    assert_eq!(space.count_tracked(), 0);
+}
+
+#[test]
+#[should_panic(expected = "cannot deep clone a cycle without placeholders")]
+fn test_deep_clone_cycle_without_placeholder() {
+    use crate::DeepClone;
+    use std::cell::OnceCell;
+
+    struct Node(OnceCell<Cc<Node>>);
+    impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer) {
+            if let Some(next) = self.0.get() {
+                next.trace(tracer);
+            }
+        }
+    }
+    impl DeepClone for Node {
+        fn deep_clone(&self, cloner: &mut crate::DeepCloner) -> Self {
+            let cell = OnceCell::new();
+            if let Some(next) = self.0.get() {
+                let _ = cell.set(next.deep_clone(cloner));
+            }
+            Node(cell)
+        }
+    }
+
+    let a = Cc::new(Node(OnceCell::new()));
+    let _ = a.0.set(a.clone());
+    let _ = crate::deep_clone(&a);
 }
@@ -1,12 +1,15 @@
 //! Test utilities.
 
-use crate::{collect, debug, Cc, Trace, Tracer};
+use crate::cc::CcDyn;
+use crate::collect::{visit_list, GcHeader, Linked};
+use crate::{collect, debug, Cc, ObjectSpace, Trace, Tracer};
 use std::cell::Cell;
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
 
-thread_local!(static NEXT_TRACKED_OVERRIDE: Cell<bool> = Cell::new(true));
+thread_local!(static NEXT_TRACKED_OVERRIDE: Cell<bool> = const { Cell::new(true) });
 
 /// Track count of drop(). Store result in AtomicUsize.
 /// The bool value controls whether this type is tracked.
@@ -25,11 +28,13 @@ impl<T> Drop for DropCounter<T> {
     }
 }
 
+type TestObject = Cc<DropCounter<RefCell<Vec<Box<dyn Trace>>>>>;
+
 pub(crate) fn create_objects(
     n: usize,
     atomic_bits: u16,
     drop_count: Arc<AtomicUsize>,
-) -> Vec<Cc<DropCounter<RefCell<Vec<Box<dyn Trace>>>>>> {
+) -> Vec<TestObject> {
     assert!(n <= 16);
     let is_tracked = |n| -> bool { (atomic_bits >> n) & 1 == 0 };
     (0..n)
@@ -103,3 +108,114 @@ pub fn test_small_graph(n: usize, edges: &[u8], atomic_bits: u16, collect_bits:
         edge_descs,
     );
 }
+
+/// Result of [`verify_collect_cycles`](fn.verify_collect_cycles.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollectVerification {
+    /// Return value of `collect_cycles`.
+    pub collected: usize,
+
+    /// Type names of objects reachable from the roots, but released by the
+    /// collector. This is a collector bug, or missing roots.
+    pub reachable_released: Vec<&'static str>,
+
+    /// Type names of objects unreachable from the roots, but kept by the
+    /// collector. This is a collector bug, missing edges in `Trace`
+    /// implementations, or references not visible as roots.
+    pub unreachable_kept: Vec<&'static str>,
+}
+
+impl CollectVerification {
+    /// Whether the collector agrees with the mark-and-sweep verifier.
+    pub fn is_consistent(&self) -> bool {
+        self.reachable_released.is_empty() && self.unreachable_kept.is_empty()
+    }
+}
+
+/// Run [`ObjectSpace::collect_cycles`](../struct.ObjectSpace.html#method.collect_cycles),
+/// and compare its decisions with an independent mark-and-sweep pass.
+///
+/// `roots` are all references to objects in `space` that are not owned by
+/// other tracked objects, like local variables. Objects reachable from
+/// `roots` by `Trace::trace` are expected to be kept. Other objects in
+/// `space` are expected to be released.
+///
+/// Weak references are taken on tracked objects during collection so
+/// released objects can be inspected afterwards. This is meant for tests
+/// and fuzzing. It walks all tracked objects and edges.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::testutil::verify_collect_cycles;
+/// use gcmodule::{ObjectSpace, Trace};
+/// use std::cell::RefCell;
+///
+/// type List = gcmodule::Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let space = ObjectSpace::default();
+/// let a: List = space.create(Default::default());
+/// let b: List = space.create(Default::default());
+/// a.borrow_mut().push(Box::new(b.clone()));
+/// b.borrow_mut().push(Box::new(b.clone()));
+/// drop(b);
+///
+/// let result = verify_collect_cycles(&space, &[&a]);
+/// assert!(result.is_consistent());
+/// assert_eq!(result.collected, 0);
+///
+/// drop(a); // `b` is only referred by itself.
+/// let result = verify_collect_cycles(&space, &[]);
+/// assert!(result.is_consistent());
+/// assert_eq!(result.collected, 1);
+/// ```
+pub fn verify_collect_cycles(space: &ObjectSpace, roots: &[&dyn Trace]) -> CollectVerification {
+    // Mark.
+    let mut reachable: HashSet<*const ()> = HashSet::new();
+    let mut stack: Vec<*const ()> = Vec::new();
+    for &root in roots {
+        root.trace(&mut |header| stack.push(header));
+    }
+    while let Some(header) = stack.pop() {
+        if reachable.insert(header) {
+            value_of(header).gc_traverse(&mut |next| stack.push(next));
+        }
+    }
+
+    // Keep allocations alive so the result can be inspected after
+    // collection.
+    let mut objects: Vec<*const ()> = Vec::new();
+    {
+        let list: &GcHeader = &space.list.borrow();
+        visit_list(list, |header| {
+            let value = header.value();
+            if !value.gc_is_dropped() {
+                value.gc_inc_weak();
+                objects.push(header as *const GcHeader as *const ());
+            }
+        });
+    }
+
+    // Sweep, and compare.
+    let mut result = CollectVerification {
+        collected: space.collect_cycles(),
+        ..Default::default()
+    };
+    for header in objects {
+        let value = value_of(header);
+        match (reachable.contains(&header), value.gc_is_dropped()) {
+            (true, true) => result.reachable_released.push(value.gc_type_name()),
+            (false, false) => result.unreachable_kept.push(value.gc_type_name()),
+            _ => {}
+        }
+        value.gc_dec_weak();
+    }
+    result
+}
+
+fn value_of<'a>(header: *const ()) -> &'a dyn CcDyn {
+    // safety: Tracers receive pointers to `GcHeader`s of live objects. The
+    // objects are kept alive by `roots`, or weak references taken by
+    // `verify_collect_cycles`.
+    let header: &GcHeader = unsafe { &*(header as *const GcHeader) };
+    header.value()
+}
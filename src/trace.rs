@@ -1,6 +1,88 @@
-/// Callback function that serves as the parameter of
+use std::any::Any;
+use std::any::TypeId;
+
+/// The parameter of [`Trace::trace`](trait.Trace.html#method.trace).
+///
+/// Implementations of `Trace` usually pass it to `trace` of fields. Use
+/// [`trace_named`](trait.Visitor.html#method.trace_named) to label the
+/// references found in a field, for heap snapshots and cycle reports.
+pub type Tracer<'a> = dyn Visitor + 'a;
+
+/// Receives references to tracked objects visited by
 /// [`Trace::trace`](trait.Trace.html#method.trace).
-pub type Tracer<'a> = dyn FnMut(*const ()) + 'a;
+///
+/// Closures taking `*const ()` implement this trait. The pointer identifies
+/// a tracked object.
+pub trait Visitor {
+    /// Visit a reference to a tracked object.
+    fn visit(&mut self, header: *const ());
+
+    /// Visit a reference to a tracked object, found in a field or edge
+    /// named `name`. By default, the name is ignored.
+    fn visit_named(&mut self, name: &'static str, header: *const ()) {
+        let _ = name;
+        self.visit(header)
+    }
+}
+
+impl<F: FnMut(*const ()) + ?Sized> Visitor for F {
+    #[inline]
+    fn visit(&mut self, header: *const ()) {
+        self(header)
+    }
+}
+
+impl<'a> dyn Visitor + 'a {
+    /// Trace `value`, labeling references found in it with `name`, ex. a
+    /// field name. `derive(Trace)` labels fields this way.
+    ///
+    /// If `value` labels references itself, the outer `name` is used, so
+    /// labels describe fields of the object holding the references.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace, Tracer};
+    /// use std::cell::RefCell;
+    ///
+    /// struct Node {
+    ///     next: RefCell<Option<Cc<Node>>>,
+    /// }
+    /// impl Trace for Node {
+    ///     fn trace(&self, tracer: &mut Tracer) {
+    ///         tracer.trace_named("next", &self.next);
+    ///     }
+    /// }
+    ///
+    /// let a = Cc::new(Node { next: RefCell::new(None) });
+    /// *a.next.borrow_mut() = Some(a.clone());
+    /// let cycle = gcmodule::analysis::find_cycle_through(&a).unwrap();
+    /// assert_eq!(cycle[0].name, Some("next"));
+    /// # a.next.borrow_mut().take();
+    /// ```
+    pub fn trace_named<T: Trace + ?Sized>(&mut self, name: &'static str, value: &T) {
+        value.trace(&mut Named { name, inner: self });
+    }
+}
+
+/// Labels references with `name`. Used by `trace_named`.
+struct Named<'a, 'b> {
+    name: &'static str,
+    inner: &'a mut (dyn Visitor + 'b),
+}
+
+impl Visitor for Named<'_, '_> {
+    #[inline]
+    fn visit(&mut self, header: *const ()) {
+        self.inner.visit_named(self.name, header)
+    }
+
+    #[inline]
+    fn visit_named(&mut self, _name: &'static str, header: *const ()) {
+        self.visit(header)
+    }
+}
 
 /// Defines how the cycle collector should collect a type.
 ///
@@ -14,6 +96,43 @@ pub type Tracer<'a> = dyn FnMut(*const ()) + 'a;
 /// The [`Drop`] implementation should also be careful about cloning
 /// (resurrecting) [`Cc<T>`] objects. If it must do so, the `trace`
 /// implementation should match by avoiding visiting those cloned objects.
+/// See also
+/// [`ObjectSpace::set_resurrection_tolerant`](struct.ObjectSpace.html#method.set_resurrection_tolerant).
+///
+/// The [`Drop`] implementation can create new [`Cc<T>`](type.Cc.html)
+/// objects, including in the space being collected, for example, to log the
+/// dropped value. New objects are not considered by the in-flight
+/// collection. They are tracked as usual, and released by reference
+/// counting or a later collection. This applies to all collection methods,
+/// and [`Trace::finalize`](trait.Trace.html#method.finalize).
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{Cc, Trace, Tracer};
+/// use std::cell::RefCell;
+///
+/// struct Node(RefCell<Option<Cc<Node>>>);
+/// impl Trace for Node {
+///     fn trace(&self, tracer: &mut Tracer) {
+///         self.0.trace(tracer);
+///     }
+/// }
+/// impl Drop for Node {
+///     fn drop(&mut self) {
+///         // A new cycle, not collected by the current collection.
+///         let log: Cc<RefCell<Vec<Box<dyn Trace>>>> = Default::default();
+///         log.borrow_mut().push(Box::new(log.clone()));
+///     }
+/// }
+///
+/// let a = Cc::new(Node(RefCell::new(None)));
+/// *a.0.borrow_mut() = Some(a.clone());
+/// drop(a);
+/// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+/// assert_eq!(gcmodule::count_thread_tracked(), 1);
+/// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+/// assert_eq!(gcmodule::count_thread_tracked(), 0);
+/// ```
 ///
 /// ## The `'static` bound
 ///
@@ -44,7 +163,8 @@ pub trait Trace: 'static {
     /// be tracked. This allows the collector to visit the `Cc` values from
     /// its parents and count references correctly.
     ///
-    /// If a type `T` is tracked, `Cc<T>` will be 3 `usize` larger and the
+    /// If a type `T` is tracked, `Cc<T>` will be 3 `usize` larger (more with
+    /// some features, see [`CcLayout`](struct.CcLayout.html)) and the
     /// collector will check them.
     ///
     /// For example,
@@ -73,6 +193,102 @@ pub trait Trace: 'static {
         Self: Sized,
     {
         // Fallback implementation: Opt-in the collector for correctness.
-        return true;
+        true
+    }
+
+    /// Called by the collector before dropping this value as part of
+    /// unreachable cycles, similar to `__del__` in Python.
+    ///
+    /// Unlike [`Drop`], all `finalize` calls of unreachable objects happen
+    /// before any of them is dropped. So `finalize` can dereference other
+    /// [`Cc<T>`] objects, including the ones being collected. For example,
+    /// to flush buffers, or to break cycles explicitly.
+    ///
+    /// - `finalize` is only called by the collector. Values released by
+    ///   reference counting are dropped without calling it.
+    /// - `finalize` is called at most once per value, on the `T` of a
+    ///   `Cc<T>`. Wrappers like `Box<T>` and `RefCell<T>` do not forward it.
+    /// - The order of `finalize` calls within a collection is unspecified.
+    /// - `finalize` should not keep new references to the objects being
+    ///   collected (resurrection). They will be dropped regardless.
+    ///   Dereferencing them afterwards can trigger the same issues as an
+    ///   incorrect `trace` implementation, unless the space is
+    ///   [resurrection tolerant](struct.ObjectSpace.html#method.set_resurrection_tolerant).
+    fn finalize(&self) {}
+
+    /// Name of the type in debug logs and heap reports, like
+    /// [`analysis::snapshot`](analysis/fn.snapshot.html) and
+    /// [`count_tracked_by_type`](struct.ObjectSpace.html#method.count_tracked_by_type).
+    ///
+    /// The default is `std::any::type_name::<Self>()`. `derive(Trace)`
+    /// returns the name from `#[trace(name = "...")]`. The name should not
+    /// depend on the value. `std::any::type_name` is used instead after
+    /// the value is dropped.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// `TypeId` of the concrete type. Used by `Cc::<dyn Trace>::downcast`
+    /// and `<dyn Trace>::downcast_ref`.
+    ///
+    /// Do not override this method. An incorrect implementation leads to
+    /// undefined behavior.
+    #[doc(hidden)]
+    fn __gcmodule_type_id(&self) -> TypeId {
+        TypeId::of::<Self>()
+    }
+
+    /// Whether the value has [`SelfRef`](struct.SelfRef.html) fields to
+    /// initialize. `derive(Trace)` implements this for fields.
+    #[doc(hidden)]
+    fn __gcmodule_has_self_ref() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Initialize [`SelfRef`](struct.SelfRef.html) fields. `weak` is the
+    /// `RawWeak` of the `RawCc` owning the value. `derive(Trace)` forwards
+    /// this to fields.
+    #[doc(hidden)]
+    fn __gcmodule_init_self_ref(&self, weak: &dyn Any) {
+        let _ = weak;
+    }
+}
+
+impl dyn Trace {
+    /// Returns `true` if the value is a `T`.
+    #[inline]
+    pub fn is<T: Trace>(&self) -> bool {
+        self.__gcmodule_type_id() == TypeId::of::<T>()
+    }
+
+    /// Returns a reference to the value if it is a `T`, or `None` if it
+    /// is not.
+    ///
+    /// This works for any `dyn Trace`, including `Box<dyn Trace>` stored in
+    /// containers. The type must match exactly. For example, a
+    /// `Box<dyn Trace>` holding a `Cc<T>` is a `Cc<T>`, not a `T`.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "std", doc = " ```")]
+    #[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+    /// use gcmodule::{Cc, Trace};
+    ///
+    /// let values: Vec<Box<dyn Trace>> = vec![Box::new(1u8), Box::new(Cc::new(2u8))];
+    /// assert_eq!(values[0].downcast_ref::<u8>(), Some(&1));
+    /// assert_eq!(values[1].downcast_ref::<u8>(), None);
+    /// assert_eq!(**values[1].downcast_ref::<Cc<u8>>().unwrap(), 2);
+    /// ```
+    #[inline]
+    pub fn downcast_ref<T: Trace>(&self) -> Option<&T> {
+        if self.is::<T>() {
+            // safety: The type is checked by TypeId.
+            Some(unsafe { &*(self as *const dyn Trace as *const T) })
+        } else {
+            None
+        }
     }
 }
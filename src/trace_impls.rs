@@ -1,4 +1,7 @@
 use crate::trace::{Trace, Tracer};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 /// Mark types as acyclic. Opt-out the cycle collector.
 ///
@@ -19,6 +22,7 @@ use crate::trace::{Trace, Tracer};
 /// trace_acyclic!(Y);
 /// trace_acyclic!(<T> Z<T>);
 /// ```
+#[macro_export]
 macro_rules! trace_acyclic {
     ( <$( $g:ident ),*> $( $t: tt )* ) => {
         impl<$( $g: 'static ),*> $crate::Trace for $($t)* {
@@ -27,7 +31,7 @@ macro_rules! trace_acyclic {
         }
     };
     ( $( $t: ty ),* ) => {
-        $( trace_acyclic!(<> $t); )*
+        $( $crate::trace_acyclic!(<> $t); )*
     };
 }
 
@@ -49,6 +53,7 @@ macro_rules! trace_acyclic {
 ///     Z { 0 }
 /// );
 /// ```
+#[macro_export]
 macro_rules! trace_fields {
     ( $( $type:ty { $( $field:tt $(: $tp:ident )? ),* } )* ) => {
         $(
@@ -69,7 +74,35 @@ macro_rules! trace_fields {
 
 trace_acyclic!(bool, char, f32, f64, i16, i32, i64, i8, isize, u16, u32, u64, u8, usize);
 trace_acyclic!(());
-trace_acyclic!(String, &'static str);
+trace_acyclic!(String);
+
+// References do not own the values they point to. Not traced.
+impl<T: ?Sized + 'static> Trace for &'static T {
+    #[inline]
+    fn is_type_tracked() -> bool {
+        false
+    }
+}
+
+mod array {
+    use super::*;
+
+    impl<T: Trace, const N: usize> Trace for [T; N] {
+        fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
+            for t in self {
+                t.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+}
 
 mod tuples {
     trace_fields!(
@@ -82,16 +115,15 @@ mod tuples {
 
 mod borrow {
     use super::*;
-    use std::borrow::Cow;
+    use alloc::borrow::{Cow, ToOwned};
 
     impl<T: ToOwned + ?Sized> Trace for Cow<'static, T>
     where
         T::Owned: Trace,
     {
         fn trace(&self, tracer: &mut Tracer) {
-            match self {
-                Cow::Owned(v) => v.trace(tracer),
-                _ => (),
+            if let Cow::Owned(v) = self {
+                v.trace(tracer)
             }
         }
 
@@ -116,6 +148,22 @@ mod boxed {
         }
     }
 
+    impl<T: Trace> Trace for Box<[T]> {
+        fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
+            for t in self.iter() {
+                t.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+
     impl Trace for Box<dyn Trace> {
         fn trace(&self, tracer: &mut Tracer) {
             self.as_ref().trace(tracer);
@@ -187,11 +235,13 @@ mod cell {
 
 mod collections {
     use super::*;
-    use std::collections;
-    use std::hash;
+    use alloc::collections;
 
     impl<K: Trace, V: Trace> Trace for collections::BTreeMap<K, V> {
         fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
             for (k, v) in self {
                 k.trace(tracer);
                 v.trace(tracer);
@@ -204,8 +254,12 @@ mod collections {
         }
     }
 
-    impl<K: Eq + hash::Hash + Trace, V: Trace> Trace for collections::HashMap<K, V> {
+    #[cfg(feature = "std")]
+    impl<K: Eq + std::hash::Hash + Trace, V: Trace> Trace for std::collections::HashMap<K, V> {
         fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
             for (k, v) in self {
                 k.trace(tracer);
                 v.trace(tracer);
@@ -218,8 +272,60 @@ mod collections {
         }
     }
 
+    impl<T: Trace> Trace for collections::BTreeSet<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
+            for t in self {
+                t.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+
+    impl<T: Trace> Trace for collections::BinaryHeap<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
+            for t in self {
+                t.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<T: Eq + std::hash::Hash + Trace> Trace for std::collections::HashSet<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
+            for t in self {
+                t.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+
     impl<T: Trace> Trace for collections::LinkedList<T> {
         fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
             for t in self {
                 t.trace(tracer);
             }
@@ -233,6 +339,9 @@ mod collections {
 
     impl<T: Trace> Trace for collections::VecDeque<T> {
         fn trace(&self, tracer: &mut Tracer) {
+            if !Self::is_type_tracked() {
+                return;
+            }
             for t in self {
                 t.trace(tracer);
             }
@@ -249,6 +358,11 @@ mod vec {
     use super::*;
     impl<T: Trace> Trace for Vec<T> {
         fn trace(&self, tracer: &mut Tracer) {
+            // Skip iterating untracked elements, ex. `Vec<Cc<u64>>`. This
+            // is a constant after inlining.
+            if !Self::is_type_tracked() {
+                return;
+            }
             for t in self {
                 t.trace(tracer);
             }
@@ -261,6 +375,50 @@ mod vec {
     }
 }
 
+mod future {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+
+    // Values captured by futures cannot be traced. They are considered
+    // referred externally, so cycles through futures are not collected.
+    // Futures are tracked like other trait objects.
+
+    impl<T: 'static> Trace for Pin<Box<dyn Future<Output = T>>> {
+        #[inline]
+        fn is_type_tracked() -> bool {
+            true
+        }
+    }
+
+    impl<T: 'static> Trace for Pin<Box<dyn Future<Output = T> + Send>> {
+        #[inline]
+        fn is_type_tracked() -> bool {
+            true
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+mod futures {
+    use super::*;
+    use futures_util::lock;
+
+    impl<T: Trace> Trace for lock::Mutex<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            // See std Mutex for why locking is optional.
+            if let Some(x) = self.try_lock() {
+                x.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+}
+
 // See https://github.com/rust-lang/rust/issues/56105#issuecomment-465709105
 #[allow(unknown_lints)]
 #[allow(coherence_leak_check)]
@@ -305,12 +463,26 @@ mod func {
     trace_acyclic!(<A, B, C, D, E, F, X> fn(A, B, C, D, E, F) -> X);
 }
 
+#[cfg(feature = "std")]
 mod ffi {
     use std::ffi;
 
     trace_acyclic!(ffi::CString, ffi::NulError, ffi::OsString);
 }
 
+mod marker {
+    use super::*;
+    use std::marker::PhantomData;
+
+    impl<T: ?Sized + 'static> Trace for PhantomData<T> {
+        #[inline]
+        fn is_type_tracked() -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 mod net {
     use std::net;
 
@@ -326,6 +498,42 @@ mod net {
     );
 }
 
+mod num {
+    use std::num;
+
+    trace_acyclic!(
+        num::NonZeroI8,
+        num::NonZeroI16,
+        num::NonZeroI32,
+        num::NonZeroI64,
+        num::NonZeroI128,
+        num::NonZeroIsize,
+        num::NonZeroU8,
+        num::NonZeroU16,
+        num::NonZeroU32,
+        num::NonZeroU64,
+        num::NonZeroU128,
+        num::NonZeroUsize
+    );
+}
+
+mod ops {
+    use super::*;
+    use std::ops::Range;
+
+    impl<T: Trace> Trace for Range<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            self.start.trace(tracer);
+            self.end.trace(tracer);
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+}
+
 mod option {
     use super::*;
 
@@ -342,12 +550,33 @@ mod option {
     }
 }
 
+#[cfg(feature = "std")]
 mod path {
     use std::path;
 
     trace_acyclic!(path::PathBuf);
 }
 
+mod pin {
+    use super::*;
+    use std::pin::Pin;
+
+    impl<P: Trace> Trace for Pin<P> {
+        fn trace(&self, tracer: &mut Tracer) {
+            // safety: `Pin` is `repr(transparent)`. `trace` only takes a
+            // shared reference, which cannot move the pointee.
+            let pointer: &P = unsafe { &*(self as *const Pin<P> as *const P) };
+            pointer.trace(tracer);
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            P::is_type_tracked()
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 mod process {
     use std::process;
 
@@ -364,7 +593,7 @@ mod process {
 }
 
 mod rc {
-    use std::rc;
+    use alloc::rc;
 
     trace_acyclic!(<T> rc::Rc<T>);
     trace_acyclic!(<T> rc::Weak<T>);
@@ -388,12 +617,16 @@ mod result {
 }
 
 mod sync {
+    #[cfg(feature = "std")]
     use super::*;
+    use alloc::sync::Arc;
+    #[cfg(feature = "std")]
     use std::sync;
 
     // See comment in Mutex for why this is acyclic.
-    trace_acyclic!(<T> sync::Arc<T>);
+    trace_acyclic!(<T> Arc<T>);
 
+    #[cfg(feature = "std")]
     impl<T: Trace> Trace for sync::Mutex<T> {
         fn trace(&self, tracer: &mut Tracer) {
             // For single-thread collector (ObjectSpace):
@@ -420,6 +653,7 @@ mod sync {
         }
     }
 
+    #[cfg(feature = "std")]
     impl<T: Trace> Trace for sync::RwLock<T> {
         fn trace(&self, tracer: &mut Tracer) {
             // See Mutex for why locking is optional.
@@ -438,6 +672,7 @@ mod sync {
     }
 }
 
+#[cfg(feature = "std")]
 mod thread {
     use std::thread;
 
@@ -446,7 +681,49 @@ mod thread {
     trace_acyclic!(thread::Thread);
 }
 
-#[cfg(test)]
+#[cfg(feature = "tokio")]
+mod tokio {
+    use super::*;
+    use ::tokio::sync;
+
+    impl<T: Trace> Trace for sync::Mutex<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            // See std Mutex for why locking is optional.
+            if let Ok(x) = self.try_lock() {
+                x.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+
+    impl<T: Trace> Trace for sync::RwLock<T> {
+        fn trace(&self, tracer: &mut Tracer) {
+            // See std RwLock for why locking is optional.
+            if let Ok(x) = self.try_write() {
+                x.trace(tracer);
+            }
+        }
+
+        #[inline]
+        fn is_type_tracked() -> bool {
+            T::is_type_tracked()
+        }
+    }
+}
+
+mod time {
+    use std::time;
+
+    trace_acyclic!(time::Duration);
+    #[cfg(feature = "std")]
+    trace_acyclic!(time::Instant);
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use crate::Cc;
@@ -472,6 +749,91 @@ mod tests {
 
         assert!(!<fn(u8) -> u8>::is_type_tracked());
         assert!(!<fn(&u8) -> u8>::is_type_tracked());
+
+        assert!(!<[u8; 3]>::is_type_tracked());
+        assert!(<[Box<dyn Trace>; 2]>::is_type_tracked());
+        assert!(!Box::<[u8]>::is_type_tracked());
+        assert!(Box::<[Box<dyn Trace>]>::is_type_tracked());
+        assert!(!std::collections::HashSet::<u8>::is_type_tracked());
+        assert!(!std::collections::BTreeSet::<u8>::is_type_tracked());
+        assert!(!std::collections::BinaryHeap::<u8>::is_type_tracked());
+        assert!(!std::marker::PhantomData::<dyn Trace>::is_type_tracked());
+        assert!(!std::ops::Range::<u8>::is_type_tracked());
+        assert!(!std::time::Duration::is_type_tracked());
+        assert!(!std::time::Instant::is_type_tracked());
+        assert!(!std::num::NonZeroU32::is_type_tracked());
+        assert!(!std::pin::Pin::<Box<u8>>::is_type_tracked());
+        assert!(std::pin::Pin::<Cc<Box<dyn Trace>>>::is_type_tracked());
+    }
+
+    #[test]
+    fn test_trace_future() {
+        use std::future::Future;
+        use std::pin::Pin;
+        assert!(Pin::<Box<dyn Future<Output = u8>>>::is_type_tracked());
+
+        // Values captured by futures are kept alive.
+        type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+        let a: List = Default::default();
+        let captured = a.clone();
+        let future: Pin<Box<dyn Future<Output = usize>>> =
+            Box::pin(async move { captured.borrow().len() });
+        a.borrow_mut().push(Box::new(future));
+        drop(a);
+        assert_eq!(crate::collect_thread_cycles(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_trace_tokio() {
+        type List = Cc<::tokio::sync::Mutex<Vec<Box<dyn Trace>>>>;
+        let a: List = Default::default();
+        a.try_lock().unwrap().push(Box::new(a.clone()));
+        let b: Cc<::tokio::sync::RwLock<Option<List>>> = Default::default();
+        *b.try_write().unwrap() = Some(Default::default());
+        b.try_read()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .try_lock()
+            .unwrap()
+            .push(Box::new(b.clone()));
+        drop((a, b));
+        assert_eq!(crate::collect_thread_cycles(), 3);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_trace_futures() {
+        type List = Cc<futures_util::lock::Mutex<Vec<Box<dyn Trace>>>>;
+        let a: List = Default::default();
+        a.try_lock().unwrap().push(Box::new(a.clone()));
+        drop(a);
+        assert_eq!(crate::collect_thread_cycles(), 1);
+    }
+
+    #[test]
+    fn test_trace_pin() {
+        type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+        let a: List = Default::default();
+        let pinned_cc = Cc::pin(RefCell::new(Some(Box::pin(a.clone()))));
+        a.borrow_mut().push(Box::new(pinned_cc));
+        drop(a);
+        assert_eq!(crate::collect_thread_cycles(), 2);
+    }
+
+    #[test]
+    fn test_trace_array_and_slice() {
+        type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+        let space = crate::ObjectSpace::default();
+        let a: List = space.create_default();
+        let b: List = space.create_default();
+        let array: [Box<dyn Trace>; 1] = [Box::new(a.clone())];
+        let slice: Box<[Box<dyn Trace>]> = vec![Box::new(b.clone()) as Box<dyn Trace>].into();
+        a.borrow_mut().push(Box::new(array));
+        b.borrow_mut().push(Box::new(slice));
+        drop((a, b));
+        assert_eq!(space.collect_cycles(), 2);
     }
 
     #[test]
@@ -0,0 +1,112 @@
+use crate::Trace;
+use crate::Tracer;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use std::fmt;
+use std::ops::Deref;
+
+/// An `Rc<T>` that traces `T` while the `Rc` is uniquely owned.
+///
+/// `Rc<T>` implements [`Trace`](trait.Trace.html) as acyclic, so a
+/// [`Cc<T>`](type.Cc.html) reachable only via an `Rc` hides cycles from the
+/// collector. `TracedRc<T>` visits `T` if the strong count is 1 and there
+/// are no weak references, like a `Box<T>`.
+///
+/// If the `Rc` is shared, `T` is not visited. Each owner would otherwise
+/// report the same references, and the collector would release objects
+/// that are still reachable. Cycles through a shared `Rc` are not
+/// collected, but values stay valid.
+///
+/// # Example
+///
+#[cfg_attr(feature = "std", doc = " ```")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use gcmodule::{Cc, Trace, TracedRc};
+/// use std::cell::RefCell;
+///
+/// type List = Cc<RefCell<Vec<Box<dyn Trace>>>>;
+/// let a: List = Default::default();
+/// let rc = TracedRc::new(RefCell::new(Vec::<Box<dyn Trace>>::new()));
+/// rc.borrow_mut().push(Box::new(a.clone()));
+/// a.borrow_mut().push(Box::new(rc));
+/// drop(a);
+/// assert_eq!(gcmodule::collect_thread_cycles(), 1);
+/// ```
+pub struct TracedRc<T: ?Sized>(Rc<T>);
+
+/// An `Arc<T>` that traces `T` while the `Arc` is uniquely owned.
+///
+/// See [`TracedRc<T>`](struct.TracedRc.html).
+pub struct TracedArc<T: ?Sized>(Arc<T>);
+
+macro_rules! impl_traced {
+    ($name:ident, $ptr:ident) => {
+        impl<T> $name<T> {
+            /// Constructs a new pointer.
+            pub fn new(value: T) -> Self {
+                Self($ptr::new(value))
+            }
+        }
+
+        impl<T: ?Sized> $name<T> {
+            /// Get the underlying pointer.
+            pub fn into_inner(this: Self) -> $ptr<T> {
+                this.0
+            }
+
+            /// Whether `T` is visited by `Trace::trace`.
+            fn is_unique(&self) -> bool {
+                $ptr::strong_count(&self.0) == 1 && $ptr::weak_count(&self.0) == 0
+            }
+        }
+
+        impl<T: ?Sized> From<$ptr<T>> for $name<T> {
+            fn from(ptr: $ptr<T>) -> Self {
+                Self(ptr)
+            }
+        }
+
+        impl<T: ?Sized> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                Self(self.0.clone())
+            }
+        }
+
+        impl<T: ?Sized> Deref for $name<T> {
+            type Target = T;
+
+            #[inline]
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T: Default> Default for $name<T> {
+            fn default() -> Self {
+                Self::new(T::default())
+            }
+        }
+
+        impl<T: fmt::Debug + ?Sized> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl<T: Trace> Trace for $name<T> {
+            fn trace(&self, tracer: &mut Tracer) {
+                if self.is_unique() {
+                    T::trace(&self.0, tracer);
+                }
+            }
+
+            #[inline]
+            fn is_type_tracked() -> bool {
+                T::is_type_tracked()
+            }
+        }
+    };
+}
+
+impl_traced!(TracedRc, Rc);
+impl_traced!(TracedArc, Arc);
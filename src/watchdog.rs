@@ -0,0 +1,120 @@
+//! Warn about spaces that keep growing without being collected.
+//!
+//! Forgetting to ever call `collect_cycles` is a common integration bug. It
+//! is silent: cyclic garbage just accumulates. The watchdog samples the
+//! number of tracked objects as objects are created, and reports when the
+//! count grew monotonically by at least a threshold, and no collection
+//! happened for at least a duration.
+
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Reported by the watchdog enabled by
+/// [`ObjectSpace::enable_watchdog`](struct.ObjectSpace.html#method.enable_watchdog).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchdogWarning {
+    /// Number of tracked objects.
+    pub tracked: usize,
+
+    /// Growth of tracked objects since the count started growing.
+    pub growth: usize,
+
+    /// Time since the last collection, or since the watchdog was enabled if
+    /// there were no collections.
+    pub since_collection: Duration,
+}
+
+pub(crate) type WatchdogCallback = Rc<dyn Fn(&WatchdogWarning)>;
+
+pub(crate) struct Watchdog {
+    threshold: usize,
+    duration: Duration,
+    callback: WatchdogCallback,
+
+    /// Sample the count every this many created objects.
+    sample_interval: usize,
+
+    /// Objects created since the last sample.
+    created: usize,
+
+    /// Count when it started growing.
+    baseline: usize,
+
+    /// Count at the last sample.
+    last_sample: usize,
+
+    /// Time of the last collection.
+    last_collection: Instant,
+
+    /// Whether a warning was reported since the last collection.
+    warned: bool,
+}
+
+impl Watchdog {
+    pub(crate) fn new(
+        threshold: usize,
+        duration: Duration,
+        callback: WatchdogCallback,
+        tracked: usize,
+    ) -> Self {
+        Self {
+            threshold,
+            duration,
+            callback,
+            sample_interval: (threshold / 4).max(1),
+            created: 0,
+            baseline: tracked,
+            last_sample: tracked,
+            last_collection: Instant::now(),
+            warned: false,
+        }
+    }
+
+    /// Called when a tracked object is created. Return whether the count
+    /// should be sampled via `sample`.
+    pub(crate) fn on_create(&mut self) -> bool {
+        if self.warned {
+            return false;
+        }
+        self.created += 1;
+        if self.created < self.sample_interval {
+            return false;
+        }
+        self.created = 0;
+        true
+    }
+
+    /// Record the number of tracked objects. Return the callback and the
+    /// warning to report. The callback should be called without borrowing
+    /// the watchdog, since it might collect.
+    pub(crate) fn sample(&mut self, tracked: usize) -> Option<(WatchdogCallback, WatchdogWarning)> {
+        if tracked < self.last_sample {
+            // Not growing monotonically. Start over.
+            self.baseline = tracked;
+        }
+        self.last_sample = tracked;
+        let growth = tracked - self.baseline;
+        let since_collection = self.last_collection.elapsed();
+        if growth >= self.threshold && since_collection >= self.duration {
+            self.warned = true;
+            let warning = WatchdogWarning {
+                tracked,
+                growth,
+                since_collection,
+            };
+            Some((self.callback.clone(), warning))
+        } else {
+            None
+        }
+    }
+
+    /// Called after a collection with the number of tracked objects.
+    pub(crate) fn on_collect(&mut self, tracked: usize) {
+        self.created = 0;
+        self.baseline = tracked;
+        self.last_sample = tracked;
+        self.last_collection = Instant::now();
+        self.warned = false;
+    }
+}
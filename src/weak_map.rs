@@ -0,0 +1,250 @@
+use crate::collect::AbstractObjectSpace;
+use crate::collect::ObjectSpace;
+use crate::RawCc;
+use crate::RawWeak;
+use crate::Trace;
+use crate::Tracer;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// A map with values held weakly, like `HashMap<K, Weak<V>>`.
+///
+/// Entries of released values, including values released by the collector,
+/// are treated as absent. They are removed when the number of entries
+/// doubles since the last [`purge`](struct.WeakValueMap.html#method.purge),
+/// so memory of released values is not kept for long.
+///
+/// Values can be in any space, ex. `O` is
+/// [`ThreadedObjectSpace`](struct.ThreadedObjectSpace.html) for
+/// `ThreadedCc` values.
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, WeakValueMap};
+///
+/// let mut cache = WeakValueMap::new();
+/// let value = Cc::new(1);
+/// cache.insert("one", &value);
+/// assert_eq!(cache.get("one").map(|v| *v), Some(1));
+///
+/// drop(value);
+/// assert!(cache.get("one").is_none());
+/// assert!(cache.is_empty());
+/// ```
+pub struct WeakValueMap<K, V: ?Sized, O: AbstractObjectSpace = ObjectSpace> {
+    pub(crate) entries: HashMap<K, RawWeak<V, O>>,
+
+    /// `entries.len()` after the last `purge`.
+    purged_len: usize,
+}
+
+/// A set of objects held weakly, compared by identity.
+///
+/// Released objects, including objects released by the collector, are
+/// treated as absent. They are removed like in
+/// [`WeakValueMap`](struct.WeakValueMap.html).
+///
+/// # Example
+///
+/// ```
+/// use gcmodule::{Cc, WeakSet};
+///
+/// let mut observers = WeakSet::new();
+/// let a = Cc::new("a");
+/// let b = Cc::new("a");
+/// assert!(observers.insert(&a));
+/// assert!(!observers.insert(&a));
+/// assert!(!observers.contains(&b));
+///
+/// drop(a);
+/// assert_eq!(observers.iter().count(), 0);
+/// ```
+pub struct WeakSet<T: ?Sized, O: AbstractObjectSpace = ObjectSpace> {
+    /// Weak references by object ids.
+    pub(crate) entries: HashMap<usize, RawWeak<T, O>>,
+
+    /// `entries.len()` after the last `purge`.
+    purged_len: usize,
+}
+
+/// Whether a collection of `len` entries should be purged.
+fn should_purge(len: usize, purged_len: usize) -> bool {
+    len > purged_len.max(16) * 2
+}
+
+impl<K: Hash + Eq, V: ?Sized, O: AbstractObjectSpace> WeakValueMap<K, V, O> {
+    /// Constructs an empty map.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            purged_len: 0,
+        }
+    }
+
+    /// Insert a weak reference to `value` for `key`. Return the old value
+    /// if it is alive.
+    pub fn insert(&mut self, key: K, value: &RawCc<V, O>) -> Option<RawCc<V, O>> {
+        let old = self.entries.insert(key, value.downgrade());
+        if should_purge(self.entries.len(), self.purged_len) {
+            self.purge();
+        }
+        old.and_then(|weak| weak.upgrade())
+    }
+
+    /// Return the value for `key` if it is alive.
+    pub fn get<Q>(&self, key: &Q) -> Option<RawCc<V, O>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.get(key)?.upgrade()
+    }
+
+    /// Whether the value for `key` is alive.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Remove the entry for `key`. Return the value if it is alive.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<RawCc<V, O>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.remove(key)?.upgrade()
+    }
+
+    /// Number of entries whose values are alive. The time complexity is
+    /// `O(n)`.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether there are no entries whose values are alive.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterate through entries whose values are alive.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, RawCc<V, O>)> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|(key, weak)| Some((key, weak.upgrade()?)))
+    }
+
+    /// Remove entries whose values are released.
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, weak| weak.strong_count() > 0);
+        self.purged_len = self.entries.len();
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> WeakSet<T, O> {
+    /// Constructs an empty set.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            purged_len: 0,
+        }
+    }
+
+    /// Insert a weak reference to `value`. Return `false` if it is already
+    /// in the set.
+    pub fn insert(&mut self, value: &RawCc<T, O>) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.entries.insert(value.id(), value.downgrade());
+        if should_purge(self.entries.len(), self.purged_len) {
+            self.purge();
+        }
+        true
+    }
+
+    /// Whether `value` is in the set.
+    pub fn contains(&self, value: &RawCc<T, O>) -> bool {
+        // The memory of the object is kept by the weak reference, so the
+        // id is not reused by other objects.
+        self.entries.contains_key(&value.id())
+    }
+
+    /// Remove `value` from the set. Return `false` if it is not in the set.
+    pub fn remove(&mut self, value: &RawCc<T, O>) -> bool {
+        self.entries.remove(&value.id()).is_some()
+    }
+
+    /// Number of objects that are alive. The time complexity is `O(n)`.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether there are no objects that are alive.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterate through objects that are alive.
+    pub fn iter(&self) -> impl Iterator<Item = RawCc<T, O>> + '_ {
+        self.entries.values().filter_map(|weak| weak.upgrade())
+    }
+
+    /// Remove objects that are released.
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, weak| weak.strong_count() > 0);
+        self.purged_len = self.entries.len();
+    }
+}
+
+impl<K: Hash + Eq, V: ?Sized, O: AbstractObjectSpace> Default for WeakValueMap<K, V, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> Default for WeakSet<T, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Values are held weakly. Keys might refer to tracked objects.
+impl<K: Trace, V: ?Sized + 'static, O: AbstractObjectSpace> Trace for WeakValueMap<K, V, O> {
+    fn trace(&self, tracer: &mut Tracer) {
+        for key in self.entries.keys() {
+            key.trace(tracer);
+        }
+    }
+
+    #[inline]
+    fn is_type_tracked() -> bool {
+        K::is_type_tracked()
+    }
+}
+
+impl<T: ?Sized + 'static, O: AbstractObjectSpace> Trace for WeakSet<T, O> {
+    #[inline]
+    fn is_type_tracked() -> bool {
+        false
+    }
+}
+
+impl<K: Hash + Eq, V: ?Sized, O: AbstractObjectSpace> fmt::Debug for WeakValueMap<K, V, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakValueMap")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T: ?Sized, O: AbstractObjectSpace> fmt::Debug for WeakSet<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakSet").field("len", &self.len()).finish()
+    }
+}